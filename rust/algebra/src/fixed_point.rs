@@ -234,6 +234,85 @@ impl<P: FixedPointParameters> FixedPoint<P> {
         self.num_muls = 0;
         self
     }
+
+    /// Adds `other` to `self`, returning `None` if the result wraps around
+    /// the field modulus
+    #[inline]
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let num_muls = self.num_muls.max(other.num_muls);
+        let exact = self
+            .aligned_inner(num_muls)
+            .and_then(|a| a.checked_add(other.aligned_inner(num_muls)?));
+        Self::check(self + other, exact)
+    }
+
+    /// Subtracts `other` from `self`, returning `None` if the result wraps
+    /// around the field modulus
+    #[inline]
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        let num_muls = self.num_muls.max(other.num_muls);
+        let exact = self
+            .aligned_inner(num_muls)
+            .and_then(|a| a.checked_sub(other.aligned_inner(num_muls)?));
+        Self::check(self - other, exact)
+    }
+
+    /// Multiplies `self` by `other`, returning `None` if the result wraps
+    /// around the field modulus
+    #[inline]
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        let (a, b) = Self::reduce_for_mul(self, other);
+        let exact = a
+            .signed_inner()
+            .and_then(|a_inner| a_inner.checked_mul(b.signed_inner()?));
+        Self::check(a * b, exact)
+    }
+
+    /// Returns `result` if it represents the integer `exact`
+    #[inline]
+    fn check(result: Self, exact: Option<i128>) -> Option<Self> {
+        match exact {
+            Some(exact) if result.signed_inner() == Some(exact) => Some(result),
+            _ => None,
+        }
+    }
+
+    /// The signed integer represented by `self.inner`, if it fits in an `i128`
+    #[inline]
+    fn signed_inner(&self) -> Option<i128> {
+        let is_negative = self.is_negative();
+        let magnitude = if is_negative { -self.inner } else { self.inner };
+        let mut limbs = magnitude.into_repr().into_iter();
+        let low = u128::from(limbs.next().unwrap_or(0));
+        let high = u128::from(limbs.next().unwrap_or(0));
+        if high >> 63 != 0 || !limbs.all(|l| l == 0) {
+            return None;
+        }
+        let magnitude = (low | (high << 64)) as i128;
+        Some(if is_negative { -magnitude } else { magnitude })
+    }
+
+    /// The signed integer represented by `self.inner` when scaled up to
+    /// `num_muls` multiplications, as addition does
+    #[inline]
+    fn aligned_inner(&self, num_muls: u8) -> Option<i128> {
+        let shift = u32::from(num_muls - self.num_muls) * u32::from(P::EXPONENT_CAPACITY);
+        self.signed_inner()?.checked_mul(2i128.checked_pow(shift)?)
+    }
+
+    /// Reduces `a` or `b` until multiplying them does not exceed the
+    /// multiplication capacity
+    #[inline]
+    fn reduce_for_mul(mut a: Self, mut b: Self) -> (Self, Self) {
+        while a.remaining_mul_capacity() <= b.num_muls && b.remaining_mul_capacity() <= a.num_muls {
+            if b.remaining_mul_capacity() < a.remaining_mul_capacity() {
+                b.signed_reduce_in_place();
+            } else {
+                a.signed_reduce_in_place();
+            }
+        }
+        (a, b)
+    }
 }
 
 pub fn discretized_cos<P: FixedPointParameters>(inp: FixedPoint<P>) -> FixedPoint<P> {
@@ -334,24 +413,13 @@ impl<P: FixedPointParameters> Mul for FixedPoint<P> {
     type Output = Self;
 
     #[inline]
-    fn mul(mut self, mut other: Self) -> Self {
-        if self.remaining_mul_capacity() > other.num_muls {
-            self.inner *= &other.inner;
-            self.num_muls += other.num_muls + 1;
-            self
-        } else if other.remaining_mul_capacity() > self.num_muls {
-            other * self
-        } else {
-            // self.num_muls + other.num_muls > Self::max_mul_capacity()
-            // That is, multiplying will overflow and wrap around.
-            if other.remaining_mul_capacity() < self.remaining_mul_capacity() {
-                other.signed_reduce_in_place();
-                other * self
-            } else {
-                self.signed_reduce_in_place();
-                self * other
-            }
-        }
+    fn mul(self, other: Self) -> Self {
+        // If self.num_muls + other.num_muls > Self::max_mul_capacity(),
+        // multiplying would overflow and wrap around, so reduce first.
+        let (mut a, b) = Self::reduce_for_mul(self, other);
+        a.inner *= &b.inner;
+        a.num_muls += b.num_muls + 1;
+        a
     }
 }
 
@@ -530,6 +598,89 @@ where
     }
 }
 
+/// A `FixedPoint` which records whether any of the arithmetic producing it
+/// wrapped around the field modulus. Evaluating generic code, such as the
+/// layers of a network, over `CheckedFixedPoint`s instead of `FixedPoint`s
+/// computes the same values while detecting overflow.
+#[derive(Derivative)]
+#[derivative(
+    Default(bound = "P: FixedPointParameters"),
+    Clone(bound = "P: FixedPointParameters"),
+    Copy(bound = "P: FixedPointParameters"),
+    Debug(bound = "P: FixedPointParameters")
+)]
+pub struct CheckedFixedPoint<P: FixedPointParameters> {
+    pub value: FixedPoint<P>,
+    pub overflowed: bool,
+}
+
+impl<P: FixedPointParameters> CheckedFixedPoint<P> {
+    #[inline]
+    fn from_checked(
+        checked: Option<FixedPoint<P>>,
+        wrapped: FixedPoint<P>,
+        overflowed: bool,
+    ) -> Self {
+        Self {
+            value: checked.unwrap_or(wrapped),
+            overflowed: overflowed || checked.is_none(),
+        }
+    }
+}
+
+impl<P: FixedPointParameters> From<FixedPoint<P>> for CheckedFixedPoint<P> {
+    #[inline]
+    fn from(value: FixedPoint<P>) -> Self {
+        Self {
+            value,
+            overflowed: false,
+        }
+    }
+}
+
+impl<P: FixedPointParameters> Zero for CheckedFixedPoint<P> {
+    fn zero() -> Self {
+        FixedPoint::zero().into()
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value.is_zero()
+    }
+}
+
+impl<P: FixedPointParameters> Add for CheckedFixedPoint<P> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        Self::from_checked(
+            self.value.checked_add(other.value),
+            self.value + other.value,
+            self.overflowed || other.overflowed,
+        )
+    }
+}
+
+impl<P: FixedPointParameters> AddAssign for CheckedFixedPoint<P> {
+    #[inline]
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other
+    }
+}
+
+impl<P: FixedPointParameters> Mul<FixedPoint<P>> for CheckedFixedPoint<P> {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, other: FixedPoint<P>) -> Self {
+        Self::from_checked(
+            self.value.checked_mul(other),
+            self.value * other,
+            self.overflowed,
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -666,6 +817,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_checked() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        for _ in 0..1000 {
+            let (_, n1) = generate_random_number(&mut rng);
+            let (_, n2) = generate_random_number(&mut rng);
+            assert_eq!(n1.checked_add(n2), Some(n1 + n2));
+            assert_eq!(n1.checked_sub(n2), Some(n1 - n2));
+            assert_eq!(n1.checked_mul(n2), Some(n1 * n2));
+        }
+
+        // 2^20 is 2^25 internally, so its square wraps around the 44-bit field
+        let large = TenBitExpFP::from(f64::from(1 << 20));
+        assert!(large.checked_mul(large).is_none());
+        assert!(large.checked_mul(-large).is_none());
+        // 2^37 is 2^42 internally, so doubling it exceeds half the modulus
+        let larger = TenBitExpFP::from(f64::from(1u32 << 31) * 64.0);
+        assert!(larger.checked_add(larger).is_none());
+        assert!((-larger).checked_sub(larger).is_none());
+        assert_eq!(larger.checked_sub(larger), Some(TenBitExpFP::zero()));
+
+        let checked = CheckedFixedPoint::from(large) * TenBitExpFP::one();
+        assert!(!checked.overflowed);
+        let checked = checked * large;
+        assert!(checked.overflowed);
+        assert!((checked + CheckedFixedPoint::zero()).overflowed);
+    }
+
     #[test]
     fn test_reduce_positive() {
         let one = TenBitExpFP::one();
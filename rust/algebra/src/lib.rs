@@ -45,6 +45,9 @@ pub use self::fields::*;
 pub mod fixed_point;
 pub use fixed_point::*;
 
+pub mod precision;
+pub use precision::*;
+
 pub mod polynomial;
 pub use polynomial::*;
 
@@ -0,0 +1,224 @@
+//! Fixed-point precisions selectable at runtime.
+//!
+//! `FixedPointParameters` are compile-time constants so that fixed-point
+//! arithmetic compiles down to plain field arithmetic. To let one binary handle
+//! models quantized at different precisions, this module defines parameters
+//! over `near_mersenne_64::F` for a fixed set of precisions, and
+//! `with_precision!` dispatches on a runtime `Precision` to code which is
+//! generic over the parameters.
+
+use crate::{fields::near_mersenne_64::F, FixedPointParameters};
+use std::{fmt, str::FromStr};
+
+macro_rules! precisions {
+    ($($variant:ident => $params:ident($exp:literal, $man:literal)),* $(,)?) => {
+        $(
+            pub struct $params {}
+
+            impl FixedPointParameters for $params {
+                type Field = F;
+                const MANTISSA_CAPACITY: u8 = $man;
+                const EXPONENT_CAPACITY: u8 = $exp;
+            }
+        )*
+
+        /// A choice of `FixedPointParameters`. Use `with_precision!` to run
+        /// generic code with the corresponding parameters.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum Precision {
+            $($variant),*
+        }
+
+        impl Precision {
+            /// Every precision, ordered by `EXPONENT_CAPACITY` and then by
+            /// `MANTISSA_CAPACITY`
+            pub const ALL: &'static [Precision] = &[$(Precision::$variant),*];
+
+            pub fn exponent_capacity(self) -> u8 {
+                match self {
+                    $(Precision::$variant => $exp),*
+                }
+            }
+
+            pub fn mantissa_capacity(self) -> u8 {
+                match self {
+                    $(Precision::$variant => $man),*
+                }
+            }
+        }
+    };
+}
+
+precisions! {
+    Exp6Man3 => Exp6Man3Params(6, 3),
+    Exp6Man5 => Exp6Man5Params(6, 5),
+    Exp6Man8 => Exp6Man8Params(6, 8),
+    Exp8Man3 => Exp8Man3Params(8, 3),
+    Exp8Man5 => Exp8Man5Params(8, 5),
+    Exp8Man8 => Exp8Man8Params(8, 8),
+    Exp10Man3 => Exp10Man3Params(10, 3),
+    Exp10Man5 => Exp10Man5Params(10, 5),
+    Exp10Man8 => Exp10Man8Params(10, 8),
+    Exp12Man3 => Exp12Man3Params(12, 3),
+    Exp12Man5 => Exp12Man5Params(12, 5),
+    Exp12Man8 => Exp12Man8Params(12, 8),
+}
+
+/// Evaluates `$body` with the type `$P` bound to the `FixedPointParameters`
+/// of `$precision`. `$body` is instantiated once per precision.
+///
+/// ```ignore
+/// let precision: Precision = "8:3".parse()?;
+/// with_precision!(precision, P => run::<P>(network))
+/// ```
+#[macro_export]
+macro_rules! with_precision {
+    ($precision:expr, $P:ident => $body:expr) => {
+        match $precision {
+            $crate::Precision::Exp6Man3 => {
+                type $P = $crate::Exp6Man3Params;
+                $body
+            }
+            $crate::Precision::Exp6Man5 => {
+                type $P = $crate::Exp6Man5Params;
+                $body
+            }
+            $crate::Precision::Exp6Man8 => {
+                type $P = $crate::Exp6Man8Params;
+                $body
+            }
+            $crate::Precision::Exp8Man3 => {
+                type $P = $crate::Exp8Man3Params;
+                $body
+            }
+            $crate::Precision::Exp8Man5 => {
+                type $P = $crate::Exp8Man5Params;
+                $body
+            }
+            $crate::Precision::Exp8Man8 => {
+                type $P = $crate::Exp8Man8Params;
+                $body
+            }
+            $crate::Precision::Exp10Man3 => {
+                type $P = $crate::Exp10Man3Params;
+                $body
+            }
+            $crate::Precision::Exp10Man5 => {
+                type $P = $crate::Exp10Man5Params;
+                $body
+            }
+            $crate::Precision::Exp10Man8 => {
+                type $P = $crate::Exp10Man8Params;
+                $body
+            }
+            $crate::Precision::Exp12Man3 => {
+                type $P = $crate::Exp12Man3Params;
+                $body
+            }
+            $crate::Precision::Exp12Man5 => {
+                type $P = $crate::Exp12Man5Params;
+                $body
+            }
+            $crate::Precision::Exp12Man8 => {
+                type $P = $crate::Exp12Man8Params;
+                $body
+            }
+        }
+    };
+}
+
+impl Precision {
+    /// Returns the precision with the given capacities, if there is one
+    pub fn from_capacities(exponent: u8, mantissa: u8) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|p| p.exponent_capacity() == exponent && p.mantissa_capacity() == mantissa)
+    }
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision::Exp8Man3
+    }
+}
+
+/// Formats as `EXPONENT:MANTISSA`
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.exponent_capacity(),
+            self.mantissa_capacity()
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsePrecisionError(String);
+
+impl fmt::Display for ParsePrecisionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let supported = Precision::ALL
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            "unsupported precision '{}', expected one of {}",
+            self.0, supported
+        )
+    }
+}
+
+impl std::error::Error for ParsePrecisionError {}
+
+/// Parses `EXPONENT:MANTISSA`
+impl FromStr for Precision {
+    type Err = ParsePrecisionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':').map(|n| n.trim().parse::<u8>().ok());
+        let precision = match (parts.next().flatten(), parts.next().flatten()) {
+            (Some(exponent), Some(mantissa)) => Self::from_capacities(exponent, mantissa),
+            _ => None,
+        };
+        precision.ok_or_else(|| ParsePrecisionError(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FixedPoint;
+
+    fn round_trip<P: FixedPointParameters>(f: f64) -> f64 {
+        f64::from(FixedPoint::<P>::from(f))
+    }
+
+    #[test]
+    fn test_precision() {
+        for &precision in Precision::ALL {
+            assert_eq!(precision.to_string().parse(), Ok(precision));
+            let (exponent, mantissa) = with_precision!(precision, P => {
+                (P::EXPONENT_CAPACITY, P::MANTISSA_CAPACITY)
+            });
+            assert_eq!(exponent, precision.exponent_capacity());
+            assert_eq!(mantissa, precision.mantissa_capacity());
+        }
+        assert!("8:4".parse::<Precision>().is_err());
+        assert!("8".parse::<Precision>().is_err());
+
+        assert_eq!("10:5".parse(), Ok(Precision::Exp10Man5));
+        assert_eq!(
+            Precision::from_capacities(12, 8),
+            Some(Precision::Exp12Man8)
+        );
+
+        let coarse = with_precision!(Precision::Exp6Man3, P => round_trip::<P>(0.3));
+        let fine = with_precision!(Precision::Exp12Man3, P => round_trip::<P>(0.3));
+        assert!((fine - 0.3).abs() < (coarse - 0.3).abs());
+    }
+}
@@ -1,8 +1,14 @@
 #[cfg(feature = "timer")]
 extern crate colored;
+#[cfg(feature = "timer")]
+#[macro_use]
+extern crate lazy_static;
 
 pub use self::inner::*;
 
+#[cfg(feature = "timer")]
+pub mod trace;
+
 #[cfg(feature = "timer")]
 #[macro_use]
 pub mod inner {
@@ -54,6 +60,7 @@ pub mod inner {
                     .unwrap();
                 writeln!(&mut file, "{}, {:?}", $time.0, final_time).unwrap();
             }
+            $crate::trace::span(&format!("{}", $time.0), time, &[]);
             let final_time = {
                 let secs = final_time.as_secs();
                 let millis = final_time.subsec_millis();
@@ -112,8 +119,10 @@ pub mod inner {
 
             let msg_indent_amount = 2 * NUM_INDENT.fetch_add(0, Ordering::Relaxed) + 2;
             let msg_indent = compute_indent_whitespace(msg_indent_amount);
+            let message = $msg();
+            $crate::trace::instant(&title.to_string(), &message.to_string());
             let mut final_message = "\n".to_string();
-            for line in $msg().lines() {
+            for line in message.lines() {
                 final_message += &format!("{}{}\n", msg_indent, line,);
             }
             if let Ok(file_name) = std::env::var("BENCH_OUTPUT_FILE") {
@@ -133,6 +142,24 @@ pub mod inner {
         }};
     }
 
+    /// Returns whether the structured trace is being exported
+    #[macro_export]
+    macro_rules! trace_enabled {
+        () => {
+            $crate::trace::enabled()
+        };
+    }
+
+    /// Records a span which began at the `Instant` `$start` in the structured
+    /// trace, with numeric arguments such as byte counts
+    #[macro_export]
+    macro_rules! add_span_to_trace {
+        ($name:expr, $start:expr, $args:expr) => {{
+            let name = $name();
+            $crate::trace::span(&format!("{}", name), $start, &$args());
+        }};
+    }
+
     pub fn compute_indent(indent_amount: usize) -> String {
         use std::env::var;
         let mut indent = String::new();
@@ -185,6 +212,20 @@ mod inner {
             let _ = $time;
         };
     }
+
+    #[macro_export]
+    macro_rules! trace_enabled {
+        () => {
+            false
+        };
+    }
+
+    #[macro_export]
+    macro_rules! add_span_to_trace {
+        ($name:expr, $start:expr, $args:expr) => {{
+            let _ = ($name, $start, $args);
+        }};
+    }
 }
 
 mod tests {
@@ -194,4 +235,14 @@ mod tests {
         add_to_trace!(|| "HelloMsg", || "Hello, I\nAm\nA\nMessage");
         timer_end!(start);
     }
+
+    #[cfg(feature = "timer")]
+    #[test]
+    fn escape_json() {
+        assert_eq!(
+            crate::trace::escape("a \"quoted\"\nline\\"),
+            r#""a \"quoted\"\nline\\""#
+        );
+        assert_eq!(crate::trace::escape("\u{1}"), r#""\u0001""#);
+    }
 }
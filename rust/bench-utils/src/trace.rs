@@ -0,0 +1,130 @@
+//! Structured trace export.
+//!
+//! When `BENCH_TRACE_FILE` is set, every timer, trace message and span is
+//! appended to that file as an event in the Chrome Trace Event format, which
+//! can be loaded in `chrome://tracing` or Perfetto. Timestamps are measured
+//! from the UNIX epoch so that the traces of the client and the server can be
+//! aligned, and events are tagged with the process id and a small per-process
+//! thread id. Nesting is recovered by the viewer from the timestamps of spans
+//! on the same thread.
+//!
+//! The variable is read once per process, the first time the trace is used,
+//! which also creates the file and keeps it open for the rest of the run. Each
+//! event is flushed as a complete line, and the trailing `]` of the event array
+//! is optional in the format, so the file stays valid even if the process is
+//! killed mid-run.
+//!
+//! Timer spans carry no arguments. Byte counts are only attached to the
+//! spans recorded through `add_span_to_trace!`, such as the communication
+//! phases of `protocols::comm`, so the traffic of a timed region has to be read
+//! off the phase spans nested within it.
+
+use std::{
+    env,
+    fs::File,
+    io::{LineWriter, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub const TRACE_FILE_VAR: &str = "BENCH_TRACE_FILE";
+
+lazy_static! {
+    static ref WRITER: Option<Mutex<LineWriter<File>>> =
+        env::var(TRACE_FILE_VAR).ok().map(|file_name| {
+            let mut file = LineWriter::new(File::create(file_name).unwrap());
+            file.write_all(b"[\n").unwrap();
+            Mutex::new(file)
+        });
+}
+
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_ID: usize = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Returns whether `TRACE_FILE_VAR` is set, i.e. whether events are recorded
+pub fn enabled() -> bool {
+    WRITER.is_some()
+}
+
+/// Records a span named `name` which began at `start` and ends now, along
+/// with numeric `args` such as byte counts
+pub fn span(name: &str, start: Instant, args: &[(&str, u64)]) {
+    if !enabled() {
+        return;
+    }
+    let dur = start.elapsed();
+    let ts = micros_since_epoch().saturating_sub(dur.as_micros());
+    let args = args
+        .iter()
+        .map(|(key, value)| format!("{}:{}", escape(key), value))
+        .collect::<Vec<_>>()
+        .join(",");
+    append(&format!(
+        r#"{{"name":{},"ph":"X","ts":{},"dur":{},"pid":{},"tid":{},"args":{{{}}}}}"#,
+        escape(name),
+        ts,
+        dur.as_micros(),
+        std::process::id(),
+        thread_id(),
+        args,
+    ));
+}
+
+/// Records a message at the current time
+pub fn instant(name: &str, message: &str) {
+    if !enabled() {
+        return;
+    }
+    append(&format!(
+        r#"{{"name":{},"ph":"i","s":"t","ts":{},"pid":{},"tid":{},"args":{{"message":{}}}}}"#,
+        escape(name),
+        micros_since_epoch(),
+        std::process::id(),
+        thread_id(),
+        escape(message),
+    ));
+}
+
+fn thread_id() -> usize {
+    THREAD_ID.with(|id| *id)
+}
+
+fn micros_since_epoch() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the UNIX epoch")
+        .as_micros()
+}
+
+fn append(event: &str) {
+    if let Some(writer) = WRITER.as_ref() {
+        // The lock keeps events from concurrent threads from interleaving
+        let mut writer = writer.lock().unwrap();
+        writeln!(writer, "{},", event).unwrap();
+    }
+}
+
+/// Quotes `s` as a JSON string
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
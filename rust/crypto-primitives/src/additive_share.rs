@@ -353,6 +353,17 @@ impl<T: AuthShare> AuthAdditiveShare<T> {
         }
     }
 
+    #[inline]
+    /// Construct an authenticated share from `value` and `mac` to which the
+    /// constants summing to `epsilon` have been added
+    pub fn from_parts(value: T, mac: T, epsilon: T) -> Self {
+        Self {
+            epsilon,
+            value,
+            mac,
+        }
+    }
+
     /// Combine two authenticated shares to obtain the shared value.
     #[inline]
     pub fn combine(&self, other: &Self, mac_key: &<T as Share>::Ring) -> Result<T, AuthError> {
@@ -405,6 +416,12 @@ impl<T: AuthShare> AuthAdditiveShare<T> {
     pub fn get_mac(self) -> AdditiveShare<T> {
         AdditiveShare::new(self.mac)
     }
+
+    /// Get the sum of the constants added to the share
+    #[inline]
+    pub fn get_epsilon(self) -> AdditiveShare<T> {
+        AdditiveShare::new(self.epsilon)
+    }
 }
 
 impl<T: AuthShare> Zero for AuthAdditiveShare<T> {
@@ -1,5 +1,5 @@
 use crate::additive_share::{AuthAdditiveShare, AuthShare, Share};
-use algebra::{Fp64, Fp64Parameters, PrimeField};
+use algebra::{Field, Fp64, Fp64Parameters, PrimeField};
 use rand_chacha::ChaChaRng;
 use rand_core::SeedableRng;
 use serde::{Deserialize, Serialize};
@@ -16,6 +16,32 @@ pub struct Triple<T: AuthShare> {
     pub c: AuthAdditiveShare<T>,
 }
 
+/// Shares of a square pair `[[a]]`, `[[a^2]]`.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: AuthShare")]
+pub struct SquarePair<T: AuthShare> {
+    /// A share of `a`.
+    pub a: AuthAdditiveShare<T>,
+    /// A share of `a^2`.
+    pub a_squared: AuthAdditiveShare<T>,
+}
+
+/// Shares of a matrix triple `[[A]]`, `[[B]]`, `[[C]]` such that `AB = C`,
+/// where `A` is `m x k` and `B` is `k x n`. Matrices are stored in row-major
+/// order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: AuthShare")]
+pub struct MatrixTriple<T: AuthShare> {
+    /// Shares of the entries of `A`.
+    pub a: Vec<AuthAdditiveShare<T>>,
+    /// Shares of the entries of `B`.
+    pub b: Vec<AuthAdditiveShare<T>>,
+    /// Shares of the entries of `C`.
+    pub c: Vec<AuthAdditiveShare<T>>,
+    /// The dimensions `(m, k, n)` of the triple.
+    pub dims: (usize, usize, usize),
+}
+
 /// Shares of the intermediate step.
 #[derive(Clone, Copy, Serialize, Deserialize)]
 #[serde(bound = "T: AuthShare")]
@@ -58,6 +84,13 @@ pub trait BeaversMul<T: AuthShare> {
         bl: BlindedInputs<T>,
         t: &Triple<T>,
     ) -> AuthAdditiveShare<T>;
+
+    /// Square a blinded input `x - a` using a square pair.
+    fn square_blinded_input(
+        party_index: usize,
+        blinded_x: T,
+        s: &SquarePair<T>,
+    ) -> AuthAdditiveShare<T>;
 }
 
 /// An implementation of Beaver's multiplication algorithm for a malicious
@@ -79,6 +112,20 @@ impl<P: Fp64Parameters> BeaversMul<Fp64<P>> for PBeaversMul<P> {
             res
         }
     }
+
+    fn square_blinded_input(
+        party_index: usize,
+        blinded_x: Fp64<P>,
+        s: &SquarePair<Fp64<P>>,
+    ) -> AuthAdditiveShare<Fp64<P>> {
+        let epsilon = blinded_x;
+        let res = s.a_squared + (s.a * epsilon.double());
+        if party_index == 1 {
+            res.add_constant(epsilon.square())
+        } else {
+            res
+        }
+    }
 }
 
 /// An **insecure** method of generating triples. This is intended *purely* for
@@ -121,12 +168,69 @@ where
         };
         (party_1_triple, party_2_triple)
     }
+
+    /// Sample a square pair for both parties.
+    pub fn generate_square_shares(
+        &mut self,
+        mac_key: <T as Share>::Ring,
+    ) -> (SquarePair<T>, SquarePair<T>) {
+        let a = T::uniform(&mut self.0);
+        let (a1, a2) = a.auth_share(&mac_key, &mut self.0);
+        let (s1, s2) = (a * a).auth_share(&mac_key, &mut self.0);
+        (
+            SquarePair {
+                a: a1,
+                a_squared: s1,
+            },
+            SquarePair {
+                a: a2,
+                a_squared: s2,
+            },
+        )
+    }
+
+    /// Sample a matrix triple of dimensions `dims` for both parties.
+    pub fn generate_matrix_triple_shares(
+        &mut self,
+        mac_key: <T as Share>::Ring,
+        dims: (usize, usize, usize),
+    ) -> (MatrixTriple<T>, MatrixTriple<T>) {
+        let (m, k, n) = dims;
+        let a: Vec<T> = (0..m * k).map(|_| T::uniform(&mut self.0)).collect();
+        let b: Vec<T> = (0..k * n).map(|_| T::uniform(&mut self.0)).collect();
+        let c: Vec<T> = (0..m * n)
+            .map(|idx| {
+                let (i, j) = (idx / n, idx % n);
+                (0..k).fold(T::zero(), |sum, l| sum + a[i * k + l] * b[l * n + j])
+            })
+            .collect();
+        let rng = &mut self.0;
+        let (a1, a2) = a.iter().map(|e| e.auth_share(&mac_key, rng)).unzip();
+        let (b1, b2) = b.iter().map(|e| e.auth_share(&mac_key, rng)).unzip();
+        let (c1, c2) = c.iter().map(|e| e.auth_share(&mac_key, rng)).unzip();
+
+        let party_1_triple = MatrixTriple {
+            a: a1,
+            b: b1,
+            c: c1,
+            dims,
+        };
+
+        let party_2_triple = MatrixTriple {
+            a: a2,
+            b: b2,
+            c: c2,
+            dims,
+        };
+        (party_1_triple, party_2_triple)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use algebra::{fields::near_mersenne_64::F, UniformRandom};
+    use num_traits::Zero;
 
     const RANDOMNESS: [u8; 32] = [
         0x11, 0xe0, 0x8f, 0xbc, 0x89, 0xa7, 0x34, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda, 0xf4,
@@ -148,6 +252,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_square_gen() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mut gen = InsecureTripleGen::<F>::new(RANDOMNESS);
+        for _ in 0..1000 {
+            let mac_key = F::uniform(&mut rng);
+            let (s1, s2) = gen.generate_square_shares(mac_key);
+            let a = s1.a.combine(&s2.a, &mac_key).unwrap();
+            let a_squared = s1.a_squared.combine(&s2.a_squared, &mac_key).unwrap();
+            assert_eq!(a * a, a_squared);
+        }
+    }
+
+    #[test]
+    fn test_matrix_triple_gen() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mut gen = InsecureTripleGen::<F>::new(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let (m, k, n) = (3, 4, 5);
+        let (t1, t2) = gen.generate_matrix_triple_shares(mac_key, (m, k, n));
+        let combine = |s1: &[AuthAdditiveShare<F>], s2: &[AuthAdditiveShare<F>]| {
+            s1.iter()
+                .zip(s2)
+                .map(|(s1, s2)| s1.combine(s2, &mac_key).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let a = combine(&t1.a, &t2.a);
+        let b = combine(&t1.b, &t2.b);
+        let c = combine(&t1.c, &t2.c);
+        for i in 0..m {
+            for j in 0..n {
+                let mut sum = F::zero();
+                for l in 0..k {
+                    sum += a[i * k + l] * b[l * n + j];
+                }
+                assert_eq!(c[i * n + j], sum);
+            }
+        }
+    }
+
     #[test]
     fn test_share_and_blind() {
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
@@ -232,4 +376,21 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_beavers_square() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mut gen = InsecureTripleGen::<F>::new(RANDOMNESS);
+        for _ in 0..1000 {
+            let mac_key = F::uniform(&mut rng);
+            let n = F::uniform(&mut rng);
+            let (sq1, sq2) = gen.generate_square_shares(mac_key);
+            let (s1, s2) = n.auth_share(&mac_key, &mut rng);
+            let blinded_x = (s1 - sq1.a).combine(&(s2 - sq2.a), &mac_key).unwrap();
+
+            let r1 = PBeaversMul::square_blinded_input(1, blinded_x, &sq1);
+            let r2 = PBeaversMul::square_blinded_input(2, blinded_x, &sq2);
+            assert_eq!(r1.combine(&r2, &mac_key).unwrap(), n * n);
+        }
+    }
 }
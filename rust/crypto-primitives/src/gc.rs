@@ -9,6 +9,12 @@ use fancy_garbling::{
     util, BinaryBundle, BinaryGadgets, Bundle, BundleGadgets, Fancy,
 };
 
+/// Number of integer bits of a ReLU input which the ReLU circuits inspect.
+/// Positive inputs of `2^RELU_INTEGER_BITS` or more have their higher bits
+/// dropped, so they are only clipped correctly if the network never produces
+/// them.
+pub const RELU_INTEGER_BITS: usize = 8;
+
 #[inline(always)]
 fn mux_single_bit<F: Fancy>(
     f: &mut F,
@@ -386,10 +392,13 @@ where
         // Compute the relu
         let mut relu_res = Vec::with_capacity(num_bits);
         let relu_6_size = exponent_size + 3;
-        // We choose 5 arbitrarily here; the idea is that we won't see values of
-        // greater than 2^8.
-        // We then drop the larger bits
-        for wire in layer_input.wires().iter().take(relu_6_size + 5) {
+        // We assume that we won't see values greater than 2^RELU_INTEGER_BITS,
+        // and drop the larger bits
+        for wire in layer_input
+            .wires()
+            .iter()
+            .take(exponent_size + RELU_INTEGER_BITS)
+        {
             relu_res.push(b.and(&zs_is_positive, wire)?);
         }
         let is_seven = b.and_many(&relu_res[(exponent_size + 1)..relu_6_size])?;
@@ -413,7 +422,7 @@ where
             *wire = mux_single_bit(b, &should_be_six, wire, &zero)?;
         }
 
-        relu_res.extend(std::iter::repeat(zero).take(num_bits - relu_6_size - 5));
+        relu_res.extend(std::iter::repeat(zero).take(num_bits - exponent_size - RELU_INTEGER_BITS));
 
         let relu_res = BinaryBundle::new(relu_res);
 
@@ -488,13 +497,13 @@ where
         let mut relu_res = Vec::with_capacity(num_bits);
         let relu_6_size = exponent_size + 3;
         // First we skip all the bits which will be truncated. Next, we take the number of bits
-        // needed for ReLU6 along with some arbitrary upperbound (we choose 2^8) for the
+        // needed for ReLU6 along with an upper bound (2^RELU_INTEGER_BITS) for the
         // intermediate values during network evaluation. We then drop the larger bits.
         for wire in layer_input
             .wires()
             .iter()
             .skip(trunc_bits as usize)
-            .take(relu_6_size + 5)
+            .take(exponent_size + RELU_INTEGER_BITS)
         {
             relu_res.push(b.and(&inp_is_positive, wire)?);
         }
@@ -519,7 +528,7 @@ where
             *wire = mux_single_bit(b, &should_be_six, wire, &zero)?;
         }
 
-        relu_res.extend(std::iter::repeat(zero).take(num_bits - relu_6_size - 5));
+        relu_res.extend(std::iter::repeat(zero).take(num_bits - exponent_size - RELU_INTEGER_BITS));
 
         let relu_res = BinaryBundle::new(relu_res);
 
@@ -13,5 +13,5 @@ path="bandwidth_data/minionn.pcap"
 tshark -i ens5 -w $path &
 sleep 5
 pid=$!
-cargo +nightly run --all-features --release --bin minionn-client;
+cargo +nightly run --all-features --release --bin muse -- client --model minionn;
 sudo kill $pid
@@ -17,7 +17,7 @@ for i in {0..4}
 do
   echo "MiniONN on 4 threads - Run #$i"
   run_path="latency_data/run$i/minionn-4.txt"
-  env RAYON_NUM_THREADS=4 CLICOLOR=0 cargo +nightly run --all-features --release --bin minionn-client 2>$err > $run_path;
+  env RAYON_NUM_THREADS=4 CLICOLOR=0 cargo +nightly run --all-features --release --bin muse -- client --model minionn 2>$err > $run_path;
   cat "$run_path/minionn-4.txt" | egrep "End.*Client online|End.*offline phase|End.*ReLU layer\."
   echo -e "\n"
   sleep 2
@@ -6,5 +6,5 @@ done
 
 for j in {0..4}
 do
-  env RAYON_NUM_THREADS=4 cargo +nightly run --all-features --release --bin minionn-server
+  env RAYON_NUM_THREADS=4 cargo +nightly run --all-features --release --bin muse -- serve --model minionn
 done
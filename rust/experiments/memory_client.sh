@@ -10,4 +10,4 @@ path="memory_data/resnet32-client-$l.out"
 valgrind --tool=massif --pages-as-heap=no --massif-out-file=$path ../target/release/resnet32-client
 
 path="memory_data/minionn-client-$l.out"
-valgrind --tool=massif --pages-as-heap=no --massif-out-file=$path ../target/release/minionn-client
+valgrind --tool=massif --pages-as-heap=no --massif-out-file=$path ../target/release/muse client --model minionn
@@ -10,4 +10,4 @@ path="memory_data/resnet32-server.out"
 valgrind --tool=massif --pages-as-heap=no --massif-out-file=$path ../target/release/resnet32-server
 
 path="memory_data/minionn-server.out"
-valgrind --tool=massif --pages-as-heap=no --massif-out-file=$path ../target/release/minionn-server
+valgrind --tool=massif --pages-as-heap=no --massif-out-file=$path ../target/release/muse serve --model minionn
@@ -0,0 +1,600 @@
+//! Accuracy of secure inference with trained weights.
+//!
+//! A network is evaluated over a labelled dataset in plaintext floating point,
+//! in plaintext fixed point, with the plaintext simulation of the protocol's
+//! arithmetic, and with the full secure inference protocol run in-process over
+//! memory transports. For each choice of fixed-point parameters we report the
+//! top-1 accuracy of each evaluation and how often their predictions disagree.
+//!
+//! Weights are loaded with `NeuralNetwork::from_numpy`. Since `npy` only reads
+//! one-dimensional arrays, images must be stored as a flattened `f64` array in
+//! `(N, C, H, W)` order and labels as an `i64` array.
+
+use super::*;
+use algebra::{
+    fp_64::{Fp64, Fp64Parameters},
+    with_precision, Precision, PrimeField,
+};
+use crypto_primitives::{AuthShare, Share};
+use nn::{
+    layers::{
+        average_pooling::AvgPoolParams, convolution::Conv2dParams,
+        fully_connected::FullyConnectedParams, LayerInfo, LinearLayerInfo, NonLinearLayerInfo,
+    },
+    npy::NpyData,
+    EvalMethod, Evaluate, NeuralArchitecture,
+};
+use num_traits::Zero;
+use protocols::{
+    neural_network::NNProtocol,
+    simulator::{self, Truncation},
+    transport::{memory, LinkConfig},
+};
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
+use std::{
+    fmt,
+    io::{self, Read},
+    ops::{Add, AddAssign, Mul},
+};
+
+/// Number of streams multiplexed over the in-memory connection
+const NUM_STREAMS: usize = 8;
+
+/// Evaluates the network with the fixed-point parameters of `precision`
+pub fn evaluate_with_precision<A, B, R: RngCore + CryptoRng>(
+    precision: Precision,
+    architecture: &NeuralArchitecture<A, B>,
+    weights: &str,
+    dataset: &Dataset,
+    float_predictions: &[usize],
+    num_secure: usize,
+    rng: &mut R,
+) -> io::Result<Report> {
+    with_precision!(precision, P => evaluate::<P, _, _, _, _>(
+        architecture,
+        weights,
+        dataset,
+        float_predictions,
+        num_secure,
+        rng,
+    ))
+}
+
+/// A labelled set of images
+pub struct Dataset {
+    pub images: Vec<Input<f64>>,
+    pub labels: Vec<usize>,
+}
+
+impl Dataset {
+    /// Loads images of dimension `input_dims` and their labels from `.npy`
+    /// files
+    pub fn from_numpy(
+        images_path: &str,
+        labels_path: &str,
+        input_dims: (usize, usize, usize, usize),
+    ) -> io::Result<Self> {
+        let images = load_images(images_path, input_dims)?;
+        let buf = read_file(labels_path)?;
+        let labels: Vec<i64> = NpyData::from_bytes(&buf)?.to_vec();
+        if images.len() != labels.len() {
+            return Err(invalid_data(format!(
+                "{} labels expected, found {}",
+                images.len(),
+                labels.len()
+            )));
+        }
+        let labels = labels
+            .into_iter()
+            .map(|l| {
+                if l < 0 {
+                    Err(invalid_data(format!("invalid label {}", l)))
+                } else {
+                    Ok(l as usize)
+                }
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { images, labels })
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Keeps only the first `num` images
+    pub fn truncate(&mut self, num: usize) {
+        self.images.truncate(num);
+        self.labels.truncate(num);
+    }
+}
+
+/// Loads images of dimension `input_dims` from a `.npy` file
+pub fn load_images(
+    path: &str,
+    input_dims: (usize, usize, usize, usize),
+) -> io::Result<Vec<Input<f64>>> {
+    let buf = read_file(path)?;
+    let pixels: Vec<f64> = NpyData::from_bytes(&buf)?.to_vec();
+    let image_size = input_dims.0 * input_dims.1 * input_dims.2 * input_dims.3;
+    if pixels.len() % image_size != 0 {
+        return Err(invalid_data(format!(
+            "{} values is not a whole number of images of {} values",
+            pixels.len(),
+            image_size
+        )));
+    }
+    Ok(pixels
+        .chunks(image_size)
+        .map(|image| Input::from_shape_vec(input_dims, image.to_vec()).unwrap())
+        .collect())
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    std::fs::File::open(path)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Accuracy of one choice of fixed-point parameters
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// `(EXPONENT_CAPACITY, MANTISSA_CAPACITY)`
+    pub precision: (u8, u8),
+    pub num_images: usize,
+    /// Number of images which were also classified with secure inference
+    pub num_secure: usize,
+    pub float_correct: usize,
+    pub fixed_correct: usize,
+    pub simulated_correct: usize,
+    pub secure_correct: usize,
+    /// Number of images on which fixed-point and float predictions differ
+    pub fixed_float_disagreements: usize,
+    /// Number of images on which fixed-point evaluation wrapped around the
+    /// field modulus or exceeded the window of the ReLU circuit
+    pub fixed_overflows: usize,
+    /// Number of images on which simulated and plaintext fixed-point
+    /// predictions differ
+    pub simulated_fixed_disagreements: usize,
+    /// Number of images on which secure and plaintext fixed-point predictions
+    /// differ
+    pub secure_fixed_disagreements: usize,
+    /// Number of images on which the output of secure inference differs from
+    /// its simulation. This should always be zero.
+    pub secure_simulated_mismatches: usize,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |correct: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                100.0 * correct as f64 / total as f64
+            }
+        };
+        writeln!(
+            f,
+            "Exponent {}, mantissa {}:",
+            self.precision.0, self.precision.1
+        )?;
+        writeln!(
+            f,
+            "  Float:       {}/{} correct ({:.2}%)",
+            self.float_correct,
+            self.num_images,
+            percent(self.float_correct, self.num_images)
+        )?;
+        writeln!(
+            f,
+            "  Fixed point: {}/{} correct ({:.2}%), {} disagreements with float, {} images \
+             overflowed",
+            self.fixed_correct,
+            self.num_images,
+            percent(self.fixed_correct, self.num_images),
+            self.fixed_float_disagreements,
+            self.fixed_overflows
+        )?;
+        writeln!(
+            f,
+            "  Simulated:   {}/{} correct ({:.2}%), {} disagreements with fixed point",
+            self.simulated_correct,
+            self.num_images,
+            percent(self.simulated_correct, self.num_images),
+            self.simulated_fixed_disagreements
+        )?;
+        write!(
+            f,
+            "  Secure:      {}/{} correct ({:.2}%), {} disagreements with fixed point, {} \
+             mismatches with simulation",
+            self.secure_correct,
+            self.num_secure,
+            percent(self.secure_correct, self.num_secure),
+            self.secure_fixed_disagreements,
+            self.secure_simulated_mismatches
+        )
+    }
+}
+
+/// Builds a network with the layers of `architecture` and the weights stored
+/// at `weights`, returning an error if they don't match
+pub fn network_from_numpy<F, C, A, B>(
+    architecture: &NeuralArchitecture<A, B>,
+    weights: &str,
+) -> io::Result<NeuralNetwork<F, C>>
+where
+    F: Zero + Mul<C, Output = F> + AddAssign + Add<Output = F> + Copy,
+    C: Zero + Copy + Into<F> + From<f64>,
+{
+    let mut network = NeuralNetwork {
+        eval_method: EvalMethod::Naive,
+        layers: vec![],
+    };
+    for layer in &architecture.layers {
+        let layer = match layer {
+            LayerInfo::LL(
+                dims,
+                LinearLayerInfo::Conv2d {
+                    kernel,
+                    padding,
+                    stride,
+                },
+            ) => {
+                let params = Conv2dParams::new(
+                    *padding,
+                    *stride,
+                    Kernel::zeros(*kernel),
+                    Kernel::zeros((kernel.0, 1, 1, 1)),
+                );
+                Layer::LL(LinearLayer::Conv2d {
+                    dims: *dims,
+                    params,
+                })
+            }
+            LayerInfo::LL(dims, LinearLayerInfo::FullyConnected) => {
+                let (_, in_channels, in_height, in_width) = dims.input_dimensions();
+                let out_channels = dims.output_dimensions().1;
+                let params = FullyConnectedParams::new(
+                    Kernel::zeros((out_channels, in_channels, in_height, in_width)),
+                    Kernel::zeros((out_channels, 1, 1, 1)),
+                );
+                Layer::LL(LinearLayer::FullyConnected {
+                    dims: *dims,
+                    params,
+                })
+            }
+            LayerInfo::LL(
+                dims,
+                LinearLayerInfo::AvgPool {
+                    pool_h,
+                    pool_w,
+                    stride,
+                    ..
+                },
+            ) => {
+                let normalizer = C::from(1.0 / (pool_h * pool_w) as f64);
+                Layer::LL(LinearLayer::AvgPool {
+                    dims: *dims,
+                    params: AvgPoolParams::new(*pool_h, *pool_w, *stride, normalizer),
+                })
+            }
+            LayerInfo::LL(dims, LinearLayerInfo::Identity) => {
+                Layer::LL(LinearLayer::Identity { dims: *dims })
+            }
+            LayerInfo::NLL(dims, NonLinearLayerInfo::ReLU { .. }) => {
+                Layer::NLL(NonLinearLayer::ReLU {
+                    dims: *dims,
+                    _f: std::marker::PhantomData,
+                    _c: std::marker::PhantomData,
+                })
+            }
+        };
+        network.layers.push(layer);
+    }
+    if !network.validate() {
+        return Err(invalid_data(
+            "the layers of the model don't fit together".into(),
+        ));
+    }
+    let buf = read_file(weights)?;
+    let weights: Vec<f64> = NpyData::from_bytes(&buf)?.to_vec();
+    network
+        .from_weights(&weights)
+        .map_err(|e| invalid_data(format!("weights don't match the model: {}", e)))?;
+    Ok(network)
+}
+
+/// Returns the index of the largest output
+fn argmax<T: Copy + Into<f64>>(output: &Output<T>) -> usize {
+    output
+        .iter()
+        .map(|o| (*o).into())
+        .enumerate()
+        .fold((0, f64::NEG_INFINITY), |(best, max), (i, o)| {
+            if o > max {
+                (i, o)
+            } else {
+                (best, max)
+            }
+        })
+        .0
+}
+
+/// Classifies every image of `dataset` in plaintext floating point
+pub fn float_predictions<A, B>(
+    architecture: &NeuralArchitecture<A, B>,
+    weights: &str,
+    dataset: &Dataset,
+) -> io::Result<Vec<usize>> {
+    let network: NeuralNetwork<f64, f64> = network_from_numpy(architecture, weights)?;
+    Ok(dataset
+        .images
+        .iter()
+        .map(|image| argmax(&network.evaluate(image)))
+        .collect())
+}
+
+/// Runs both parties of secure inference on `input` in-process
+fn secure_inference<P, Fp, R>(
+    network: &NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+    architecture: &NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
+    input: &Input<FixedPoint<P>>,
+    rng: &mut R,
+) -> Output<FixedPoint<P>>
+where
+    Fp: Fp64Parameters,
+    P: FixedPointParameters<Field = Fp64<Fp>>,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: Share<
+        Constant = <P as FixedPointParameters>::Field,
+        Ring = <P as FixedPointParameters>::Field,
+    >,
+    P::Field: AuthShare,
+    R: RngCore + CryptoRng,
+{
+    let mut server_rng = ChaChaRng::from_rng(&mut *rng).unwrap();
+    let mut client_rng = ChaChaRng::from_rng(&mut *rng).unwrap();
+    let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+        memory::connect(NUM_STREAMS, LinkConfig::default());
+
+    crossbeam::thread::scope(|s| {
+        let server = s.spawn(|_| {
+            let state = NNProtocol::offline_server_protocol(
+                &mut server_reader,
+                &mut server_writer,
+                network,
+                &mut server_rng,
+            )
+            .unwrap();
+            NNProtocol::online_server_protocol(
+                &mut server_reader,
+                &mut server_writer,
+                network,
+                &state,
+            )
+            .unwrap();
+        });
+        let state = NNProtocol::offline_client_protocol(
+            &mut client_reader,
+            &mut client_writer,
+            architecture,
+            &mut client_rng,
+        )
+        .unwrap();
+        let output = NNProtocol::online_client_protocol(
+            &mut client_reader,
+            &mut client_writer,
+            input,
+            architecture,
+            &state,
+        )
+        .unwrap();
+        server.join().unwrap();
+        output
+    })
+    .unwrap()
+}
+
+/// Evaluates the network with fixed-point parameters `P` over `dataset`,
+/// running secure inference on the first `num_secure` images
+pub fn evaluate<P, Fp, A, B, R>(
+    architecture: &NeuralArchitecture<A, B>,
+    weights: &str,
+    dataset: &Dataset,
+    float_predictions: &[usize],
+    num_secure: usize,
+    rng: &mut R,
+) -> io::Result<Report>
+where
+    Fp: Fp64Parameters,
+    P: FixedPointParameters<Field = Fp64<Fp>>,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: Share<
+        Constant = <P as FixedPointParameters>::Field,
+        Ring = <P as FixedPointParameters>::Field,
+    >,
+    P::Field: AuthShare,
+    R: RngCore + CryptoRng,
+{
+    let fixed_network: NeuralNetwork<FixedPoint<P>, FixedPoint<P>> =
+        network_from_numpy(architecture, weights)?;
+    let secure_network: NeuralNetwork<AdditiveShare<P>, FixedPoint<P>> =
+        network_from_numpy(architecture, weights)?;
+    let secure_architecture = (&secure_network).into();
+    let num_secure = num_secure.min(dataset.len());
+
+    let mut report = Report {
+        precision: (P::EXPONENT_CAPACITY, P::MANTISSA_CAPACITY),
+        num_images: dataset.len(),
+        num_secure,
+        float_correct: 0,
+        fixed_correct: 0,
+        simulated_correct: 0,
+        secure_correct: 0,
+        fixed_float_disagreements: 0,
+        fixed_overflows: 0,
+        simulated_fixed_disagreements: 0,
+        secure_fixed_disagreements: 0,
+        secure_simulated_mismatches: 0,
+    };
+    for (i, (image, &label)) in dataset.images.iter().zip(&dataset.labels).enumerate() {
+        let input = Input::from_shape_vec(
+            image.dim(),
+            image.iter().map(|&p| FixedPoint::<P>::from(p)).collect(),
+        )
+        .unwrap();
+        let float_prediction = float_predictions[i];
+        let (fixed_output, overflows) = fixed_network.evaluate_checked(&input);
+        let fixed_prediction = argmax(&fixed_output);
+        report.fixed_overflows += !overflows.is_empty() as usize;
+        report.float_correct += (float_prediction == label) as usize;
+        report.fixed_correct += (fixed_prediction == label) as usize;
+        report.fixed_float_disagreements += (fixed_prediction != float_prediction) as usize;
+
+        let simulated =
+            simulator::simulate(&secure_network, &input, Truncation::InCircuit, &mut *rng);
+        let simulated_prediction = argmax(&simulated);
+        report.simulated_correct += (simulated_prediction == label) as usize;
+        report.simulated_fixed_disagreements += (simulated_prediction != fixed_prediction) as usize;
+
+        if i < num_secure {
+            let output = secure_inference(&secure_network, &secure_architecture, &input, rng);
+            let secure_prediction = argmax(&output);
+            report.secure_correct += (secure_prediction == label) as usize;
+            report.secure_fixed_disagreements += (secure_prediction != fixed_prediction) as usize;
+            report.secure_simulated_mismatches += output
+                .iter()
+                .zip(simulated.iter())
+                .any(|(o, s)| o.inner != s.inner)
+                as usize;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `data` to a `.npy` file in the temporary directory
+    fn write_npy<T: nn::npy::Serializable>(name: &str, data: Vec<T>) -> String {
+        let path =
+            std::env::temp_dir().join(format!("muse-accuracy-{}-{}.npy", std::process::id(), name));
+        nn::npy::to_file(&path, data).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Two fully connected layers around a ReLU over inputs of two pixels
+    fn architecture() -> NeuralArchitecture<TenBitAS, TenBitExpFP> {
+        type Dims = (usize, usize, usize, usize);
+        let fc = |input_dims: Dims, output_dims: Dims| {
+            let (_, in_channels, in_height, in_width) = input_dims;
+            let out_channels = output_dims.1;
+            let params = FullyConnectedParams::new(
+                Kernel::zeros((out_channels, in_channels, in_height, in_width)),
+                Kernel::zeros((out_channels, 1, 1, 1)),
+            );
+            Layer::LL(LinearLayer::FullyConnected {
+                dims: LayerDims {
+                    input_dims,
+                    output_dims,
+                },
+                params,
+            })
+        };
+        let network: NeuralNetwork<TenBitAS, TenBitExpFP> = NeuralNetwork {
+            eval_method: EvalMethod::Naive,
+            layers: vec![
+                fc((1, 1, 1, 2), (1, 2, 1, 1)),
+                Layer::NLL(NonLinearLayer::ReLU {
+                    dims: LayerDims {
+                        input_dims: (1, 2, 1, 1),
+                        output_dims: (1, 2, 1, 1),
+                    },
+                    _f: std::marker::PhantomData,
+                    _c: std::marker::PhantomData,
+                }),
+                fc((1, 2, 1, 1), (1, 2, 1, 1)),
+            ],
+        };
+        (&network).into()
+    }
+
+    #[test]
+    fn test_evaluate_accuracy() {
+        let architecture = architecture();
+        // Both layers are the identity, so each image is classified by its
+        // largest pixel
+        let weights = write_npy(
+            "weights",
+            vec![1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+        );
+        let images = write_npy("images", vec![1.0, 0.0, 0.0, 1.0, 0.25, 0.75, 0.875, 0.125]);
+        // The third label is wrong
+        let labels = write_npy("labels", vec![0i64, 1, 0, 0]);
+        let dataset = Dataset::from_numpy(&images, &labels, (1, 1, 1, 2)).unwrap();
+        assert_eq!(dataset.len(), 4);
+
+        let float_predictions = float_predictions(&architecture, &weights, &dataset).unwrap();
+        assert_eq!(float_predictions, vec![0, 1, 1, 0]);
+
+        let mut rng = ChaChaRng::from_seed([7u8; 32]);
+        let report = evaluate_with_precision(
+            Precision::Exp8Man3,
+            &architecture,
+            &weights,
+            &dataset,
+            &float_predictions,
+            1,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(report.precision, (8, 3));
+        assert_eq!(report.num_images, 4);
+        assert_eq!(report.num_secure, 1);
+        assert_eq!(report.float_correct, 3);
+        assert_eq!(report.fixed_correct, 3);
+        assert_eq!(report.simulated_correct, 3);
+        assert_eq!(report.secure_correct, 1);
+        assert_eq!(report.fixed_float_disagreements, 0);
+        assert_eq!(report.fixed_overflows, 0);
+        assert_eq!(report.simulated_fixed_disagreements, 0);
+        assert_eq!(report.secure_fixed_disagreements, 0);
+        assert_eq!(report.secure_simulated_mismatches, 0);
+
+        for path in &[weights, images, labels] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dataset_rejects_mismatched_labels() {
+        let images = write_npy("short-images", vec![1.0, 0.0, 0.0, 1.0]);
+        let labels = write_npy("short-labels", vec![0i64, 1, 1]);
+        assert!(Dataset::from_numpy(&images, &labels, (1, 1, 1, 2)).is_err());
+        for path in &[images, labels] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_network_rejects_mismatched_weights() {
+        let architecture = architecture();
+        let weights = write_npy("short-weights", vec![1.0, 0.0, 0.0, 1.0]);
+        let network: io::Result<NeuralNetwork<f64, f64>> =
+            network_from_numpy(&architecture, &weights);
+        assert!(network.is_err());
+        assert!(
+            network_from_numpy::<f64, f64, _, _>(&architecture, "missing-weights.npy").is_err()
+        );
+        std::fs::remove_file(weights).unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+//! Calibration of fixed-point parameters for a trained model.
+//!
+//! Secure inference silently produces wrong results when a value leaves the
+//! range the protocol assumes. The output of a linear layer is computed in the
+//! field at a scale of `2^(EXPONENT_CAPACITY * (k + 1))`, where `k` counts
+//! the linear layers since the last ReLU, and wraps around once it exceeds the
+//! field capacity. The ReLU circuits then only inspect
+//! `RELU_INTEGER_BITS` integer bits of their input.
+//!
+//! Calibration evaluates the float model over sample images, records the
+//! range of every layer's output, and recommends fixed-point capacities and
+//! per-layer scaling which keep every value inside both bounds.
+
+use super::*;
+use algebra::{FpParameters, Precision, PrimeField};
+use crypto_primitives::gc::RELU_INTEGER_BITS;
+use nn::{Evaluate, NeuralArchitecture};
+use std::{cmp::Reverse, fmt};
+
+/// Number of bits available to the magnitude of a signed field element
+const FIELD_CAPACITY: u32 = <<F as PrimeField>::Params as FpParameters>::CAPACITY - 1;
+
+/// Observed range of the output of one layer
+#[derive(Clone, Debug)]
+pub struct LayerRange {
+    pub layer: usize,
+    pub is_linear: bool,
+    pub min: f64,
+    pub max: f64,
+    /// Number of fixed-point multiplications accumulated by the output, i.e.
+    /// the number of linear layers since the last ReLU. Zero for ReLUs.
+    pub num_muls: u32,
+    /// Whether the output is the input of a ReLU
+    pub feeds_relu: bool,
+}
+
+impl LayerRange {
+    pub fn max_abs(&self) -> f64 {
+        self.min.abs().max(self.max.abs())
+    }
+
+    /// Number of bits by which the output must be scaled down to stay inside
+    /// the field when computed with `exponent_capacity` fractional bits
+    pub fn field_shift(&self, exponent_capacity: u8) -> u32 {
+        if !self.is_linear {
+            return 0;
+        }
+        let scale_bits = u32::from(exponent_capacity) * (self.num_muls + 1);
+        (integer_bits(self.max_abs()) + scale_bits).saturating_sub(FIELD_CAPACITY)
+    }
+
+    /// Number of bits by which the output must be scaled down to fit inside
+    /// the window of the ReLU circuit
+    pub fn relu_shift(&self) -> u32 {
+        if !self.feeds_relu {
+            return 0;
+        }
+        integer_bits(self.max.max(0.0)).saturating_sub(RELU_INTEGER_BITS as u32)
+    }
+}
+
+/// Number of bits needed to represent the integer part of `x`
+fn integer_bits(x: f64) -> u32 {
+    if x < 1.0 {
+        0
+    } else {
+        x.log2().floor() as u32 + 1
+    }
+}
+
+/// Observed ranges of the input and of every layer of a model
+#[derive(Clone, Debug)]
+pub struct Calibration {
+    pub num_images: usize,
+    pub input_min: f64,
+    pub input_max: f64,
+    pub layers: Vec<LayerRange>,
+}
+
+impl Calibration {
+    /// Evaluates `network` over `images`, recording the range of every layer
+    pub fn new(network: &NeuralNetwork<f64, f64>, images: &[Input<f64>]) -> Self {
+        let mut layers: Vec<LayerRange> = Vec::with_capacity(network.layers.len());
+        let mut num_muls = 0;
+        for (i, layer) in network.layers.iter().enumerate() {
+            num_muls = if layer.is_linear() { num_muls + 1 } else { 0 };
+            layers.push(LayerRange {
+                layer: i,
+                is_linear: layer.is_linear(),
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+                num_muls,
+                feeds_relu: network
+                    .layers
+                    .get(i + 1)
+                    .map_or(false, |next| next.is_non_linear()),
+            });
+        }
+
+        let (mut input_min, mut input_max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for image in images {
+            image.iter().for_each(|&x| {
+                input_min = input_min.min(x);
+                input_max = input_max.max(x);
+            });
+            let mut input = image.clone();
+            for (layer, range) in network.layers.iter().zip(&mut layers) {
+                input = layer.evaluate(&input);
+                input.iter().for_each(|&x| {
+                    range.min = range.min.min(x);
+                    range.max = range.max.max(x);
+                });
+            }
+        }
+        Self {
+            num_images: images.len(),
+            input_min,
+            input_max,
+            layers,
+        }
+    }
+
+    /// Evaluates the float model with the layers of `architecture` and the
+    /// weights stored at `weights` over `images`
+    pub fn from_numpy<A, B>(
+        architecture: &NeuralArchitecture<A, B>,
+        weights: &str,
+        images: &[Input<f64>],
+    ) -> io::Result<Self> {
+        let network = accuracy::network_from_numpy(architecture, weights)?;
+        Ok(Self::new(&network, images))
+    }
+
+    /// Number of integer bits needed by the values passed between layers,
+    /// i.e. the model input and the reduced output of every layer
+    pub fn mantissa_capacity(&self) -> u8 {
+        let max = self
+            .layers
+            .iter()
+            .map(LayerRange::max_abs)
+            .fold(self.input_min.abs().max(self.input_max.abs()), f64::max);
+        integer_bits(max).max(1) as u8
+    }
+
+    /// Largest number of fractional bits for which no linear layer leaves
+    /// the field
+    pub fn exponent_capacity(&self) -> u8 {
+        self.layers
+            .iter()
+            .filter(|range| range.is_linear)
+            .map(|range| {
+                FIELD_CAPACITY.saturating_sub(integer_bits(range.max_abs())) / (range.num_muls + 1)
+            })
+            .min()
+            .unwrap_or(FIELD_CAPACITY)
+            .min(u32::from(u8::MAX)) as u8
+    }
+
+    /// Recommends fixed-point parameters along with the scaling needed by
+    /// each layer under them
+    pub fn recommend(&self) -> Recommendation {
+        let exponent_capacity = self.exponent_capacity();
+        let mantissa_capacity = self.mantissa_capacity();
+        // The most precise supported precision which keeps every value in
+        // range, with the smallest sufficient mantissa
+        let precision = Precision::ALL
+            .iter()
+            .copied()
+            .filter(|p| {
+                p.exponent_capacity() <= exponent_capacity
+                    && p.mantissa_capacity() >= mantissa_capacity
+            })
+            .max_by_key(|p| (p.exponent_capacity(), Reverse(p.mantissa_capacity())));
+        let shift_exponent = precision.unwrap_or_default().exponent_capacity();
+        let layer_shifts = self
+            .layers
+            .iter()
+            .map(|range| {
+                (
+                    range.layer,
+                    range.field_shift(shift_exponent).max(range.relu_shift()),
+                )
+            })
+            .filter(|(_, shift)| *shift > 0)
+            .collect();
+        Recommendation {
+            exponent_capacity,
+            mantissa_capacity,
+            precision,
+            layer_shifts,
+        }
+    }
+}
+
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Ranges over {} images:", self.num_images)?;
+        writeln!(
+            f,
+            "  Input:     [{:.3}, {:.3}]",
+            self.input_min, self.input_max
+        )?;
+        for range in &self.layers {
+            write!(
+                f,
+                "  Layer {:>2} ({}): [{:.3}, {:.3}]",
+                range.layer,
+                if range.is_linear { "linear" } else { "ReLU  " },
+                range.min,
+                range.max,
+            )?;
+            if range.is_linear {
+                write!(f, ", {} accumulated multiplications", range.num_muls)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fixed-point parameters recommended by `Calibration::recommend`
+#[derive(Clone, Debug)]
+pub struct Recommendation {
+    /// Largest `EXPONENT_CAPACITY` for which no linear layer leaves the field
+    pub exponent_capacity: u8,
+    /// Smallest `MANTISSA_CAPACITY` which holds the values between layers
+    pub mantissa_capacity: u8,
+    /// The most precise supported precision satisfying both capacities
+    pub precision: Option<Precision>,
+    /// Layers whose weights and bias must be divided by `2^shift` to stay in
+    /// range under `precision`, or the default precision if there is none.
+    /// Scaling a layer which feeds a ReLU6 also scales its clipping point, so
+    /// such layers should instead be retrained with smaller activations.
+    pub layer_shifts: Vec<(usize, u32)>,
+}
+
+impl fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Recommended capacities: exponent at most {}, mantissa at least {}",
+            self.exponent_capacity, self.mantissa_capacity
+        )?;
+        match self.precision {
+            Some(precision) => writeln!(f, "Recommended precision: {}", precision)?,
+            None => writeln!(
+                f,
+                "No supported precision satisfies both capacities; scaling for {}",
+                Precision::default()
+            )?,
+        }
+        if self.layer_shifts.is_empty() {
+            write!(f, "No layers need to be scaled")
+        } else {
+            for (i, (layer, shift)) in self.layer_shifts.iter().enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write!(
+                    f,
+                    "Layer {} must be scaled down by 2^{} to stay in range",
+                    layer, shift
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_DIMS: (usize, usize, usize, usize) = (1, 1, 1, 2);
+    const HIDDEN_DIMS: (usize, usize, usize, usize) = (1, 2, 1, 1);
+
+    /// A fully connected layer over two values which multiplies both by
+    /// `scale`
+    fn scaled_fc(input_dims: (usize, usize, usize, usize), scale: f64) -> Layer<f64, f64> {
+        let (_, channels, height, width) = input_dims;
+        let mut weights = Kernel::zeros((2, channels, height, width));
+        let weights_slice = weights.as_slice_mut().unwrap();
+        weights_slice[0] = scale;
+        weights_slice[3] = scale;
+        Layer::LL(LinearLayer::FullyConnected {
+            dims: LayerDims {
+                input_dims,
+                output_dims: HIDDEN_DIMS,
+            },
+            params: FullyConnectedParams::new(weights, Kernel::zeros((2, 1, 1, 1))),
+        })
+    }
+
+    fn relu() -> Layer<f64, f64> {
+        Layer::NLL(NonLinearLayer::ReLU {
+            dims: LayerDims {
+                input_dims: HIDDEN_DIMS,
+                output_dims: HIDDEN_DIMS,
+            },
+            _f: std::marker::PhantomData,
+            _c: std::marker::PhantomData,
+        })
+    }
+
+    fn calibrate(layers: Vec<Layer<f64, f64>>, pixels: &[(f64, f64)]) -> Calibration {
+        let network = NeuralNetwork {
+            eval_method: nn::EvalMethod::Naive,
+            layers,
+        };
+        let images: Vec<_> = pixels
+            .iter()
+            .map(|&(a, b)| Input::from_shape_vec(IMAGE_DIMS, vec![a, b]).unwrap())
+            .collect();
+        Calibration::new(&network, &images)
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let calibration = calibrate(
+            vec![scaled_fc(IMAGE_DIMS, 2.0), relu()],
+            &[(-1.5, 0.5), (3.0, -2.0)],
+        );
+        assert_eq!(calibration.num_images, 2);
+        assert_eq!((calibration.input_min, calibration.input_max), (-2.0, 3.0));
+        let (linear, relu) = (&calibration.layers[0], &calibration.layers[1]);
+        assert_eq!((linear.min, linear.max), (-4.0, 6.0));
+        assert!(linear.is_linear && linear.feeds_relu);
+        assert_eq!(linear.num_muls, 1);
+        assert_eq!((relu.min, relu.max), (0.0, 6.0));
+        assert!(!relu.is_linear && !relu.feeds_relu);
+        assert_eq!(relu.num_muls, 0);
+
+        // 6 needs three integer bits
+        assert_eq!(calibration.mantissa_capacity(), 3);
+        assert_eq!(
+            u32::from(calibration.exponent_capacity()),
+            (FIELD_CAPACITY - 3) / 2
+        );
+        let recommendation = calibration.recommend();
+        assert_eq!(recommendation.precision, Some(Precision::Exp12Man3));
+        assert!(recommendation.layer_shifts.is_empty());
+    }
+
+    #[test]
+    fn test_recommend_larger_mantissa() {
+        // 24 needs five integer bits, so mantissa-3 precisions don't suffice
+        let calibration = calibrate(vec![scaled_fc(IMAGE_DIMS, 8.0)], &[(3.0, -1.0)]);
+        assert_eq!(calibration.mantissa_capacity(), 5);
+        let recommendation = calibration.recommend();
+        assert_eq!(recommendation.mantissa_capacity, 5);
+        assert_eq!(recommendation.precision, Some(Precision::Exp12Man5));
+        assert!(recommendation.layer_shifts.is_empty());
+
+        // Too many integer bits for any precision
+        let calibration = calibrate(vec![scaled_fc(IMAGE_DIMS, 1024.0)], &[(3.0, -1.0)]);
+        assert_eq!(calibration.mantissa_capacity(), 12);
+        assert_eq!(calibration.recommend().precision, None);
+    }
+
+    #[test]
+    fn test_recommend_layer_shifts() {
+        // Outputs feeding a ReLU are scaled down to the window of the circuit
+        let max = f64::from(1u32 << (RELU_INTEGER_BITS + 2));
+        let calibration = calibrate(vec![scaled_fc(IMAGE_DIMS, max), relu()], &[(1.0, 0.5)]);
+        let recommendation = calibration.recommend();
+        assert_eq!(recommendation.layer_shifts, vec![(0, 3)]);
+
+        // Repeated linear layers leave the field at large exponents
+        let calibration = calibrate(
+            vec![
+                scaled_fc(IMAGE_DIMS, 1.0),
+                scaled_fc(HIDDEN_DIMS, 1.0),
+                scaled_fc(HIDDEN_DIMS, 1.0),
+            ],
+            &[(0.5, 0.5)],
+        );
+        let exponent = calibration.exponent_capacity();
+        assert_eq!(u32::from(exponent), FIELD_CAPACITY / 4);
+        let last = &calibration.layers[2];
+        assert_eq!(last.num_muls, 3);
+        assert_eq!(last.field_shift(exponent), 0);
+        assert!(last.field_shift(exponent + 1) > 0);
+    }
+}
@@ -4,9 +4,9 @@ use ::neural_network::{
     tensors::{Input, Output},
     NeuralArchitecture,
 };
-use algebra::{fields::near_mersenne_64::F, PrimeField, UniformRandom};
+use algebra::{fields::near_mersenne_64::F, PrimeField};
 use async_std::{
-    io::{BufReader, BufWriter},
+    io::{BufReader, BufWriter, Read, Write},
     net::TcpStream,
     task,
 };
@@ -14,27 +14,33 @@ use crypto_primitives::AuthAdditiveShare;
 use io_utils::{counting::CountingIO, imux::IMuxAsync};
 use num_traits::identities::Zero;
 use protocols::{
-    client_keygen,
+    client_keygen, comm,
     gc::ClientGcMsgRcv,
     linear_layer::LinearProtocol,
     mpc::{ClientMPC, MPC},
     mpc_offline::{ClientOfflineMPC, OfflineMPC},
     neural_network::NNProtocol,
+    ot_offline::OtClientOfflineMPC,
+    transport::{
+        secure::{self, ClientConfig, SecureReader, SecureWriter},
+        wan::{WanLink, WanWriter},
+        LinkConfig,
+    },
 };
-use protocols_sys::{client_acg, ClientACG, SealClientACG};
+use protocols_sys::{client_acg, ClientACG, SealClientACG, SealClientGen};
 use std::collections::BTreeMap;
 
 pub fn client_connect(
     addr: &str,
+    num_connections: usize,
 ) -> (
     IMuxAsync<CountingIO<BufReader<TcpStream>>>,
     IMuxAsync<CountingIO<BufWriter<TcpStream>>>,
 ) {
-    // TODO: Maybe change to rayon_num_threads
-    let mut readers = Vec::with_capacity(16);
-    let mut writers = Vec::with_capacity(16);
+    let mut readers = Vec::with_capacity(num_connections);
+    let mut writers = Vec::with_capacity(num_connections);
     task::block_on(async {
-        for _ in 0..16 {
+        for _ in 0..num_connections {
             let stream = TcpStream::connect(addr).await.unwrap();
             readers.push(CountingIO::new(BufReader::new(stream.clone())));
             writers.push(CountingIO::new(BufWriter::new(stream)));
@@ -43,20 +49,120 @@ pub fn client_connect(
     })
 }
 
-pub fn nn_client<R: RngCore + CryptoRng>(
+pub fn client_connect_secure(
+    addr: &str,
+    num_connections: usize,
+    config: &ClientConfig,
+) -> (
+    IMuxAsync<CountingIO<SecureReader<BufReader<TcpStream>>>>,
+    IMuxAsync<CountingIO<SecureWriter<BufWriter<TcpStream>>>>,
+) {
+    let mut readers = Vec::with_capacity(num_connections);
+    let mut writers = Vec::with_capacity(num_connections);
+    task::block_on(async {
+        for _ in 0..num_connections {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let session = secure::client_handshake(&mut stream, config)
+                .await
+                .expect("Secure handshake failed");
+            let (reader, writer) =
+                session.split(BufReader::new(stream.clone()), BufWriter::new(stream));
+            readers.push(CountingIO::new(reader));
+            writers.push(CountingIO::new(writer));
+        }
+        (IMuxAsync::new(readers), IMuxAsync::new(writers))
+    })
+}
+
+pub fn client_connect_wan(
+    addr: &str,
+    num_connections: usize,
+    config: LinkConfig,
+) -> (
+    IMuxAsync<CountingIO<BufReader<TcpStream>>>,
+    IMuxAsync<CountingIO<WanWriter>>,
+) {
+    let link = WanLink::new(config);
+    let mut readers = Vec::with_capacity(num_connections);
+    let mut writers = Vec::with_capacity(num_connections);
+    task::block_on(async {
+        for _ in 0..num_connections {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            readers.push(CountingIO::new(BufReader::new(stream.clone())));
+            writers.push(CountingIO::new(link.writer(BufWriter::new(stream))));
+        }
+        (IMuxAsync::new(readers), IMuxAsync::new(writers))
+    })
+}
+
+pub fn nn_client<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
     server_addr: &str,
-    architecture: NeuralArchitecture<TenBitAS, TenBitExpFP>,
+    num_connections: usize,
+    architecture: NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
     rng: &mut R,
 ) {
-    // Sample a random input.
-    let input_dims = architecture.layers.first().unwrap().input_dimensions();
-    let mut input = Input::zeros(input_dims);
-    input
-        .iter_mut()
-        .for_each(|in_i| *in_i = generate_random_number(rng).1);
+    nn_client_with(
+        || client_connect(server_addr, num_connections),
+        architecture,
+        rng,
+    )
+}
+
+pub fn nn_client_secure<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    config: &ClientConfig,
+    architecture: NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
+    rng: &mut R,
+) {
+    nn_client_with(
+        || client_connect_secure(server_addr, num_connections, config),
+        architecture,
+        rng,
+    )
+}
+
+pub fn nn_client_wan<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    config: LinkConfig,
+    architecture: NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
+    rng: &mut R,
+) {
+    nn_client_with(
+        || client_connect_wan(server_addr, num_connections, config),
+        architecture,
+        rng,
+    )
+}
+
+/// Runs the offline and online phases over two connections returned by
+/// `connect`
+pub fn nn_client_with<P, RNG, R, W, C>(
+    connect: C,
+    architecture: NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
+    rng: &mut RNG,
+) where
+    P: FixedPointParameters<Field = F>,
+    RNG: RngCore + CryptoRng,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    C: Fn() -> (IMuxAsync<CountingIO<R>>, IMuxAsync<CountingIO<W>>),
+{
+    // Sample a random input for each item in the batch.
+    let (batch_size, c, h, w) = architecture.layers.first().unwrap().input_dimensions();
+    let inputs: Vec<_> = (0..batch_size)
+        .map(|_| {
+            let mut input = Input::zeros((1, c, h, w));
+            input
+                .iter_mut()
+                .for_each(|in_i| *in_i = generate_random_number(rng).1);
+            input
+        })
+        .collect();
 
     let (client_state, offline_read, offline_write) = {
-        let (mut reader, mut writer) = client_connect(server_addr);
+        let (mut reader, mut writer) = connect();
         (
             NNProtocol::offline_client_protocol(&mut reader, &mut writer, &architecture, rng)
                 .unwrap(),
@@ -65,13 +171,13 @@ pub fn nn_client<R: RngCore + CryptoRng>(
         )
     };
 
-    let (_client_output, online_read, online_write) = {
-        let (mut reader, mut writer) = client_connect(server_addr);
+    let (_client_outputs, online_read, online_write) = {
+        let (mut reader, mut writer) = connect();
         (
-            NNProtocol::online_client_protocol(
+            NNProtocol::online_client_batch_protocol(
                 &mut reader,
                 &mut writer,
-                &input,
+                &inputs,
                 &architecture,
                 &client_state,
             )
@@ -88,15 +194,17 @@ pub fn nn_client<R: RngCore + CryptoRng>(
         "Read {} bytes\nWrote {} bytes",
         online_read, online_write
     ));
+    add_to_trace!(|| "Communication by phase", || comm::report());
 }
 
 // TODO: Pull out this functionality in `neural_network.rs` so this is clean
 pub fn acg<R: RngCore + CryptoRng>(
     server_addr: &str,
+    num_connections: usize,
     architecture: NeuralArchitecture<TenBitAS, TenBitExpFP>,
     rng: &mut R,
 ) {
-    let (mut reader, mut writer) = client_connect(server_addr);
+    let (mut reader, mut writer) = client_connect(server_addr, num_connections);
 
     // Keygen
     let cfhe = client_keygen(&mut writer).unwrap();
@@ -137,7 +245,8 @@ pub fn acg<R: RngCore + CryptoRng>(
                             &mut writer,
                             layer.input_dimensions(),
                             layer.output_dimensions(),
-                            &mut acg_handler,
+                            1,
+                            std::slice::from_mut(&mut acg_handler),
                             rng,
                         )
                         .unwrap()
@@ -156,17 +265,17 @@ pub fn acg<R: RngCore + CryptoRng>(
                                 output_share,
                             )
                         } else {
-                            // If the layer comes after a non-linear layer, generate a
-                            // randomizer, send it to the server to receive back an
-                            // authenticated share, and apply the function to that share
-                            let mut randomizer = Input::zeros(input_dims);
-                            randomizer.iter_mut().for_each(|e| *e = F::uniform(rng));
+                            // If the layer comes after a non-linear layer, generate an
+                            // authenticated random randomizer with the server, and apply
+                            // the function to that share
                             let randomizer =
-                                LinearProtocol::<TenBitExpParams>::offline_client_auth_share(
-                                    &mut reader,
-                                    &mut writer,
-                                    randomizer,
-                                    &cfhe,
+                                LinearProtocol::<TenBitExpParams>::offline_client_auth_rand::<
+                                    ClientOfflineMPC<F, SealClientGen>,
+                                    _,
+                                    _,
+                                    _,
+                                >(
+                                    &mut reader, &mut writer, input_dims, &cfhe, rng
                                 )
                                 .unwrap();
                             linear_layer_info.evaluate_naive_auth(&randomizer, &mut output_share);
@@ -190,8 +299,13 @@ pub fn acg<R: RngCore + CryptoRng>(
 }
 
 // TODO: Pull out this functionality in `neural_network.rs` so this is clean
-pub fn garbling<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], _rng: &mut R) {
-    let (mut reader, writer) = client_connect(server_addr);
+pub fn garbling<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    layers: &[usize],
+    _rng: &mut R,
+) {
+    let (mut reader, writer) = client_connect(server_addr, num_connections);
 
     let activations: usize = layers.iter().map(|e| *e).sum();
     let rcv_gc_time = timer_start!(|| "Receiving GCs");
@@ -217,8 +331,13 @@ pub fn garbling<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], _rn
     ));
 }
 
-pub fn triples_gen<R: RngCore + CryptoRng>(server_addr: &str, num: usize, rng: &mut R) {
-    let (mut reader, mut writer) = client_connect(server_addr);
+pub fn triples_gen<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    num: usize,
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = client_connect(server_addr, num_connections);
 
     // Keygen
     let cfhe = client_keygen(&mut writer).unwrap();
@@ -227,7 +346,31 @@ pub fn triples_gen<R: RngCore + CryptoRng>(server_addr: &str, num: usize, rng: &
     // Generate triples
     let client_gen = ClientOfflineMPC::<F, _>::new(&cfhe);
     let triples = timer_start!(|| "Generating triples");
-    client_gen.triples_gen(&mut reader, &mut writer, rng, num);
+    client_gen
+        .triples_gen(&mut reader, &mut writer, rng, num)
+        .unwrap();
+    timer_end!(triples);
+    add_to_trace!(|| "Communication", || format!(
+        "Read {} bytes\nWrote {} bytes",
+        reader.count(),
+        writer.count()
+    ));
+}
+
+pub fn ot_triples_gen<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    num: usize,
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = client_connect(server_addr, num_connections);
+
+    // Generate triples
+    let client_gen = OtClientOfflineMPC::<F>::new();
+    let triples = timer_start!(|| "Generating triples");
+    client_gen
+        .triples_gen(&mut reader, &mut writer, rng, num)
+        .unwrap();
     timer_end!(triples);
     add_to_trace!(|| "Communication", || format!(
         "Read {} bytes\nWrote {} bytes",
@@ -236,8 +379,13 @@ pub fn triples_gen<R: RngCore + CryptoRng>(server_addr: &str, num: usize, rng: &
     ));
 }
 
-pub fn cds<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mut R) {
-    let (mut reader, mut writer) = client_connect(server_addr);
+pub fn cds<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    layers: &[usize],
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = client_connect(server_addr, num_connections);
 
     // Keygen
     let cfhe = client_keygen(&mut writer).unwrap();
@@ -251,7 +399,12 @@ pub fn cds<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mu
     let inp_rands = vec![F::zero(); activations];
 
     // Generate triples
-    protocols::cds::CDSProtocol::<TenBitExpParams>::client_cds(
+    protocols::cds::CDSProtocol::<TenBitExpParams>::client_cds::<
+        ClientOfflineMPC<F, SealClientGen>,
+        _,
+        _,
+        _,
+    >(
         &mut reader,
         &mut writer,
         &cfhe,
@@ -270,8 +423,13 @@ pub fn cds<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mu
     ));
 }
 
-pub fn input_auth<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mut R) {
-    let (mut reader, mut writer) = client_connect(server_addr);
+pub fn input_auth<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    layers: &[usize],
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = client_connect(server_addr, num_connections);
 
     // Keygen
     let cfhe = client_keygen(&mut writer).unwrap();
@@ -293,7 +451,9 @@ pub fn input_auth<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], r
     let gen = ClientOfflineMPC::new(&cfhe);
 
     let input_time = timer_start!(|| "Input Auth");
-    let rands = gen.rands_gen(&mut reader, &mut writer, rng, num_rands);
+    let rands = gen
+        .rands_gen(&mut reader, &mut writer, rng, num_rands)
+        .unwrap();
     let mut mpc = ClientMPC::new(rands, Vec::new());
 
     // Share inputs
@@ -1,2 +1,47 @@
+use clap::{Arg, ArgMatches};
+use protocols::transport::LinkConfig;
+use std::time::Duration;
+
 pub mod client;
 pub mod server;
+
+/// Command-line arguments configuring WAN emulation
+pub fn wan_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("rtt")
+            .long("rtt")
+            .takes_value(true)
+            .help("Emulate a WAN with this round-trip time in milliseconds")
+            .required(false),
+        Arg::with_name("bandwidth")
+            .long("bandwidth")
+            .takes_value(true)
+            .help("Emulated WAN bandwidth in Mbit/s (default unlimited)")
+            .required(false),
+        Arg::with_name("jitter")
+            .long("jitter")
+            .takes_value(true)
+            .help("Maximum extra one-way delay in milliseconds (default 0)")
+            .required(false),
+    ]
+}
+
+/// Parses the arguments from `wan_args`, returning `None` if WAN emulation
+/// wasn't requested
+pub fn wan_config(args: &ArgMatches) -> Option<LinkConfig> {
+    if !(args.is_present("rtt") || args.is_present("bandwidth") || args.is_present("jitter")) {
+        return None;
+    }
+    let millis = |name: &str| {
+        let ms = clap::value_t!(args, name, f64).unwrap_or(0.0);
+        Duration::from_secs_f64(ms / 1000.0)
+    };
+    let bandwidth = clap::value_t!(args, "bandwidth", f64)
+        .ok()
+        .map(|mbps| (mbps * 1e6 / 8.0) as u64);
+    Some(LinkConfig::with_rtt(
+        millis("rtt"),
+        bandwidth,
+        millis("jitter"),
+    ))
+}
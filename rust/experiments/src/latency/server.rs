@@ -15,38 +15,45 @@ use neural_network::{
 };
 use num_traits::identities::Zero;
 use protocols::{
+    comm,
     gc::ServerGcMsgSend,
     linear_layer::LinearProtocol,
     mpc::{ServerMPC, MPC},
     mpc_offline::{OfflineMPC, ServerOfflineMPC},
     neural_network::NNProtocol,
+    ot_offline::OtServerOfflineMPC,
     server_keygen,
+    transport::{
+        secure::{self, Keypair, SecureReader, SecureWriter, ServerConfig},
+        wan::{WanLink, WanWriter},
+        LinkConfig,
+    },
 };
-use protocols_sys::{server_acg, SealServerACG, ServerACG};
+use protocols_sys::{server_acg, SealServerACG, SealServerGen, ServerACG};
 use rand::{CryptoRng, RngCore};
 use rayon::prelude::*;
 use scuttlebutt::Block;
 use std::collections::BTreeMap;
 
 use async_std::{
-    io::{BufReader, BufWriter},
+    io::{BufReader, BufWriter, Read, Write},
     net::{TcpListener, TcpStream},
     task,
 };
 
 pub fn server_connect(
     addr: &str,
+    num_connections: usize,
 ) -> (
     IMuxAsync<CountingIO<BufReader<TcpStream>>>,
     IMuxAsync<CountingIO<BufWriter<TcpStream>>>,
 ) {
     task::block_on(async {
-        // TODO: Maybe change to rayon_num_threads
         let listener = TcpListener::bind(addr).await.unwrap();
         let mut incoming = listener.incoming();
-        let mut readers = Vec::with_capacity(16);
-        let mut writers = Vec::with_capacity(16);
-        for _ in 0..16 {
+        let mut readers = Vec::with_capacity(num_connections);
+        let mut writers = Vec::with_capacity(num_connections);
+        for _ in 0..num_connections {
             let stream = incoming.next().await.unwrap().unwrap();
             readers.push(CountingIO::new(BufReader::new(stream.clone())));
             writers.push(CountingIO::new(BufWriter::new(stream)));
@@ -55,13 +62,125 @@ pub fn server_connect(
     })
 }
 
-pub fn nn_server<R: RngCore + CryptoRng>(
+pub fn server_connect_secure(
+    addr: &str,
+    num_connections: usize,
+    config: &ServerConfig,
+) -> (
+    IMuxAsync<CountingIO<SecureReader<BufReader<TcpStream>>>>,
+    IMuxAsync<CountingIO<SecureWriter<BufWriter<TcpStream>>>>,
+) {
+    task::block_on(async {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let mut incoming = listener.incoming();
+        let mut readers = Vec::with_capacity(num_connections);
+        let mut writers = Vec::with_capacity(num_connections);
+        for _ in 0..num_connections {
+            let mut stream = incoming.next().await.unwrap().unwrap();
+            let session = secure::server_handshake(&mut stream, config)
+                .await
+                .expect("Secure handshake failed");
+            let (reader, writer) =
+                session.split(BufReader::new(stream.clone()), BufWriter::new(stream));
+            readers.push(CountingIO::new(reader));
+            writers.push(CountingIO::new(writer));
+        }
+        (IMuxAsync::new(readers), IMuxAsync::new(writers))
+    })
+}
+
+/// Loads the server's static keypair from `path`, generating one (and writing
+/// its public half to `<path>.pub` for clients to pin) if it doesn't exist
+pub fn load_or_create_keypair(path: &str) -> Keypair {
+    if std::path::Path::new(path).exists() {
+        Keypair::read_from(path).expect("Failed to read server keypair")
+    } else {
+        let keypair = Keypair::generate().unwrap();
+        keypair.write_to(path).unwrap();
+        keypair.write_public_to(format!("{}.pub", path)).unwrap();
+        println!(
+            "Wrote new server keypair to {} and public key to {}.pub",
+            path, path
+        );
+        keypair
+    }
+}
+
+pub fn server_connect_wan(
+    addr: &str,
+    num_connections: usize,
+    config: LinkConfig,
+) -> (
+    IMuxAsync<CountingIO<BufReader<TcpStream>>>,
+    IMuxAsync<CountingIO<WanWriter>>,
+) {
+    let link = WanLink::new(config);
+    task::block_on(async {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let mut incoming = listener.incoming();
+        let mut readers = Vec::with_capacity(num_connections);
+        let mut writers = Vec::with_capacity(num_connections);
+        for _ in 0..num_connections {
+            let stream = incoming.next().await.unwrap().unwrap();
+            readers.push(CountingIO::new(BufReader::new(stream.clone())));
+            writers.push(CountingIO::new(link.writer(BufWriter::new(stream))));
+        }
+        (IMuxAsync::new(readers), IMuxAsync::new(writers))
+    })
+}
+
+pub fn nn_server<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
     server_addr: &str,
-    nn: NeuralNetwork<TenBitAS, TenBitExpFP>,
+    num_connections: usize,
+    nn: NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
     rng: &mut R,
 ) {
+    nn_server_with(|| server_connect(server_addr, num_connections), nn, rng)
+}
+
+pub fn nn_server_secure<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    config: &ServerConfig,
+    nn: NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+    rng: &mut R,
+) {
+    nn_server_with(
+        || server_connect_secure(server_addr, num_connections, config),
+        nn,
+        rng,
+    )
+}
+
+pub fn nn_server_wan<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    config: LinkConfig,
+    nn: NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+    rng: &mut R,
+) {
+    nn_server_with(
+        || server_connect_wan(server_addr, num_connections, config),
+        nn,
+        rng,
+    )
+}
+
+/// Runs the offline and online phases over two connections returned by
+/// `connect`
+pub fn nn_server_with<P, RNG, R, W, C>(
+    connect: C,
+    nn: NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+    rng: &mut RNG,
+) where
+    P: FixedPointParameters<Field = F>,
+    RNG: RngCore + CryptoRng,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    C: Fn() -> (IMuxAsync<CountingIO<R>>, IMuxAsync<CountingIO<W>>),
+{
     let (server_offline_state, offline_read, offline_write) = {
-        let (mut reader, mut writer) = server_connect(server_addr);
+        let (mut reader, mut writer) = connect();
         (
             NNProtocol::offline_server_protocol(&mut reader, &mut writer, &nn, rng).unwrap(),
             reader.count(),
@@ -70,7 +189,7 @@ pub fn nn_server<R: RngCore + CryptoRng>(
     };
 
     let (_, online_read, online_write) = {
-        let (mut reader, mut writer) = server_connect(server_addr);
+        let (mut reader, mut writer) = connect();
         (
             NNProtocol::online_server_protocol(
                 &mut reader,
@@ -91,15 +210,17 @@ pub fn nn_server<R: RngCore + CryptoRng>(
         "Read {} bytes\nWrote {} bytes",
         online_read, online_write
     ));
+    add_to_trace!(|| "Communication by phase", || comm::report());
 }
 
 // TODO: Pull out this functionality in `neural_network.rs` so this is clean
 pub fn acg<R: RngCore + CryptoRng>(
     server_addr: &str,
+    num_connections: usize,
     nn: NeuralNetwork<TenBitAS, TenBitExpFP>,
     rng: &mut R,
 ) {
-    let (mut reader, mut writer) = server_connect(server_addr);
+    let (mut reader, mut writer) = server_connect(server_addr, num_connections);
 
     // Keygen
     let sfhe = server_keygen(&mut reader).unwrap();
@@ -140,7 +261,8 @@ pub fn acg<R: RngCore + CryptoRng>(
                             &mut writer,
                             layer.input_dimensions(),
                             layer.output_dimensions(),
-                            &mut acg_handler,
+                            1,
+                            std::slice::from_mut(&mut acg_handler),
                             rng,
                         )
                         .unwrap()
@@ -163,11 +285,16 @@ pub fn acg<R: RngCore + CryptoRng>(
                                 prev_mac_keys.clone(),
                             )
                         } else {
-                            // If the layer comes after a non-linear layer, receive the
-                            // randomizer from the client, authenticate it, and then apply the
+                            // If the layer comes after a non-linear layer, authenticate a
+                            // random randomizer known to the client, and then apply the
                             // function to the MAC share
                             let (key, input_share) =
-                                LinearProtocol::<TenBitExpParams>::offline_server_auth_share(
+                                LinearProtocol::<TenBitExpParams>::offline_server_auth_rand::<
+                                    ServerOfflineMPC<F, SealServerGen>,
+                                    _,
+                                    _,
+                                    _,
+                                >(
                                     &mut reader,
                                     &mut writer,
                                     dims.input_dimensions(),
@@ -201,8 +328,13 @@ pub fn acg<R: RngCore + CryptoRng>(
     ));
 }
 
-pub fn garbling<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mut R) {
-    let (reader, mut writer) = server_connect(server_addr);
+pub fn garbling<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    layers: &[usize],
+    rng: &mut R,
+) {
+    let (reader, mut writer) = server_connect(server_addr, num_connections);
 
     let activations: usize = layers.iter().map(|e| *e).sum();
     let _garble_time = timer_start!(|| "Garbling Time");
@@ -304,8 +436,13 @@ pub fn garbling<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng
     ));
 }
 
-pub fn triples_gen<R: RngCore + CryptoRng>(server_addr: &str, num: usize, rng: &mut R) {
-    let (mut reader, mut writer) = server_connect(server_addr);
+pub fn triples_gen<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    num: usize,
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = server_connect(server_addr, num_connections);
 
     // Keygen
     let sfhe = server_keygen(&mut reader).unwrap();
@@ -315,7 +452,32 @@ pub fn triples_gen<R: RngCore + CryptoRng>(server_addr: &str, num: usize, rng: &
     // Generate triples
     let server_gen = ServerOfflineMPC::<F, _>::new(&sfhe, mac_key.into_repr().0);
     let triples = timer_start!(|| "Generating triples");
-    server_gen.triples_gen(&mut reader, &mut writer, rng, num);
+    server_gen
+        .triples_gen(&mut reader, &mut writer, rng, num)
+        .unwrap();
+    timer_end!(triples);
+    add_to_trace!(|| "Communication", || format!(
+        "Read {} bytes\nWrote {} bytes",
+        reader.count(),
+        writer.count()
+    ));
+}
+
+pub fn ot_triples_gen<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    num: usize,
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = server_connect(server_addr, num_connections);
+    let mac_key = F::uniform(rng);
+
+    // Generate triples
+    let server_gen = OtServerOfflineMPC::<F>::new(mac_key.into_repr().0);
+    let triples = timer_start!(|| "Generating triples");
+    server_gen
+        .triples_gen(&mut reader, &mut writer, rng, num)
+        .unwrap();
     timer_end!(triples);
     add_to_trace!(|| "Communication", || format!(
         "Read {} bytes\nWrote {} bytes",
@@ -324,8 +486,13 @@ pub fn triples_gen<R: RngCore + CryptoRng>(server_addr: &str, num: usize, rng: &
     ));
 }
 
-pub fn cds<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mut R) {
-    let (mut reader, mut writer) = server_connect(server_addr);
+pub fn cds<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    layers: &[usize],
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = server_connect(server_addr, num_connections);
 
     // Keygen
     let sfhe = server_keygen(&mut reader).unwrap();
@@ -342,7 +509,12 @@ pub fn cds<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mu
     let labels: Vec<(Block, Block)> = vec![(0.into(), 0.into()); 2 * activations * modulus_bits];
 
     // Generate triples
-    protocols::cds::CDSProtocol::<TenBitExpParams>::server_cds(
+    protocols::cds::CDSProtocol::<TenBitExpParams>::server_cds::<
+        ServerOfflineMPC<F, SealServerGen>,
+        _,
+        _,
+        _,
+    >(
         &mut reader,
         &mut writer,
         &sfhe,
@@ -362,8 +534,13 @@ pub fn cds<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mu
     ));
 }
 
-pub fn input_auth<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], rng: &mut R) {
-    let (mut reader, mut writer) = server_connect(server_addr);
+pub fn input_auth<R: RngCore + CryptoRng>(
+    server_addr: &str,
+    num_connections: usize,
+    layers: &[usize],
+    rng: &mut R,
+) {
+    let (mut reader, mut writer) = server_connect(server_addr, num_connections);
 
     // Keygen
     let sfhe = server_keygen(&mut reader).unwrap();
@@ -388,7 +565,9 @@ pub fn input_auth<R: RngCore + CryptoRng>(server_addr: &str, layers: &[usize], r
     let gen = ServerOfflineMPC::new(&sfhe, mac_key.into_repr().0);
 
     let input_time = timer_start!(|| "Input Auth");
-    let rands = gen.rands_gen(&mut reader, &mut writer, rng, num_rands);
+    let rands = gen
+        .rands_gen(&mut reader, &mut writer, rng, num_rands)
+        .unwrap();
     let mut mpc = ServerMPC::new(rands, Vec::new(), mac_key);
 
     // Share inputs
@@ -17,40 +17,49 @@ use nn::{
     NeuralNetwork,
 };
 
+pub mod accuracy;
+pub mod calibration;
 pub mod latency;
 pub mod minionn;
 pub mod mnist;
 
-pub struct TenBitExpParams {}
+/// A path into a libtorch variable store, on whose device linear layers are
+/// evaluated
+#[cfg(feature = "torch")]
+pub type VarPath<'a> = tch::nn::Path<'a>;
 
-impl FixedPointParameters for TenBitExpParams {
-    type Field = F;
-    const MANTISSA_CAPACITY: u8 = 3;
-    const EXPONENT_CAPACITY: u8 = 8;
-}
+/// Stands in for `tch::nn::Path` when built without libtorch. It has no
+/// values, so linear layers are always evaluated natively.
+#[cfg(not(feature = "torch"))]
+pub enum VarPath {}
+
+/// The default precision, `Precision::Exp8Man3`
+pub type TenBitExpParams = algebra::Exp8Man3Params;
 
-type TenBitExpFP = FixedPoint<TenBitExpParams>;
-type TenBitAS = AdditiveShare<TenBitExpParams>;
+pub type TenBitExpFP = FixedPoint<TenBitExpParams>;
+pub type TenBitAS = AdditiveShare<TenBitExpParams>;
 
-pub fn generate_random_number<R: Rng>(rng: &mut R) -> (f64, TenBitExpFP) {
+pub fn generate_random_number<P: FixedPointParameters, R: Rng>(
+    rng: &mut R,
+) -> (f64, FixedPoint<P>) {
     let is_neg: bool = rng.gen();
     let mul = if is_neg { -1.0 } else { 1.0 };
     let float: f64 = rng.gen();
-    let f = TenBitExpFP::truncate_float(float * mul);
-    let n = TenBitExpFP::from(f);
+    let f = FixedPoint::<P>::truncate_float(float * mul);
+    let n = FixedPoint::<P>::from(f);
     (f, n)
 }
 
-fn sample_conv_layer<R: RngCore + CryptoRng>(
-    vs: Option<&tch::nn::Path>,
+fn sample_conv_layer<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
+    vs: Option<&VarPath>,
     input_dims: (usize, usize, usize, usize),
     kernel_dims: (usize, usize, usize, usize),
     stride: usize,
     padding: Padding,
     rng: &mut R,
 ) -> (
-    LinearLayer<TenBitAS, TenBitExpFP>,
-    LinearLayer<TenBitExpFP, TenBitExpFP>,
+    LinearLayer<AdditiveShare<P>, FixedPoint<P>>,
+    LinearLayer<FixedPoint<P>, FixedPoint<P>>,
 ) {
     let mut kernel = Kernel::zeros(kernel_dims);
     let mut bias = Kernel::zeros((kernel_dims.0, 1, 1, 1));
@@ -60,14 +69,26 @@ fn sample_conv_layer<R: RngCore + CryptoRng>(
     bias.iter_mut()
         .for_each(|bias_i| *bias_i = generate_random_number(rng).1);
     let layer_params = match vs {
-        Some(vs) => Conv2dParams::<TenBitAS, _>::new_with_gpu(
+        #[cfg(feature = "torch")]
+        Some(vs) => Conv2dParams::<AdditiveShare<P>, _>::new_with_gpu(
             vs,
             padding,
             stride,
             kernel.clone(),
             bias.clone(),
         ),
-        None => Conv2dParams::<TenBitAS, _>::new(padding, stride, kernel.clone(), bias.clone()),
+        #[cfg(not(feature = "torch"))]
+        Some(vs) => match *vs {},
+        None => {
+            let mut params = Conv2dParams::<AdditiveShare<P>, _>::new(
+                padding,
+                stride,
+                kernel.clone(),
+                bias.clone(),
+            );
+            params.eval_method = nn::EvalMethod::Gemm;
+            params
+        }
     };
     let output_dims = layer_params.calculate_output_size(input_dims);
     let layer_dims = LayerDims {
@@ -80,7 +101,7 @@ fn sample_conv_layer<R: RngCore + CryptoRng>(
     };
 
     let pt_layer_params =
-        Conv2dParams::<TenBitExpFP, _>::new(padding, stride, kernel.clone(), bias.clone());
+        Conv2dParams::<FixedPoint<P>, _>::new(padding, stride, kernel.clone(), bias.clone());
     let pt_layer = LinearLayer::Conv2d {
         dims: layer_dims,
         params: pt_layer_params,
@@ -88,14 +109,14 @@ fn sample_conv_layer<R: RngCore + CryptoRng>(
     (layer, pt_layer)
 }
 
-fn sample_fc_layer<R: RngCore + CryptoRng>(
-    vs: Option<&tch::nn::Path>,
+fn sample_fc_layer<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
+    vs: Option<&VarPath>,
     input_dims: (usize, usize, usize, usize),
     out_chn: usize,
     rng: &mut R,
 ) -> (
-    LinearLayer<TenBitAS, TenBitExpFP>,
-    LinearLayer<TenBitExpFP, TenBitExpFP>,
+    LinearLayer<AdditiveShare<P>, FixedPoint<P>>,
+    LinearLayer<FixedPoint<P>, FixedPoint<P>>,
 ) {
     let weight_dims = (out_chn, input_dims.1, input_dims.2, input_dims.3);
     let mut weights = Kernel::zeros(weight_dims);
@@ -111,8 +132,15 @@ fn sample_fc_layer<R: RngCore + CryptoRng>(
     let pt_weights = weights.clone();
     let pt_bias = bias.clone();
     let params = match vs {
+        #[cfg(feature = "torch")]
         Some(vs) => FullyConnectedParams::new_with_gpu(vs, weights, bias),
-        None => FullyConnectedParams::new(weights, bias),
+        #[cfg(not(feature = "torch"))]
+        Some(vs) => match *vs {},
+        None => {
+            let mut params = FullyConnectedParams::new(weights, bias);
+            params.eval_method = nn::EvalMethod::Gemm;
+            params
+        }
     };
     let output_dims = params.calculate_output_size(input_dims);
     let dims = LayerDims {
@@ -129,11 +157,11 @@ fn sample_fc_layer<R: RngCore + CryptoRng>(
 }
 
 #[allow(dead_code)]
-fn sample_iden_layer(
+fn sample_iden_layer<P: FixedPointParameters>(
     input_dims: (usize, usize, usize, usize),
 ) -> (
-    LinearLayer<TenBitAS, TenBitExpFP>,
-    LinearLayer<TenBitExpFP, TenBitExpFP>,
+    LinearLayer<AdditiveShare<P>, FixedPoint<P>>,
+    LinearLayer<FixedPoint<P>, FixedPoint<P>>,
 ) {
     let output_dims = input_dims;
     let layer_dims = LayerDims {
@@ -146,13 +174,14 @@ fn sample_iden_layer(
 }
 
 #[allow(dead_code)]
-fn sample_avg_pool_layer(
+fn sample_avg_pool_layer<P: FixedPointParameters>(
     input_dims: (usize, usize, usize, usize),
     (pool_h, pool_w): (usize, usize),
     stride: usize,
-) -> LinearLayer<TenBitAS, TenBitExpFP> {
+) -> LinearLayer<AdditiveShare<P>, FixedPoint<P>> {
     let size = (pool_h * pool_w) as f64;
-    let avg_pool_params = AvgPoolParams::new(pool_h, pool_w, stride, TenBitExpFP::from(1.0 / size));
+    let avg_pool_params =
+        AvgPoolParams::new(pool_h, pool_w, stride, FixedPoint::<P>::from(1.0 / size));
     let pool_dims = LayerDims {
         input_dims,
         output_dims: avg_pool_params.calculate_output_size(input_dims),
@@ -164,7 +193,9 @@ fn sample_avg_pool_layer(
     }
 }
 
-fn add_activation_layer(nn: &mut NeuralNetwork<TenBitAS, TenBitExpFP>) {
+fn add_activation_layer<P: FixedPointParameters>(
+    nn: &mut NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+) {
     let cur_input_dims = nn.layers.last().as_ref().unwrap().output_dimensions();
     let layer_dims = LayerDims {
         input_dims: cur_input_dims,
@@ -7,16 +7,19 @@ use rand::{CryptoRng, RngCore};
 
 use super::*;
 
-pub fn construct_mnist<R: RngCore + CryptoRng>(
-    vs: Option<&tch::nn::Path>,
+pub fn construct_mnist<P: FixedPointParameters<Field = F>, R: RngCore + CryptoRng>(
+    vs: Option<&VarPath>,
     batch_size: usize,
     rng: &mut R,
-) -> NeuralNetwork<TenBitAS, TenBitExpFP> {
-    let mut network = match &vs {
+) -> NeuralNetwork<AdditiveShare<P>, FixedPoint<P>> {
+    let mut network = match vs {
+        #[cfg(feature = "torch")]
         Some(vs) => NeuralNetwork {
             layers: vec![],
             eval_method: ::neural_network::EvalMethod::TorchDevice(vs.device()),
         },
+        #[cfg(not(feature = "torch"))]
+        Some(vs) => match *vs {},
         None => NeuralNetwork {
             layers: vec![],
             ..Default::default()
@@ -0,0 +1,608 @@
+use algebra::{
+    fields::near_mersenne_64::F, with_precision, FixedPoint, FixedPointParameters, Precision,
+};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use experiments::{
+    accuracy::{self, Dataset},
+    calibration::Calibration,
+    latency::{client, server, wan_args, wan_config},
+    minionn::construct_minionn,
+    mnist::construct_mnist,
+    TenBitExpParams, VarPath,
+};
+use neural_network::{NeuralArchitecture, NeuralNetwork};
+use protocols::{
+    randomness::Randomness,
+    transport::secure::{ClientConfig, Keypair, ServerConfig},
+    AdditiveShare,
+};
+use rand_chacha::ChaChaRng;
+
+#[derive(Clone, Copy)]
+enum Model {
+    Mnist,
+    MiniONN,
+}
+
+impl Model {
+    fn from_args(args: &ArgMatches) -> Self {
+        match args.value_of("model").unwrap() {
+            "mnist" => Model::Mnist,
+            "minionn" => Model::MiniONN,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Number of ReLUs in each activation layer
+    fn relu_layers(self) -> Vec<usize> {
+        match self {
+            Model::Mnist => vec![9216, 1024, 100],
+            Model::MiniONN => vec![65536, 65536, 16384, 16384, 4096, 4096, 1024],
+        }
+    }
+
+    /// Number of triples needed for the CDS protocol
+    fn num_triples(self) -> usize {
+        match self {
+            Model::Mnist => 2729760,
+            Model::MiniONN => 45686784,
+        }
+    }
+
+    fn construct<P: FixedPointParameters<Field = F>>(
+        self,
+        vs: Option<&VarPath>,
+        batch_size: usize,
+        rng: &mut ChaChaRng,
+    ) -> NeuralNetwork<AdditiveShare<P>, FixedPoint<P>> {
+        match self {
+            Model::Mnist => construct_mnist(vs, batch_size, rng),
+            Model::MiniONN => construct_minionn(vs, batch_size, rng),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Server,
+    Client,
+}
+
+impl Role {
+    fn from_args(args: &ArgMatches) -> Self {
+        match args.value_of("role").unwrap() {
+            "server" => Role::Server,
+            "client" => Role::Client,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Arguments shared by every subcommand
+fn run_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("model")
+            .short("m")
+            .long("model")
+            .takes_value(true)
+            .possible_values(&["mnist", "minionn"])
+            .help("Network architecture")
+            .required(true),
+        Arg::with_name("threads")
+            .short("t")
+            .long("threads")
+            .takes_value(true)
+            .help("Number of worker threads (default one per core)")
+            .required(false),
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .takes_value(true)
+            .help("Append timings and communication to this file")
+            .required(false),
+        Arg::with_name("trace")
+            .long("trace")
+            .takes_value(true)
+            .help("Write a Chrome trace of timings and communication to this file")
+            .required(false),
+        Arg::with_name("insecure_seed")
+            .long("insecure-seed")
+            .takes_value(true)
+            .help("INSECURE: seed all randomness from this number for reproducible benchmarks (default OS entropy)")
+            .required(false),
+    ]
+}
+
+/// Arguments shared by every subcommand which connects the two parties
+fn common_args() -> Vec<Arg<'static, 'static>> {
+    let mut args = run_args();
+    args.extend(vec![
+        Arg::with_name("address")
+            .short("a")
+            .long("address")
+            .takes_value(true)
+            .help("Address to listen on as the server (default 0.0.0.0) or to connect to as the client (default 127.0.0.1)")
+            .required(false),
+        Arg::with_name("port")
+            .short("p")
+            .long("port")
+            .takes_value(true)
+            .default_value("8000")
+            .help("Server port"),
+        Arg::with_name("connections")
+            .short("c")
+            .long("connections")
+            .takes_value(true)
+            .default_value("16")
+            .help("Number of TCP connections to multiplex; must match on both sides"),
+    ]);
+    args
+}
+
+fn weights_arg() -> Arg<'static, 'static> {
+    Arg::with_name("weights")
+        .short("w")
+        .long("weights")
+        .takes_value(true)
+        .help("Path to the model weights as a .npy file (default sampled at random)")
+        .required(false)
+}
+
+fn role_arg() -> Arg<'static, 'static> {
+    Arg::with_name("role")
+        .possible_values(&["server", "client"])
+        .help("Which party to run")
+        .required(true)
+}
+
+fn precision_arg() -> Arg<'static, 'static> {
+    Arg::with_name("precision")
+        .long("precision")
+        .takes_value(true)
+        .default_value("8:3")
+        .help("Fixed-point precision given as EXPONENT:MANTISSA; must match on both sides")
+}
+
+fn batch_size_arg() -> Arg<'static, 'static> {
+    Arg::with_name("batch_size")
+        .short("b")
+        .long("batch-size")
+        .takes_value(true)
+        .default_value("1")
+        .help("Number of inputs to evaluate together; must match on both sides")
+}
+
+fn layers_arg() -> Arg<'static, 'static> {
+    Arg::with_name("layers")
+        .short("l")
+        .long("layers")
+        .takes_value(true)
+        .use_delimiter(true)
+        .help("Comma-separated number of ReLUs per layer (default taken from the model)")
+        .required(false)
+}
+
+fn get_args() -> ArgMatches<'static> {
+    App::new("muse")
+        .about("Runs MUSE secure inference and its component benchmarks")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Runs the server side of secure inference")
+                .args(&common_args())
+                .arg(weights_arg())
+                .arg(precision_arg())
+                .arg(batch_size_arg())
+                .arg(
+                    Arg::with_name("key")
+                        .short("k")
+                        .long("key")
+                        .takes_value(true)
+                        .help("Encrypt connections using the keypair at this path (created if missing)")
+                        .required(false)
+                        .conflicts_with_all(&["rtt", "bandwidth", "jitter"]),
+                )
+                .args(&wan_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("client")
+                .about("Runs the client side of secure inference on random inputs")
+                .args(&common_args())
+                .arg(precision_arg())
+                .arg(batch_size_arg())
+                .arg(
+                    Arg::with_name("server_key")
+                        .short("k")
+                        .long("server-key")
+                        .takes_value(true)
+                        .help("Encrypt connections and pin the server public key at this path")
+                        .required(false)
+                        .conflicts_with_all(&["rtt", "bandwidth", "jitter"]),
+                )
+                .args(&wan_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("acg")
+                .about("Benchmarks preprocessing of the linear layers")
+                .arg(role_arg())
+                .args(&common_args())
+                .arg(weights_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("garbling")
+                .about("Benchmarks garbling and transferring the ReLU circuits")
+                .arg(role_arg())
+                .args(&common_args())
+                .arg(layers_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("cds")
+                .about("Benchmarks the CDS protocol")
+                .arg(role_arg())
+                .args(&common_args())
+                .arg(layers_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("triples-gen")
+                .about("Benchmarks triple generation")
+                .arg(role_arg())
+                .args(&common_args())
+                .arg(
+                    Arg::with_name("num")
+                        .short("n")
+                        .long("num")
+                        .takes_value(true)
+                        .help("Number of triples to generate (default taken from the model)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("backend")
+                        .long("backend")
+                        .takes_value(true)
+                        .possible_values(&["he", "ot"])
+                        .default_value("he")
+                        .help("Generate triples with homomorphic encryption or with OT extension; must match on both sides"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("input-auth")
+                .about("Benchmarks authenticating the inputs of the ReLU layers")
+                .arg(role_arg())
+                .args(&common_args())
+                .arg(layers_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("accuracy")
+                .about("Measures the accuracy of plaintext and secure inference with trained weights")
+                .args(&run_args())
+                .arg(weights_arg().required(true))
+                .arg(
+                    Arg::with_name("images")
+                        .short("i")
+                        .long("images")
+                        .takes_value(true)
+                        .help("Flattened f64 images in (N, C, H, W) order as a .npy file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("labels")
+                        .short("l")
+                        .long("labels")
+                        .takes_value(true)
+                        .help("i64 labels as a .npy file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("num")
+                        .short("n")
+                        .long("num")
+                        .takes_value(true)
+                        .help("Number of images to classify in plaintext (default all)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::with_name("secure")
+                        .short("s")
+                        .long("secure")
+                        .takes_value(true)
+                        .default_value("10")
+                        .help("Number of images to also classify with secure inference"),
+                )
+                .arg(
+                    Arg::with_name("precision")
+                        .long("precision")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .default_value("8:3")
+                        .help("Comma-separated fixed-point precisions to evaluate, each given as EXPONENT:MANTISSA"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("calibrate")
+                .about("Recommends fixed-point precision from the activation ranges of trained weights")
+                .args(&run_args())
+                .arg(weights_arg().required(true))
+                .arg(
+                    Arg::with_name("images")
+                        .short("i")
+                        .long("images")
+                        .takes_value(true)
+                        .help("Flattened f64 sample images in (N, C, H, W) order as a .npy file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("num")
+                        .short("n")
+                        .long("num")
+                        .takes_value(true)
+                        .help("Number of images to calibrate on (default all)")
+                        .required(false),
+                ),
+        )
+        .get_matches()
+}
+
+/// Applies the thread count and output arguments to the process
+fn configure(args: &ArgMatches) {
+    if args.is_present("threads") {
+        let threads = clap::value_t!(args, "threads", usize).unwrap_or_else(|e| e.exit());
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+    if let Some(path) = args.value_of("output") {
+        std::env::set_var("BENCH_OUTPUT_FILE", path);
+    }
+    if let Some(path) = args.value_of("trace") {
+        std::env::set_var("BENCH_TRACE_FILE", path);
+    }
+}
+
+fn address(args: &ArgMatches, role: Role) -> String {
+    let default = match role {
+        Role::Server => "0.0.0.0",
+        Role::Client => "127.0.0.1",
+    };
+    let ip = args.value_of("address").unwrap_or(default);
+    let port = clap::value_t!(args, "port", u16).unwrap_or_else(|e| e.exit());
+    format!("{}:{}", ip, port)
+}
+
+fn randomness(args: &ArgMatches) -> Randomness {
+    if args.is_present("insecure_seed") {
+        let seed = clap::value_t!(args, "insecure_seed", u64).unwrap_or_else(|e| e.exit());
+        Randomness::insecure_from_u64(seed)
+    } else {
+        Randomness::OsEntropy
+    }
+}
+
+fn layers(args: &ArgMatches, model: Model) -> Vec<usize> {
+    if args.is_present("layers") {
+        clap::values_t!(args, "layers", usize).unwrap_or_else(|e| e.exit())
+    } else {
+        model.relu_layers()
+    }
+}
+
+fn evaluate_accuracy(args: &ArgMatches, model: Model, rng: &mut ChaChaRng) {
+    let precisions = clap::values_t!(args, "precision", Precision).unwrap_or_else(|e| e.exit());
+    let weights = args.value_of("weights").unwrap();
+    // Only the shape of the architecture is used, so any precision will do
+    let architecture = NeuralArchitecture::from(&model.construct::<TenBitExpParams>(None, 1, rng));
+    let input_dims = architecture.layers[0].input_dimensions();
+
+    let mut dataset = Dataset::from_numpy(
+        args.value_of("images").unwrap(),
+        args.value_of("labels").unwrap(),
+        input_dims,
+    )
+    .expect("Failed to load dataset");
+    if args.is_present("num") {
+        let num = clap::value_t!(args, "num", usize).unwrap_or_else(|e| e.exit());
+        dataset.truncate(num);
+    }
+    let num_secure = clap::value_t!(args, "secure", usize).unwrap_or_else(|e| e.exit());
+
+    let float_predictions = accuracy::float_predictions(&architecture, weights, &dataset)
+        .expect("Failed to load weights");
+    for precision in precisions {
+        let report = accuracy::evaluate_with_precision(
+            precision,
+            &architecture,
+            weights,
+            &dataset,
+            &float_predictions,
+            num_secure,
+            rng,
+        )
+        .expect("Failed to load weights");
+        println!("{}", report);
+    }
+}
+
+fn calibrate(args: &ArgMatches, model: Model, rng: &mut ChaChaRng) {
+    let weights = args.value_of("weights").unwrap();
+    let architecture = NeuralArchitecture::from(&model.construct::<TenBitExpParams>(None, 1, rng));
+    let input_dims = architecture.layers[0].input_dimensions();
+
+    let mut images = accuracy::load_images(args.value_of("images").unwrap(), input_dims)
+        .expect("Failed to load images");
+    if args.is_present("num") {
+        let num = clap::value_t!(args, "num", usize).unwrap_or_else(|e| e.exit());
+        images.truncate(num);
+    }
+
+    let calibration =
+        Calibration::from_numpy(&architecture, weights, &images).expect("Failed to load weights");
+    println!("{}", calibration);
+    println!("{}", calibration.recommend());
+}
+
+/// Constructs `model` with its linear layers evaluated by libtorch, on a GPU
+/// if one is available
+#[cfg(feature = "torch")]
+fn construct_on_device<P: FixedPointParameters<Field = F>>(
+    model: Model,
+    batch_size: usize,
+    rng: &mut ChaChaRng,
+) -> NeuralNetwork<AdditiveShare<P>, FixedPoint<P>> {
+    let vs = tch::nn::VarStore::new(tch::Device::cuda_if_available());
+    model.construct(Some(&vs.root()), batch_size, rng)
+}
+
+/// Constructs `model` with its linear layers evaluated natively
+#[cfg(not(feature = "torch"))]
+fn construct_on_device<P: FixedPointParameters<Field = F>>(
+    model: Model,
+    batch_size: usize,
+    rng: &mut ChaChaRng,
+) -> NeuralNetwork<AdditiveShare<P>, FixedPoint<P>> {
+    model.construct(None, batch_size, rng)
+}
+
+/// Constructs the server's copy of `model`, loading the weights given as
+/// arguments or sampling them otherwise
+fn server_network<P: FixedPointParameters<Field = F>>(
+    args: &ArgMatches,
+    model: Model,
+    batch_size: usize,
+    rng: &mut ChaChaRng,
+) -> NeuralNetwork<AdditiveShare<P>, FixedPoint<P>> {
+    match args.value_of("weights") {
+        // GPU layers keep their own copy of the weights, so evaluate on the
+        // CPU when the weights are replaced
+        Some(path) => {
+            let mut network = model.construct::<P>(None, batch_size, rng);
+            network
+                .from_numpy(path)
+                .expect("Weights don't match the model");
+            network
+        }
+        None => construct_on_device(model, batch_size, rng),
+    }
+}
+
+fn serve<P: FixedPointParameters<Field = F>>(
+    args: &ArgMatches,
+    model: Model,
+    connections: usize,
+    rng: &mut ChaChaRng,
+) {
+    let addr = address(args, Role::Server);
+    let batch_size = clap::value_t!(args, "batch_size", usize).unwrap_or_else(|e| e.exit());
+    let network = server_network::<P>(args, model, batch_size, rng);
+
+    if let Some(path) = args.value_of("key") {
+        let config = ServerConfig {
+            keypair: server::load_or_create_keypair(path),
+            allowed_clients: None,
+        };
+        server::nn_server_secure(&addr, connections, &config, network, rng);
+    } else if let Some(config) = wan_config(args) {
+        server::nn_server_wan(&addr, connections, config, network, rng);
+    } else {
+        server::nn_server(&addr, connections, network, rng);
+    }
+}
+
+fn client<P: FixedPointParameters<Field = F>>(
+    args: &ArgMatches,
+    model: Model,
+    connections: usize,
+    rng: &mut ChaChaRng,
+) {
+    let addr = address(args, Role::Client);
+    let batch_size = clap::value_t!(args, "batch_size", usize).unwrap_or_else(|e| e.exit());
+    let architecture = NeuralArchitecture::from(&model.construct::<P>(None, batch_size, rng));
+
+    if let Some(path) = args.value_of("server_key") {
+        let server_public =
+            Keypair::read_public_from(path).expect("Failed to read server public key");
+        let config = ClientConfig::new(server_public).unwrap();
+        client::nn_client_secure(&addr, connections, &config, architecture, rng);
+    } else if let Some(config) = wan_config(args) {
+        client::nn_client_wan(&addr, connections, config, architecture, rng);
+    } else {
+        client::nn_client(&addr, connections, architecture, rng);
+    }
+}
+
+fn main() {
+    let args = get_args();
+    let (command, args) = match args.subcommand() {
+        (command, Some(args)) => (command, args),
+        _ => unreachable!(),
+    };
+    configure(args);
+
+    let mut rng = randomness(args).rng();
+    let model = Model::from_args(args);
+    if command == "accuracy" {
+        return evaluate_accuracy(args, model, &mut rng);
+    }
+    if command == "calibrate" {
+        return calibrate(args, model, &mut rng);
+    }
+    let connections = clap::value_t!(args, "connections", usize).unwrap_or_else(|e| e.exit());
+
+    match command {
+        "serve" | "client" => {
+            let precision =
+                clap::value_t!(args, "precision", Precision).unwrap_or_else(|e| e.exit());
+            if command == "serve" {
+                with_precision!(precision, P => serve::<P>(args, model, connections, &mut rng))
+            } else {
+                with_precision!(precision, P => client::<P>(args, model, connections, &mut rng))
+            }
+        }
+        "acg" => {
+            let network = server_network::<TenBitExpParams>(args, model, 1, &mut rng);
+            let role = Role::from_args(args);
+            let addr = address(args, role);
+            match role {
+                Role::Server => server::acg(&addr, connections, network, &mut rng),
+                Role::Client => client::acg(&addr, connections, (&network).into(), &mut rng),
+            }
+        }
+        "triples-gen" => {
+            let num = if args.is_present("num") {
+                clap::value_t!(args, "num", usize).unwrap_or_else(|e| e.exit())
+            } else {
+                model.num_triples()
+            };
+            let role = Role::from_args(args);
+            let addr = address(args, role);
+            match (role, args.value_of("backend").unwrap()) {
+                (Role::Server, "he") => server::triples_gen(&addr, connections, num, &mut rng),
+                (Role::Client, "he") => client::triples_gen(&addr, connections, num, &mut rng),
+                (Role::Server, _) => server::ot_triples_gen(&addr, connections, num, &mut rng),
+                (Role::Client, _) => client::ot_triples_gen(&addr, connections, num, &mut rng),
+            }
+        }
+        "garbling" | "cds" | "input-auth" => {
+            let layers = layers(args, model);
+            let role = Role::from_args(args);
+            let addr = address(args, role);
+            match (command, role) {
+                ("garbling", Role::Server) => {
+                    server::garbling(&addr, connections, &layers, &mut rng)
+                }
+                ("garbling", Role::Client) => {
+                    client::garbling(&addr, connections, &layers, &mut rng)
+                }
+                ("cds", Role::Server) => server::cds(&addr, connections, &layers, &mut rng),
+                ("cds", Role::Client) => client::cds(&addr, connections, &layers, &mut rng),
+                ("input-auth", Role::Server) => {
+                    server::input_auth(&addr, connections, &layers, &mut rng)
+                }
+                ("input-auth", Role::Client) => {
+                    client::input_auth(&addr, connections, &layers, &mut rng)
+                }
+                _ => unreachable!(),
+            }
+        }
+        _ => unreachable!(),
+    }
+}
@@ -29,6 +29,32 @@ const RANDOMNESS: [u8; 32] = [
     0x5d, 0xc9, 0x8d, 0xea, 0x23, 0xf2, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77, 0xd3, 0x4a, 0x52, 0xd2,
 ];
 
+fn sample_conv2d(
+    input_dim: (usize, usize, usize, usize),
+    kernel_dim: (usize, usize, usize, usize),
+    stride: usize,
+    padding: Padding,
+) -> (Input<TenBitExpFP>, Conv2dParams<TenBitExpFP, TenBitExpFP>) {
+    let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+    let mut input = Input::zeros(input_dim);
+    for in_i in input.iter_mut() {
+        let (_, n) = generate_random_number(&mut rng);
+        *in_i = n;
+    }
+    let mut kernel = Kernel::zeros(kernel_dim);
+    for ker_i in kernel.iter_mut() {
+        let (_, n) = generate_random_number(&mut rng);
+        *ker_i = n;
+    }
+    let mut bias = Kernel::zeros((kernel_dim.0, 1, 1, 1));
+    for b_i in bias.iter_mut() {
+        let (_, n) = generate_random_number(&mut rng);
+        *b_i = n;
+    }
+    let conv_params = Conv2dParams::<TenBitExpFP, _>::new(padding, stride, kernel, bias);
+    (input, conv_params)
+}
+
 fn make_conv2d(
     c: &mut Criterion,
     input_dim: (usize, usize, usize, usize),
@@ -39,29 +65,23 @@ fn make_conv2d(
     c.bench_function(
         &format!("convolution_{:?}_{:?}", input_dim, kernel_dim),
         move |bench| {
-            let mut rng = ChaChaRng::from_seed(RANDOMNESS);
-            let mut input = Input::zeros(input_dim);
-            for in_i in input.iter_mut() {
-                let (_, n) = generate_random_number(&mut rng);
-                *in_i = n;
-            }
-            let mut kernel = Kernel::zeros(kernel_dim);
-            for ker_i in kernel.iter_mut() {
-                let (_, n) = generate_random_number(&mut rng);
-                *ker_i = n;
-            }
-            let mut bias = Kernel::zeros((kernel_dim.0, 1, 1, 1));
-            for b_i in bias.iter_mut() {
-                let (_, n) = generate_random_number(&mut rng);
-                *b_i = n;
-            }
-            let conv_params = Conv2dParams::<TenBitExpFP, _>::new(padding, stride, kernel, bias);
+            let (input, conv_params) = sample_conv2d(input_dim, kernel_dim, stride, padding);
             let output_dims = conv_params.calculate_output_size(input_dim);
 
             let mut output = Output::zeros(output_dims);
             bench.iter(|| conv_params.conv2d_naive(&input, &mut output));
         },
     );
+    c.bench_function(
+        &format!("convolution_gemm_{:?}_{:?}", input_dim, kernel_dim),
+        move |bench| {
+            let (input, conv_params) = sample_conv2d(input_dim, kernel_dim, stride, padding);
+            let output_dims = conv_params.calculate_output_size(input_dim);
+
+            let mut output = Output::zeros(output_dims);
+            bench.iter(|| conv_params.conv2d_gemm(&input, &mut output));
+        },
+    );
 }
 
 fn bench_conv2d_resnet32_2_1(c: &mut Criterion) {
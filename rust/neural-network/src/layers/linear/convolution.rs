@@ -1,10 +1,14 @@
-use crate::tensors::{Input, Kernel, Output};
+use crate::{
+    layers::linear::gemm,
+    tensors::{Input, Kernel, Output},
+};
 use algebra::{fp_64::Fp64Parameters, FixedPoint, FixedPointParameters, FpParameters, PrimeField};
 use num_traits::Zero;
 use std::{
     marker::PhantomData,
     ops::{AddAssign, Mul},
 };
+#[cfg(feature = "torch")]
 use tch::nn;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -19,6 +23,7 @@ pub struct Conv2dParams<F, C> {
     pub stride: usize,
     pub kernel: Kernel<C>,
     pub bias: Kernel<C>,
+    #[cfg(feature = "torch")]
     pub tch_config: Option<nn::Conv2D>,
     pub eval_method: crate::EvalMethod,
     _variable: PhantomData<F>,
@@ -48,6 +53,7 @@ where
             stride,
             kernel,
             bias,
+            #[cfg(feature = "torch")]
             tch_config: None,
             eval_method: crate::EvalMethod::Naive,
             _variable: PhantomData,
@@ -136,20 +142,103 @@ where
                     }
                 }
             }
-            // Add the appropiate bias to each channel of output
-            out.outer_iter_mut().for_each(|mut batch| {
-                batch
-                    .outer_iter_mut()
-                    .enumerate()
-                    .for_each(|(i, mut view)| {
-                        let b = unsafe { *self.bias.uget((i, 0, 0, 0)) };
-                        view.iter_mut().for_each(|e| *e += b.into());
+        }
+        // Add the appropiate bias to each channel of output
+        out.outer_iter_mut().for_each(|mut batch| {
+            batch
+                .outer_iter_mut()
+                .enumerate()
+                .for_each(|(i, mut view)| {
+                    let b = unsafe { *self.bias.uget((i, 0, 0, 0)) };
+                    view.iter_mut().for_each(|e| *e += b.into());
+                });
+        });
+    }
+}
+
+impl<P, I> Conv2dParams<I, FixedPoint<P>>
+where
+    P: FixedPointParameters,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
+    I: Copy + Into<FixedPoint<P>> + From<FixedPoint<P>> + AddAssign,
+{
+    /// Lays out the patch of `input` under each output position as a row of a
+    /// `(batch_size * out_height * out_width) x (in_channels * k_height *
+    /// k_width)` matrix of canonical field representatives
+    fn im2col(&self, input: &Input<I>, out_dim: (usize, usize, usize, usize)) -> Vec<u64> {
+        let (batch_size, in_channels, in_height, in_width) = input.dim();
+        let (_, _, k_height, k_width) = self.kernel.dim();
+        let (_, _, out_height, out_width) = out_dim;
+        let p = match self.padding {
+            Padding::Same => (k_height - 1) / 2,
+            Padding::Valid => 0,
+        };
+        let input: Vec<u64> = input.iter().map(|&e| gemm::to_repr::<P, _>(e)).collect();
+        let mut patches = Vec::with_capacity(
+            batch_size * out_height * out_width * in_channels * k_height * k_width,
+        );
+        for b_i in 0..batch_size {
+            for out_i in 0..out_height {
+                for out_j in 0..out_width {
+                    for chan in 0..in_channels {
+                        for k_i in 0..k_height {
+                            for k_j in 0..k_width {
+                                let row = (out_i * self.stride + k_i).wrapping_sub(p);
+                                let col = (out_j * self.stride + k_j).wrapping_sub(p);
+                                patches.push(if row < in_height && col < in_width {
+                                    input[((b_i * in_channels + chan) * in_height + row) * in_width
+                                        + col]
+                                } else {
+                                    0
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        patches
+    }
+
+    /// Evaluates the convolution as a matrix product of the kernel and the
+    /// patches of `input`. The kernel and `input` must not have been
+    /// multiplied.
+    pub fn conv2d_gemm(&self, input: &Input<I>, out: &mut Output<I>) {
+        let (batch_size, in_channels, ..) = input.dim();
+        let (num, k_channels, k_height, k_width) = self.kernel.dim();
+        assert_eq!(k_channels, in_channels);
+        let out_dim = self.calculate_output_size(input.dim());
+        assert_eq!(out.dim(), out_dim);
+        let (_, _, out_height, out_width) = out_dim;
+        let k = in_channels * k_height * k_width;
+        let n = out_height * out_width;
+
+        let kernel: Vec<u64> = self
+            .kernel
+            .iter()
+            .map(|&w| gemm::to_repr::<P, _>(w))
+            .collect();
+        let patches = self.im2col(input, out_dim);
+        for b_i in 0..batch_size {
+            let patches = &patches[b_i * n * k..(b_i + 1) * n * k];
+            let result = gemm::gemm(&kernel, patches, num, n, k, gemm::modulus::<P>());
+            let mut out = out.slice_mut(s![b_i, .., .., ..]);
+            out.outer_iter_mut()
+                .zip(result.chunks(n.max(1)))
+                .enumerate()
+                .for_each(|(i, (mut view, result))| {
+                    let b = unsafe { *self.bias.uget((i, 0, 0, 0)) };
+                    view.iter_mut().zip(result).for_each(|(e, &r)| {
+                        *e = gemm::from_repr::<P, _>(r);
+                        *e += b.into();
                     });
-            });
+                });
         }
     }
 }
 
+#[cfg(feature = "torch")]
 impl<P: FixedPointParameters, I> Conv2dParams<I, FixedPoint<P>>
 where
     I: Zero + Copy + Into<FixedPoint<P>> + AddAssign + Mul<FixedPoint<P>, Output = I>,
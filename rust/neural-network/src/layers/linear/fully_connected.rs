@@ -1,17 +1,23 @@
-use crate::tensors::{Input, Kernel, Output};
+use crate::{
+    layers::linear::gemm,
+    tensors::{Input, Kernel, Output},
+};
 use algebra::{fp_64::Fp64Parameters, FixedPoint, FixedPointParameters, FpParameters, PrimeField};
+#[cfg(feature = "torch")]
 use crypto_primitives::AdditiveShare;
 use num_traits::Zero;
 use std::{
     marker::PhantomData,
     ops::{Add, AddAssign, Mul},
 };
+#[cfg(feature = "torch")]
 use tch::nn;
 
 #[derive(Debug)]
 pub struct FullyConnectedParams<F, C> {
     pub weights: Kernel<C>,
     pub bias: Kernel<C>,
+    #[cfg(feature = "torch")]
     pub tch_config: Option<nn::Linear>,
     pub eval_method: crate::EvalMethod,
     _variable: PhantomData<F>,
@@ -37,6 +43,7 @@ where
         Self {
             weights,
             bias,
+            #[cfg(feature = "torch")]
             tch_config: None,
             eval_method: crate::EvalMethod::Naive,
             _variable: PhantomData,
@@ -119,6 +126,41 @@ where
     }
 }
 
+impl<P, I> FullyConnectedParams<I, FixedPoint<P>>
+where
+    P: FixedPointParameters,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
+    I: Copy + Into<FixedPoint<P>> + From<FixedPoint<P>> + AddAssign,
+{
+    /// Evaluates the layer as a matrix product of the weights and the batch
+    /// of inputs. The weights and `input` must not have been multiplied.
+    pub fn fully_connected_gemm(&self, input: &Input<I>, out: &mut Output<I>) {
+        let (batch_size, ..) = input.dim();
+        let out_dim = self.calculate_output_size(input.dim());
+        assert_eq!(out.dim(), out_dim);
+        let (num, ..) = self.weights.dim();
+        let k = input.len() / batch_size.max(1);
+
+        let weights: Vec<u64> = self
+            .weights
+            .iter()
+            .map(|&w| gemm::to_repr::<P, _>(w))
+            .collect();
+        let input: Vec<u64> = input.iter().map(|&e| gemm::to_repr::<P, _>(e)).collect();
+        let result = gemm::gemm(&weights, &input, num, batch_size, k, gemm::modulus::<P>());
+        for (i, result) in result.chunks(batch_size.max(1)).enumerate() {
+            let b = unsafe { *self.bias.uget((i, 0, 0, 0)) };
+            for (b_i, &r) in result.iter().enumerate() {
+                let e = &mut out[[b_i, i, 0, 0]];
+                *e = gemm::from_repr::<P, _>(r);
+                *e += b.into();
+            }
+        }
+    }
+}
+
+#[cfg(feature = "torch")]
 impl<P: FixedPointParameters> FullyConnectedParams<AdditiveShare<FixedPoint<P>>, FixedPoint<P>>
 where
     <P::Field as PrimeField>::Params: Fp64Parameters,
@@ -0,0 +1,151 @@
+//! Native evaluation of linear layers over `Fp64` by matrix multiplication.
+//!
+//! Convolutions are lowered to a matrix product with im2col, and fully
+//! connected layers are one already. The product is computed on the canonical
+//! representatives of the field elements: products are accumulated in a
+//! `u128` and only reduced modulo the field once per output element, or once
+//! every `lazy_terms` products for moduli too large to accumulate a whole
+//! row. Rows of the output are computed in parallel with rayon.
+
+use algebra::{fp_64::Fp64Parameters, FixedPoint, FixedPointParameters, FpParameters, PrimeField};
+use rayon::prelude::*;
+use std::convert::TryFrom;
+
+/// Number of rows of the left matrix processed together by one task
+const ROW_BLOCK: usize = 8;
+/// Number of rows of the right matrix processed together, chosen so that a
+/// block of rows stays in cache while it is reused by `ROW_BLOCK` rows
+const COL_BLOCK: usize = 64;
+
+/// Returns the modulus of the field underlying `P`
+#[inline]
+pub(crate) fn modulus<P>() -> u64
+where
+    P: FixedPointParameters,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
+{
+    <P::Field as PrimeField>::Params::MODULUS.0
+}
+
+/// Returns the canonical representative of `elem`. Panics if `elem` has been
+/// multiplied, since the output would then be at the wrong scale.
+#[inline]
+pub(crate) fn to_repr<P, I>(elem: I) -> u64
+where
+    P: FixedPointParameters,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
+    I: Into<FixedPoint<P>>,
+{
+    let fp: FixedPoint<P> = elem.into();
+    assert_eq!(
+        fp.num_muls(),
+        0,
+        "GEMM evaluation requires unmultiplied inputs"
+    );
+    fp.inner.into_repr().0
+}
+
+/// Returns the output element represented by `repr`, which is the result of
+/// one multiplication
+#[inline]
+pub(crate) fn from_repr<P, I>(repr: u64) -> I
+where
+    P: FixedPointParameters,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
+    I: From<FixedPoint<P>>,
+{
+    FixedPoint::with_num_muls(P::Field::from_repr(repr.into()), 1).into()
+}
+
+/// Computes `a * b^T` modulo `modulus`, where `a` is an `m x k` matrix and
+/// `b` is an `n x k` matrix, both stored in row-major order with entries
+/// smaller than `modulus`. Returns the `m x n` result in row-major order.
+pub fn gemm(a: &[u64], b: &[u64], m: usize, n: usize, k: usize, modulus: u64) -> Vec<u64> {
+    assert_eq!(a.len(), m * k);
+    assert_eq!(b.len(), n * k);
+    let mut c = vec![0u64; m * n];
+    if m == 0 || n == 0 || k == 0 {
+        return c;
+    }
+    // The number of products which can be added to a reduced accumulator
+    // without overflowing a `u128`
+    let max_product = u128::from(modulus - 1).pow(2).max(1);
+    let lazy_terms = usize::try_from((u128::max_value() - u128::from(modulus)) / max_product)
+        .unwrap_or(usize::max_value());
+    let modulus = u128::from(modulus);
+
+    c.par_chunks_mut(ROW_BLOCK * n)
+        .zip(a.par_chunks(ROW_BLOCK * k))
+        .for_each(|(c_rows, a_rows)| {
+            for (j, b_rows) in b.chunks(COL_BLOCK * k).enumerate() {
+                for (c_row, a_row) in c_rows.chunks_mut(n).zip(a_rows.chunks(k)) {
+                    let c_block = &mut c_row[j * COL_BLOCK..];
+                    for (c, b_row) in c_block.iter_mut().zip(b_rows.chunks(k)) {
+                        *c = dot(a_row, b_row, modulus, lazy_terms);
+                    }
+                }
+            }
+        });
+    c
+}
+
+/// Computes the inner product of `a` and `b` modulo `modulus`
+#[inline]
+fn dot(a: &[u64], b: &[u64], modulus: u128, lazy_terms: usize) -> u64 {
+    let sum = a
+        .chunks(lazy_terms)
+        .zip(b.chunks(lazy_terms))
+        .fold(0u128, |sum, (a, b)| {
+            a.iter().zip(b).fold(sum % modulus, |s, (&x, &y)| {
+                s + u128::from(x) * u128::from(y)
+            })
+        });
+    (sum % modulus) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    const RANDOMNESS: [u8; 32] = [
+        0x11, 0xe0, 0x84, 0xbc, 0x89, 0xa7, 0x94, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda, 0xf4,
+        0x76, 0x5d, 0xc9, 0x8d, 0xea, 0x43, 0x72, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77, 0xd3, 0x4a,
+        0x52, 0xd2,
+    ];
+
+    fn naive_gemm(a: &[u64], b: &[u64], m: usize, n: usize, k: usize, modulus: u64) -> Vec<u64> {
+        let mut c = vec![0u64; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                for l in 0..k {
+                    let product = u128::from(a[i * k + l]) * u128::from(b[j * k + l]);
+                    let sum = u128::from(c[i * n + j]) + product % u128::from(modulus);
+                    c[i * n + j] = (sum % u128::from(modulus)) as u64;
+                }
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn test_gemm() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        // A 44-bit modulus, which never needs intermediate reduction, and a
+        // 64-bit one, which needs it after every product
+        for &modulus in &[17592060215297u64, 18446744073709551557] {
+            for &(m, n, k) in &[(1, 1, 1), (3, 5, 7), (17, 70, 9), (9, 130, 3), (2, 0, 4)] {
+                let a: Vec<u64> = (0..m * k).map(|_| rng.gen_range(0, modulus)).collect();
+                let b: Vec<u64> = (0..n * k).map(|_| rng.gen_range(0, modulus)).collect();
+                assert_eq!(
+                    gemm(&a, &b, m, n, k, modulus),
+                    naive_gemm(&a, &b, m, n, k, modulus)
+                );
+            }
+        }
+    }
+}
@@ -7,8 +7,9 @@ use crypto_primitives::{AdditiveShare, AuthAdditiveShare, AuthShare, Share};
 use num_traits::{One, Zero};
 use std::{
     marker::PhantomData,
-    ops::{AddAssign, Mul},
+    ops::{Add, AddAssign, Mul},
 };
+#[cfg(feature = "torch")]
 use tch::nn::Module;
 
 use crate::layers::LayerDims;
@@ -23,6 +24,8 @@ use average_pooling::*;
 pub mod fully_connected;
 use fully_connected::*;
 
+pub mod gemm;
+
 #[cfg(test)]
 mod tests;
 
@@ -151,6 +154,49 @@ impl<F, C> LinearLayer<F, C> {
     }
 }
 
+impl<F, C> LinearLayer<F, C>
+where
+    F: Zero + Mul<C, Output = F> + AddAssign + Add<Output = F> + Copy,
+    C: Copy + Into<F> + Zero,
+{
+    /// Returns a layer with a batch size of one which evaluates `self` on
+    /// `group_size` items laid out one after another along the channel
+    /// dimension, as in `Input::merge_batch`. Panics on layers other than
+    /// `Conv2d` and `FullyConnected`.
+    pub fn merge_batch(&self, group_size: usize) -> Self {
+        let (_, in_c, in_h, in_w) = self.input_dimensions();
+        let (_, out_c, out_h, out_w) = self.output_dimensions();
+        let dims = LayerDims {
+            input_dims: (1, group_size * in_c, in_h, in_w),
+            output_dims: (1, group_size * out_c, out_h, out_w),
+        };
+        let tile_bias = |bias: &Kernel<C>| {
+            let elems = (0..group_size).flat_map(|_| bias.iter().cloned()).collect();
+            Kernel::from_shape_vec((group_size * bias.dim().0, 1, 1, 1), elems)
+                .expect("Shapes should be same")
+        };
+        match self {
+            Conv2d { params: p, .. } => Conv2d {
+                dims,
+                params: Conv2dParams::new(
+                    p.padding,
+                    p.stride,
+                    p.kernel.block_diagonal(group_size),
+                    tile_bias(&p.bias),
+                ),
+            },
+            FullyConnected { params: p, .. } => FullyConnected {
+                dims,
+                params: FullyConnectedParams::new(
+                    p.weights.block_diagonal(group_size),
+                    tile_bias(&p.bias),
+                ),
+            },
+            _ => panic!("Identity/AvgPool layers do not have a kernel"),
+        }
+    }
+}
+
 impl<F, C> Evaluate<F> for LinearLayer<F, C>
 where
     F: Zero + Mul<C, Output = F> + AddAssign + Copy,
@@ -163,7 +209,9 @@ where
     default fn evaluate_with_method(&self, method: EvalMethod, input: &Input<F>) -> Output<F> {
         let mut output = Output::zeros(self.output_dimensions());
         match method {
-            EvalMethod::Naive => self.evaluate_naive(input, &mut output),
+            // GEMM is only implemented over `Fp64` fixed point
+            EvalMethod::Naive | EvalMethod::Gemm => self.evaluate_naive(input, &mut output),
+            #[cfg(feature = "torch")]
             EvalMethod::TorchDevice(_) => {
                 unimplemented!("cannot evaluate general networks with torch")
             }
@@ -182,7 +230,9 @@ impl<P: FixedPointParameters> Evaluate<FixedPoint<P>>
     ) -> Output<FixedPoint<P>> {
         let mut output = Output::zeros(self.output_dimensions());
         match method {
-            EvalMethod::Naive => self.evaluate_naive(input, &mut output),
+            // GEMM is only implemented over `Fp64` fixed point
+            EvalMethod::Naive | EvalMethod::Gemm => self.evaluate_naive(input, &mut output),
+            #[cfg(feature = "torch")]
             EvalMethod::TorchDevice(_) => {
                 unimplemented!("cannot evaluate general networks with torch")
             }
@@ -209,6 +259,8 @@ where
         let mut output = Output::zeros(self.output_dimensions());
         match method {
             EvalMethod::Naive => self.evaluate_naive(input, &mut output),
+            EvalMethod::Gemm => self.evaluate_gemm(input, &mut output),
+            #[cfg(feature = "torch")]
             EvalMethod::TorchDevice(m) => {
                 match self {
                     Conv2d { dims: _, params: p } => {
@@ -252,6 +304,8 @@ where
         let mut output = Output::zeros(self.output_dimensions());
         match method {
             EvalMethod::Naive => self.evaluate_naive(input, &mut output),
+            EvalMethod::Gemm => self.evaluate_gemm(input, &mut output),
+            #[cfg(feature = "torch")]
             EvalMethod::TorchDevice(m) => {
                 match self {
                     Conv2d { dims: _, params: p } => {
@@ -278,6 +332,27 @@ where
     }
 }
 
+impl<P, I> LinearLayer<I, FixedPoint<P>>
+where
+    P: FixedPointParameters,
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
+    I: Zero
+        + Copy
+        + Into<FixedPoint<P>>
+        + From<FixedPoint<P>>
+        + AddAssign
+        + Mul<FixedPoint<P>, Output = I>,
+{
+    fn evaluate_gemm(&self, input: &Input<I>, output: &mut Output<I>) {
+        match self {
+            Conv2d { dims: _, params: p } => p.conv2d_gemm(input, output),
+            FullyConnected { dims: _, params: p } => p.fully_connected_gemm(input, output),
+            _ => self.evaluate_naive(input, output),
+        }
+    }
+}
+
 impl<P> LinearLayer<AdditiveShare<FixedPoint<P>>, FixedPoint<P>>
 where
     P: FixedPointParameters,
@@ -305,6 +380,30 @@ where
     }
 }
 
+impl<F, C: Clone> LinearLayerInfo<F, C> {
+    /// Returns the info of `LinearLayer::merge_batch` for the layer described
+    /// by `self`
+    pub fn merge_batch(&self, group_size: usize) -> Self {
+        match self {
+            LinearLayerInfo::Conv2d {
+                kernel,
+                padding,
+                stride,
+            } => LinearLayerInfo::Conv2d {
+                kernel: (
+                    group_size * kernel.0,
+                    group_size * kernel.1,
+                    kernel.2,
+                    kernel.3,
+                ),
+                padding: *padding,
+                stride: *stride,
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
 impl<F, C> LinearLayerInfo<F, C> {
     pub fn evaluate_naive(&self, input: &Input<F>, output: &mut Output<F>)
     where
@@ -30,8 +30,10 @@ fn generate_random_number<R: Rng>(rng: &mut R) -> (f64, TenBitExpFP) {
 mod convolution {
     use super::*;
     use rand::SeedableRng;
+    #[cfg(feature = "torch")]
     use tch::nn;
 
+    #[cfg(feature = "torch")]
     #[test]
     fn test_torch() {
         println!("CUDA device is available: {:?}", tch::Cuda::is_available());
@@ -90,6 +92,129 @@ mod convolution {
         assert_eq!(result_1, result_2);
     }
 
+    #[test]
+    fn test_gemm() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (1, 3, 7, 7);
+        let kernel_dims = (4, 3, 3, 3);
+        for &(padding, stride) in &[(Padding::Same, 1), (Padding::Same, 2), (Padding::Valid, 1)] {
+            let mut kernel = Kernel::zeros(kernel_dims);
+            let mut bias = Kernel::zeros((kernel_dims.0, 1, 1, 1));
+            kernel
+                .iter_mut()
+                .for_each(|ker_i| *ker_i = generate_random_number(&mut rng).1);
+            bias.iter_mut()
+                .for_each(|bias_i| *bias_i = generate_random_number(&mut rng).1);
+
+            let conv_params = Conv2dParams::<AdditiveShare<TenBitExpFP>, _>::new(
+                padding,
+                stride,
+                kernel.clone(),
+                bias.clone(),
+            );
+            let output_dims = conv_params.calculate_output_size(input_dims);
+            let layer = LinearLayer::Conv2d {
+                dims: LayerDims {
+                    input_dims,
+                    output_dims,
+                },
+                params: conv_params,
+            };
+            let pt_layer = LinearLayer::Conv2d {
+                dims: layer.dimensions(),
+                params: Conv2dParams::<TenBitExpFP, _>::new(padding, stride, kernel, bias),
+            };
+
+            let mut input = Input::zeros(input_dims);
+            input
+                .iter_mut()
+                .for_each(|in_i| *in_i = generate_random_number(&mut rng).1);
+            let shares = Input::from_shape_vec(
+                input_dims,
+                input.iter().map(|&e| AdditiveShare::new(e)).collect(),
+            )
+            .unwrap();
+
+            assert_eq!(
+                pt_layer.evaluate_with_method(EvalMethod::Gemm, &input),
+                pt_layer.evaluate_with_method(EvalMethod::Naive, &input)
+            );
+            assert_eq!(
+                layer.evaluate_with_method(EvalMethod::Gemm, &shares),
+                layer.evaluate_with_method(EvalMethod::Naive, &shares)
+            );
+        }
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (3, 2, 5, 5);
+        let kernel_dims = (4, 2, 3, 3);
+        let mut kernel = Kernel::zeros(kernel_dims);
+        let mut bias = Kernel::zeros((kernel_dims.0, 1, 1, 1));
+        kernel
+            .iter_mut()
+            .for_each(|ker_i| *ker_i = generate_random_number(&mut rng).1);
+        bias.iter_mut()
+            .for_each(|bias_i| *bias_i = generate_random_number(&mut rng).1);
+        let conv_params = Conv2dParams::<TenBitExpFP, _>::new(Padding::Same, 1, kernel, bias);
+
+        let mut input = Input::zeros(input_dims);
+        input
+            .iter_mut()
+            .for_each(|in_i| *in_i = generate_random_number(&mut rng).1);
+        let mut output = Output::zeros(conv_params.calculate_output_size(input_dims));
+        conv_params.conv2d_naive(&input, &mut output);
+
+        // Evaluating a batch should match evaluating each item on its own
+        for (item, item_output) in input.split_batch().iter().zip(output.split_batch()) {
+            let mut expected = Output::zeros(conv_params.calculate_output_size(item.dim()));
+            conv_params.conv2d_naive(item, &mut expected);
+            assert_eq!(item_output, expected);
+        }
+        assert_eq!(Input::stack_batch(&input.split_batch()), input);
+    }
+
+    #[test]
+    fn test_merge_batch() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (3, 2, 5, 5);
+        let kernel_dims = (4, 2, 3, 3);
+        let mut kernel = Kernel::zeros(kernel_dims);
+        let mut bias = Kernel::zeros((kernel_dims.0, 1, 1, 1));
+        kernel
+            .iter_mut()
+            .for_each(|ker_i| *ker_i = generate_random_number(&mut rng).1);
+        bias.iter_mut()
+            .for_each(|bias_i| *bias_i = generate_random_number(&mut rng).1);
+        let conv_params = Conv2dParams::<TenBitExpFP, _>::new(Padding::Same, 1, kernel, bias);
+        let layer = LinearLayer::Conv2d {
+            dims: LayerDims {
+                input_dims,
+                output_dims: conv_params.calculate_output_size(input_dims),
+            },
+            params: conv_params,
+        };
+
+        let mut input = Input::zeros(input_dims);
+        input
+            .iter_mut()
+            .for_each(|in_i| *in_i = generate_random_number(&mut rng).1);
+        let output = layer.evaluate(&input);
+
+        // Evaluating the merged layer on each group should match evaluating the
+        // items of the group on their own
+        let groups = input.merge_batch(2);
+        assert_eq!(groups.len(), 2);
+        let merged_outputs: Vec<_> = groups
+            .iter()
+            .map(|group| layer.merge_batch(group.dim().1 / 2).evaluate(group))
+            .collect();
+        assert_eq!(Output::unmerge_batch(&merged_outputs, 4), output);
+        assert_eq!(Input::unmerge_batch(&groups, 2), input);
+    }
+
     #[test]
     fn load_conv2d_weights() {
         let input: Input<TenBitExpFP> = ndarray::Array4::from_shape_vec(
@@ -289,6 +414,74 @@ mod convolution {
     }
 }
 
+mod fully_connected {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_gemm() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (2, 3, 4, 4);
+        let weight_dims = (10, 3, 4, 4);
+        let mut weights = Kernel::zeros(weight_dims);
+        let mut bias = Kernel::zeros((weight_dims.0, 1, 1, 1));
+        weights
+            .iter_mut()
+            .for_each(|w_i| *w_i = generate_random_number(&mut rng).1);
+        bias.iter_mut()
+            .for_each(|b_i| *b_i = generate_random_number(&mut rng).1);
+
+        let fc_params = FullyConnectedParams::<TenBitExpFP, _>::new(weights, bias);
+        let layer = LinearLayer::FullyConnected {
+            dims: LayerDims {
+                input_dims,
+                output_dims: fc_params.calculate_output_size(input_dims),
+            },
+            params: fc_params,
+        };
+
+        let mut input = Input::zeros(input_dims);
+        input
+            .iter_mut()
+            .for_each(|in_i| *in_i = generate_random_number(&mut rng).1);
+        assert_eq!(
+            layer.evaluate_with_method(EvalMethod::Gemm, &input),
+            layer.evaluate_with_method(EvalMethod::Naive, &input)
+        );
+    }
+
+    #[test]
+    fn test_gemm_falls_back_to_naive() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (1, 2, 3, 3);
+        let weight_dims = (4, 2, 3, 3);
+        let mut weights = Kernel::zeros(weight_dims);
+        let bias = Kernel::zeros((weight_dims.0, 1, 1, 1));
+        weights
+            .iter_mut()
+            .for_each(|w_i| *w_i = generate_random_number(&mut rng).0);
+
+        let fc_params = FullyConnectedParams::<f64, f64>::new(weights, bias);
+        let layer = LinearLayer::FullyConnected {
+            dims: LayerDims {
+                input_dims,
+                output_dims: fc_params.calculate_output_size(input_dims),
+            },
+            params: fc_params,
+        };
+
+        let mut input = Input::zeros(input_dims);
+        input
+            .iter_mut()
+            .for_each(|in_i| *in_i = generate_random_number(&mut rng).0);
+        // Float layers have no GEMM implementation
+        assert_eq!(
+            layer.evaluate_with_method(EvalMethod::Gemm, &input),
+            layer.evaluate_with_method(EvalMethod::Naive, &input)
+        );
+    }
+}
+
 mod pooling {
     use super::*;
     #[test]
@@ -14,6 +14,7 @@ pub extern crate npy;
 extern crate npy_derive;
 
 pub mod layers;
+pub mod overflow;
 pub mod tensors;
 
 use layers::{
@@ -34,6 +35,10 @@ pub trait Evaluate<F> {
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum EvalMethod {
     Naive,
+    /// Evaluates convolutions and fully connected layers over `Fp64` as
+    /// parallel matrix products, without libtorch
+    Gemm,
+    #[cfg(feature = "torch")]
     TorchDevice(tch::Device),
 }
 
@@ -43,6 +48,7 @@ impl Default for EvalMethod {
     }
 }
 
+#[cfg(feature = "torch")]
 impl EvalMethod {
     #[inline]
     pub fn with_tch_device(dev: tch::Device) -> Self {
@@ -110,6 +116,12 @@ where
             .read_to_end(&mut buf)
             .unwrap();
         let weights: Vec<f64> = NpyData::from_bytes(&buf).unwrap().to_vec();
+        self.from_weights(&weights)
+    }
+
+    /// Replaces the kernels and biases of the linear layers with consecutive
+    /// chunks of `weights`, returning an error if `weights` is too short
+    pub fn from_weights(&mut self, weights: &[f64]) -> Result<(), ndarray::ShapeError> {
         let mut weights_idx = 0;
 
         // npy can't do multi-dimensional numpy serialization so all the weights are
@@ -124,14 +136,14 @@ where
             let kernel_size = kernel_dims.0 * kernel_dims.1 * kernel_dims.2 * kernel_dims.3;
             let new_kernel = ArrayView::from_shape(
                 kernel_dims,
-                &weights[weights_idx..(weights_idx + kernel_size)],
+                weights_chunk(weights, weights_idx, kernel_size)?,
             )?;
             weights_idx += kernel_size;
 
             let bias_dims = bias.dim();
             let bias_size = bias_dims.0;
             let new_bias =
-                ArrayView::from_shape(bias_dims, &weights[weights_idx..(weights_idx + bias_size)])?;
+                ArrayView::from_shape(bias_dims, weights_chunk(weights, weights_idx, bias_size)?)?;
             weights_idx += bias_size;
 
             match layer {
@@ -154,6 +166,14 @@ where
     }
 }
 
+/// Returns the `len` weights starting at `start`, or an error if `weights`
+/// ends before them
+fn weights_chunk(weights: &[f64], start: usize, len: usize) -> Result<&[f64], ndarray::ShapeError> {
+    weights
+        .get(start..start + len)
+        .ok_or_else(|| ndarray::ShapeError::from_kind(ndarray::ErrorKind::OutOfBounds))
+}
+
 impl<'a, F, C: Clone> From<&'a NeuralNetwork<F, C>> for NeuralArchitecture<F, C> {
     fn from(other: &'a NeuralNetwork<F, C>) -> Self {
         let layers = other.layers.iter().map(|layer| layer.into()).collect();
@@ -0,0 +1,212 @@
+//! Detection of overflow in fixed-point evaluation.
+//!
+//! Fixed-point arithmetic silently wraps around the field modulus, and the
+//! garbled ReLU circuit silently drops the integer bits of its input above
+//! `RELU_INTEGER_BITS`. `NeuralNetwork::evaluate_checked` evaluates a network
+//! in plaintext and additionally reports every element at which either occurs.
+
+use crate::{
+    layers::{
+        average_pooling::AvgPoolParams, convolution::Conv2dParams,
+        fully_connected::FullyConnectedParams, Layer, LinearLayer,
+    },
+    tensors::{Input, Output},
+    Evaluate, NeuralNetwork,
+};
+use algebra::{CheckedFixedPoint, FixedPoint, FixedPointParameters};
+use crypto_primitives::gc::RELU_INTEGER_BITS;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowKind {
+    /// The output of a linear layer wrapped around the field modulus
+    FieldModulus,
+    /// The input of a ReLU has more integer bits than the ReLU circuit
+    /// inspects
+    ReluWindow,
+}
+
+/// An element at which fixed-point evaluation overflowed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overflow {
+    pub layer: usize,
+    /// Index of the element in the output of a linear layer, or in the input
+    /// of a ReLU
+    pub index: (usize, usize, usize, usize),
+    pub kind: OverflowKind,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            OverflowKind::FieldModulus => "wrapped around the field modulus",
+            OverflowKind::ReluWindow => "exceeded the ReLU window",
+        };
+        write!(
+            f,
+            "layer {}, element {:?}: {}",
+            self.layer, self.index, kind
+        )
+    }
+}
+
+/// Rebuilds `layer` to evaluate over `CheckedFixedPoint`s
+fn checked_layer<P: FixedPointParameters>(
+    layer: &LinearLayer<FixedPoint<P>, FixedPoint<P>>,
+) -> LinearLayer<CheckedFixedPoint<P>, FixedPoint<P>> {
+    match layer {
+        LinearLayer::Conv2d { dims, params } => LinearLayer::Conv2d {
+            dims: *dims,
+            params: Conv2dParams::new(
+                params.padding,
+                params.stride,
+                params.kernel.clone(),
+                params.bias.clone(),
+            ),
+        },
+        LinearLayer::FullyConnected { dims, params } => LinearLayer::FullyConnected {
+            dims: *dims,
+            params: FullyConnectedParams::new(params.weights.clone(), params.bias.clone()),
+        },
+        LinearLayer::AvgPool { dims, params } => LinearLayer::AvgPool {
+            dims: *dims,
+            params: AvgPoolParams::new(
+                params.pool_h,
+                params.pool_w,
+                params.stride,
+                params.normalizer,
+            ),
+        },
+        LinearLayer::Identity { dims } => LinearLayer::Identity { dims: *dims },
+    }
+}
+
+impl<P: FixedPointParameters> NeuralNetwork<FixedPoint<P>, FixedPoint<P>> {
+    /// Evaluates `self` over `input` with naive evaluation, returning the
+    /// output along with every element at which a linear layer wrapped around
+    /// the field modulus or a ReLU input exceeded the window of the ReLU
+    /// circuit. Wrapped elements keep their wrapped values.
+    pub fn evaluate_checked(
+        &self,
+        input: &Input<FixedPoint<P>>,
+    ) -> (Output<FixedPoint<P>>, Vec<Overflow>) {
+        assert!(self.validate());
+        let relu_window = f64::from(1u32 << RELU_INTEGER_BITS);
+        let mut overflows = Vec::new();
+        let mut input = input.clone();
+        for (i, layer) in self.layers.iter().enumerate() {
+            input = match layer {
+                Layer::LL(layer) => {
+                    let checked_input = Input::from_shape_vec(
+                        input.dim(),
+                        input.iter().map(|&e| CheckedFixedPoint::from(e)).collect(),
+                    )
+                    .expect("shape should be correct");
+                    let output = checked_layer(layer).evaluate(&checked_input);
+                    overflows.extend(output.indexed_iter().filter(|(_, e)| e.overflowed).map(
+                        |(index, _)| Overflow {
+                            layer: i,
+                            index,
+                            kind: OverflowKind::FieldModulus,
+                        },
+                    ));
+                    Output::from_shape_vec(output.dim(), output.iter().map(|e| e.value).collect())
+                        .expect("shape should be correct")
+                }
+                Layer::NLL(layer) => {
+                    overflows.extend(
+                        input
+                            .indexed_iter()
+                            .filter(|(_, e)| f64::from(**e) >= relu_window)
+                            .map(|(index, _)| Overflow {
+                                layer: i,
+                                index,
+                                kind: OverflowKind::ReluWindow,
+                            }),
+                    );
+                    layer.evaluate(&input)
+                }
+            };
+        }
+        (input, overflows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layers::{LayerDims, NonLinearLayer},
+        tensors::Kernel,
+    };
+    use algebra::fields::near_mersenne_64::F;
+    use std::marker::PhantomData;
+
+    struct TenBitExpParams {}
+    impl FixedPointParameters for TenBitExpParams {
+        type Field = F;
+        const MANTISSA_CAPACITY: u8 = 3;
+        const EXPONENT_CAPACITY: u8 = 8;
+    }
+
+    type TenBitExpFP = FixedPoint<TenBitExpParams>;
+
+    #[test]
+    fn test_evaluate_checked() {
+        // An fc layer computing (x, 2^20 * x), followed by a ReLU
+        let weights = Kernel::from_shape_vec(
+            (2, 1, 1, 1),
+            vec![
+                TenBitExpFP::from(1.0),
+                TenBitExpFP::from(f64::from(1 << 20)),
+            ],
+        )
+        .unwrap();
+        let fc = FullyConnectedParams::new(weights, Kernel::zeros((2, 1, 1, 1)));
+        let relu_dims = LayerDims {
+            input_dims: (1, 2, 1, 1),
+            output_dims: (1, 2, 1, 1),
+        };
+        let network = NeuralNetwork {
+            layers: vec![
+                Layer::LL(LinearLayer::FullyConnected {
+                    dims: LayerDims {
+                        input_dims: (1, 1, 1, 1),
+                        output_dims: (1, 2, 1, 1),
+                    },
+                    params: fc,
+                }),
+                Layer::NLL(NonLinearLayer::ReLU {
+                    dims: relu_dims,
+                    _f: PhantomData,
+                    _c: PhantomData,
+                }),
+            ],
+            ..Default::default()
+        };
+
+        let small = Input::from_elem((1, 1, 1, 1), TenBitExpFP::from(-0.5));
+        let (output, overflows) = network.evaluate_checked(&small);
+        assert!(overflows.is_empty(), "unexpected overflows {:?}", overflows);
+        assert_eq!(output, network.evaluate(&small));
+
+        // 2^20 * 2^20 wraps around the field, while 2^20 exceeds the window
+        let large = Input::from_elem((1, 1, 1, 1), TenBitExpFP::from(f64::from(1 << 20)));
+        let (_, overflows) = network.evaluate_checked(&large);
+        assert!(overflows.contains(&Overflow {
+            layer: 0,
+            index: (0, 1, 0, 0),
+            kind: OverflowKind::FieldModulus,
+        }));
+        assert!(!overflows.contains(&Overflow {
+            layer: 0,
+            index: (0, 0, 0, 0),
+            kind: OverflowKind::FieldModulus,
+        }));
+        assert!(overflows.contains(&Overflow {
+            layer: 1,
+            index: (0, 0, 0, 0),
+            kind: OverflowKind::ReluWindow,
+        }));
+    }
+}
@@ -4,6 +4,7 @@ use ndarray::Array4;
 use num_traits::Zero;
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "torch")]
 use tch::Tensor;
 
 #[macro_use]
@@ -16,6 +17,70 @@ ndarray_impl!(Kernel, Array4, Quadruple);
 
 pub type Output<F> = Input<F>;
 
+impl<F: Clone> Input<F> {
+    /// Splits `self` along the batch dimension into one `Input` per batch item,
+    /// each with a batch size of one
+    pub fn split_batch(&self) -> Vec<Input<F>> {
+        let (_, c, h, w) = self.dim();
+        self.outer_iter()
+            .map(|item| {
+                Input::from_shape_vec((1, c, h, w), item.iter().cloned().collect())
+                    .expect("Shapes should be same")
+            })
+            .collect()
+    }
+
+    /// Concatenates `items` along the batch dimension. Panics if `items` is
+    /// empty or if their other dimensions differ.
+    pub fn stack_batch(items: &[Input<F>]) -> Input<F> {
+        let (_, c, h, w) = items.first().expect("No items to stack").dim();
+        let mut batch_size = 0;
+        for item in items {
+            let (b, item_c, item_h, item_w) = item.dim();
+            assert_eq!((c, h, w), (item_c, item_h, item_w));
+            batch_size += b;
+        }
+        let stacked = items.iter().flat_map(|item| item.iter().cloned()).collect();
+        Input::from_shape_vec((batch_size, c, h, w), stacked).expect("Shapes should be same")
+    }
+
+    /// Splits `self` along the batch dimension into groups of `group_size`
+    /// items (the last group may be smaller), and lays out the items of each
+    /// group one after another along the channel dimension. Each group is an
+    /// `Input` with a batch size of one.
+    pub fn merge_batch(&self, group_size: usize) -> Vec<Input<F>> {
+        let (b, c, h, w) = self.dim();
+        let item_size = c * h * w;
+        let elems: Vec<_> = self.iter().cloned().collect();
+        (0..b)
+            .step_by(group_size)
+            .map(|start| {
+                let items = group_size.min(b - start);
+                let group = elems[start * item_size..(start + items) * item_size].to_vec();
+                Input::from_shape_vec((1, items * c, h, w), group).expect("Shapes should be same")
+            })
+            .collect()
+    }
+
+    /// Inverse of `merge_batch` for items with `channels` channels. Panics if
+    /// the channels of a group are not a multiple of `channels`.
+    pub fn unmerge_batch(groups: &[Input<F>], channels: usize) -> Input<F> {
+        let items: Vec<_> = groups
+            .iter()
+            .map(|group| {
+                let (b, group_c, h, w) = group.dim();
+                assert_eq!(group_c % channels, 0);
+                Input::from_shape_vec(
+                    (b * group_c / channels, channels, h, w),
+                    group.iter().cloned().collect(),
+                )
+                .expect("Shapes should be same")
+            })
+            .collect();
+        Input::stack_batch(&items)
+    }
+}
+
 impl<T: Share> Input<T> {
     pub fn share<R: RngCore + CryptoRng>(
         &self,
@@ -123,6 +188,7 @@ impl<I> Input<I> {
     /// If the underlying elements of `Input` are `FixedPoint` elements having
     /// base field `Fp64` backed by a `u64` (thus allowing lazy reduction),
     /// then we can convert it to an equivalent `nn::Tensor` containing `i64`s.
+    #[cfg(feature = "torch")]
     pub fn to_tensor<P>(&self) -> Tensor
     where
         P: FixedPointParameters,
@@ -148,6 +214,7 @@ impl<I> Input<I> {
         result
     }
 
+    #[cfg(feature = "torch")]
     pub fn from_tensor<P>(tensor: Tensor) -> Option<Self>
     where
         P: FixedPointParameters,
@@ -250,6 +317,7 @@ impl<I> Kernel<I> {
     /// If the underlying elements of `Kernel` are `FixedPoint` elements having
     /// base field `Fp64` backed by a `u64` then we can convert to an equivalent
     /// 'nn::Tensor' containing `i64`s.
+    #[cfg(feature = "torch")]
     pub fn to_tensor<P>(&self) -> Tensor
     where
         P: FixedPointParameters,
@@ -290,6 +358,27 @@ impl<I> Kernel<I> {
     }
 }
 
+impl<I: Copy + Zero> Kernel<I> {
+    /// Returns a kernel which applies `self` to each of `blocks` groups of
+    /// input channels separately, i.e. a block diagonal kernel over the output
+    /// and input channels
+    pub fn block_diagonal(&self, blocks: usize) -> Kernel<I> {
+        let (c_out, c_in, h, w) = self.dim();
+        let mut kernel = Kernel::zeros((blocks * c_out, blocks * c_in, h, w));
+        for block in 0..blocks {
+            kernel
+                .slice_mut(s![
+                    block * c_out..(block + 1) * c_out,
+                    block * c_in..(block + 1) * c_in,
+                    ..,
+                    ..
+                ])
+                .assign(&self.0);
+        }
+        kernel
+    }
+}
+
 impl<P: FixedPointParameters> From<Input<AuthAdditiveShare<P::Field>>>
     for Input<AdditiveShare<FixedPoint<P>>>
 where
@@ -1,11 +1,11 @@
 use crate::*;
-use algebra::{fixed_point::*, fp_64::Fp64Parameters, FpParameters, PrimeField};
+use algebra::PrimeField;
 use crypto_primitives::additive_share::{AuthAdditiveShare, AuthShare};
 use neural_network::{
     layers::{convolution::Padding, LinearLayerInfo},
     tensors::{Input, Output},
 };
-use std::os::raw::c_char;
+use std::{io, os::raw::c_char};
 
 pub struct Conv2D<'a> {
     data: Metadata,
@@ -38,57 +38,75 @@ pub trait ClientACG {
 
     fn preprocess(&mut self, r: &Input<u64>) -> Vec<c_char>;
 
+    /// Decrypts the server's reply to `preprocess`, returning an error if it
+    /// is malformed
     fn decrypt(
         &mut self,
         linear_ct: Vec<c_char>,
         linear_mac_ct: Vec<c_char>,
         r_mac_ct: Vec<c_char>,
-    );
+    ) -> io::Result<()>;
 
-    fn postprocess<P>(
+    fn postprocess<F>(
         &self,
-        linear_auth: &mut Output<AuthAdditiveShare<P::Field>>,
-        r_mac_share: &mut Input<P::Field>,
+        linear_auth: &mut Output<AuthAdditiveShare<F>>,
+        r_mac_share: &mut Input<F>,
     ) where
-        P: FixedPointParameters,
-        <P::Field as PrimeField>::Params: Fp64Parameters,
-        P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
-        P::Field: AuthShare;
+        F: PrimeField + AuthShare;
 }
 
-impl<'a> SealClientACG<'a> {
-    pub fn preprocess(&mut self, r: &Input<u64>) -> Vec<c_char> {
+impl<'a> ClientACG for SealClientACG<'a> {
+    type Keys = &'a ClientFHE;
+
+    fn new<F, C>(
+        cfhe: &'a ClientFHE,
+        layer_info: &LinearLayerInfo<F, C>,
+        input_dims: (usize, usize, usize, usize),
+        output_dims: (usize, usize, usize, usize),
+    ) -> Self {
+        match layer_info {
+            LinearLayerInfo::Conv2d { .. } => {
+                Self::Conv2D(Conv2D::new(cfhe, layer_info, input_dims, output_dims))
+            }
+            LinearLayerInfo::FullyConnected => Self::FullyConnected(FullyConnected::new(
+                cfhe,
+                layer_info,
+                input_dims,
+                output_dims,
+            )),
+            _ => panic!("Incorrect Layer Type"),
+        }
+    }
+
+    fn preprocess(&mut self, r: &Input<u64>) -> Vec<c_char> {
         match self {
             Self::Conv2D(s) => s.preprocess(r),
             Self::FullyConnected(s) => s.preprocess(r),
         }
     }
 
-    pub fn decrypt(
+    fn decrypt(
         &mut self,
         linear_ct: Vec<c_char>,
         linear_mac_ct: Vec<c_char>,
         r_mac_ct: Vec<c_char>,
-    ) {
+    ) -> io::Result<()> {
         match self {
             Self::Conv2D(s) => s.decrypt(linear_ct, linear_mac_ct, r_mac_ct),
             Self::FullyConnected(s) => s.decrypt(linear_ct, linear_mac_ct, r_mac_ct),
-        };
+        }
     }
 
-    pub fn postprocess<P>(
+    fn postprocess<F>(
         &self,
-        linear_auth: &mut Output<AuthAdditiveShare<P::Field>>,
-        r_mac_share: &mut Input<P::Field>,
+        linear_auth: &mut Output<AuthAdditiveShare<F>>,
+        r_mac_share: &mut Input<F>,
     ) where
-        P: FixedPointParameters,
-        <P::Field as PrimeField>::Params: Fp64Parameters,
-        P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
-        P::Field: AuthShare,
+        F: PrimeField + AuthShare,
     {
         match self {
-            Self::Conv2D(s) => ClientACG::postprocess::<P>(s, linear_auth, r_mac_share),
-            Self::FullyConnected(s) => ClientACG::postprocess::<P>(s, linear_auth, r_mac_share),
+            Self::Conv2D(s) => ClientACG::postprocess::<F>(s, linear_auth, r_mac_share),
+            Self::FullyConnected(s) => ClientACG::postprocess::<F>(s, linear_auth, r_mac_share),
         };
     }
 }
@@ -156,7 +174,7 @@ impl<'a> ClientACG for Conv2D<'a> {
         mut linear_ct: Vec<c_char>,
         mut linear_mac_ct: Vec<c_char>,
         mut r_mac_ct: Vec<c_char>,
-    ) {
+    ) -> io::Result<()> {
         let mut shares = self.shares.unwrap();
         // Copy the received ciphertexts into share struct
         shares.linear_ct = SerialCT {
@@ -174,17 +192,15 @@ impl<'a> ClientACG for Conv2D<'a> {
         // Decrypt everything
         unsafe { client_conv_decrypt(self.cfhe, &self.data, &mut shares) };
         self.shares = Some(shares);
+        Ok(())
     }
 
-    fn postprocess<P>(
+    fn postprocess<F>(
         &self,
-        linear_auth: &mut Output<AuthAdditiveShare<P::Field>>,
-        r_mac_share: &mut Input<P::Field>,
+        linear_auth: &mut Output<AuthAdditiveShare<F>>,
+        r_mac_share: &mut Input<F>,
     ) where
-        P: FixedPointParameters,
-        <P::Field as PrimeField>::Params: Fp64Parameters,
-        P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
-        P::Field: AuthShare,
+        F: PrimeField + AuthShare,
     {
         let shares = self.shares.unwrap();
         for chan in 0..self.data.out_chans as usize {
@@ -197,8 +213,8 @@ impl<'a> ClientACG for Conv2D<'a> {
                         *(*(shares.linear_mac.offset(chan as isize))).offset(idx as isize)
                     };
                     linear_auth[[0, chan, row, col]] = AuthAdditiveShare::new(
-                        P::Field::from_repr(linear_val.into()),
-                        P::Field::from_repr(linear_mac_val.into()),
+                        F::from_repr(linear_val.into()),
+                        F::from_repr(linear_mac_val.into()),
                     );
                 }
             }
@@ -209,7 +225,7 @@ impl<'a> ClientACG for Conv2D<'a> {
                     let idx = (row * (self.data.image_w as usize) + col) as isize;
                     let r_mac_val =
                         unsafe { *(*(shares.r_mac.offset(chan as isize))).offset(idx as isize) };
-                    r_mac_share[[0, chan, row, col]] = P::Field::from_repr(r_mac_val.into());
+                    r_mac_share[[0, chan, row, col]] = F::from_repr(r_mac_val.into());
                 }
             }
         }
@@ -260,7 +276,7 @@ impl<'a> ClientACG for FullyConnected<'a> {
         mut linear_ct: Vec<c_char>,
         mut linear_mac_ct: Vec<c_char>,
         mut r_mac_ct: Vec<c_char>,
-    ) {
+    ) -> io::Result<()> {
         let mut shares = self.shares.unwrap();
         // Copy the received ciphertexts into share struct
         shares.linear_ct = SerialCT {
@@ -278,30 +294,28 @@ impl<'a> ClientACG for FullyConnected<'a> {
         // Decrypt everything
         unsafe { client_fc_decrypt(self.cfhe, &self.data, &mut shares) };
         self.shares = Some(shares);
+        Ok(())
     }
 
-    fn postprocess<P>(
+    fn postprocess<F>(
         &self,
-        linear_auth: &mut Output<AuthAdditiveShare<P::Field>>,
-        r_mac_share: &mut Input<P::Field>,
+        linear_auth: &mut Output<AuthAdditiveShare<F>>,
+        r_mac_share: &mut Input<F>,
     ) where
-        P: FixedPointParameters,
-        <P::Field as PrimeField>::Params: Fp64Parameters,
-        P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
-        P::Field: AuthShare,
+        F: PrimeField + AuthShare,
     {
         let shares = self.shares.unwrap();
         for row in 0..self.data.filter_h as usize {
             let linear_val = unsafe { *(*(shares.linear.offset(0))).offset(row as isize) };
             let linear_mac_val = unsafe { *(*(shares.linear_mac.offset(0))).offset(row as isize) };
             linear_auth[[0, row, 0, 0]] = AuthAdditiveShare::new(
-                P::Field::from_repr(linear_val.into()),
-                P::Field::from_repr(linear_mac_val.into()),
+                F::from_repr(linear_val.into()),
+                F::from_repr(linear_mac_val.into()),
             );
         }
         r_mac_share.iter_mut().enumerate().for_each(|(col, e)| {
             let r_mac_val = unsafe { *(*(shares.r_mac.offset(0))).offset(col as isize) };
-            *e = P::Field::from_repr(r_mac_val.into());
+            *e = F::from_repr(r_mac_val.into());
         });
     }
 }
@@ -47,6 +47,13 @@ pub trait ClientGen {
         r: &mut [Self::MsgType],
         r_mac: &mut [Self::MsgType],
     ) -> (Vec<u64>, Vec<u64>);
+
+    /// Preprocess an input to be authenticated by the server
+    fn auth_preprocess(&self, input: &[u64]) -> Vec<Self::MsgType>;
+
+    /// Postprocess server's response and return MAC shares of the `len`
+    /// inputs
+    fn auth_postprocess(&self, mac: Vec<Self::MsgType>, len: usize) -> Vec<u64>;
 }
 
 /// SEAL implementation of ClientGen
@@ -186,4 +193,12 @@ impl<'a> ClientGen for SealClientGen<'a> {
         unsafe { client_rand_free(shares) };
         result
     }
+
+    fn auth_preprocess(&self, input: &[u64]) -> Vec<c_char> {
+        SealCT::new().encrypt_vec(self.cfhe, input.to_vec())
+    }
+
+    fn auth_postprocess(&self, mac: Vec<c_char>, len: usize) -> Vec<u64> {
+        SealCT::new().decrypt_vec(self.cfhe, mac, len)
+    }
 }
@@ -3,7 +3,7 @@ use neural_network::{
     layers::{convolution::Padding, LinearLayer},
     tensors::{Input, Kernel, Output},
 };
-use std::os::raw::c_char;
+use std::{io, os::raw::c_char};
 
 pub struct Conv2D<'a> {
     data: Metadata,
@@ -38,11 +38,29 @@ pub trait ServerACG {
         mac_key_b: u64,
     );
 
-    fn process(&mut self, client_share: Vec<c_char>) -> (Vec<c_char>, Vec<c_char>, Vec<c_char>);
+    /// Returns the ciphertexts of the client's linear share, its MAC share and
+    /// the MAC share of its randomizer, or an error if `client_share` is
+    /// malformed
+    fn process(
+        &mut self,
+        client_share: Vec<c_char>,
+    ) -> io::Result<(Vec<c_char>, Vec<c_char>, Vec<c_char>)>;
 }
 
-impl<'a> SealServerACG<'a> {
-    pub fn preprocess(
+impl<'a> ServerACG for SealServerACG<'a> {
+    type Keys = &'a ServerFHE;
+
+    fn new<F, C>(sfhe: &'a ServerFHE, layer: &LinearLayer<F, C>, kernel: &Kernel<u64>) -> Self {
+        match layer {
+            LinearLayer::Conv2d { .. } => Self::Conv2D(Conv2D::new(sfhe, layer, kernel)),
+            LinearLayer::FullyConnected { .. } => {
+                Self::FullyConnected(FullyConnected::new(sfhe, layer, kernel))
+            }
+            _ => panic!("Incorrect Layer"),
+        }
+    }
+
+    fn preprocess(
         &mut self,
         linear_share: &Output<u64>,
         linear_mac_share: &Output<u64>,
@@ -68,10 +86,10 @@ impl<'a> SealServerACG<'a> {
         }
     }
 
-    pub fn process(
+    fn process(
         &mut self,
         client_share: Vec<c_char>,
-    ) -> (Vec<c_char>, Vec<c_char>, Vec<c_char>) {
+    ) -> io::Result<(Vec<c_char>, Vec<c_char>, Vec<c_char>)> {
         match self {
             Self::Conv2D(s) => s.process(client_share),
             Self::FullyConnected(s) => s.process(client_share),
@@ -181,7 +199,7 @@ impl<'a> ServerACG for Conv2D<'a> {
     fn process(
         &mut self,
         mut client_share: Vec<c_char>,
-    ) -> (Vec<c_char>, Vec<c_char>, Vec<c_char>) {
+    ) -> io::Result<(Vec<c_char>, Vec<c_char>, Vec<c_char>)> {
         let mut shares = self.shares.unwrap();
         let client_share_ct = SerialCT {
             inner: client_share.as_mut_ptr(),
@@ -213,7 +231,7 @@ impl<'a> ServerACG for Conv2D<'a> {
             std::slice::from_raw_parts(shares.r_mac_ct.inner, shares.r_mac_ct.size as usize)
                 .to_vec()
         };
-        (linear_ct_vec, linear_mac_ct_vec, r_mac_ct_vec)
+        Ok((linear_ct_vec, linear_mac_ct_vec, r_mac_ct_vec))
     }
 }
 
@@ -288,7 +306,7 @@ impl<'a> ServerACG for FullyConnected<'a> {
     fn process(
         &mut self,
         mut client_share: Vec<c_char>,
-    ) -> (Vec<c_char>, Vec<c_char>, Vec<c_char>) {
+    ) -> io::Result<(Vec<c_char>, Vec<c_char>, Vec<c_char>)> {
         let mut shares = self.shares.unwrap();
         let client_share_ct = SerialCT {
             inner: client_share.as_mut_ptr(),
@@ -320,7 +338,7 @@ impl<'a> ServerACG for FullyConnected<'a> {
             std::slice::from_raw_parts(shares.r_mac_ct.inner, shares.r_mac_ct.size as usize)
                 .to_vec()
         };
-        (linear_ct_vec, linear_mac_ct_vec, r_mac_ct_vec)
+        Ok((linear_ct_vec, linear_mac_ct_vec, r_mac_ct_vec))
     }
 }
 
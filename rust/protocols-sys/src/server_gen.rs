@@ -50,6 +50,10 @@ pub trait ServerGen {
         shares: &mut ServerTriples,
         r: &mut [Self::MsgType],
     ) -> (Vec<Self::MsgType>, Vec<Self::MsgType>);
+
+    /// Process client's input and return the client's MAC share of it, given
+    /// the server's MAC share
+    fn auth_online(&self, input: &mut [Self::MsgType], mac_share: &[u64]) -> Vec<Self::MsgType>;
 }
 
 /// SEAL implementation of ClientGen
@@ -169,4 +173,11 @@ impl<'a> ServerGen for SealServerGen<'a> {
         unsafe { server_rand_free(shares) };
         result
     }
+
+    fn auth_online(&self, input: &mut [Self::MsgType], mac_share: &[u64]) -> Vec<Self::MsgType> {
+        let mut share = SealCT::new();
+        share.inner.inner = input.as_mut_ptr();
+        share.inner.size = input.len() as u64;
+        share.gen_mac_share(self.sfhe, mac_share.to_vec(), self.mac_key)
+    }
 }
@@ -115,10 +115,12 @@ fn interface<R: Rng + rand::CryptoRng>(
     );
 
     // Server receive ciphertext and compute convolution
-    let (linear_ct, linear_mac_ct, r_mac_ct) = server_acg.process(input_ct_vec);
+    let (linear_ct, linear_mac_ct, r_mac_ct) = server_acg.process(input_ct_vec).unwrap();
 
     // Client receives ciphertexts
-    client_acg.decrypt(linear_ct, linear_mac_ct, r_mac_ct);
+    client_acg
+        .decrypt(linear_ct, linear_mac_ct, r_mac_ct)
+        .unwrap();
 
     let mut linear: Output<TenBitAS> = Output::zeros(output_dims);
     let mut linear_mac: Output<TenBitAS> = Output::zeros(output_dims);
@@ -129,7 +131,7 @@ fn interface<R: Rng + rand::CryptoRng>(
     let mut linear_auth =
         Output::auth_share_from_parts(Output::zeros(output_dims), Output::zeros(output_dims));
     let mut r_auth = Input::zeros(input_dims);
-    client_acg.postprocess::<TenBitExpParams>(&mut linear_auth, &mut r_auth);
+    client_acg.postprocess::<F>(&mut linear_auth, &mut r_auth);
 
     izip!(linear.iter_mut(), linear_mac.iter_mut(), linear_auth.iter()).for_each(|(s, m, a)| {
         *s = FixedPoint::with_num_muls(a.get_value().inner, 1).into();
@@ -11,6 +11,7 @@ where
     T: serde::Serialize + ?Sized,
 {
     let bytes: Vec<u8> = bincode::serialize(value)?;
+    crate::comm::record_written(bytes.len());
     w.write(&bytes).await?;
     w.flush().await.map_err(|_| {
         Box::new(bincode::ErrorKind::Custom(
@@ -26,6 +27,7 @@ where
     T: serde::de::DeserializeOwned,
 {
     let bytes = reader.read().await?;
+    crate::comm::record_read(bytes.len());
     bincode::deserialize(&bytes[..])
 }
 
@@ -12,8 +12,7 @@ use crypto_primitives::{
 };
 use io_utils::imux::IMuxAsync;
 use itertools::{interleave, izip};
-use num_traits::{One, Zero};
-use protocols_sys::{ClientFHE, ServerFHE};
+use num_traits::Zero;
 use rand::{CryptoRng, RngCore};
 use rayon::prelude::*;
 use scuttlebutt::Block;
@@ -64,25 +63,24 @@ where
     >,
     P::Field: AuthShare,
 {
-    /// Returns the number of rand pairs and triples needed for the CDS circuit
-    fn num_rands_triples(
+    /// Returns the number of rand pairs, triples, and square pairs needed for
+    /// the CDS circuit
+    fn num_rands_triples_squares(
         num_layers: usize,
         total_size: usize,
         modulus_bits: usize,
         elems_per_label: usize,
-    ) -> (usize, usize) {
+    ) -> (usize, usize, usize) {
         let rands = 2
             * (num_layers
                 + 2 * total_size
                 + total_size * modulus_bits
                 + 2 * (modulus_bits * total_size * elems_per_label));
-        let triples = 2
-            * (
-                total_size * modulus_bits   // Check that Client's input is bits
-            + modulus_bits * total_size * elems_per_label
-                // Share GC labels
-            );
-        (rands, triples)
+        // Share GC labels
+        let triples = 2 * modulus_bits * total_size * elems_per_label;
+        // Check that Client's input is bits
+        let squares = 2 * total_size * modulus_bits;
+        (rands, triples, squares)
     }
 
     /// Embed a GC label as a vector of field elements
@@ -199,10 +197,16 @@ where
         Ok((label_shares, rho_1, rho_2))
     }
 
-    pub fn server_cds<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: CryptoRng + RngCore>(
+    /// Runs CDS with triples, squares, and rands generated by `G`
+    pub fn server_cds<
+        G: ServerOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: CryptoRng + RngCore,
+    >(
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
-        sfhe: &ServerFHE,
+        gen_keys: G::Keys,
         layer_sizes: &[usize],
         out_mac_keys: &[P::Field],
         out_mac_shares: &[P::Field],
@@ -215,7 +219,7 @@ where
         let modulus_bits = <P::Field as PrimeField>::size_in_bits();
         let total_size = out_mac_shares.len();
         let elems_per_label = (128.0 / (modulus_bits - 1) as f64).ceil() as usize;
-        let (num_rands, num_triples) = CDSProtocol::<P>::num_rands_triples(
+        let (num_rands, num_triples, num_squares) = CDSProtocol::<P>::num_rands_triples_squares(
             layer_sizes.len(),
             total_size,
             modulus_bits,
@@ -232,19 +236,49 @@ where
             })
             .unzip();
 
-        // Generate rands and triples
+        // Generate rands, triples, and squares under the server's share of
+        // the MAC key
         let mac_key = P::Field::uniform(rng);
-        //let gen = InsecureServerOfflineMPC::new(&sfhe, mac_key.into_repr().0);
-        let gen = ServerOfflineMPC::new(&sfhe, mac_key.into_repr().0);
-        let rands = gen.rands_gen(reader, writer, rng, num_rands);
-        let triples = gen.triples_gen(reader, writer, rng, num_triples);
-        let mut mpc = ServerMPC::new(rands, triples, mac_key);
+        let gen = G::from_keys(gen_keys, mac_key.into_repr().0);
+        let mut rands = gen.rands_gen(reader, writer, rng, num_rands)?;
+        let mut triples = gen.triples_gen(reader, writer, rng, num_triples)?;
+        let mut squares = gen.squares_gen(reader, writer, rng, num_squares)?;
 
-        // Share inputs
+        // Share inputs, which the client holds zero shares of, and
+        // authenticate everything under the MAC key shared with the client
         // TODO: Trim amount of randomness generated
         let share_time = timer_start!(|| "Server sharing inputs");
-        let zero_labels = mpc.private_inputs(reader, writer, zero_labels.as_slice(), rng)?;
-        let one_labels = mpc.private_inputs(reader, writer, one_labels.as_slice(), rng)?;
+        let (mut zero_labels, mut one_labels): (Vec<_>, Vec<_>) = izip!(zero_labels, one_labels)
+            .map(|(l0, l1)| {
+                (
+                    AuthAdditiveShare::new(l0, mac_key * l0),
+                    AuthAdditiveShare::new(l1, mac_key * l1),
+                )
+            })
+            .unzip();
+        server_share_mac_key(
+            &gen,
+            reader,
+            writer,
+            rng,
+            rands
+                .iter_mut()
+                .chain(
+                    triples
+                        .iter_mut()
+                        .flat_map(|t| vec![&mut t.a, &mut t.b, &mut t.c]),
+                )
+                .chain(
+                    squares
+                        .iter_mut()
+                        .flat_map(|s| vec![&mut s.a, &mut s.a_squared]),
+                )
+                .chain(zero_labels.iter_mut())
+                .chain(one_labels.iter_mut()),
+        )?;
+        let mut mpc = ServerMPC::new(rands, triples, mac_key)
+            .with_squares(squares)
+            .with_mac_key_share(Vec::new());
         timer_end!(share_time);
 
         // Receive client shares
@@ -254,24 +288,8 @@ where
         timer_end!(recv_time);
 
         // Check that inputs are bits
-        // TODO: Could do some sort of linear combo here if opening is expensive
-        let one_minus_out_bits = out_bits
-            .iter()
-            .map(|b| b.sub_constant(P::Field::one()))
-            .collect::<Vec<_>>();
-        let one_minus_inp_bits = inp_bits
-            .iter()
-            .map(|b| b.sub_constant(P::Field::one()))
-            .collect::<Vec<_>>();
-        // Multiply
-        let out_are_bits = mpc.mul(reader, writer, &out_bits, &one_minus_out_bits)?;
-        let inp_are_bits = mpc.mul(reader, writer, &inp_bits, &one_minus_inp_bits)?;
-        // Receive opening
-        let out_are_bits = mpc.private_recv(reader, &out_are_bits)?;
-        let inp_are_bits = mpc.private_recv(reader, &inp_are_bits)?;
-        if !(out_are_bits.iter().all(|e| e.is_zero()) && inp_are_bits.iter().all(|e| e.is_zero())) {
-            return Err(MpcError::NotBits);
-        }
+        mpc.check_bits(reader, writer, &out_bits)?;
+        mpc.check_bits(reader, writer, &inp_bits)?;
 
         let cds_time = timer_start!(|| "CDS Protocol");
         let mut processed = 0;
@@ -322,9 +340,9 @@ where
             });
             timer_end!(comb_time);
 
-            // Receive opening of rho_1, rho_2
-            let recv_time = timer_start!(|| "Server receiving rho");
-            let rho_open = mpc.private_recv(reader, &[rho_1, rho_2])?;
+            // Open rho_1, rho_2
+            let recv_time = timer_start!(|| "Server opening rho");
+            let rho_open = mpc.commit_open(reader, writer, &[rho_1, rho_2], rng)?;
             timer_end!(recv_time);
 
             // Receive omega_1, omega_2
@@ -336,13 +354,14 @@ where
             let client_sigma_2 = msg[1];
             timer_end!(recv_time);
 
-            // Check relations
+            // Check relations and the MACs of every opened value
             let result_1 = out_mac_keys[i] * rho_open[0] - (client_sigma_1 + server_sigma_1);
             let result_2 = inp_mac_keys[i] * rho_open[1] - (client_sigma_2 + server_sigma_2);
+            let macs_valid = mpc.mac_check(reader, writer, rng).is_ok();
 
             // Send label shares if shares are zero and all MACs are correct
             let send_time = timer_start!(|| "Server sending label shares");
-            if result_1.is_zero() && result_2.is_zero() && mpc.check_macs().is_ok() {
+            if result_1.is_zero() && result_2.is_zero() && macs_valid {
                 mpc.private_open(writer, label_shares.as_slice())?;
             } else {
                 return Err(MpcError::InvalidMAC);
@@ -358,10 +377,16 @@ where
         Ok(())
     }
 
-    pub fn client_cds<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: CryptoRng + RngCore>(
+    /// Runs CDS with triples, squares, and rands generated by `G`
+    pub fn client_cds<
+        G: ClientOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: CryptoRng + RngCore,
+    >(
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
-        cfhe: &ClientFHE,
+        gen_keys: G::Keys,
         layer_sizes: &[usize],
         out_mac_shares: &[P::Field],
         out_shares: &[P::Field],
@@ -373,7 +398,7 @@ where
         let modulus_bits = <P::Field as PrimeField>::size_in_bits();
         let total_size = out_mac_shares.len();
         let elems_per_label = (128.0 / (modulus_bits - 1) as f64).ceil() as usize;
-        let (num_rands, num_triples) = CDSProtocol::<P>::num_rands_triples(
+        let (num_rands, num_triples, num_squares) = CDSProtocol::<P>::num_rands_triples_squares(
             layer_sizes.len(),
             out_mac_shares.len(),
             modulus_bits,
@@ -399,25 +424,43 @@ where
             .map(|b| P::Field::from_repr((b as u64).into()))
             .collect();
 
-        // Generate rands and triples
-        //let gen = InsecureClientOfflineMPC::new(&cfhe);
-        let gen = ClientOfflineMPC::new(&cfhe);
-        let rands = gen.rands_gen(reader, writer, rng, num_rands);
-        let triples = gen.triples_gen(reader, writer, rng, num_triples);
-        let mut mpc = ClientMPC::new(rands, triples);
+        // Generate rands, triples, and squares
+        let gen = G::from_keys(gen_keys);
+        let mut rands = gen.rands_gen(reader, writer, rng, num_rands)?;
+        let mut triples = gen.triples_gen(reader, writer, rng, num_triples)?;
+        let mut squares = gen.squares_gen(reader, writer, rng, num_squares)?;
 
-        // Receive server inputs
+        // Receive server inputs, which the client holds zero shares of, and
+        // authenticate everything under the MAC key shared with the server
         let recv_time = timer_start!(|| "Client receiving inputs");
-        let zero_labels = mpc.recv_private_inputs(
-            reader,
-            writer,
-            2 * total_size * modulus_bits * elems_per_label,
-        )?;
-        let one_labels = mpc.recv_private_inputs(
+        let mac_key_share = P::Field::uniform(rng);
+        let mut zero_labels =
+            vec![AuthAdditiveShare::zero(); 2 * total_size * modulus_bits * elems_per_label];
+        let mut one_labels = zero_labels.clone();
+        client_share_mac_key(
+            &gen,
             reader,
             writer,
-            2 * total_size * modulus_bits * elems_per_label,
+            rng,
+            mac_key_share,
+            rands
+                .iter_mut()
+                .chain(
+                    triples
+                        .iter_mut()
+                        .flat_map(|t| vec![&mut t.a, &mut t.b, &mut t.c]),
+                )
+                .chain(
+                    squares
+                        .iter_mut()
+                        .flat_map(|s| vec![&mut s.a, &mut s.a_squared]),
+                )
+                .chain(zero_labels.iter_mut())
+                .chain(one_labels.iter_mut()),
         )?;
+        let mut mpc = ClientMPC::new(rands, triples)
+            .with_squares(squares)
+            .with_mac_key_share(mac_key_share, Vec::new());
         timer_end!(recv_time);
 
         // Share inputs
@@ -427,15 +470,8 @@ where
         timer_end!(send_time);
 
         // Check that inputs are bits
-        // Currently for constants, only one party does it
-        let one_minus_out_bits = out_bits.clone();
-        let one_minus_inp_bits = inp_bits.clone();
-        // Multiply
-        let out_are_bits = mpc.mul(reader, writer, &out_bits, &one_minus_out_bits)?;
-        let inp_are_bits = mpc.mul(reader, writer, &inp_bits, &one_minus_inp_bits)?;
-        // Send opening
-        mpc.private_open(writer, &out_are_bits)?;
-        mpc.private_open(writer, &inp_are_bits)?;
+        mpc.check_bits(reader, writer, &out_bits)?;
+        mpc.check_bits(reader, writer, &inp_bits)?;
 
         // TODO: Parallelize this
         let cds_time = timer_start!(|| "CDS Protocol");
@@ -488,9 +524,9 @@ where
             });
             timer_end!(comb_time);
 
-            // Send opening of rho_1, rho_2
+            // Open rho_1, rho_2
             let open_time = timer_start!(|| "Client opening rho");
-            mpc.private_open(writer, &[rho_1, rho_2])?;
+            mpc.commit_open(reader, writer, &[rho_1, rho_2], rng)?;
             timer_end!(open_time);
 
             // Send sigma_1, sigma_2
@@ -500,6 +536,9 @@ where
             bytes::serialize(&mut *writer, &send_message)?;
             timer_end!(open_time);
 
+            // Check the MACs of every opened value
+            mpc.mac_check(reader, writer, rng)?;
+
             // Receive label shares
             let recv_time = timer_start!(|| "Client receiving label shares");
             let label_elems = mpc
@@ -531,7 +570,6 @@ where
     >(
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
-        _sfhe: &ServerFHE,
         layer_sizes: &[usize],
         output_mac_keys: &[P::Field],
         output_mac_shares: &[P::Field],
@@ -623,7 +661,6 @@ where
     >(
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
-        _cfhe: &ClientFHE,
         _layer_sizes: &[usize],
         output_mac_shares: &[P::Field],
         output_shares: &[P::Field],
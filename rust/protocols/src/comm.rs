@@ -0,0 +1,249 @@
+//! Communication accounting broken down by protocol phase.
+//!
+//! Every message sent or received through `crate::bytes` is attributed to the
+//! innermost phase entered with `phase` on the current thread. Phases nest and
+//! are identified by their `/`-separated path, e.g. `Offline/ReLU/CDS/Triples`.
+//! Protocols which perform IO from worker threads must pass the phases of the
+//! spawning thread along with `scope`.
+//!
+//! Counts are global to the process, so running both parties in one process
+//! merges their counts.
+//!
+//! Each phase is also recorded as a span carrying its byte counts in the
+//! structured trace of `bench_utils`, if enabled.
+
+use std::{cell::RefCell, fmt::Write, sync::Mutex, time::Instant};
+
+lazy_static! {
+    static ref COUNTS: Mutex<Vec<(String, Count)>> = Mutex::new(Vec::new());
+}
+
+thread_local! {
+    static STACK: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Path used for messages sent or received outside of any phase
+pub const UNATTRIBUTED: &str = "Unattributed";
+
+/// Bytes and messages exchanged during a phase
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Count {
+    pub bytes_read: usize,
+    pub bytes_written: usize,
+    pub messages_read: usize,
+    pub messages_written: usize,
+}
+
+/// Guard for an active phase. The phase ends when the guard is dropped.
+#[must_use = "the phase ends as soon as the guard is dropped"]
+pub struct Phase {
+    depth: usize,
+    path: String,
+    start: Instant,
+    count_at_start: Count,
+}
+
+/// Enters the phase `name`, nested inside the currently active phase
+pub fn phase<S: Into<String>>(name: S) -> Phase {
+    let (depth, path) = STACK.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        stack.push(name.into());
+        (stack.len(), stack.join("/"))
+    });
+    // Counts at the start are only needed for the trace
+    let count_at_start = if trace_enabled!() {
+        subtree_count(&path)
+    } else {
+        Count::default()
+    };
+    Phase {
+        depth,
+        count_at_start,
+        path,
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        // Also ends any inner phases which were leaked
+        STACK.with(|stack| stack.borrow_mut().truncate(self.depth - 1));
+        if !trace_enabled!() {
+            return;
+        }
+        let end = subtree_count(&self.path);
+        let start = &self.count_at_start;
+        add_span_to_trace!(|| &self.path, self.start, || [
+            (
+                "bytes_read",
+                end.bytes_read.saturating_sub(start.bytes_read) as u64
+            ),
+            (
+                "bytes_written",
+                end.bytes_written.saturating_sub(start.bytes_written) as u64
+            ),
+        ]);
+    }
+}
+
+/// Sums the counts of the phase at `path` and of all phases nested inside it
+fn subtree_count(path: &str) -> Count {
+    let prefix = format!("{}/", path);
+    let mut total = Count::default();
+    for (_, count) in COUNTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(p, _)| p == path || p.starts_with(&prefix))
+    {
+        add(&mut total, count);
+    }
+    total
+}
+
+fn add(total: &mut Count, count: &Count) {
+    total.bytes_read += count.bytes_read;
+    total.bytes_written += count.bytes_written;
+    total.messages_read += count.messages_read;
+    total.messages_written += count.messages_written;
+}
+
+/// The active phases of a thread
+#[derive(Clone, Debug)]
+pub struct Scope(Vec<String>);
+
+/// Guard for phases entered through `Scope::enter`. The previously active
+/// phases are restored when the guard is dropped.
+#[must_use = "the scope ends as soon as the guard is dropped"]
+pub struct ScopeGuard {
+    previous: Vec<String>,
+}
+
+/// Returns the active phases of the current thread
+pub fn scope() -> Scope {
+    STACK.with(|stack| Scope(stack.borrow().clone()))
+}
+
+impl Scope {
+    /// Makes these phases active on the current thread
+    pub fn enter(&self) -> ScopeGuard {
+        let previous = STACK.with(|stack| stack.replace(self.0.clone()));
+        ScopeGuard { previous }
+    }
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        let previous = std::mem::replace(&mut self.previous, Vec::new());
+        STACK.with(|stack| stack.replace(previous));
+    }
+}
+
+fn record(f: impl FnOnce(&mut Count)) {
+    let path = STACK.with(|stack| {
+        let stack = stack.borrow();
+        if stack.is_empty() {
+            UNATTRIBUTED.to_string()
+        } else {
+            stack.join("/")
+        }
+    });
+    let mut counts = COUNTS.lock().unwrap();
+    match counts.iter_mut().find(|(p, _)| *p == path) {
+        Some((_, count)) => f(count),
+        None => {
+            let mut count = Count::default();
+            f(&mut count);
+            counts.push((path, count));
+        }
+    }
+}
+
+pub(crate) fn record_read(len: usize) {
+    record(|count| {
+        count.bytes_read += len;
+        count.messages_read += 1;
+    });
+}
+
+pub(crate) fn record_written(len: usize) {
+    record(|count| {
+        count.bytes_written += len;
+        count.messages_written += 1;
+    });
+}
+
+/// Returns the counts of every phase seen since the last `reset`, in the
+/// order they were first seen
+pub fn counts() -> Vec<(String, Count)> {
+    COUNTS.lock().unwrap().clone()
+}
+
+/// Clears all counts
+pub fn reset() {
+    COUNTS.lock().unwrap().clear();
+}
+
+/// Formats the counts of every phase, one per line, followed by the total
+pub fn report() -> String {
+    let counts = counts();
+    let mut total = Count::default();
+    let mut report = String::new();
+    for (path, count) in &counts {
+        add(&mut total, count);
+        writeln!(report, "{}: {}", path, format_count(count)).unwrap();
+    }
+    write!(report, "Total: {}", format_count(&total)).unwrap();
+    report
+}
+
+fn format_count(count: &Count) -> String {
+    format!(
+        "read {} bytes in {} messages, wrote {} bytes in {} messages",
+        count.bytes_read, count.messages_read, count.bytes_written, count.messages_written
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bytes::{deserialize, serialize},
+        transport::{memory, LinkConfig},
+    };
+
+    #[test]
+    fn test_phase_accounting() {
+        let ((mut client_reader, _), (_, mut server_writer)) =
+            memory::connect(1, LinkConfig::default());
+        // Phase names are unique to this test since the counts are global
+        {
+            let _outer = phase("Accounting test");
+            {
+                let _inner = phase("Inner");
+                serialize(&mut server_writer, &[0u8; 100][..]).unwrap();
+            }
+            let scope = scope();
+            crossbeam::scope(|s| {
+                s.spawn(|_| {
+                    let _scope = scope.enter();
+                    serialize(&mut server_writer, &[0u8; 10][..]).unwrap();
+                });
+            })
+            .unwrap();
+            let _: Vec<u8> = deserialize(&mut client_reader).unwrap();
+            let _: Vec<u8> = deserialize(&mut client_reader).unwrap();
+        }
+        let counts = counts();
+        let get = |path: &str| counts.iter().find(|(p, _)| p == path).unwrap().1;
+
+        // bincode prefixes slices with a u64 length
+        let inner = get("Accounting test/Inner");
+        assert_eq!(inner.bytes_written, 108);
+        assert_eq!(inner.messages_written, 1);
+        let outer = get("Accounting test");
+        assert_eq!(outer.bytes_written, 18);
+        assert_eq!(outer.bytes_read, 126);
+        assert_eq!(outer.messages_read, 2);
+    }
+}
@@ -24,12 +24,41 @@ pub enum MpcError {
         /// Number rands needed
         needed: usize,
     },
+    /// Insufficient number of square pairs for requested operation
+    InsufficientSquares {
+        /// Number square pairs available
+        num: usize,
+        /// Number square pairs needed
+        needed: usize,
+    },
+    /// Insufficient number of input masks for requested operation
+    InsufficientInputMasks {
+        /// Number input masks available
+        num: usize,
+        /// Number input masks needed
+        needed: usize,
+    },
     /// A communication error occured
     CommunicationError(String),
     /// Attempted to open share with invalid MAC
     InvalidMAC,
+    /// Opened value did not match its commitment
+    InvalidCommitment,
     /// Committed values were not bits
     NotBits,
+    /// A generated triple or square pair failed its consistency check
+    InvalidTriple,
+    /// The operation needs the whole MAC key, which is shared with the client
+    MacKeyShared,
+    /// A number of bits was outside the range supported by an operation
+    InvalidBitLength {
+        /// Requested number of bits
+        bits: usize,
+        /// Smallest supported number of bits
+        min: usize,
+        /// Largest supported number of bits
+        max: usize,
+    },
 }
 
 impl From<crypto_primitives::additive_share::AuthError> for MpcError {
@@ -44,6 +73,12 @@ impl From<bincode::Error> for MpcError {
     }
 }
 
+impl From<ocelot::Error> for MpcError {
+    fn from(e: ocelot::Error) -> Self {
+        MpcError::CommunicationError(e.to_string())
+    }
+}
+
 impl From<std::io::Error> for MpcError {
     fn from(e: std::io::Error) -> Self {
         MpcError::CommunicationError(e.to_string())
@@ -62,9 +97,23 @@ impl Display for MpcError {
             MpcError::InsufficientRand { num, needed } => {
                 write!(f, "InsufficientTriples: Had {} needed {}", num, needed)
             }
+            MpcError::InsufficientSquares { num, needed } => {
+                write!(f, "InsufficientSquares: Had {} needed {}", num, needed)
+            }
+            MpcError::InsufficientInputMasks { num, needed } => {
+                write!(f, "InsufficientInputMasks: Had {} needed {}", num, needed)
+            }
             MpcError::CommunicationError(s) => write!(f, "Communication error: {}", s),
             MpcError::InvalidMAC => "Attempted to open share with an invalid MAC".fmt(f),
+            MpcError::InvalidCommitment => "Opened value did not match its commitment".fmt(f),
             MpcError::NotBits => "Committed values were not bits".fmt(f),
+            MpcError::InvalidTriple => "Generated triple failed its consistency check".fmt(f),
+            MpcError::MacKeyShared => "The MAC key is shared with the client".fmt(f),
+            MpcError::InvalidBitLength { bits, min, max } => write!(
+                f,
+                "InvalidBitLength: Had {} expected between {} and {}",
+                bits, min, max
+            ),
         }
     }
 }
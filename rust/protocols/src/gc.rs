@@ -1,4 +1,9 @@
-use crate::{bytes, cds, error::MpcError, AdditiveShare, InMessage, OutMessage};
+use crate::{
+    bytes, cds, comm,
+    error::MpcError,
+    mpc_offline::{ClientOfflineGen, ServerOfflineGen},
+    AdditiveShare, InMessage, OutMessage,
+};
 use algebra::{
     fields::PrimeField,
     fixed_point::{FixedPoint, FixedPointParameters},
@@ -17,7 +22,6 @@ use crypto_primitives::{
 };
 use io_utils::imux::IMuxAsync;
 use itertools::interleave;
-use protocols_sys::{ClientFHE, ServerFHE};
 use rand::{CryptoRng, RngCore};
 use rayon::prelude::*;
 use scuttlebutt::Block;
@@ -97,7 +101,10 @@ where
         make_relu::<P>().num_evaluator_inputs()
     }
 
+    /// Garbles a ReLU circuit for every ReLU and runs CDS to give the client
+    /// its input labels, with triples generated by `G`
     pub fn offline_server_protocol<
+        G: ServerOfflineGen<P::Field>,
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: CryptoRng + RngCore,
@@ -105,7 +112,7 @@ where
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
         number_of_relus: usize,
-        sfhe: &ServerFHE,
+        gen_keys: G::Keys,
         layer_sizes: &[usize],
         output_mac_keys: &[P::Field],
         output_mac_shares: &[P::Field],
@@ -199,6 +206,7 @@ where
         timer_end!(encode_time);
 
         let send_gc_time = timer_start!(|| "Sending GCs");
+        let phase = comm::phase("Garbled circuits");
         let randomizer_label_per_relu = if number_of_relus == 0 {
             8192
         } else {
@@ -211,14 +219,16 @@ where
             let sent_message = ServerGcMsgSend::new(&msg_contents);
             bytes::serialize(writer, &sent_message)?;
         }
+        drop(phase);
         timer_end!(send_gc_time);
 
         let cds_time = timer_start!(|| "CDS Protocol");
-        if number_of_relus > 0 {
-            cds::CDSProtocol::<P>::server_cds(
+        let phase = comm::phase("CDS");
+        if number_of_relus != 0 {
+            cds::CDSProtocol::<P>::server_cds::<G, _, _, _>(
                 reader,
                 writer,
-                sfhe,
+                gen_keys,
                 layer_sizes,
                 output_mac_keys,
                 output_mac_shares,
@@ -228,10 +238,12 @@ where
                 rng,
             )?;
         }
+        drop(phase);
         timer_end!(cds_time);
 
         // Send carry labels to client
         let send_time = timer_start!(|| "Sending carry labels");
+        let _phase = comm::phase("Carry labels");
         let tmp = vec![carry_labels];
         let send_message = ServerLabelMsgSend::new(&tmp);
         bytes::serialize(writer, &send_message)?;
@@ -244,7 +256,10 @@ where
         })
     }
 
+    /// Receives the garbled ReLU circuits and runs CDS to obtain the client's
+    /// input labels, with triples generated by `G`
     pub fn offline_client_protocol<
+        G: ClientOfflineGen<P::Field>,
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: CryptoRng + RngCore,
@@ -252,7 +267,7 @@ where
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
         number_of_relus: usize,
-        cfhe: &ClientFHE,
+        gen_keys: G::Keys,
         layer_sizes: &[usize],
         output_mac_shares: &[P::Field],
         output_shares: &[P::Field],
@@ -262,6 +277,7 @@ where
     ) -> Result<ClientState, MpcError> {
         let start_time = timer_start!(|| "ReLU offline protocol");
         let rcv_gc_time = timer_start!(|| "Receiving GCs");
+        let phase = comm::phase("Garbled circuits");
         let mut gc_s = Vec::with_capacity(number_of_relus);
         let mut r_wires = Vec::with_capacity(number_of_relus);
 
@@ -275,16 +291,20 @@ where
             gc_s.extend(gc_chunks);
             r_wires.extend(r_wire_chunks);
         }
+        drop(phase);
         timer_end!(rcv_gc_time);
 
         assert_eq!(gc_s.len(), number_of_relus);
 
         let cds_time = timer_start!(|| "CDS Protocol");
-        let labels = if number_of_relus > 0 {
-            cds::CDSProtocol::<P>::client_cds(
+        let phase = comm::phase("CDS");
+        let labels = if number_of_relus == 0 {
+            Vec::new()
+        } else {
+            cds::CDSProtocol::<P>::client_cds::<G, _, _, _>(
                 reader,
                 writer,
-                cfhe,
+                gen_keys,
                 layer_sizes,
                 output_mac_shares,
                 output_shares,
@@ -292,14 +312,13 @@ where
                 input_rands,
                 rng,
             )?
-        } else {
-            Vec::new()
         };
+        drop(phase);
         timer_end!(cds_time);
 
         // Receive carry labels
         let recv_time = timer_start!(|| "Receiving carry labels");
-
+        let _phase = comm::phase("Carry labels");
         let recv_msg: ClientLabelMsgRcv = bytes::deserialize(reader)?;
         let carry_labels: Vec<Wire> = recv_msg.msg().remove(0);
 
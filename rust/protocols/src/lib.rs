@@ -7,16 +7,24 @@ use std::marker::PhantomData;
 
 #[macro_use]
 extern crate bench_utils;
+#[macro_use]
+extern crate lazy_static;
 
 extern crate ndarray;
 
 pub mod bytes;
 pub mod cds;
+pub mod comm;
 pub mod gc;
 pub mod linear_layer;
 pub mod mpc;
 pub mod mpc_offline;
 pub mod neural_network;
+pub mod ot_offline;
+pub mod packing;
+pub mod randomness;
+pub mod simulator;
+pub mod transport;
 
 mod error;
 
@@ -33,6 +41,7 @@ pub type ClientKeySend<'a> = OutMessage<'a, Vec<std::os::raw::c_char>, KeygenTyp
 pub fn client_keygen<W: Write + Unpin>(
     writer: &mut IMuxAsync<W>,
 ) -> Result<ClientFHE, bincode::Error> {
+    let _phase = crate::comm::phase("Keygen");
     let mut key_share = KeyShare::new();
     let gen_time = timer_start!(|| "Generating keys");
     let (cfhe, keys_vec) = key_share.generate();
@@ -48,6 +57,7 @@ pub fn client_keygen<W: Write + Unpin>(
 pub fn server_keygen<R: Read + Unpin>(
     reader: &mut IMuxAsync<R>,
 ) -> Result<ServerFHE, bincode::Error> {
+    let _phase = crate::comm::phase("Keygen");
     let recv_time = timer_start!(|| "Receiving keys");
     let keys: ServerKeyRcv = crate::bytes::deserialize(reader)?;
     timer_end!(recv_time);
@@ -1,19 +1,26 @@
-use crate::{AdditiveShare, AuthAdditiveShare, InMessage, OutMessage};
+use crate::{
+    error::MpcError,
+    mpc::{ClientMPC, ServerMPC, MPC},
+    mpc_offline::{ClientOfflineGen, OfflineMPC, ServerOfflineGen},
+    packing::{Packable, Packed, PackedSlice},
+    AdditiveShare, AuthAdditiveShare, InMessage, OutMessage,
+};
 use algebra::{
     fixed_point::{FixedPoint, FixedPointParameters},
-    fp_64::Fp64Parameters,
+    fp_64::{Fp64, Fp64Parameters},
     FpParameters, PrimeField, UniformRandom,
 };
 use crypto_primitives::additive_share::{AuthShare, Share};
 use io_utils::imux::IMuxAsync;
 use neural_network::{
-    layers::*,
-    tensors::{Input, Output},
-    Evaluate,
+    layers::{convolution::Conv2dParams, fully_connected::FullyConnectedParams, *},
+    tensors::{Input, Kernel, Output},
+    EvalMethod, Evaluate,
 };
-use protocols_sys::{SealClientACG, SealServerACG, *};
+use num_traits::Zero;
+use protocols_sys::{ClientACG, ServerACG};
 use rand::{CryptoRng, RngCore};
-use std::{marker::PhantomData, os::raw::c_char};
+use std::{io, marker::PhantomData, os::raw::c_char};
 
 use async_std::io::{Read, Write};
 
@@ -29,8 +36,73 @@ pub type OfflineServerMsgRcv = InMessage<Vec<c_char>, LinearProtocolType>;
 pub type OfflineClientMsgSend<'a> = OutMessage<'a, Vec<c_char>, LinearProtocolType>;
 pub type OfflineClientMsgRcv = InMessage<Vec<c_char>, LinearProtocolType>;
 
-pub type MsgSend<'a, P> = OutMessage<'a, Input<AdditiveShare<P>>, LinearProtocolType>;
-pub type MsgRcv<P> = InMessage<Input<AdditiveShare<P>>, LinearProtocolType>;
+pub type OfflineServerBatchMsgSend<'a> = OutMessage<'a, Vec<Vec<c_char>>, LinearProtocolType>;
+pub type OfflineServerBatchMsgRcv = InMessage<Vec<Vec<c_char>>, LinearProtocolType>;
+
+pub type OfflineClientBatchMsgSend<'a> = OutMessage<'a, Vec<Vec<c_char>>, LinearProtocolType>;
+pub type OfflineClientBatchMsgRcv = InMessage<Vec<Vec<c_char>>, LinearProtocolType>;
+
+/// The client's input shares, bit-packed along with their dimensions
+pub type MsgSend<'a, P> = OutMessage<
+    'a,
+    (
+        (usize, usize, usize, usize),
+        PackedSlice<'a, AdditiveShare<P>>,
+    ),
+    LinearProtocolType,
+>;
+pub type MsgRcv<P> =
+    InMessage<((usize, usize, usize, usize), Packed<AdditiveShare<P>>), LinearProtocolType>;
+
+/// Number of elements in a tensor of dimensions `dims`
+fn num_elements((b, c, h, w): (usize, usize, usize, usize)) -> usize {
+    b * c * h * w
+}
+
+/// Returns how many items of a batch with dimensions `input_dims` fit in the
+/// same ciphertexts when preprocessing the layer described by `layer_info`.
+/// Groups of this many items are merged along the channel dimension (see
+/// `Input::merge_batch`) and preprocessed by a single ACG handler.
+pub fn acg_group_size<F, C>(
+    layer_info: &LinearLayerInfo<F, C>,
+    (batch_size, c, h, w): (usize, usize, usize, usize),
+) -> usize {
+    let slot_count = protocols_sys::POLY_MOD_DEGREE as usize;
+    let group_size = match layer_info {
+        // Each half of a ciphertext holds as many channels as fit after
+        // padding them to a power of two
+        LinearLayerInfo::Conv2d { .. } => {
+            let chans_per_ct = 2 * ((slot_count / 2) / (h * w).next_power_of_two());
+            chans_per_ct / c
+        }
+        // The whole input vector must fit in a single ciphertext
+        LinearLayerInfo::FullyConnected => slot_count / (c * h * w).next_power_of_two(),
+        _ => 1,
+    };
+    group_size.max(1).min(batch_size.max(1))
+}
+
+/// Dimensions of a group of `items` batch items merged along the channel
+/// dimension
+pub fn merged_dims(
+    items: usize,
+    (_, c, h, w): (usize, usize, usize, usize),
+) -> (usize, usize, usize, usize) {
+    (1, items * c, h, w)
+}
+
+/// Returns an error if a batch message from the peer does not have one entry
+/// per ACG handler
+fn check_batch_len<T>(items: &[T], expected: usize) -> Result<(), bincode::Error> {
+    if items.len() != expected {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "Expected {} batch items but received {}",
+            expected,
+            items.len()
+        ))));
+    }
+    Ok(())
+}
 
 impl<P: FixedPointParameters> LinearProtocol<P>
 where
@@ -39,11 +111,68 @@ where
     P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
     P::Field: AuthShare,
 {
+    /// Builds the server ACG handlers of `layer` for
+    /// `offline_server_acg_protocol`, returning the number of batch items
+    /// preprocessed by each handler along with the handlers
+    pub fn server_acg_handlers<A: ServerACG>(
+        keys: A::Keys,
+        layer: &LinearLayer<AdditiveShare<P>, FixedPoint<P>>,
+    ) -> (usize, Vec<A>)
+    where
+        A::Keys: Copy,
+    {
+        let input_dims = layer.input_dimensions();
+        let group_size = acg_group_size(&LinearLayerInfo::from(layer), input_dims);
+        let handlers = (0..input_dims.0)
+            .step_by(group_size)
+            .map(|start| {
+                let merged = layer.merge_batch(group_size.min(input_dims.0 - start));
+                A::new(keys, &merged, &merged.kernel_to_repr())
+            })
+            .collect();
+        (group_size, handlers)
+    }
+
+    /// Builds the client ACG handlers of the layer described by `layer_info`
+    /// for `offline_client_acg_protocol`, returning the number of batch items
+    /// preprocessed by each handler along with the handlers
+    pub fn client_acg_handlers<C: ClientACG>(
+        keys: C::Keys,
+        layer_info: &LinearLayerInfo<AdditiveShare<P>, FixedPoint<P>>,
+        input_dims: (usize, usize, usize, usize),
+        output_dims: (usize, usize, usize, usize),
+    ) -> (usize, Vec<C>)
+    where
+        C::Keys: Copy,
+    {
+        let group_size = acg_group_size(layer_info, input_dims);
+        let handlers = (0..input_dims.0)
+            .step_by(group_size)
+            .map(|start| {
+                let items = group_size.min(input_dims.0 - start);
+                C::new(
+                    keys,
+                    &layer_info.merge_batch(items),
+                    merged_dims(items, input_dims),
+                    merged_dims(items, output_dims),
+                )
+            })
+            .collect();
+        (group_size, handlers)
+    }
+
     /// Runs server ACG protocol. Receives client input `r`, and homomorphically
     /// evaluates `Lr`, returning authenticated shares of `r`, shares of `Lr`,
     /// and authenticated shares of shares of `Lr` --> [[r]]_2, <Lr>_2,
     /// [[<Lr>_2]]_2
+    ///
+    /// `server_acgs` holds one handler per group of `group_size` batch items
+    /// (the last group may be smaller), built from `LinearLayer::merge_batch`.
+    /// Each group is packed into the same ciphertexts, and the ciphertexts of
+    /// all groups are sent in a single message in each direction, so a batch
+    /// takes as many rounds as a single input.
     pub fn offline_server_acg_protocol<
+        A: ServerACG,
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: RngCore + CryptoRng,
@@ -52,7 +181,8 @@ where
         writer: &mut IMuxAsync<W>,
         input_dims: (usize, usize, usize, usize),
         output_dims: (usize, usize, usize, usize),
-        server_acg: &mut SealServerACG,
+        group_size: usize,
+        server_acgs: &mut [A],
         rng: &mut RNG,
     ) -> Result<
         (
@@ -65,11 +195,15 @@ where
         ),
         bincode::Error,
     > {
-        // TODO: Add batch size
+        assert_eq!(
+            server_acgs.len(),
+            (input_dims.0 + group_size - 1) / group_size,
+            "need one ACG handler per group of batch items"
+        );
         let start_time = timer_start!(|| "Server linear offline protocol");
         let preprocess_time = timer_start!(|| "Preprocessing");
 
-        // Sample MAC keys
+        // Sample MAC keys, which are shared by every item in the batch
         let mac_key_r = P::Field::uniform(rng);
         let mac_key_y = P::Field::uniform(rng);
 
@@ -89,36 +223,49 @@ where
             .iter_mut()
             .for_each(|e| *e = P::Field::uniform(rng));
 
-        // Create SEALServer object for C++ interopt
-        // Preprocess filter rotations and noise masks
-        server_acg.preprocess(
-            &linear_share.to_u64(),
-            &linear_mac_share.to_u64(),
-            &r_mac_share.to_u64(),
-            mac_key_y.into_repr().0,
-            mac_key_r.into_repr().0,
-        );
+        // Preprocess filter rotations and noise masks for each group of items
+        let linear_items = linear_share.to_u64().merge_batch(group_size);
+        let linear_mac_items = linear_mac_share.to_u64().merge_batch(group_size);
+        let r_mac_items = r_mac_share.to_u64().merge_batch(group_size);
+        for (b, server_acg) in server_acgs.iter_mut().enumerate() {
+            server_acg.preprocess(
+                &linear_items[b],
+                &linear_mac_items[b],
+                &r_mac_items[b],
+                mac_key_y.into_repr().0,
+                mac_key_r.into_repr().0,
+            );
+        }
         timer_end!(preprocess_time);
 
         // Receive client Enc(r_i)
         let rcv_time = timer_start!(|| "Receiving Input");
-        let client_share: OfflineServerMsgRcv = crate::bytes::deserialize(reader)?;
-        let client_share_i = client_share.msg();
+        let client_shares: OfflineServerBatchMsgRcv = crate::bytes::deserialize(reader)?;
+        let client_shares = client_shares.msg();
+        check_batch_len(&client_shares, server_acgs.len())?;
         timer_end!(rcv_time);
 
         // Compute client's MAC share for layer `i`, and share + MAC share for layer `i
         // + 1`, That is, compute Lr - s, [a(Lr-s)]_1, [ar]_1
         let processing = timer_start!(|| "Processing Layer");
-        let (linear_ct_vec, linear_mac_ct_vec, r_mac_ct_vec) = server_acg.process(client_share_i);
+        let mut linear_cts = Vec::with_capacity(server_acgs.len());
+        let mut linear_mac_cts = Vec::with_capacity(server_acgs.len());
+        let mut r_mac_cts = Vec::with_capacity(server_acgs.len());
+        for (server_acg, client_share) in server_acgs.iter_mut().zip(client_shares) {
+            let (linear_ct, linear_mac_ct, r_mac_ct) = server_acg.process(client_share)?;
+            linear_cts.push(linear_ct);
+            linear_mac_cts.push(linear_mac_ct);
+            r_mac_cts.push(r_mac_ct);
+        }
         timer_end!(processing);
 
         // Send shares to client
         let send_time = timer_start!(|| "Sending result");
-        let sent_message = OfflineServerMsgSend::new(&linear_ct_vec);
+        let sent_message = OfflineServerBatchMsgSend::new(&linear_cts);
         crate::bytes::serialize(&mut *writer, &sent_message)?;
-        let sent_message = OfflineServerMsgSend::new(&linear_mac_ct_vec);
+        let sent_message = OfflineServerBatchMsgSend::new(&linear_mac_cts);
         crate::bytes::serialize(&mut *writer, &sent_message)?;
-        let sent_message = OfflineServerMsgSend::new(&r_mac_ct_vec);
+        let sent_message = OfflineServerBatchMsgSend::new(&r_mac_cts);
         crate::bytes::serialize(writer, &sent_message)?;
         timer_end!(send_time);
 
@@ -135,7 +282,11 @@ where
     /// Runs client ACG protocol. Generates random input `r` and receives back
     /// authenticated shares of `r`, shares of `Lr`, and authenticated shares of
     /// shares of `Lr` --> [[r]]_1, <Lr>_1, [[<Lr>_1]]_1
+    ///
+    /// `client_acgs` holds one handler per group of `group_size` batch items,
+    /// as in `offline_server_acg_protocol`.
     pub fn offline_client_acg_protocol<
+        C: ClientACG,
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: RngCore + CryptoRng,
@@ -144,7 +295,8 @@ where
         writer: &mut IMuxAsync<W>,
         input_dims: (usize, usize, usize, usize),
         output_dims: (usize, usize, usize, usize),
-        client_acg: &mut SealClientACG,
+        group_size: usize,
+        client_acgs: &mut [C],
         rng: &mut RNG,
     ) -> Result<
         (
@@ -153,7 +305,11 @@ where
         ),
         bincode::Error,
     > {
-        // TODO: Add batch size
+        assert_eq!(
+            client_acgs.len(),
+            (input_dims.0 + group_size - 1) / group_size,
+            "need one ACG handler per group of batch items"
+        );
         let start_time = timer_start!(|| "Linear offline protocol");
         let preprocess_time = timer_start!(|| "Client preprocessing");
         // Generate random share r
@@ -161,30 +317,53 @@ where
         r.iter_mut()
             .for_each(|e| *e = FixedPoint::new(P::Field::uniform(&mut *rng)));
 
-        // Create SEALClient object for C++ interopt
-        // Preprocess and encrypt client secret share for sending
-        let ct_vec = client_acg.preprocess(&r.to_repr());
+        // Preprocess and encrypt each group of the client secret share for sending
+        let ct_vecs: Vec<_> = client_acgs
+            .iter_mut()
+            .zip(r.to_repr().merge_batch(group_size))
+            .map(|(client_acg, r_i)| client_acg.preprocess(&r_i))
+            .collect();
         timer_end!(preprocess_time);
 
         // Send layer_i randomness for processing by server.
         let send_time = timer_start!(|| "Sending input");
-        let sent_message = OfflineClientMsgSend::new(&ct_vec);
+        let sent_message = OfflineClientBatchMsgSend::new(&ct_vecs);
         crate::bytes::serialize(writer, &sent_message)?;
         timer_end!(send_time);
 
         // Receive shares for layer `i + 1` output, MAC, and layer `i` MAC
         let rcv_time = timer_start!(|| "Receiving Result");
-        let linear_ct: OfflineClientMsgRcv = crate::bytes::deserialize(&mut *reader)?;
-        let linear_mac_ct: OfflineClientMsgRcv = crate::bytes::deserialize(&mut *reader)?;
-        let r_mac_ct: OfflineClientMsgRcv = crate::bytes::deserialize(reader)?;
+        let linear_cts: OfflineClientBatchMsgRcv = crate::bytes::deserialize(&mut *reader)?;
+        let linear_mac_cts: OfflineClientBatchMsgRcv = crate::bytes::deserialize(&mut *reader)?;
+        let r_mac_cts: OfflineClientBatchMsgRcv = crate::bytes::deserialize(reader)?;
+        let (linear_cts, linear_mac_cts, r_mac_cts) =
+            (linear_cts.msg(), linear_mac_cts.msg(), r_mac_cts.msg());
+        check_batch_len(&linear_cts, client_acgs.len())?;
+        check_batch_len(&linear_mac_cts, client_acgs.len())?;
+        check_batch_len(&r_mac_cts, client_acgs.len())?;
         timer_end!(rcv_time);
 
         let post_time = timer_start!(|| "Post-processing");
-        let mut linear_auth = Output::zeros(output_dims);
-        let mut r_mac = Input::zeros(input_dims);
-        // Decrypt + reshape resulting ciphertext and free C++ allocations
-        client_acg.decrypt(linear_ct.msg(), linear_mac_ct.msg(), r_mac_ct.msg());
-        client_acg.postprocess::<P>(&mut linear_auth, &mut r_mac);
+        let mut linear_auth = Vec::with_capacity(client_acgs.len());
+        let mut r_mac = Vec::with_capacity(client_acgs.len());
+        for (g, (((client_acg, linear_ct), linear_mac_ct), r_mac_ct)) in client_acgs
+            .iter_mut()
+            .zip(linear_cts)
+            .zip(linear_mac_cts)
+            .zip(r_mac_cts)
+            .enumerate()
+        {
+            let items = group_size.min(input_dims.0 - g * group_size);
+            let mut linear_auth_g = Output::zeros(merged_dims(items, output_dims));
+            let mut r_mac_g = Input::zeros(merged_dims(items, input_dims));
+            // Decrypt + reshape resulting ciphertext and free C++ allocations
+            client_acg.decrypt(linear_ct, linear_mac_ct, r_mac_ct)?;
+            client_acg.postprocess::<P::Field>(&mut linear_auth_g, &mut r_mac_g);
+            linear_auth.push(linear_auth_g);
+            r_mac.push(r_mac_g);
+        }
+        let linear_auth = Output::unmerge_batch(&linear_auth, output_dims.1);
+        let r_mac = Input::unmerge_batch(&r_mac, input_dims.1);
 
         // Negate both shares here so that we receive the correct
         // labels for the online phase
@@ -196,95 +375,20 @@ where
         Ok((r_auth, linear_auth))
     }
 
-    /// Client sends a value to the server and receives back a share of it's
-    /// MAC'd value
-    pub fn offline_client_auth_share<R: Read + Send + Unpin, W: Write + Send + Unpin>(
-        reader: &mut IMuxAsync<R>,
-        writer: &mut IMuxAsync<W>,
-        input: Input<P::Field>,
-        cfhe: &ClientFHE,
-    ) -> Result<Input<AuthAdditiveShare<P::Field>>, bincode::Error> {
-        let start_time = timer_start!(|| "Linear offline protocol");
-
-        // Encrypt input and send to the server
-        let mut share = SealCT::new();
-        let ct = share.encrypt_vec(cfhe, input.to_u64().as_slice().unwrap().to_vec());
-
-        let send_time = timer_start!(|| "Sending input");
-        let sent_message = OfflineClientMsgSend::new(&ct);
-        crate::bytes::serialize(writer, &sent_message)?;
-        timer_end!(send_time);
-
-        // Receive the result and decrypt
-        let rcv_time = timer_start!(|| "Receiving Result");
-        let auth_ct: OfflineClientMsgRcv = crate::bytes::deserialize(&mut *reader)?;
-        timer_end!(rcv_time);
-
-        let result = share
-            .decrypt_vec(cfhe, auth_ct.msg(), input.len())
-            .iter()
-            .map(|e| P::Field::from_repr((*e).into()))
-            .collect();
-        let input_mac = Input::from_shape_vec(input.dim(), result).expect("Shapes should be same");
-        timer_end!(start_time);
-        Ok(Input::auth_share_from_parts(input, input_mac))
-    }
-
-    /// Server receives an encrypted vector from the client and shares its MAC
-    pub fn offline_server_auth_share<
-        R: Read + Send + Unpin,
-        W: Write + Send + Unpin,
-        RNG: RngCore + CryptoRng,
-    >(
-        reader: &mut IMuxAsync<R>,
-        writer: &mut IMuxAsync<W>,
-        input_dims: (usize, usize, usize, usize),
-        sfhe: &ServerFHE,
-        rng: &mut RNG,
-    ) -> Result<(P::Field, Input<AuthAdditiveShare<P::Field>>), bincode::Error> {
-        let start_time = timer_start!(|| "Linear offline protocol");
-
-        // Sample MAC key and MAC share
-        let mac_key = P::Field::uniform(rng);
-        let mut mac_share = Input::zeros(input_dims);
-        mac_share
-            .iter_mut()
-            .for_each(|e| *e = P::Field::uniform(rng));
-        let mac_share_c: Vec<u64> = mac_share.to_u64().as_slice().unwrap().to_vec();
-
-        // Receive client input and compute MAC share
-        let mut share = SealCT::new();
-
-        let rcv_time = timer_start!(|| "Receiving Input");
-        let input: OfflineServerMsgRcv = crate::bytes::deserialize(&mut *reader)?;
-        let mut input_ct = input.msg();
-        timer_end!(rcv_time);
-
-        share.inner.inner = input_ct.as_mut_ptr();
-        share.inner.size = input_ct.len() as u64;
-        let result_ct = share.gen_mac_share(sfhe, mac_share_c, mac_key.into_repr().0);
-
-        // Send result back to client
-        let send_time = timer_start!(|| "Sending Result");
-        let sent_message = OfflineClientMsgSend::new(&result_ct);
-        crate::bytes::serialize(writer, &sent_message)?;
-        timer_end!(send_time);
-        timer_end!(start_time);
-        Ok((
-            mac_key,
-            Input::auth_share_from_parts(Input::zeros(input_dims), mac_share),
-        ))
-    }
-
     pub fn online_client_protocol<W: Write + Send + Unpin>(
         writer: &mut IMuxAsync<W>,
         x_s: &Input<AdditiveShare<P>>,
         layer: &LinearLayerInfo<AdditiveShare<P>, FixedPoint<P>>,
-    ) -> Result<(), bincode::Error> {
+    ) -> Result<(), bincode::Error>
+    where
+        P::Field: Packable,
+    {
         let start = timer_start!(|| "Linear online protocol");
         match layer {
             LinearLayerInfo::Conv2d { .. } | LinearLayerInfo::FullyConnected => {
-                let sent_message = MsgSend::new(x_s);
+                let shares: Vec<_> = x_s.iter().cloned().collect();
+                let msg = (x_s.dim(), PackedSlice(shares.as_slice()));
+                let sent_message = MsgSend::new(&msg);
                 crate::bytes::serialize(&mut *writer, &sent_message)?;
             }
             _ => {}
@@ -299,17 +403,35 @@ where
         output_rerandomizer: &Output<P::Field>,
         input_derandomizer: &Input<P::Field>,
         output: &mut Output<AdditiveShare<P>>,
-    ) -> Result<(), bincode::Error> {
+    ) -> Result<(), bincode::Error>
+    where
+        P::Field: Packable,
+    {
         let start = timer_start!(|| "Linear online protocol");
         let mut input: Input<AdditiveShare<P>> = match &layer {
             LinearLayer::Conv2d { .. } | LinearLayer::FullyConnected { .. } => {
-                let recv: MsgRcv<P> = crate::bytes::deserialize(reader).unwrap();
-                recv.msg()
+                let recv: MsgRcv<P> = crate::bytes::deserialize(reader)?;
+                let (dims, shares) = recv.msg();
+                if dims != input_derandomizer.dim() {
+                    return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                        "Expected input of dimensions {:?} but received {:?}",
+                        input_derandomizer.dim(),
+                        dims
+                    ))));
+                }
+                Input::from_shape_vec(dims, shares.0)
+                    .map_err(|e| Box::new(bincode::ErrorKind::Custom(e.to_string())))?
             }
             _ => Input::zeros(input_derandomizer.dim()),
         };
         input.randomize_local_share(input_derandomizer);
-        *output = layer.evaluate(&input);
+        // Layers on a torch device stay there, all others are evaluated with
+        // the native GEMM
+        let method = match layer.eval_method() {
+            EvalMethod::Naive => EvalMethod::Gemm,
+            method => method,
+        };
+        *output = layer.evaluate_with_method(method, &input);
         output.zip_mut_with(output_rerandomizer, |out, s| {
             *out = FixedPoint::randomize_local_share(out, s)
         });
@@ -317,3 +439,328 @@ where
         Ok(())
     }
 }
+
+impl<P: FixedPointParameters, F: Fp64Parameters> LinearProtocol<P>
+where
+    P: FixedPointParameters<Field = Fp64<F>>,
+    P::Field: AuthShare,
+{
+    /// Client authenticates `input` under the MAC key of the server's
+    /// `offline_server_auth_share`. The client masks `input` with pairwise
+    /// randomness generated by `G` and returns its authenticated share of
+    /// `input`, whose value share is `input` itself
+    pub fn offline_client_auth_share<
+        G: ClientOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        input: Input<P::Field>,
+        keys: G::Keys,
+        rng: &mut RNG,
+    ) -> Result<Input<AuthAdditiveShare<P::Field>>, MpcError> {
+        let start_time = timer_start!(|| "Linear offline protocol");
+        let gen = G::from_keys(keys);
+        let rands = gen.rands_gen(reader, writer, rng, input.len())?;
+        let mut mpc = ClientMPC::new(rands, Vec::new());
+        let shares = mpc.private_inputs(reader, writer, input.as_slice().unwrap(), rng)?;
+        // The client knows the whole value, so it keeps all of it and only
+        // the MAC stays shared
+        let shares = input
+            .iter()
+            .zip(shares)
+            .map(|(x, s)| AuthAdditiveShare::new(*x, s.get_mac().inner))
+            .collect();
+        timer_end!(start_time);
+        Ok(Input::from_shape_vec(input.dim(), shares).expect("Shapes should be same"))
+    }
+
+    /// Server samples a MAC key and authenticates a client input of
+    /// dimensions `input_dims` under it with pairwise randomness generated by
+    /// `G`. Returns the MAC key and the server's share of the input, whose
+    /// value share is zero.
+    pub fn offline_server_auth_share<
+        G: ServerOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        input_dims: (usize, usize, usize, usize),
+        keys: G::Keys,
+        rng: &mut RNG,
+    ) -> Result<(P::Field, Input<AuthAdditiveShare<P::Field>>), MpcError> {
+        let start_time = timer_start!(|| "Linear offline protocol");
+        let mac_key = P::Field::uniform(rng);
+        let num = num_elements(input_dims);
+        let gen = G::from_keys(keys, mac_key.into_repr().0);
+        let rands = gen.rands_gen(reader, writer, rng, num)?;
+        let mut mpc = ServerMPC::new(rands, Vec::new(), mac_key);
+        let shares = mpc.recv_private_inputs(reader, writer, num)?;
+        // Move the client's value entirely to its share, and fold the
+        // constant it added into the MAC share
+        let shares = shares
+            .into_iter()
+            .map(|s| {
+                let mac = s.get_mac().inner + mac_key * s.get_epsilon().inner;
+                AuthAdditiveShare::new(P::Field::zero(), mac)
+            })
+            .collect();
+        timer_end!(start_time);
+        Ok((
+            mac_key,
+            Input::from_shape_vec(input_dims, shares).expect("Shapes should be same"),
+        ))
+    }
+
+    /// Client generates a uniformly random input of dimensions `input_dims`,
+    /// authenticated under the MAC key of the server's
+    /// `offline_server_auth_rand` with randomness generated by `G`. Unlike
+    /// `offline_client_auth_share` the input is never sent, since the
+    /// client's value shares of the randomness are the input itself.
+    pub fn offline_client_auth_rand<
+        G: ClientOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        input_dims: (usize, usize, usize, usize),
+        keys: G::Keys,
+        rng: &mut RNG,
+    ) -> Result<Input<AuthAdditiveShare<P::Field>>, MpcError> {
+        let start_time = timer_start!(|| "Linear offline protocol");
+        let gen = G::from_keys(keys);
+        let shares = gen.client_rands_gen(reader, writer, rng, num_elements(input_dims))?;
+        timer_end!(start_time);
+        Ok(Input::from_shape_vec(input_dims, shares).expect("Shapes should be same"))
+    }
+
+    /// Server samples a MAC key and authenticates the client's uniformly
+    /// random input of `offline_client_auth_rand` under it. Returns the MAC
+    /// key and the server's share of the input, whose value share is zero.
+    pub fn offline_server_auth_rand<
+        G: ServerOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        input_dims: (usize, usize, usize, usize),
+        keys: G::Keys,
+        rng: &mut RNG,
+    ) -> Result<(P::Field, Input<AuthAdditiveShare<P::Field>>), MpcError> {
+        let start_time = timer_start!(|| "Linear offline protocol");
+        let mac_key = P::Field::uniform(rng);
+        let gen = G::from_keys(keys, mac_key.into_repr().0);
+        let shares = gen.client_rands_gen(reader, writer, rng, num_elements(input_dims))?;
+        timer_end!(start_time);
+        Ok((
+            mac_key,
+            Input::from_shape_vec(input_dims, shares).expect("Shapes should be same"),
+        ))
+    }
+}
+
+/// Encodes field elements given by their representation as the bytes of an
+/// ACG message
+fn to_bytes(elems: impl Iterator<Item = u64>) -> Vec<c_char> {
+    elems
+        .flat_map(|e| e.to_le_bytes().to_vec())
+        .map(|b| b as c_char)
+        .collect()
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Decodes the representations of the `len` field elements of an ACG message,
+/// returning an error if the message has a different length
+fn from_bytes(bytes: &[c_char], len: usize) -> io::Result<Vec<u64>> {
+    if Some(bytes.len()) != len.checked_mul(8) {
+        return Err(invalid_data(format!(
+            "Expected {} field elements but received {} bytes",
+            len,
+            bytes.len()
+        )));
+    }
+    Ok(bytes
+        .chunks_exact(8)
+        .map(|chunk| {
+            let mut buf = [0u8; 8];
+            buf.iter_mut().zip(chunk).for_each(|(b, c)| *b = *c as u8);
+            u64::from_le_bytes(buf)
+        })
+        .collect())
+}
+
+/// INSECURE: Server ACG which receives the client's randomizer `r` in the
+/// clear and evaluates the layer on it directly. Needs no keys or HE, so it
+/// must only be used for testing.
+pub struct InsecureServerACG<F: Fp64Parameters> {
+    layer: LinearLayer<Fp64<F>, Fp64<F>>,
+    linear_share: Vec<Fp64<F>>,
+    linear_mac_share: Vec<Fp64<F>>,
+    r_mac_share: Vec<Fp64<F>>,
+    mac_key_a: Fp64<F>,
+    mac_key_b: Fp64<F>,
+}
+
+/// INSECURE: Client ACG which sends its randomizer `r` in the clear, see
+/// `InsecureServerACG`
+pub struct InsecureClientACG {
+    input_dims: (usize, usize, usize, usize),
+    output_dims: (usize, usize, usize, usize),
+    linear: Vec<u64>,
+    linear_mac: Vec<u64>,
+    r_mac: Vec<u64>,
+}
+
+impl<F: Fp64Parameters> ServerACG for InsecureServerACG<F> {
+    type Keys = ();
+
+    fn new<I, C>(_: (), layer: &LinearLayer<I, C>, kernel: &Kernel<u64>) -> Self {
+        let kernel: Kernel<Fp64<F>> = kernel.mapv(|k| Fp64::from_repr(k.into())).into();
+        // The bias is added to the output share online, so leave it out
+        let bias = Kernel::zeros((kernel.dim().0, 1, 1, 1));
+        let layer = match layer {
+            LinearLayer::Conv2d { dims, params } => LinearLayer::Conv2d {
+                dims: *dims,
+                params: Conv2dParams::new(params.padding, params.stride, kernel, bias),
+            },
+            LinearLayer::FullyConnected { dims, .. } => LinearLayer::FullyConnected {
+                dims: *dims,
+                params: FullyConnectedParams::new(kernel, bias),
+            },
+            _ => panic!("Incorrect Layer"),
+        };
+        Self {
+            layer,
+            linear_share: Vec::new(),
+            linear_mac_share: Vec::new(),
+            r_mac_share: Vec::new(),
+            mac_key_a: Fp64::zero(),
+            mac_key_b: Fp64::zero(),
+        }
+    }
+
+    fn preprocess(
+        &mut self,
+        linear_share: &Output<u64>,
+        linear_mac_share: &Output<u64>,
+        r_mac_share: &Input<u64>,
+        mac_key_a: u64,
+        mac_key_b: u64,
+    ) {
+        let to_field = |e: &u64| Fp64::from_repr((*e).into());
+        self.linear_share = linear_share.iter().map(to_field).collect();
+        self.linear_mac_share = linear_mac_share.iter().map(to_field).collect();
+        self.r_mac_share = r_mac_share.iter().map(to_field).collect();
+        self.mac_key_a = to_field(&mac_key_a);
+        self.mac_key_b = to_field(&mac_key_b);
+    }
+
+    /// Returns `Lr - s`, `a(Lr - s) - [a(Lr - s)]_2` and `br - [br]_2`
+    fn process(
+        &mut self,
+        client_share: Vec<c_char>,
+    ) -> io::Result<(Vec<c_char>, Vec<c_char>, Vec<c_char>)> {
+        let input_dims = self.layer.input_dimensions();
+        let r = from_bytes(&client_share, num_elements(input_dims))?
+            .into_iter()
+            .map(|e| {
+                if e < F::MODULUS.0 {
+                    Ok(Fp64::from_repr(e.into()))
+                } else {
+                    Err(invalid_data(format!("Invalid field element {}", e)))
+                }
+            })
+            .collect::<io::Result<Vec<Fp64<F>>>>()?;
+        let r = Input::from_shape_vec(input_dims, r).expect("Shapes should be same");
+        let lr = self.layer.evaluate(&r);
+        let linear: Vec<Fp64<F>> = lr
+            .iter()
+            .zip(&self.linear_share)
+            .map(|(y, s)| *y - *s)
+            .collect();
+        let linear_mac = linear
+            .iter()
+            .zip(&self.linear_mac_share)
+            .map(|(y, m)| self.mac_key_a * *y - *m);
+        let r_mac = r
+            .iter()
+            .zip(&self.r_mac_share)
+            .map(|(r, m)| self.mac_key_b * *r - *m);
+        Ok((
+            to_bytes(linear.iter().map(|e| e.into_repr().0)),
+            to_bytes(linear_mac.map(|e| e.into_repr().0)),
+            to_bytes(r_mac.map(|e| e.into_repr().0)),
+        ))
+    }
+}
+
+impl ClientACG for InsecureClientACG {
+    type Keys = ();
+
+    fn new<F, C>(
+        _: (),
+        _: &LinearLayerInfo<F, C>,
+        input_dims: (usize, usize, usize, usize),
+        output_dims: (usize, usize, usize, usize),
+    ) -> Self {
+        Self {
+            input_dims,
+            output_dims,
+            linear: Vec::new(),
+            linear_mac: Vec::new(),
+            r_mac: Vec::new(),
+        }
+    }
+
+    fn preprocess(&mut self, r: &Input<u64>) -> Vec<c_char> {
+        to_bytes(r.iter().copied())
+    }
+
+    fn decrypt(
+        &mut self,
+        linear_ct: Vec<c_char>,
+        linear_mac_ct: Vec<c_char>,
+        r_mac_ct: Vec<c_char>,
+    ) -> io::Result<()> {
+        self.linear = from_bytes(&linear_ct, num_elements(self.output_dims))?;
+        self.linear_mac = from_bytes(&linear_mac_ct, num_elements(self.output_dims))?;
+        self.r_mac = from_bytes(&r_mac_ct, num_elements(self.input_dims))?;
+        Ok(())
+    }
+
+    fn postprocess<P>(
+        &self,
+        linear_auth: &mut Output<AuthAdditiveShare<P::Field>>,
+        r_mac_share: &mut Input<P::Field>,
+    ) where
+        P: FixedPointParameters,
+        <P::Field as PrimeField>::Params: Fp64Parameters,
+        P::Field: PrimeField<BigInt = <<P::Field as PrimeField>::Params as FpParameters>::BigInt>,
+        P::Field: AuthShare,
+    {
+        linear_auth
+            .iter_mut()
+            .zip(self.linear.iter().zip(&self.linear_mac))
+            .for_each(|(share, (value, mac))| {
+                *share = AuthAdditiveShare::new(
+                    P::Field::from_repr((*value).into()),
+                    P::Field::from_repr((*mac).into()),
+                )
+            });
+        r_mac_share
+            .iter_mut()
+            .zip(&self.r_mac)
+            .for_each(|(share, mac)| *share = P::Field::from_repr((*mac).into()));
+    }
+}
@@ -1,30 +1,60 @@
-use crate::bytes;
-use crate::{error::MpcError, InMessage, OutMessage};
-use algebra::fields::{Fp64, Fp64Parameters};
+use crate::{bytes, comm};
+use crate::{
+    error::MpcError,
+    packing::{Packed, PackedSlice},
+    randomness::{auth_shares_from_seed, complement_auth_share, sample_seed},
+    InMessage, OutMessage,
+};
+use algebra::{
+    fields::{Fp64, Fp64Parameters, PrimeField},
+    BigInteger64, UniformRandom,
+};
 use async_std::io::{Read, Write};
 use crypto_primitives::{
     additive_share::{AdditiveShare, AuthAdditiveShare, AuthShare, Share},
-    beavers_mul::{BeaversMul, BlindedInputs, BlindedSharedInputs, PBeaversMul, Triple},
+    beavers_mul::{BeaversMul, BlindedInputs, MatrixTriple, PBeaversMul, SquarePair, Triple},
 };
 use io_utils::imux::IMuxAsync;
 use itertools::izip;
+use neural_network::{
+    layers::convolution::Padding,
+    tensors::{Input, Kernel, Output},
+};
 use num_traits::identities::Zero;
-use rand::{CryptoRng, RngCore};
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
 use rayon::prelude::*;
+use scuttlebutt::commitment::{Commitment, ShaCommitment};
+use serde::{de::DeserializeOwned, Serialize};
 
 pub struct MpcProtocolType;
 
-type AuthShareSend<'a, T> = OutMessage<'a, [AuthAdditiveShare<T>], MpcProtocolType>;
-type AuthShareRcv<T> = InMessage<Vec<AuthAdditiveShare<T>>, MpcProtocolType>;
+type AuthShareSend<'a, T> = OutMessage<'a, PackedSlice<'a, AuthAdditiveShare<T>>, MpcProtocolType>;
+type AuthShareRcv<T> = InMessage<Packed<AuthAdditiveShare<T>>, MpcProtocolType>;
+
+pub type ShareSend<'a, T> = OutMessage<'a, PackedSlice<'a, AdditiveShare<T>>, MpcProtocolType>;
+pub type ShareRcv<T> = InMessage<Packed<AdditiveShare<T>>, MpcProtocolType>;
+
+type ConstantMsgSend<'a, T> = OutMessage<'a, PackedSlice<'a, T>, MpcProtocolType>;
+type ConstantMsgRcv<T> = InMessage<Packed<T>, MpcProtocolType>;
 
-pub type ShareSend<'a, T> = OutMessage<'a, [AdditiveShare<T>], MpcProtocolType>;
-pub type ShareRcv<T> = InMessage<Vec<AdditiveShare<T>>, MpcProtocolType>;
+type CommitmentSend<'a> = OutMessage<'a, [u8; 32], MpcProtocolType>;
+type CommitmentRcv = InMessage<[u8; 32], MpcProtocolType>;
 
-type MulMsgSend<'a, T> = OutMessage<'a, [BlindedSharedInputs<T>], MpcProtocolType>;
-type MulMsgRcv<T> = InMessage<Vec<BlindedSharedInputs<T>>, MpcProtocolType>;
+type SeedSend<'a> = OutMessage<'a, [u8; 32], MpcProtocolType>;
+type SeedRcv = InMessage<[u8; 32], MpcProtocolType>;
 
-type ConstantMsgSend<'a, T> = OutMessage<'a, [T], MpcProtocolType>;
-type ConstantMsgRcv<T> = InMessage<Vec<T>, MpcProtocolType>;
+type CommittedSend<'a, V> = OutMessage<'a, V, MpcProtocolType>;
+type CommittedRcv<V> = InMessage<V, MpcProtocolType>;
+
+/// Shares of a random mask consumed by `MPC::truncate`, along with shares of
+/// the integer formed by its `trunc_bits` least significant bits
+#[derive(Copy, Clone, Debug)]
+pub struct TruncationMask<T: AuthShare> {
+    pub mask: AuthAdditiveShare<T>,
+    pub low_bits: AuthAdditiveShare<T>,
+    pub trunc_bits: usize,
+}
 
 // TODO: Handle errors better
 // TODO: Explore using rayon
@@ -69,7 +99,9 @@ pub trait MPC<T: AuthShare, M: BeaversMul<T>>: Send + Sync {
         shares: &[AuthAdditiveShare<T>],
     ) -> Result<Vec<T>, MpcError>;
 
-    /// Opens `shares` publically and returns result
+    /// Opens `shares` publically and returns result. Only values are
+    /// exchanged: the opened values are recorded alongside this party's MAC
+    /// shares to be verified by `mac_check`
     fn public_open<R: Read + Send + Unpin, W: Write + Send + Unpin>(
         &mut self,
         reader: &mut IMuxAsync<R>,
@@ -77,6 +109,31 @@ pub trait MPC<T: AuthShare, M: BeaversMul<T>>: Send + Sync {
         shares: &[AuthAdditiveShare<T>],
     ) -> Result<Vec<T>, MpcError>;
 
+    /// Opens `shares` publically, with the client committing to its shares
+    /// before seeing the server's. Only values are exchanged: the opened
+    /// values are recorded alongside this party's MAC shares to be verified
+    /// by `mac_check`
+    fn commit_open<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        shares: &[AuthAdditiveShare<T>],
+        rng: &mut RNG,
+    ) -> Result<Vec<T>, MpcError>;
+
+    /// Checks the MACs of every value opened by `public_open` or
+    /// `commit_open` since the last check. Shares of a random linear
+    /// combination of the MACs minus the MAC key times the opened values are
+    /// opened as in `commit_open` and must sum to zero, so either party learns
+    /// if the other deviated. Public constants added by either party are
+    /// accounted for by first opening the same combination of their epsilons.
+    fn mac_check<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+    ) -> Result<(), MpcError>;
+
     /// Add shares `x` and `y`
     fn add(
         &mut self,
@@ -180,51 +237,141 @@ pub trait MPC<T: AuthShare, M: BeaversMul<T>>: Send + Sync {
         // Consume necessary triples
         let triples = self.get_triples(x.len())?;
 
-        // Compute blinded shares using the triples.
-        let self_blinded_and_shared = izip!(x.iter(), y.iter(), triples.iter())
-            .map(|(left, right, t)| M::share_and_blind_inputs(left, right, t))
-            .collect::<Vec<_>>();
+        // Compute blinded shares using the triples and open them
+        let (blinded_x, blinded_y): (Vec<_>, Vec<_>) = izip!(x, y, &triples)
+            .map(|(left, right, t)| {
+                let blinded = M::share_and_blind_inputs(left, right, t);
+                (blinded.blinded_x, blinded.blinded_y)
+            })
+            .unzip();
+        let opened = self.public_open(reader, writer, &[blinded_x, blinded_y].concat())?;
+        let (opened_x, opened_y) = opened.split_at(x.len());
+
+        // Perform multiplication with the opened blinded inputs
+        Ok(izip!(opened_x, opened_y, &triples)
+            .map(|(blinded_x, blinded_y, triple)| {
+                let inp = BlindedInputs {
+                    blinded_x: *blinded_x,
+                    blinded_y: *blinded_y,
+                };
+                M::multiply_blinded_inputs(Self::PARTY_IDX, inp, triple)
+            })
+            .collect())
+    }
 
-        let mut result = Vec::with_capacity(triples.len());
-        let (snd, rcv) = crossbeam::channel::unbounded();
-        crossbeam::scope(|s| {
-            // Receive blinded shares
-            s.spawn(|_| {
-                for _ in self_blinded_and_shared.chunks(Self::BATCH_SIZE) {
-                    let in_msg: MulMsgRcv<_> = bytes::deserialize(&mut *reader).unwrap();
-                    let shares = in_msg.msg();
-                    snd.send(shares).unwrap();
-                }
-                // Need to drop the sending channel so the second thread doesn't
-                // block
-                drop(snd);
+    /// Squares shares `x`, consuming one square pair per element. Only
+    /// `x - a` is opened, half as much as multiplying `x` by itself.
+    fn square<R: Read + Send + Unpin, W: Write + Send + Unpin>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        x: &[AuthAdditiveShare<T>],
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError> {
+        let squares = self.get_squares(x.len())?;
+        let blinded: Vec<_> = izip!(x, &squares).map(|(x, s)| x - &s.a).collect();
+        let blinded = self.public_open(reader, writer, &blinded)?;
+        Ok(izip!(blinded, &squares)
+            .map(|(e, s)| M::square_blinded_input(Self::PARTY_IDX, e, s))
+            .collect())
+    }
+
+    /// Multiplies the `m x k` matrix shared in `x` by the `k x n` matrix
+    /// shared in `y`, consuming `triple` of dimensions `(m, k, n)`. Matrices
+    /// are in row-major order.
+    ///
+    /// Only `m * k + k * n` elements are opened, compared to `2 * m * k * n`
+    /// for the equivalent `mul`. Convolutions are lowered to `matmul` with
+    /// im2col in `conv`.
+    fn matmul<R: Read + Send + Unpin, W: Write + Send + Unpin>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        x: &[AuthAdditiveShare<T>],
+        y: &[AuthAdditiveShare<T>],
+        triple: &MatrixTriple<T>,
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>
+    where
+        T: PrimeField + Share<Constant = T>,
+    {
+        let (m, k, n) = triple.dims;
+        if x.len() != m * k {
+            return Err(MpcError::MismatchedInputLength {
+                left: x.len(),
+                right: m * k,
             });
-            // Send blinded shares
-            s.spawn(|_| {
-                for msg_contents in self_blinded_and_shared.chunks(Self::BATCH_SIZE) {
-                    let sent_message = MulMsgSend::new(&msg_contents);
-                    bytes::serialize(&mut *writer, &sent_message).unwrap();
-                }
+        }
+        if y.len() != k * n {
+            return Err(MpcError::MismatchedInputLength {
+                left: y.len(),
+                right: k * n,
             });
-            // Open blinded shares and perform multiplication
-            for (cur_chunk, other_chunk, triple_chunk) in izip!(
-                self_blinded_and_shared.chunks(Self::BATCH_SIZE),
-                rcv.iter(),
-                triples.chunks(Self::BATCH_SIZE)
-            ) {
-                let result_chunk: Vec<AuthAdditiveShare<T>> = izip!(cur_chunk, other_chunk.iter())
-                    .map(|(cur, other)| BlindedInputs {
-                        blinded_x: (cur.blinded_x + other.blinded_x).get_value().inner,
-                        blinded_y: (cur.blinded_y + other.blinded_y).get_value().inner,
-                    })
-                    .zip(triple_chunk)
-                    .map(|(inp, triple)| M::multiply_blinded_inputs(Self::PARTY_IDX, inp, triple))
-                    .collect();
-                result.extend_from_slice(result_chunk.as_slice());
+        }
+        // Open E = X - A and F = Y - B
+        let blinded: Vec<_> = izip!(x, &triple.a)
+            .map(|(x, a)| x - a)
+            .chain(izip!(y, &triple.b).map(|(y, b)| y - b))
+            .collect();
+        let opened = self.public_open(reader, writer, &blinded)?;
+        let (e, f) = opened.split_at(m * k);
+
+        // XY = C + EB + AF + EF
+        Ok((0..m * n)
+            .into_par_iter()
+            .map(|idx| {
+                let (i, j) = (idx / n, idx % n);
+                let (share, public) =
+                    (0..k).fold((triple.c[idx], T::zero()), |(share, public), l| {
+                        let (e, f) = (e[i * k + l], f[l * n + j]);
+                        (
+                            share + triple.b[l * n + j] * e + triple.a[i * k + l] * f,
+                            public + e * f,
+                        )
+                    });
+                add_public::<_, M, Self>(share, public)
+            })
+            .collect())
+    }
+
+    /// Convolves the images shared in `x` with the kernel shared in `kernel`,
+    /// consuming `triple`. The convolution is lowered to `matmul` with im2col,
+    /// so `triple` must have the dimensions given by `conv_triple_dims`.
+    #[allow(clippy::too_many_arguments)]
+    fn conv<R: Read + Send + Unpin, W: Write + Send + Unpin>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        x: &Input<AuthAdditiveShare<T>>,
+        kernel: &Kernel<AuthAdditiveShare<T>>,
+        padding: Padding,
+        stride: usize,
+        triple: &MatrixTriple<T>,
+    ) -> Result<Output<AuthAdditiveShare<T>>, MpcError>
+    where
+        T: PrimeField + Share<Constant = T>,
+    {
+        let (batch_size, in_channels, ..) = x.dim();
+        let (_, k_channels, k_height, k_width) = kernel.dim();
+        if in_channels != k_channels {
+            return Err(MpcError::MismatchedInputLength {
+                left: in_channels,
+                right: k_channels,
+            });
+        }
+        let out_dims = conv_output_dims(x.dim(), kernel.dim(), padding, stride);
+        let (_, _, out_height, out_width) = out_dims;
+        let patches = im2col(x, (k_height, k_width), padding, stride, out_dims);
+        let kernel: Vec<_> = kernel.iter().copied().collect();
+        let product = self.matmul(reader, writer, &kernel, &patches, triple)?;
+
+        // Each row of `product` holds one output channel of every image
+        let n = out_height * out_width;
+        let mut out = Vec::with_capacity(product.len());
+        for b_i in 0..batch_size {
+            for row in product.chunks(batch_size * n) {
+                out.extend_from_slice(&row[b_i * n..(b_i + 1) * n]);
             }
-        })
-        .unwrap();
-        Ok(result)
+        }
+        Ok(Output::from_shape_vec(out_dims, out).expect("Shapes should be same"))
     }
 
     /// Add shares `x` with constants `c`
@@ -264,6 +411,292 @@ pub trait MPC<T: AuthShare, M: BeaversMul<T>>: Send + Sync {
             .reduce(|| AuthAdditiveShare::zero(), |l, r| l + r)
     }
 
+    /// Checks that every share in `bits` is a bit by opening `b - b^2`.
+    /// Consumes one square pair per bit. The MACs of the openings are only
+    /// checked on the next call to `mac_check`.
+    fn check_bits<R: Read + Send + Unpin, W: Write + Send + Unpin>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        bits: &[AuthAdditiveShare<T>],
+    ) -> Result<(), MpcError> {
+        // TODO: Could do some sort of linear combo here if opening is expensive
+        let squares = self.square(reader, writer, bits)?;
+        let are_bits: Vec<_> = izip!(bits, squares).map(|(b, s)| *b - s).collect();
+        let are_bits = self.public_open(reader, writer, &are_bits)?;
+        if !are_bits.iter().all(|e| e.is_zero()) {
+            return Err(MpcError::NotBits);
+        }
+        Ok(())
+    }
+
+    /// Returns shares of `num` uniformly random bits which neither party
+    /// knows. Each party inputs a random bit and the result is their XOR.
+    /// Consumes `num` rands, `num` square pairs and `num` triples.
+    fn rand_bits<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        num: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError> {
+        let bits: Vec<T> = (0..num)
+            .map(|_| {
+                if rng.next_u32() & 1 == 1 {
+                    T::one()
+                } else {
+                    T::zero()
+                }
+            })
+            .collect();
+        // The server shares its bits first
+        let (server_bits, client_bits) = if Self::PARTY_IDX == 1 {
+            let server_bits = self.private_inputs(reader, writer, &bits, rng)?;
+            (server_bits, self.recv_private_inputs(reader, writer, num)?)
+        } else {
+            let server_bits = self.recv_private_inputs(reader, writer, num)?;
+            (
+                server_bits,
+                self.private_inputs(reader, writer, &bits, rng)?,
+            )
+        };
+        // Only the client's bits need to be checked
+        self.check_bits(reader, writer, &client_bits)?;
+        // s XOR c = s + c - 2sc
+        let products = self.mul(reader, writer, &server_bits, &client_bits)?;
+        Ok(izip!(server_bits, client_bits, products)
+            .map(|(s, c, p)| s + c - p - p)
+            .collect())
+    }
+
+    /// Returns the bit decomposition of every share in `x`. The bits of each
+    /// element are returned consecutively, least significant first, with as
+    /// many bits as the modulus.
+    fn bit_decompose<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        x: &[AuthAdditiveShare<T>],
+        rng: &mut RNG,
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>
+    where
+        T: PrimeField<BigInt = BigInteger64>,
+    {
+        let num_bits = T::size_in_bits();
+        let modulus = T::characteristic().0;
+        let (masks, masked) = open_masked::<_, M, _, _, _, _>(self, reader, writer, x, rng)?;
+        // If x + r did not wrap around the modulus then x = c - r, and
+        // otherwise x = c + p - r. Both differences are computed, and the
+        // borrow out of the first one selects between them.
+        let minuends: Vec<u64> = masked
+            .iter()
+            .flat_map(|c| vec![*c, c.wrapping_add(modulus)])
+            .collect();
+        let subtrahends: Vec<_> = masks
+            .chunks(num_bits)
+            .flat_map(|r| r.iter().chain(r).cloned())
+            .collect();
+        let (diffs, borrows) = sub_from_public::<_, M, _, _, _>(
+            self,
+            reader,
+            writer,
+            &minuends,
+            &subtrahends,
+            num_bits,
+        )?;
+        let wrapped: Vec<_> = borrows
+            .iter()
+            .step_by(2)
+            .flat_map(|w| vec![*w; num_bits])
+            .collect();
+        let (no_wrap, deltas): (Vec<_>, Vec<_>) = diffs
+            .chunks(2 * num_bits)
+            .flat_map(|d| {
+                let (no_wrap, wrap) = d.split_at(num_bits);
+                izip!(no_wrap, wrap).map(|(y, z)| (*y, *z - *y))
+            })
+            .unzip();
+        let selected = self.mul(reader, writer, &wrapped, &deltas)?;
+        self.add(&no_wrap, &selected)
+    }
+
+    /// Returns shares of 1 where `x` is less than `y` and 0 elsewhere. Field
+    /// elements above `(p - 1) / 2` are treated as negative, so the result is
+    /// correct whenever `x - y` lies in `(-p / 2, p / 2)`.
+    fn less_than<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        x: &[AuthAdditiveShare<T>],
+        y: &[AuthAdditiveShare<T>],
+        rng: &mut RNG,
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>
+    where
+        T: PrimeField<BigInt = BigInteger64>,
+    {
+        // x - y is negative exactly when doubling it wraps around the odd
+        // modulus, which makes the result odd
+        let doubled: Vec<_> = self.sub(x, y)?.into_iter().map(|d| d + d).collect();
+        let num_bits = T::size_in_bits();
+        let (masks, masked) = open_masked::<_, M, _, _, _, _>(self, reader, writer, &doubled, rng)?;
+        // 2(x - y) = c - r + wp, so its parity is c_0 XOR r_0 XOR w
+        let (_, wrapped) =
+            sub_from_public::<_, M, _, _, _>(self, reader, writer, &masked, &masks, num_bits)?;
+        let low_bits: Vec<_> = masks.iter().step_by(num_bits).cloned().collect();
+        let products = self.mul(reader, writer, &low_bits, &wrapped)?;
+        Ok(izip!(masked, low_bits, wrapped, products)
+            .map(|(c, r, w, p)| {
+                let r_xor_w = r + w - p - p;
+                if c & 1 == 1 {
+                    add_public::<_, M, Self>(-r_xor_w, T::one())
+                } else {
+                    r_xor_w
+                }
+            })
+            .collect())
+    }
+
+    /// Returns shares of 1 where `x` equals `y` and 0 elsewhere
+    fn equal<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        x: &[AuthAdditiveShare<T>],
+        y: &[AuthAdditiveShare<T>],
+        rng: &mut RNG,
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>
+    where
+        T: PrimeField<BigInt = BigInteger64>,
+    {
+        let diffs = self.sub(x, y)?;
+        if diffs.is_empty() {
+            return Ok(diffs);
+        }
+        let num_bits = T::size_in_bits();
+        let (masks, masked) = open_masked::<_, M, _, _, _, _>(self, reader, writer, &diffs, rng)?;
+        // x - y is zero exactly when every bit of the mask matches the opening
+        let mut terms: Vec<_> = izip!(masks.chunks(num_bits), masked)
+            .flat_map(|(r, c)| {
+                r.iter()
+                    .enumerate()
+                    .map(|(i, r)| {
+                        if (c >> i) & 1 == 1 {
+                            *r
+                        } else {
+                            add_public::<_, M, Self>(-*r, T::one())
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        // Multiply the matches of each element together in a tree
+        let mut len = num_bits;
+        while len > 1 {
+            let half = len / 2;
+            let (left, right): (Vec<_>, Vec<_>) = terms
+                .chunks(len)
+                .flat_map(|t| izip!(&t[..half], &t[half..2 * half]).map(|(l, r)| (*l, *r)))
+                .unzip();
+            let products = self.mul(reader, writer, &left, &right)?;
+            terms = izip!(products.chunks(half), terms.chunks(len))
+                .flat_map(|(p, t)| p.iter().chain(&t[2 * half..]).cloned().collect::<Vec<_>>())
+                .collect();
+            len -= half;
+        }
+        Ok(terms)
+    }
+
+    /// Returns masks for truncating `num` shares by `trunc_bits` bits with
+    /// `truncate`, so that they can be generated ahead of time. Each mask has
+    /// two bits less than the modulus, and consumes as many rands, square
+    /// pairs and triples.
+    fn truncation_masks<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        num: usize,
+        trunc_bits: usize,
+        rng: &mut RNG,
+    ) -> Result<Vec<TruncationMask<T>>, MpcError>
+    where
+        T: PrimeField,
+    {
+        let mask_bits = T::size_in_bits() - 2;
+        if trunc_bits >= mask_bits {
+            return Err(MpcError::InvalidBitLength {
+                bits: trunc_bits,
+                min: 0,
+                max: mask_bits - 1,
+            });
+        }
+        let bits = self.rand_bits(reader, writer, num * mask_bits, rng)?;
+        Ok(bits
+            .chunks(mask_bits)
+            .map(|bits| TruncationMask {
+                mask: compose(bits),
+                low_bits: compose(&bits[..trunc_bits]),
+                trunc_bits,
+            })
+            .collect())
+    }
+
+    /// Truncates every share in `x` by the `trunc_bits` of its mask. Each
+    /// value must be a signed integer of `value_bits` bits, and is masked with
+    /// statistical security of `modulus_bits - 2 - value_bits` bits.
+    ///
+    /// The truncation is probabilistic: the result is `floor(x / 2^m)` plus
+    /// one with probability `(x mod 2^m) / 2^m`, where `m` is `trunc_bits`.
+    fn truncate<R: Read + Send + Unpin, W: Write + Send + Unpin>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        x: &[AuthAdditiveShare<T>],
+        value_bits: usize,
+        masks: &[TruncationMask<T>],
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>
+    where
+        T: PrimeField<BigInt = BigInteger64> + Share<Constant = T>,
+    {
+        if x.len() != masks.len() {
+            return Err(MpcError::MismatchedInputLength {
+                left: x.len(),
+                right: masks.len(),
+            });
+        }
+        if value_bits == 0 || value_bits >= T::size_in_bits() - 2 {
+            return Err(MpcError::InvalidBitLength {
+                bits: value_bits,
+                min: 1,
+                max: T::size_in_bits() - 3,
+            });
+        }
+        if let Some(m) = masks.iter().find(|m| m.trunc_bits >= value_bits) {
+            return Err(MpcError::InvalidBitLength {
+                bits: m.trunc_bits,
+                min: 0,
+                max: value_bits - 1,
+            });
+        }
+        // Shifting x to be non-negative ensures that x + r does not wrap
+        // around the modulus
+        let offset = T::from_repr((1u64 << (value_bits - 1)).into());
+        let masked: Vec<_> = izip!(x, masks)
+            .map(|(x, m)| add_public::<_, M, Self>(*x + m.mask, offset))
+            .collect();
+        let masked = self.public_open(reader, writer, &masked)?;
+        // The low bits of c - r are the low bits of x + 2^(k - 1), up to a
+        // borrow. Subtracting them leaves a multiple of 2^m.
+        Ok(izip!(x, masks, masked)
+            .map(|(x, m, c)| {
+                let c_low = c.into_repr().0 & ((1u64 << m.trunc_bits) - 1);
+                let scale = T::from_repr((1u64 << m.trunc_bits).into())
+                    .inverse()
+                    .expect("powers of two are invertible");
+                add_public::<_, M, Self>(*x + m.low_bits, -T::from_repr(c_low.into())) * scale
+            })
+            .collect())
+    }
+
     /// Returns number of available triples
     fn num_triples(&self) -> usize;
 
@@ -275,26 +708,443 @@ pub trait MPC<T: AuthShare, M: BeaversMul<T>>: Send + Sync {
 
     /// Returns `num` rands if available
     fn get_rands(&mut self, num: usize) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>;
+
+    /// Returns number of available square pairs
+    fn num_squares(&self) -> usize;
+
+    /// Returns `num` square pairs if available
+    fn get_squares(&mut self, num: usize) -> Result<Vec<SquarePair<T>>, MpcError>;
+}
+
+/// Adds the public `c` to `x`. Only the server adds public constants so that
+/// they are added exactly once.
+fn add_public<T: AuthShare, M: BeaversMul<T>, S: MPC<T, M> + ?Sized>(
+    x: AuthAdditiveShare<T>,
+    c: T,
+) -> AuthAdditiveShare<T> {
+    if S::PARTY_IDX == 1 {
+        x.add_constant(c)
+    } else {
+        x
+    }
+}
+
+/// Returns the dimensions of the convolution of an input of dimensions
+/// `input_dims` with a kernel of dimensions `kernel_dims`
+fn conv_output_dims(
+    (batch_size, _, in_height, in_width): (usize, usize, usize, usize),
+    (out_channels, _, k_height, k_width): (usize, usize, usize, usize),
+    padding: Padding,
+    stride: usize,
+) -> (usize, usize, usize, usize) {
+    let p = match padding {
+        Padding::Same => (k_height - 1) / 2,
+        Padding::Valid => 0,
+    };
+    let out_height = (in_height - k_height + 2 * p) / stride + 1;
+    let out_width = (in_width - k_width + 2 * p) / stride + 1;
+    (batch_size, out_channels, out_height, out_width)
+}
+
+/// Returns the dimensions of the matrix triple consumed by `MPC::conv` for an
+/// input of dimensions `input_dims` and a kernel of dimensions `kernel_dims`
+pub fn conv_triple_dims(
+    input_dims: (usize, usize, usize, usize),
+    kernel_dims: (usize, usize, usize, usize),
+    padding: Padding,
+    stride: usize,
+) -> (usize, usize, usize) {
+    let (batch_size, out_channels, out_height, out_width) =
+        conv_output_dims(input_dims, kernel_dims, padding, stride);
+    let (_, in_channels, k_height, k_width) = kernel_dims;
+    (
+        out_channels,
+        in_channels * k_height * k_width,
+        batch_size * out_height * out_width,
+    )
+}
+
+/// Lays out the patch of `x` under each output position as a column of an
+/// `(in_channels * k_height * k_width) x (batch_size * out_height *
+/// out_width)` matrix. Padding is filled with shares of zero.
+fn im2col<T: AuthShare>(
+    x: &Input<AuthAdditiveShare<T>>,
+    (k_height, k_width): (usize, usize),
+    padding: Padding,
+    stride: usize,
+    (_, _, out_height, out_width): (usize, usize, usize, usize),
+) -> Vec<AuthAdditiveShare<T>> {
+    let (batch_size, in_channels, in_height, in_width) = x.dim();
+    let p = match padding {
+        Padding::Same => (k_height - 1) / 2,
+        Padding::Valid => 0,
+    };
+    let mut patches =
+        Vec::with_capacity(in_channels * k_height * k_width * batch_size * out_height * out_width);
+    for chan in 0..in_channels {
+        for k_i in 0..k_height {
+            for k_j in 0..k_width {
+                for b_i in 0..batch_size {
+                    for out_i in 0..out_height {
+                        for out_j in 0..out_width {
+                            let row = (out_i * stride + k_i).wrapping_sub(p);
+                            let col = (out_j * stride + k_j).wrapping_sub(p);
+                            patches.push(if row < in_height && col < in_width {
+                                x[[b_i, chan, row, col]]
+                            } else {
+                                AuthAdditiveShare::zero()
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    patches
+}
+
+/// Composes the integer whose bits are shared in `bits`, least significant
+/// first
+fn compose<T: AuthShare>(bits: &[AuthAdditiveShare<T>]) -> AuthAdditiveShare<T> {
+    bits.iter()
+        .rev()
+        .fold(AuthAdditiveShare::zero(), |acc, b| acc + acc + *b)
+}
+
+/// Subtracts the integers whose `num_bits` bits are shared consecutively in
+/// `bits`, least significant first, from the public `minuends` modulo
+/// `2^num_bits`. Returns the bits of each difference and the borrow out of
+/// its most significant bit, which is 1 exactly when the minuend is smaller.
+/// Consumes `num_bits - 1` triples per minuend over as many rounds.
+fn sub_from_public<T, M, S, R, W>(
+    mpc: &mut S,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    minuends: &[u64],
+    bits: &[AuthAdditiveShare<T>],
+    num_bits: usize,
+) -> Result<(Vec<AuthAdditiveShare<T>>, Vec<AuthAdditiveShare<T>>), MpcError>
+where
+    T: AuthShare,
+    M: BeaversMul<T>,
+    S: MPC<T, M> + ?Sized,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+{
+    if minuends.len() * num_bits != bits.len() {
+        return Err(MpcError::MismatchedInputLength {
+            left: minuends.len() * num_bits,
+            right: bits.len(),
+        });
+    }
+    let mut diffs = vec![AuthAdditiveShare::zero(); bits.len()];
+    let mut borrows = vec![AuthAdditiveShare::zero(); minuends.len()];
+    for i in 0..num_bits {
+        let r_i: Vec<_> = bits.iter().skip(i).step_by(num_bits).cloned().collect();
+        // There is no borrow into the least significant bit
+        let products = if i == 0 {
+            vec![AuthAdditiveShare::zero(); minuends.len()]
+        } else {
+            mpc.mul(reader, writer, &r_i, &borrows)?
+        };
+        for (j, (c, r, b, p)) in izip!(minuends, r_i, borrows.iter_mut(), products).enumerate() {
+            let r_xor_b = r + *b - p - p;
+            if (c >> i) & 1 == 1 {
+                diffs[j * num_bits + i] = add_public::<_, M, S>(-r_xor_b, T::one());
+                // 1 - r - b borrows only if both are set
+                *b = p;
+            } else {
+                diffs[j * num_bits + i] = r_xor_b;
+                // 0 - r - b borrows if either is set
+                *b = r + *b - p;
+            }
+        }
+    }
+    Ok((diffs, borrows))
+}
+
+/// Samples shares of a uniformly random element `r` for every element `x` of
+/// `x` along with the bits of `r`, and publicly opens `x + r`. Returns the bits
+/// of every `r`, laid out as in `MPC::bit_decompose`, and the openings.
+fn open_masked<T, M, S, R, W, RNG>(
+    mpc: &mut S,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    x: &[AuthAdditiveShare<T>],
+    rng: &mut RNG,
+) -> Result<(Vec<AuthAdditiveShare<T>>, Vec<u64>), MpcError>
+where
+    T: AuthShare + PrimeField<BigInt = BigInteger64>,
+    M: BeaversMul<T>,
+    S: MPC<T, M> + ?Sized,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let num_bits = T::size_in_bits();
+    let max = T::characteristic().0 - 1;
+    let mut masks = Vec::with_capacity(x.len() * num_bits);
+    // Random bits may compose an integer larger than the modulus, so such
+    // masks are rejected by opening whether they are
+    while masks.len() < x.len() * num_bits {
+        let needed = x.len() - masks.len() / num_bits;
+        let bits = mpc.rand_bits(reader, writer, needed * num_bits, rng)?;
+        let (_, too_large) = sub_from_public::<_, M, _, _, _>(
+            mpc,
+            reader,
+            writer,
+            &vec![max; needed],
+            &bits,
+            num_bits,
+        )?;
+        let too_large = mpc.public_open(reader, writer, &too_large)?;
+        for (mask, t) in izip!(bits.chunks(num_bits), too_large) {
+            if t.is_zero() {
+                masks.extend_from_slice(mask);
+            }
+        }
+    }
+    let masked: Vec<_> = izip!(x, masks.chunks(num_bits))
+        .map(|(x, r)| *x + compose(r))
+        .collect();
+    let masked = mpc.public_open(reader, writer, &masked)?;
+    Ok((masks, masked.iter().map(|c| c.into_repr().0).collect()))
+}
+
+/// Hash commitment to `value` under `seed`
+fn commitment<V: Serialize + ?Sized>(value: &V, seed: [u8; 32]) -> Result<[u8; 32], MpcError> {
+    let mut commitment = ShaCommitment::new(seed);
+    commitment.input(&bincode::serialize(value)?);
+    Ok(commitment.finish())
+}
+
+/// Sends a commitment to `value`, receives the other party's value and only
+/// then opens `value`. Returns the other party's value.
+fn commit_then_open<V, R, W, RNG>(
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    value: &V,
+    rng: &mut RNG,
+) -> Result<V, MpcError>
+where
+    V: Serialize + DeserializeOwned,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let seed = sample_seed(rng);
+    bytes::serialize(
+        &mut *writer,
+        &CommitmentSend::new(&commitment(value, seed)?),
+    )?;
+    let other: CommittedRcv<V> = bytes::deserialize(&mut *reader)?;
+    bytes::serialize(&mut *writer, &CommittedSend::new(value))?;
+    bytes::serialize(&mut *writer, &SeedSend::new(&seed))?;
+    Ok(other.msg())
+}
+
+/// Counterpart to `commit_then_open`: receives the other party's commitment,
+/// sends `value`, and returns the other party's value if it matches the
+/// commitment
+fn open_then_verify<V, R, W>(
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    value: &V,
+) -> Result<V, MpcError>
+where
+    V: Serialize + DeserializeOwned,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+{
+    let committed: CommitmentRcv = bytes::deserialize(&mut *reader)?;
+    bytes::serialize(&mut *writer, &CommittedSend::new(value))?;
+    let other: CommittedRcv<V> = bytes::deserialize(&mut *reader)?;
+    let seed: SeedRcv = bytes::deserialize(&mut *reader)?;
+    let other = other.msg();
+    if !ShaCommitment::check(&committed.msg(), &commitment(&other, seed.msg())?) {
+        return Err(MpcError::InvalidCommitment);
+    }
+    Ok(other)
+}
+
+/// Sends the value shares of `shares` in batches of `batch_size`
+fn send_value_shares<P, W>(
+    writer: &mut IMuxAsync<W>,
+    shares: &[AuthAdditiveShare<Fp64<P>>],
+    batch_size: usize,
+) -> Result<(), MpcError>
+where
+    P: Fp64Parameters,
+    W: Write + Send + Unpin,
+{
+    let stripped_shares: Vec<AdditiveShare<Fp64<P>>> =
+        shares.par_iter().map(|e| e.get_value()).collect();
+    for shares in stripped_shares.chunks(batch_size) {
+        let packed = PackedSlice(shares);
+        let send_message = ShareSend::new(&packed);
+        bytes::serialize(&mut *writer, &send_message)?;
+    }
+    Ok(())
+}
+
+/// Receives `num` value shares sent by `send_value_shares`
+fn recv_value_shares<P, R>(
+    reader: &mut IMuxAsync<R>,
+    num: usize,
+    batch_size: usize,
+) -> Result<Vec<Fp64<P>>, MpcError>
+where
+    P: Fp64Parameters,
+    R: Read + Send + Unpin,
+{
+    let mut recv_shares = Vec::with_capacity(num);
+    for _ in 0..((num as f64 / batch_size as f64).ceil() as usize) {
+        let recv_message: ShareRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+        recv_shares.extend(recv_message.msg().0.iter().map(|s| s.inner));
+    }
+    Ok(recv_shares)
+}
+
+/// Adds the other party's value shares `other` to `shares`, recording every
+/// opened value with this party's MAC share and epsilon in `opened`
+fn record_opened<P: Fp64Parameters>(
+    opened: &mut Vec<(Fp64<P>, Fp64<P>, Fp64<P>)>,
+    shares: &[AuthAdditiveShare<Fp64<P>>],
+    other: &[Fp64<P>],
+) -> Result<Vec<Fp64<P>>, MpcError> {
+    if shares.len() != other.len() {
+        return Err(MpcError::MismatchedInputLength {
+            left: shares.len(),
+            right: other.len(),
+        });
+    }
+    Ok(izip!(shares, other)
+        .map(|(s, o)| {
+            let value = s.get_value().inner + o;
+            opened.push((value, s.get_mac().inner, s.get_epsilon().inner));
+            value
+        })
+        .collect())
+}
+
+/// Returns `sum_j r_j * x_j`, `sum_j r_j * mac_j` and `sum_j r_j * epsilon_j`
+/// over every opened `(x_j, mac_j, epsilon_j)`, where the `r_j` are derived
+/// from the jointly sampled `seed`
+fn combine_opened<P: Fp64Parameters>(
+    opened: &[(Fp64<P>, Fp64<P>, Fp64<P>)],
+    seed: [u8; 32],
+) -> (Fp64<P>, Fp64<P>, Fp64<P>) {
+    let mut rng = ChaChaRng::from_seed(seed);
+    opened.iter().fold(
+        (Fp64::zero(), Fp64::zero(), Fp64::zero()),
+        |(value, mac, epsilon), (x, m, e)| {
+            let r = Fp64::<P>::uniform(&mut rng);
+            (value + r * x, mac + r * m, epsilon + r * e)
+        },
+    )
+}
+
+/// Returns this party's share of `sum_j r_j * (mac_j - key * (x_j - epsilon_j))`
+/// given the combinations returned by `combine_opened` and the other party's
+/// combined epsilons. The shares of both parties sum to zero iff all MACs are
+/// valid, except with probability `1/p`.
+fn mac_check_share<P: Fp64Parameters>(
+    (value, mac, epsilon): (Fp64<P>, Fp64<P>, Fp64<P>),
+    other_epsilon: Fp64<P>,
+    key_share: Fp64<P>,
+) -> Fp64<P> {
+    mac - key_share * (value - epsilon - other_epsilon)
+}
+
+/// XORs the coins sampled by both parties into a single seed
+fn combine_coins(coin: [u8; 32], other: [u8; 32]) -> [u8; 32] {
+    let mut seed = coin;
+    izip!(seed.iter_mut(), &other).for_each(|(s, o)| *s ^= o);
+    seed
+}
+
+/// Removes and returns `num` of `masks` if available
+fn take_input_masks<T: AuthShare>(
+    masks: &mut Vec<AuthAdditiveShare<T>>,
+    num: usize,
+) -> Result<Vec<AuthAdditiveShare<T>>, MpcError> {
+    if masks.len() < num {
+        return Err(MpcError::InsufficientInputMasks {
+            num: masks.len(),
+            needed: num,
+        });
+    }
+    Ok(masks.split_off(masks.len() - num))
+}
+
+/// The MAC key held by the server
+#[derive(Clone, Copy, Debug)]
+enum MacKey<T> {
+    /// The whole key
+    Full(T),
+    /// The server's share of a key shared with the client
+    Share(T),
 }
 
 /// Client MPC instance
 pub struct ClientMPC<T: AuthShare> {
     rands: Vec<AuthAdditiveShare<T>>,
     triples: Vec<Triple<T>>,
+    squares: Vec<SquarePair<T>>,
+    /// Client's share of the MAC key, `None` if the server holds the whole key
+    mac_key_share: Option<<T as Share>::Ring>,
+    /// Shares of the masks of the server's inputs if the key is shared
+    input_masks: Vec<AuthAdditiveShare<T>>,
+    /// Values opened by `public_open` and `commit_open` with the client's MAC
+    /// shares and epsilons
+    opened: Vec<(T, T, T)>,
 }
 
 /// Server MPC instance
 pub struct ServerMPC<T: AuthShare> {
     rands: Vec<AuthAdditiveShare<T>>,
     triples: Vec<Triple<T>>,
-    mac_key: <T as Share>::Ring,
+    squares: Vec<SquarePair<T>>,
+    mac_key: MacKey<<T as Share>::Ring>,
+    /// Masks of the server's inputs if the key is shared
+    input_masks: Vec<AuthAdditiveShare<T>>,
     /// Opened auth_shares with unchecked MACs
     unchecked: Vec<AuthAdditiveShare<T>>,
+    /// Values opened by `public_open` and `commit_open` with the server's MAC
+    /// shares and epsilons
+    opened: Vec<(T, T, T)>,
 }
 
 impl<P: Fp64Parameters> ClientMPC<Fp64<P>> {
     pub fn new(rands: Vec<AuthAdditiveShare<Fp64<P>>>, triples: Vec<Triple<Fp64<P>>>) -> Self {
-        Self { rands, triples }
+        Self {
+            rands,
+            triples,
+            squares: Vec::new(),
+            mac_key_share: None,
+            input_masks: Vec::new(),
+            opened: Vec::new(),
+        }
+    }
+
+    /// Provides `squares` to be consumed by `square`
+    pub fn with_squares(mut self, squares: Vec<SquarePair<Fp64<P>>>) -> Self {
+        self.squares = squares;
+        self
+    }
+
+    /// Sets the client's share of the MAC key used by `mac_check`, as
+    /// generated by `client_share_mac_key`. The server must then be built with
+    /// `ServerMPC::with_mac_key_share`, and `recv_private_inputs` consumes
+    /// `input_masks` from `client_input_masks`.
+    pub fn with_mac_key_share(
+        mut self,
+        mac_key_share: <Fp64<P> as Share>::Ring,
+        input_masks: Vec<AuthAdditiveShare<Fp64<P>>>,
+    ) -> Self {
+        self.mac_key_share = Some(mac_key_share);
+        self.input_masks = input_masks;
+        self
     }
 }
 
@@ -307,19 +1157,50 @@ impl<P: Fp64Parameters> ServerMPC<Fp64<P>> {
         Self {
             rands,
             triples,
-            mac_key,
+            squares: Vec::new(),
+            mac_key: MacKey::Full(mac_key),
+            input_masks: Vec::new(),
             unchecked: Vec::with_capacity(Self::BATCH_SIZE * 100),
+            opened: Vec::new(),
+        }
+    }
+
+    /// Provides `squares` to be consumed by `square`
+    pub fn with_squares(mut self, squares: Vec<SquarePair<Fp64<P>>>) -> Self {
+        self.squares = squares;
+        self
+    }
+
+    /// Treats the `mac_key` passed to `new` as only the server's share of a
+    /// key shared with the client by `server_share_mac_key`. `private_inputs`
+    /// then consumes `input_masks` from `server_input_masks`, and
+    /// `check_macs` and `private_recv` return `MpcError::MacKeyShared`.
+    pub fn with_mac_key_share(mut self, input_masks: Vec<AuthAdditiveShare<Fp64<P>>>) -> Self {
+        self.mac_key = MacKey::Share(self.mac_key_share());
+        self.input_masks = input_masks;
+        self
+    }
+
+    /// Returns the server's share of the MAC key, which is the whole key
+    /// unless it is shared with the client
+    fn mac_key_share(&self) -> Fp64<P> {
+        match self.mac_key {
+            MacKey::Full(key) | MacKey::Share(key) => key,
         }
     }
 
     /// Check all AuthAdditiveShares in `unchecked`
     pub fn check_macs(&mut self) -> Result<(), MpcError> {
+        let mac_key = match self.mac_key {
+            MacKey::Full(key) => key,
+            MacKey::Share(_) => return Err(MpcError::MacKeyShared),
+        };
         if !self
             .unchecked
             .drain(..)
             .collect::<Vec<_>>()
             .par_iter()
-            .map(|s| AuthShare::open(*s, &self.mac_key).is_ok())
+            .map(|s| AuthShare::open(*s, &mac_key).is_ok())
             .reduce(|| true, |a, b| a && b)
         {
             return Err(MpcError::InvalidMAC);
@@ -342,9 +1223,11 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ClientMPC<Fp64<P>> {
         // Consume necessary random shares
         let rands = self.get_rands(inputs.len())?;
         let (snd, rcv) = crossbeam::channel::unbounded();
+        let scope = comm::scope();
         crossbeam::scope(|s| {
             // Receive rand openings
             s.spawn(|_| {
+                let _scope = scope.enter();
                 for r_chunk in rands.chunks(Self::BATCH_SIZE) {
                     snd.send(self.private_recv(reader, r_chunk).unwrap())
                         .unwrap()
@@ -361,7 +1244,8 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ClientMPC<Fp64<P>> {
                         epsilon
                     })
                     .collect();
-                let send_message = ConstantMsgSend::new(epsilon_vec.as_slice());
+                let packed = PackedSlice(epsilon_vec.as_slice());
+                let send_message = ConstantMsgSend::new(&packed);
                 bytes::serialize(&mut *writer, &send_message).unwrap();
             }
         })
@@ -369,22 +1253,20 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ClientMPC<Fp64<P>> {
         Ok(rands)
     }
 
-    /// Receive `num_recv` shares from the server
+    /// Receive `num_recv` shares from the server, which are expanded from a
+    /// seed. If the MAC key is shared they are shares of the server's input
+    /// masks instead, and nothing is received.
     fn recv_private_inputs<R: Read + Send + Unpin, W: Write + Send + Unpin>(
         &mut self,
         reader: &mut IMuxAsync<R>,
         _writer: &mut IMuxAsync<W>,
         num_recv: usize,
     ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError> {
-        let mut shares = Vec::with_capacity(num_recv);
-
-        // Send shares to client
-        // TODO: Thread this
-        for _ in 0..((num_recv as f64 / Self::BATCH_SIZE as f64).ceil() as usize) {
-            let recv_message: AuthShareRcv<Fp64<P>> = bytes::deserialize(&mut *reader).unwrap();
-            shares.extend(recv_message.msg());
+        if self.mac_key_share.is_some() {
+            return take_input_masks(&mut self.input_masks, num_recv);
         }
-        Ok(shares)
+        let seed: SeedRcv = bytes::deserialize(&mut *reader)?;
+        Ok(auth_shares_from_seed(seed.msg(), num_recv))
     }
 
     /// To open a share to the server, the client sends full AuthAdditiveShare
@@ -394,7 +1276,8 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ClientMPC<Fp64<P>> {
         shares: &[AuthAdditiveShare<Fp64<P>>],
     ) -> Result<(), MpcError> {
         for shares_chunk in shares.chunks(Self::BATCH_SIZE) {
-            let send_message = AuthShareSend::new(shares_chunk);
+            let packed = PackedSlice(shares_chunk);
+            let send_message = AuthShareSend::new(&packed);
             bytes::serialize(&mut *writer, &send_message)?;
         }
         Ok(())
@@ -407,25 +1290,56 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ClientMPC<Fp64<P>> {
         reader: &mut IMuxAsync<R>,
         shares: &[AuthAdditiveShare<Fp64<P>>],
     ) -> Result<Vec<Fp64<P>>, MpcError> {
-        let mut recv_shares = Vec::with_capacity(shares.len());
-        for _ in 0..((shares.len() as f64 / Self::BATCH_SIZE as f64).ceil() as usize) {
-            let recv_message: ShareRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
-            recv_shares.extend(recv_message.msg());
-        }
+        let recv_shares = recv_value_shares(reader, shares.len(), Self::BATCH_SIZE)?;
         let result = izip!(shares.iter(), recv_shares.iter())
-            .map(|(s1, s2)| (s1 + s2).get_value().inner)
+            .map(|(s1, s2)| s1.get_value().inner + *s2)
             .collect();
         Ok(result)
     }
 
+    /// The client sends its value shares before receiving the server's
     fn public_open<R: Read + Send + Unpin, W: Write + Send + Unpin>(
         &mut self,
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
         shares: &[AuthAdditiveShare<Fp64<P>>],
     ) -> Result<Vec<Fp64<P>>, MpcError> {
-        self.private_open(writer, shares)?;
-        self.private_recv(reader, shares)
+        send_value_shares(writer, shares, Self::BATCH_SIZE)?;
+        let other = recv_value_shares(reader, shares.len(), Self::BATCH_SIZE)?;
+        record_opened(&mut self.opened, shares, &other)
+    }
+
+    /// The client commits to its shares before receiving the server's
+    fn commit_open<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        shares: &[AuthAdditiveShare<Fp64<P>>],
+        rng: &mut RNG,
+    ) -> Result<Vec<Fp64<P>>, MpcError> {
+        let values: Vec<_> = shares.iter().map(|s| s.get_value().inner).collect();
+        let other = commit_then_open(reader, writer, &values, rng)?;
+        record_opened(&mut self.opened, shares, &other)
+    }
+
+    fn mac_check<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+    ) -> Result<(), MpcError> {
+        let coin = sample_seed(rng);
+        let other_coin = commit_then_open(reader, writer, &coin, rng)?;
+        let combined = combine_opened(&self.opened, combine_coins(coin, other_coin));
+        self.opened.clear();
+        let other_epsilon = commit_then_open(reader, writer, &combined.2, rng)?;
+        let key_share = self.mac_key_share.unwrap_or_else(Fp64::zero);
+        let sigma = mac_check_share(combined, other_epsilon, key_share);
+        let other_sigma = commit_then_open(reader, writer, &sigma, rng)?;
+        if !(sigma + other_sigma).is_zero() {
+            return Err(MpcError::InvalidMAC);
+        }
+        Ok(())
     }
 
     fn num_triples(&self) -> usize {
@@ -456,6 +1370,20 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ClientMPC<Fp64<P>> {
         }
         Ok(self.rands.split_off(self.rands.len() - num))
     }
+
+    fn num_squares(&self) -> usize {
+        self.squares.len()
+    }
+
+    fn get_squares(&mut self, num: usize) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError> {
+        if self.squares.len() < num {
+            return Err(MpcError::InsufficientSquares {
+                num: self.squares.len(),
+                needed: num,
+            });
+        }
+        Ok(self.squares.split_off(self.squares.len() - num))
+    }
 }
 
 impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ServerMPC<Fp64<P>> {
@@ -469,18 +1397,26 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ServerMPC<Fp64<P>> {
         inputs: &[Fp64<P>],
         rng: &mut RNG,
     ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError> {
-        // Secret share all inputs
-        let (server_shares, client_shares): (Vec<_>, Vec<_>) = inputs
-            .iter()
-            .map(|e| e.auth_share(&self.mac_key, rng))
-            .unzip();
-
-        // Send shares to client
-        // TODO: Thread this
-        for client_share in client_shares.chunks(Self::BATCH_SIZE) {
-            let send_message = AuthShareSend::new(client_share);
-            bytes::serialize(&mut *writer, &send_message)?;
-        }
+        let mac_key = match self.mac_key {
+            MacKey::Full(key) => key,
+            MacKey::Share(_) => {
+                // Only the server knows the value of each mask, which is its
+                // value share, so it adds the difference to its input as a
+                // public constant without sending anything
+                let masks = take_input_masks(&mut self.input_masks, inputs.len())?;
+                return Ok(izip!(inputs, masks)
+                    .map(|(x, m)| m.add_constant(*x - m.get_value().inner))
+                    .collect());
+            }
+        };
+        // The client's shares are uniformly random, so only send the seed they
+        // are expanded from and keep their complements
+        let seed = sample_seed(rng);
+        let client_shares = auth_shares_from_seed(seed, inputs.len());
+        let server_shares = izip!(inputs, &client_shares)
+            .map(|(e, s)| complement_auth_share(*e, mac_key, s))
+            .collect();
+        bytes::serialize(&mut *writer, &SeedSend::new(&seed))?;
         Ok(server_shares)
     }
 
@@ -494,9 +1430,11 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ServerMPC<Fp64<P>> {
         // Consume necessary random shares
         let rands = self.get_rands(num_recv)?;
         let mut result = Vec::with_capacity(num_recv);
+        let scope = comm::scope();
         crossbeam::scope(|s| {
             // Send rand openings
             s.spawn(|_| {
+                let _scope = scope.enter();
                 for r_chunk in rands.chunks(Self::BATCH_SIZE) {
                     self.private_open(writer, r_chunk).unwrap()
                 }
@@ -505,7 +1443,7 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ServerMPC<Fp64<P>> {
             for r_chunk in rands.chunks(Self::BATCH_SIZE) {
                 let recv_message: ConstantMsgRcv<Fp64<P>> =
                     bytes::deserialize(&mut *reader).unwrap();
-                let epsilon = recv_message.msg();
+                let epsilon = recv_message.msg().0;
                 izip!(r_chunk, epsilon.iter()).for_each(|(r, e)| result.push(r.add_constant(*e)));
             }
         })
@@ -519,27 +1457,24 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ServerMPC<Fp64<P>> {
         writer: &mut IMuxAsync<W>,
         shares: &[AuthAdditiveShare<Fp64<P>>],
     ) -> Result<(), MpcError> {
-        let stripped_shares: Vec<AdditiveShare<Fp64<P>>> =
-            shares.par_iter().map(|e| e.get_value()).collect();
-        for shares in stripped_shares.chunks(Self::BATCH_SIZE) {
-            let send_message = ShareSend::new(shares);
-            bytes::serialize(&mut *writer, &send_message)?;
-        }
-        Ok(())
+        send_value_shares(writer, shares, Self::BATCH_SIZE)
     }
 
     /// To receive a share from the client, the server is sent an
     /// AuthAdditiveShare. It adds the share to `unchecked` and
-    /// eagerly opens value
+    /// eagerly opens value. The MACs can only be checked with the whole key.
     fn private_recv<R: Read + Send + Unpin>(
         &mut self,
         reader: &mut IMuxAsync<R>,
         shares: &[AuthAdditiveShare<Fp64<P>>],
     ) -> Result<Vec<Fp64<P>>, MpcError> {
+        if let MacKey::Share(_) = self.mac_key {
+            return Err(MpcError::MacKeyShared);
+        }
         let mut recv_shares = Vec::with_capacity(shares.len());
         for _ in 0..((shares.len() as f64 / Self::BATCH_SIZE as f64).ceil() as usize) {
             let recv_message: AuthShareRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
-            recv_shares.extend(recv_message.msg());
+            recv_shares.extend(recv_message.msg().0);
         }
         let result = izip!(shares.iter(), recv_shares.iter())
             .map(|(s1, s2)| {
@@ -551,15 +1486,49 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ServerMPC<Fp64<P>> {
         Ok(result)
     }
 
+    /// The server only sends its value shares after receiving the client's
     fn public_open<R: Read + Send + Unpin, W: Write + Send + Unpin>(
         &mut self,
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
         shares: &[AuthAdditiveShare<Fp64<P>>],
     ) -> Result<Vec<Fp64<P>>, MpcError> {
-        let result = Self::private_recv(self, reader, shares);
-        self.private_open(writer, shares)?;
-        result
+        let other = recv_value_shares(reader, shares.len(), Self::BATCH_SIZE)?;
+        send_value_shares(writer, shares, Self::BATCH_SIZE)?;
+        record_opened(&mut self.opened, shares, &other)
+    }
+
+    /// The server only sends its shares after receiving the client's
+    /// commitment
+    fn commit_open<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        shares: &[AuthAdditiveShare<Fp64<P>>],
+        _: &mut RNG,
+    ) -> Result<Vec<Fp64<P>>, MpcError> {
+        let values: Vec<_> = shares.iter().map(|s| s.get_value().inner).collect();
+        let other = open_then_verify(reader, writer, &values)?;
+        record_opened(&mut self.opened, shares, &other)
+    }
+
+    fn mac_check<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &mut self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+    ) -> Result<(), MpcError> {
+        let coin = sample_seed(rng);
+        let other_coin = open_then_verify(reader, writer, &coin)?;
+        let combined = combine_opened(&self.opened, combine_coins(coin, other_coin));
+        self.opened.clear();
+        let other_epsilon = open_then_verify(reader, writer, &combined.2)?;
+        let sigma = mac_check_share(combined, other_epsilon, self.mac_key_share());
+        let other_sigma = open_then_verify(reader, writer, &sigma)?;
+        if !(sigma + other_sigma).is_zero() {
+            return Err(MpcError::InvalidMAC);
+        }
+        Ok(())
     }
 
     fn num_triples(&self) -> usize {
@@ -590,21 +1559,32 @@ impl<P: Fp64Parameters> MPC<Fp64<P>, PBeaversMul<P>> for ServerMPC<Fp64<P>> {
         }
         Ok(self.rands.split_off(self.rands.len() - num))
     }
+
+    fn num_squares(&self) -> usize {
+        self.squares.len()
+    }
+
+    fn get_squares(&mut self, num: usize) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError> {
+        if self.squares.len() < num {
+            return Err(MpcError::InsufficientSquares {
+                num: self.squares.len(),
+                needed: num,
+            });
+        }
+        Ok(self.squares.split_off(self.squares.len() - num))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::{memory, LinkConfig};
     use algebra::{fields::near_mersenne_64::F, UniformRandom};
-    use async_std::{
-        io::{BufReader, BufWriter, Read, Write},
-        net::{TcpListener, TcpStream},
-        task,
-    };
+    use async_std::io::{Read, Write};
     use crypto_primitives::beavers_mul::InsecureTripleGen;
-    use futures::stream::StreamExt;
     use io_utils::imux::IMuxAsync;
-    use num_traits::identities::Zero;
+    use neural_network::layers::convolution::Conv2dParams;
+    use num_traits::identities::{One, Zero};
     use rand::{Rng, SeedableRng};
     use rand_chacha::ChaChaRng;
 
@@ -614,43 +1594,11 @@ mod tests {
         0x52, 0xd2,
     ];
 
-    fn get_connection(
-        server_addr: &str,
-    ) -> (
+    fn get_connection() -> (
         (IMuxAsync<impl Read>, IMuxAsync<impl Write>),
         (IMuxAsync<impl Read>, IMuxAsync<impl Write>),
     ) {
-        crossbeam::thread::scope(|s| {
-            let server_io = s.spawn(|_| {
-                task::block_on(async {
-                    let server_listener = TcpListener::bind(server_addr).await.unwrap();
-                    let stream = server_listener
-                        .incoming()
-                        .next()
-                        .await
-                        .unwrap()
-                        .expect("Server connection failed!");
-                    let read_stream = IMuxAsync::new(vec![BufReader::new(stream.clone())]);
-                    let write_stream = IMuxAsync::new(vec![BufWriter::new(stream)]);
-                    (read_stream, write_stream)
-                })
-            });
-            // Sometimes the client thread will start too soon and connection fails so put a
-            // small delay
-            std::thread::sleep(std::time::Duration::from_millis(10));
-            let client_io = s.spawn(|_| {
-                task::block_on(async {
-                    let stream = TcpStream::connect(server_addr)
-                        .await
-                        .expect("Client connection failed!");
-                    let read_stream = IMuxAsync::new(vec![BufReader::new(stream.clone())]);
-                    let write_stream = IMuxAsync::new(vec![BufWriter::new(stream)]);
-                    (read_stream, write_stream)
-                })
-            });
-            (client_io.join().unwrap(), server_io.join().unwrap())
-        })
-        .unwrap()
+        memory::connect(1, LinkConfig::default())
     }
 
     fn insecure_gen(
@@ -679,13 +1627,45 @@ mod tests {
         ((rands_1, rands_2), (triples_1, triples_2))
     }
 
+    fn insecure_squares(mac_key: F, num: usize) -> (Vec<SquarePair<F>>, Vec<SquarePair<F>>) {
+        let mut gen = InsecureTripleGen::<F>::new(RANDOMNESS);
+        (0..num)
+            .map(|_| gen.generate_square_shares(mac_key))
+            .unzip()
+    }
+
+    /// Runs `mac_check` for both parties, returning the client's and the
+    /// server's results
+    fn mac_check_both<CR, CW, SR, SW>(
+        client_mpc: &mut ClientMPC<F>,
+        server_mpc: &mut ServerMPC<F>,
+        (client_reader, client_writer): (&mut IMuxAsync<CR>, &mut IMuxAsync<CW>),
+        (server_reader, server_writer): (&mut IMuxAsync<SR>, &mut IMuxAsync<SW>),
+    ) -> (Result<(), MpcError>, Result<(), MpcError>)
+    where
+        CR: Read + Send + Unpin,
+        CW: Write + Send + Unpin,
+        SR: Read + Send + Unpin,
+        SW: Write + Send + Unpin,
+    {
+        let mut client_rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mut server_rng = ChaChaRng::from_seed(RANDOMNESS);
+        crossbeam::thread::scope(|s| {
+            let client_check =
+                s.spawn(|_| client_mpc.mac_check(client_reader, client_writer, &mut client_rng));
+            let server_check =
+                s.spawn(|_| server_mpc.mac_check(server_reader, server_writer, &mut server_rng));
+            (client_check.join().unwrap(), server_check.join().unwrap())
+        })
+        .unwrap()
+    }
+
     #[test]
     fn test_private_open() {
-        let server_addr = "127.0.0.1:8010";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
 
@@ -739,11 +1719,10 @@ mod tests {
 
     #[test]
     fn test_public_open() {
-        let server_addr = "127.0.0.1:8011";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
 
@@ -788,11 +1767,10 @@ mod tests {
 
     #[test]
     fn test_invalid_mac() {
-        let server_addr = "127.0.0.1:8012";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
         let ((mut c_rands, s_rands), (..)) = insecure_gen(mac_key, num_rands, 0);
@@ -830,16 +1808,140 @@ mod tests {
         });
 
         // The MAC check should fail
-        assert!(server_mpc.check_macs().is_err());
+        let (_, server_check) = mac_check_both(
+            &mut client_mpc,
+            &mut server_mpc,
+            (&mut client_reader, &mut client_writer),
+            (&mut server_reader, &mut server_writer),
+        );
+        match server_check {
+            Err(MpcError::InvalidMAC) => {}
+            result => panic!("Expected InvalidMAC, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_commit_open() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let server_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+
+        let num_rands = 100000;
+        let ((c_rands, s_rands), (..)) = insecure_gen(mac_key, num_rands, 0);
+        let mut client_mpc = ClientMPC::new(Vec::new(), Vec::new())
+            .with_mac_key_share(mac_key - server_key, Vec::new());
+        let mut server_mpc =
+            ServerMPC::new(Vec::new(), Vec::new(), server_key).with_mac_key_share(Vec::new());
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let opened = server_mpc
+                    .commit_open(
+                        &mut server_reader,
+                        &mut server_writer,
+                        &s_rands,
+                        &mut server_rng,
+                    )
+                    .unwrap();
+                let check =
+                    server_mpc.mac_check(&mut server_reader, &mut server_writer, &mut server_rng);
+                (opened, check)
+            });
+            let client_result = s.spawn(|_| {
+                let opened = client_mpc
+                    .commit_open(
+                        &mut client_reader,
+                        &mut client_writer,
+                        &c_rands,
+                        &mut client_rng,
+                    )
+                    .unwrap();
+                let check =
+                    client_mpc.mac_check(&mut client_reader, &mut client_writer, &mut client_rng);
+                (opened, check)
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        let ((client_opened, client_check), (server_opened, server_check)) =
+            (client_result, server_result);
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+        izip!(&c_rands, &s_rands, client_opened, server_opened).for_each(|(c, s, co, so)| {
+            let expected = (c + s).get_value().inner;
+            assert_eq!(co, expected);
+            assert_eq!(so, expected);
+        });
+    }
+
+    #[test]
+    fn test_commit_open_invalid_mac() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let server_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+
+        let num_rands = 1000;
+        let ((c_rands, mut s_rands), (..)) = insecure_gen(mac_key, num_rands, 0);
+        let mut client_mpc = ClientMPC::new(Vec::new(), Vec::new())
+            .with_mac_key_share(mac_key - server_key, Vec::new());
+        let mut server_mpc =
+            ServerMPC::new(Vec::new(), Vec::new(), server_key).with_mac_key_share(Vec::new());
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        // The server shifts one of the opened values
+        s_rands[rng.gen_range(0, num_rands)] +=
+            AuthAdditiveShare::new(F::uniform(&mut rng), F::zero());
+
+        let (client_check, server_check) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                server_mpc
+                    .commit_open(
+                        &mut server_reader,
+                        &mut server_writer,
+                        &s_rands,
+                        &mut server_rng,
+                    )
+                    .unwrap();
+                server_mpc.mac_check(&mut server_reader, &mut server_writer, &mut server_rng)
+            });
+            let client_result = s.spawn(|_| {
+                client_mpc
+                    .commit_open(
+                        &mut client_reader,
+                        &mut client_writer,
+                        &c_rands,
+                        &mut client_rng,
+                    )
+                    .unwrap();
+                client_mpc.mac_check(&mut client_reader, &mut client_writer, &mut client_rng)
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        // Both parties should detect the invalid opening
+        for check in vec![client_check, server_check] {
+            match check {
+                Err(MpcError::InvalidMAC) => {}
+                result => panic!("Expected InvalidMAC, got {:?}", result),
+            }
+        }
     }
 
     #[test]
     fn test_private_inputs() {
-        let server_addr = "127.0.0.1:8013";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
         let ((c_rands, s_rands), (..)) = insecure_gen(mac_key, num_rands, 0);
@@ -920,11 +2022,10 @@ mod tests {
 
     #[test]
     fn test_add() {
-        let server_addr = "127.0.0.1:8014";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
 
@@ -1000,11 +2101,10 @@ mod tests {
 
     #[test]
     fn test_sub() {
-        let server_addr = "127.0.0.1:8015";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
 
@@ -1080,11 +2180,10 @@ mod tests {
 
     #[test]
     fn test_mul() {
-        let server_addr = "127.0.0.1:8016";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
         let num_triples = num_rands / 2;
@@ -1180,11 +2279,10 @@ mod tests {
 
     #[test]
     fn test_basic_circuit() {
-        let server_addr = "127.0.0.1:8017";
         let mut rng = ChaChaRng::from_seed(RANDOMNESS);
         let mac_key = F::uniform(&mut rng);
         let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
-            get_connection(server_addr);
+            get_connection();
 
         let num_rands = 100000;
         let num_triples = num_rands / 2;
@@ -1308,4 +2406,595 @@ mod tests {
             result, expected
         )
     }
+
+    /// Returns `F` encoding the signed integer `v`
+    fn signed(v: i64) -> F {
+        let abs = F::from_repr((v.abs() as u64).into());
+        if v < 0 {
+            -abs
+        } else {
+            abs
+        }
+    }
+
+    /// Authenticates and shares every element of `values`, returning the
+    /// client's and the server's shares
+    fn auth_share_all(
+        values: &[F],
+        mac_key: F,
+        rng: &mut ChaChaRng,
+    ) -> (Vec<AuthAdditiveShare<F>>, Vec<AuthAdditiveShare<F>>) {
+        values.iter().map(|v| v.auth_share(&mac_key, rng)).unzip()
+    }
+
+    /// Combines the client's and the server's shares, checking their MACs
+    fn combine_all(
+        client: &[AuthAdditiveShare<F>],
+        server: &[AuthAdditiveShare<F>],
+        mac_key: F,
+    ) -> Vec<F> {
+        izip!(client, server)
+            .map(|(c, s)| c.combine(s, &mac_key).expect("MAC check failure"))
+            .collect()
+    }
+
+    #[test]
+    fn test_square() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+
+        let num = 1000;
+        let (c_squares, s_squares) = insecure_squares(mac_key, num);
+        let mut client_mpc = ClientMPC::new(Vec::new(), Vec::new()).with_squares(c_squares);
+        let mut server_mpc =
+            ServerMPC::new(Vec::new(), Vec::new(), mac_key).with_squares(s_squares);
+
+        let x: Vec<F> = (0..num).map(|_| F::uniform(&mut rng)).collect();
+        let (c_x, s_x) = auth_share_all(&x, mac_key, &mut rng);
+
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let client_result = s.spawn(|_| {
+                client_mpc
+                    .square(&mut client_reader, &mut client_writer, &c_x)
+                    .unwrap()
+            });
+            let server_result = s.spawn(|_| {
+                server_mpc
+                    .square(&mut server_reader, &mut server_writer, &s_x)
+                    .unwrap()
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+        let (client_check, server_check) = mac_check_both(
+            &mut client_mpc,
+            &mut server_mpc,
+            (&mut client_reader, &mut client_writer),
+            (&mut server_reader, &mut server_writer),
+        );
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+        assert_eq!(client_mpc.num_squares(), 0);
+
+        let squared = combine_all(&client_result, &server_result, mac_key);
+        izip!(x, squared).for_each(|(x, s)| assert_eq!(x * x, s));
+
+        // All square pairs have been consumed
+        match client_mpc.square(&mut client_reader, &mut client_writer, &c_x[..1]) {
+            Err(MpcError::InsufficientSquares { num: 0, needed: 1 }) => {}
+            result => panic!("Expected InsufficientSquares, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_check_bits() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+
+        let (c_squares, s_squares) = insecure_squares(mac_key, 100);
+        let mut client_mpc = ClientMPC::new(Vec::new(), Vec::new()).with_squares(c_squares);
+        let mut server_mpc =
+            ServerMPC::new(Vec::new(), Vec::new(), mac_key).with_squares(s_squares);
+
+        let bits = [F::zero(), F::one(), F::one(), F::zero()];
+        let not_bits = [F::one(), signed(2), F::zero(), signed(-1)];
+        let (c_bits, s_bits) = auth_share_all(&bits, mac_key, &mut rng);
+        let (c_not_bits, s_not_bits) = auth_share_all(&not_bits, mac_key, &mut rng);
+
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let client_result = s.spawn(|_| {
+                let valid = client_mpc.check_bits(&mut client_reader, &mut client_writer, &c_bits);
+                let invalid =
+                    client_mpc.check_bits(&mut client_reader, &mut client_writer, &c_not_bits);
+                (valid, invalid)
+            });
+            let server_result = s.spawn(|_| {
+                let valid = server_mpc.check_bits(&mut server_reader, &mut server_writer, &s_bits);
+                let invalid =
+                    server_mpc.check_bits(&mut server_reader, &mut server_writer, &s_not_bits);
+                (valid, invalid)
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        for (valid, invalid) in vec![client_result, server_result] {
+            assert!(valid.is_ok());
+            match invalid {
+                Err(MpcError::NotBits) => {}
+                _ => panic!("Expected NotBits, got {:?}", invalid),
+            }
+        }
+        let (client_check, server_check) = mac_check_both(
+            &mut client_mpc,
+            &mut server_mpc,
+            (&mut client_reader, &mut client_writer),
+            (&mut server_reader, &mut server_writer),
+        );
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+    }
+
+    #[test]
+    fn test_bit_decompose() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        let ((c_rands, s_rands), (c_triples, s_triples)) = insecure_gen(mac_key, 2000, 10000);
+        let (c_squares, s_squares) = insecure_squares(mac_key, 2000);
+        let mut client_mpc = ClientMPC::new(c_rands, c_triples).with_squares(c_squares);
+        let mut server_mpc = ServerMPC::new(s_rands, s_triples, mac_key).with_squares(s_squares);
+
+        let mut values = vec![F::zero(), F::one(), -F::one()];
+        values.extend((0..20).map(|_| F::uniform(&mut rng)));
+        let (c_values, s_values) = auth_share_all(&values, mac_key, &mut rng);
+
+        let (client_bits, server_bits) = crossbeam::thread::scope(|s| {
+            let client_result = s.spawn(|_| {
+                client_mpc
+                    .bit_decompose(
+                        &mut client_reader,
+                        &mut client_writer,
+                        &c_values,
+                        &mut client_rng,
+                    )
+                    .unwrap()
+            });
+            let server_result = s.spawn(|_| {
+                server_mpc
+                    .bit_decompose(
+                        &mut server_reader,
+                        &mut server_writer,
+                        &s_values,
+                        &mut server_rng,
+                    )
+                    .unwrap()
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+        let (client_check, server_check) = mac_check_both(
+            &mut client_mpc,
+            &mut server_mpc,
+            (&mut client_reader, &mut client_writer),
+            (&mut server_reader, &mut server_writer),
+        );
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+
+        let num_bits = <F as PrimeField>::size_in_bits();
+        let bits = combine_all(&client_bits, &server_bits, mac_key);
+        assert_eq!(bits.len(), values.len() * num_bits);
+        izip!(values, bits.chunks(num_bits)).for_each(|(v, bits)| {
+            let expected: Vec<F> = (0..num_bits)
+                .map(|i| F::from_repr(((v.into_repr().0 >> i) & 1).into()))
+                .collect();
+            assert_eq!(bits, expected.as_slice(), "Wrong bits of {:?}", v);
+        });
+    }
+
+    #[test]
+    fn test_matmul() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+
+        let (m, k, n) = (7, 13, 5);
+        let mut gen = InsecureTripleGen::<F>::new(RANDOMNESS);
+        let (c_triple, s_triple) = gen.generate_matrix_triple_shares(mac_key, (m, k, n));
+        let mut client_mpc = ClientMPC::new(Vec::new(), Vec::new());
+        let mut server_mpc = ServerMPC::new(Vec::new(), Vec::new(), mac_key);
+
+        let x: Vec<F> = (0..m * k).map(|_| F::uniform(&mut rng)).collect();
+        let y: Vec<F> = (0..k * n).map(|_| F::uniform(&mut rng)).collect();
+        let (c_x, s_x) = auth_share_all(&x, mac_key, &mut rng);
+        let (c_y, s_y) = auth_share_all(&y, mac_key, &mut rng);
+
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let client_result = s.spawn(|_| {
+                client_mpc
+                    .matmul(
+                        &mut client_reader,
+                        &mut client_writer,
+                        &c_x,
+                        &c_y,
+                        &c_triple,
+                    )
+                    .unwrap()
+            });
+            let server_result = s.spawn(|_| {
+                server_mpc
+                    .matmul(
+                        &mut server_reader,
+                        &mut server_writer,
+                        &s_x,
+                        &s_y,
+                        &s_triple,
+                    )
+                    .unwrap()
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+        let (client_check, server_check) = mac_check_both(
+            &mut client_mpc,
+            &mut server_mpc,
+            (&mut client_reader, &mut client_writer),
+            (&mut server_reader, &mut server_writer),
+        );
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+
+        let product = combine_all(&client_result, &server_result, mac_key);
+        for i in 0..m {
+            for j in 0..n {
+                let expected = (0..k).fold(F::zero(), |sum, l| sum + x[i * k + l] * y[l * n + j]);
+                assert_eq!(product[i * n + j], expected);
+            }
+        }
+
+        // Mismatched operands are rejected
+        let result = client_mpc.matmul(
+            &mut client_reader,
+            &mut client_writer,
+            &c_x[1..],
+            &c_y,
+            &c_triple,
+        );
+        match result {
+            Err(MpcError::MismatchedInputLength { left, right }) => {
+                assert_eq!((left, right), (m * k - 1, m * k))
+            }
+            _ => panic!("expected mismatched input lengths"),
+        }
+    }
+
+    #[test]
+    fn test_conv() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let input_dims = (2, 3, 6, 6);
+        let kernel_dims = (4, 3, 3, 3);
+        let mut gen = InsecureTripleGen::<F>::new(RANDOMNESS);
+
+        for &(padding, stride) in &[(Padding::Same, 2), (Padding::Valid, 1)] {
+            let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+                get_connection();
+            let dims = conv_triple_dims(input_dims, kernel_dims, padding, stride);
+            let (c_triple, s_triple) = gen.generate_matrix_triple_shares(mac_key, dims);
+            let mut client_mpc = ClientMPC::new(Vec::new(), Vec::new());
+            let mut server_mpc = ServerMPC::new(Vec::new(), Vec::new(), mac_key);
+
+            let x = Input::from_shape_vec(
+                input_dims,
+                (0..2 * 3 * 6 * 6).map(|_| F::uniform(&mut rng)).collect(),
+            )
+            .unwrap();
+            let kernel = Kernel::from_shape_vec(
+                kernel_dims,
+                (0..4 * 3 * 3 * 3).map(|_| F::uniform(&mut rng)).collect(),
+            )
+            .unwrap();
+            let (c_x, s_x) =
+                auth_share_all(&x.iter().copied().collect::<Vec<_>>(), mac_key, &mut rng);
+            let (c_kernel, s_kernel) = auth_share_all(
+                &kernel.iter().copied().collect::<Vec<_>>(),
+                mac_key,
+                &mut rng,
+            );
+            let c_x = Input::from_shape_vec(input_dims, c_x).unwrap();
+            let s_x = Input::from_shape_vec(input_dims, s_x).unwrap();
+            let c_kernel = Kernel::from_shape_vec(kernel_dims, c_kernel).unwrap();
+            let s_kernel = Kernel::from_shape_vec(kernel_dims, s_kernel).unwrap();
+
+            let (client_result, server_result) = crossbeam::thread::scope(|s| {
+                let client_result = s.spawn(|_| {
+                    client_mpc
+                        .conv(
+                            &mut client_reader,
+                            &mut client_writer,
+                            &c_x,
+                            &c_kernel,
+                            padding,
+                            stride,
+                            &c_triple,
+                        )
+                        .unwrap()
+                });
+                let server_result = s.spawn(|_| {
+                    server_mpc
+                        .conv(
+                            &mut server_reader,
+                            &mut server_writer,
+                            &s_x,
+                            &s_kernel,
+                            padding,
+                            stride,
+                            &s_triple,
+                        )
+                        .unwrap()
+                });
+                (client_result.join().unwrap(), server_result.join().unwrap())
+            })
+            .unwrap();
+            let (client_check, server_check) = mac_check_both(
+                &mut client_mpc,
+                &mut server_mpc,
+                (&mut client_reader, &mut client_writer),
+                (&mut server_reader, &mut server_writer),
+            );
+            assert!(client_check.is_ok());
+            assert!(server_check.is_ok());
+
+            let layer = Conv2dParams::<F, F>::new(
+                padding,
+                stride,
+                kernel,
+                Kernel::zeros((kernel_dims.0, 1, 1, 1)),
+            );
+            let out_dims = layer.calculate_output_size(input_dims);
+            let mut expected = Output::zeros(out_dims);
+            layer.conv2d_naive(&x, &mut expected);
+            assert_eq!(client_result.dim(), out_dims);
+            let result = combine_all(
+                &client_result.iter().copied().collect::<Vec<_>>(),
+                &server_result.iter().copied().collect::<Vec<_>>(),
+                mac_key,
+            );
+            assert_eq!(result, expected.iter().copied().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        let num = 100;
+        let ((c_rands, s_rands), (c_triples, s_triples)) = insecure_gen(mac_key, 5000, 10000);
+        let (c_squares, s_squares) = insecure_squares(mac_key, 5000);
+        let mut client_mpc = ClientMPC::new(c_rands, c_triples).with_squares(c_squares);
+        let mut server_mpc = ServerMPC::new(s_rands, s_triples, mac_key).with_squares(s_squares);
+
+        let (value_bits, trunc_bits) = (24, 8);
+        let bound = 1 << (value_bits - 1);
+        let mut values = vec![0, -1, 255, 256, -256, bound - 1, -bound];
+        values.extend((values.len()..num).map(|_| rng.gen_range(-bound, bound)));
+        let x: Vec<F> = values.iter().map(|v| signed(*v)).collect();
+        let (c_x, s_x) = auth_share_all(&x, mac_key, &mut rng);
+
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let client_result = s.spawn(|_| {
+                let masks = client_mpc
+                    .truncation_masks(
+                        &mut client_reader,
+                        &mut client_writer,
+                        num,
+                        trunc_bits,
+                        &mut client_rng,
+                    )
+                    .unwrap();
+                client_mpc
+                    .truncate(
+                        &mut client_reader,
+                        &mut client_writer,
+                        &c_x,
+                        value_bits,
+                        &masks,
+                    )
+                    .unwrap()
+            });
+            let server_result = s.spawn(|_| {
+                let masks = server_mpc
+                    .truncation_masks(
+                        &mut server_reader,
+                        &mut server_writer,
+                        num,
+                        trunc_bits,
+                        &mut server_rng,
+                    )
+                    .unwrap();
+                server_mpc
+                    .truncate(
+                        &mut server_reader,
+                        &mut server_writer,
+                        &s_x,
+                        value_bits,
+                        &masks,
+                    )
+                    .unwrap()
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+        let (client_check, server_check) = mac_check_both(
+            &mut client_mpc,
+            &mut server_mpc,
+            (&mut client_reader, &mut client_writer),
+            (&mut server_reader, &mut server_writer),
+        );
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+
+        let truncated = combine_all(&client_result, &server_result, mac_key);
+        izip!(values, truncated).for_each(|(v, t)| {
+            let floor = v >> trunc_bits;
+            assert!(
+                t == signed(floor) || t == signed(floor + 1),
+                "{} truncated to {:?}",
+                v,
+                t
+            );
+        });
+    }
+
+    #[test]
+    fn test_truncate_rejects_invalid_bits() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), _) = get_connection();
+
+        let ((c_rands, _), (c_triples, _)) = insecure_gen(mac_key, 10, 10);
+        let mut client_mpc = ClientMPC::new(c_rands, c_triples);
+        let mask_bits = F::size_in_bits() - 2;
+
+        // Both checks fail before anything is sent, so no server is needed
+        let masks = client_mpc.truncation_masks(
+            &mut client_reader,
+            &mut client_writer,
+            1,
+            mask_bits,
+            &mut rng,
+        );
+        match masks {
+            Err(MpcError::InvalidBitLength { bits, .. }) => assert_eq!(bits, mask_bits),
+            result => panic!("Expected InvalidBitLength, got {:?}", result),
+        }
+
+        let (c_x, _) = auth_share_all(&[F::zero()], mac_key, &mut rng);
+        let mask = TruncationMask {
+            mask: AuthAdditiveShare::zero(),
+            low_bits: AuthAdditiveShare::zero(),
+            trunc_bits: 8,
+        };
+        for &value_bits in &[0, 8, mask_bits] {
+            let result = client_mpc.truncate(
+                &mut client_reader,
+                &mut client_writer,
+                &c_x,
+                value_bits,
+                &[mask],
+            );
+            match result {
+                Err(MpcError::InvalidBitLength { .. }) => {}
+                result => panic!("Expected InvalidBitLength, got {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_less_than_and_equal() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            get_connection();
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        let ((c_rands, s_rands), (c_triples, s_triples)) = insecure_gen(mac_key, 4000, 20000);
+        let (c_squares, s_squares) = insecure_squares(mac_key, 4000);
+        let mut client_mpc = ClientMPC::new(c_rands, c_triples).with_squares(c_squares);
+        let mut server_mpc = ServerMPC::new(s_rands, s_triples, mac_key).with_squares(s_squares);
+
+        let bound = 1 << 40;
+        let mut pairs = vec![(0, 0), (-1, 0), (0, -1), (5, 5), (-bound, bound - 1)];
+        pairs.extend((0..20).map(|_| {
+            let x = rng.gen_range(-bound, bound);
+            if rng.gen() {
+                (x, x)
+            } else {
+                (x, rng.gen_range(-bound, bound))
+            }
+        }));
+        let x: Vec<F> = pairs.iter().map(|(x, _)| signed(*x)).collect();
+        let y: Vec<F> = pairs.iter().map(|(_, y)| signed(*y)).collect();
+        let (c_x, s_x) = auth_share_all(&x, mac_key, &mut rng);
+        let (c_y, s_y) = auth_share_all(&y, mac_key, &mut rng);
+
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let client_result = s.spawn(|_| {
+                let lt = client_mpc
+                    .less_than(
+                        &mut client_reader,
+                        &mut client_writer,
+                        &c_x,
+                        &c_y,
+                        &mut client_rng,
+                    )
+                    .unwrap();
+                let eq = client_mpc
+                    .equal(
+                        &mut client_reader,
+                        &mut client_writer,
+                        &c_x,
+                        &c_y,
+                        &mut client_rng,
+                    )
+                    .unwrap();
+                (lt, eq)
+            });
+            let server_result = s.spawn(|_| {
+                let lt = server_mpc
+                    .less_than(
+                        &mut server_reader,
+                        &mut server_writer,
+                        &s_x,
+                        &s_y,
+                        &mut server_rng,
+                    )
+                    .unwrap();
+                let eq = server_mpc
+                    .equal(
+                        &mut server_reader,
+                        &mut server_writer,
+                        &s_x,
+                        &s_y,
+                        &mut server_rng,
+                    )
+                    .unwrap();
+                (lt, eq)
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+        let (client_check, server_check) = mac_check_both(
+            &mut client_mpc,
+            &mut server_mpc,
+            (&mut client_reader, &mut client_writer),
+            (&mut server_reader, &mut server_writer),
+        );
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+
+        let lt = combine_all(&client_result.0, &server_result.0, mac_key);
+        let eq = combine_all(&client_result.1, &server_result.1, mac_key);
+        izip!(pairs, lt, eq).for_each(|((x, y), lt, eq)| {
+            let bit = |b: bool| if b { F::one() } else { F::zero() };
+            assert_eq!(lt, bit(x < y), "Wrong comparison of {} and {}", x, y);
+            assert_eq!(eq, bit(x == y), "Wrong equality of {} and {}", x, y);
+        });
+    }
 }
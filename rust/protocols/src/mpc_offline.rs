@@ -1,19 +1,33 @@
-use crate::bytes;
-use crate::{InMessage, OutMessage};
-use algebra::{Fp64, Fp64Parameters, PrimeField, UniformRandom};
+use crate::{bytes, comm};
+use crate::{
+    error::MpcError,
+    linear_layer::{acg_group_size, merged_dims},
+    packing::{Packed, PackedSlice},
+    randomness::{auth_shares_from_seed, complement_auth_share, expand_seed, sample_seed},
+    InMessage, OutMessage,
+};
+use algebra::{Field, Fp64, Fp64Parameters, PrimeField, UniformRandom};
 use crypto_primitives::{
     additive_share::{AdditiveShare, AuthAdditiveShare, AuthShare, Share},
-    beavers_mul::Triple,
+    beavers_mul::{MatrixTriple, SquarePair, Triple},
 };
 use io_utils::imux::IMuxAsync;
 use itertools::izip;
+use neural_network::{
+    layers::{fully_connected::FullyConnectedParams, LayerDims, LinearLayer, LinearLayerInfo},
+    tensors::{Input, Kernel, Output},
+};
 use num_traits::Zero;
 use protocols_sys::SealCT;
-use protocols_sys::{ClientFHE, ClientGen, SealClientGen, SealServerGen, ServerFHE, ServerGen};
-use rand::{ChaChaRng, CryptoRng, RngCore, SeedableRng};
+use protocols_sys::{
+    ClientACG, ClientFHE, ClientGen, SealClientGen, SealServerGen, ServerACG, ServerFHE, ServerGen,
+};
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
 use std::{
     cmp::min,
     marker::PhantomData,
+    ops::Mul,
     os::raw::c_char,
     sync::{Arc, Mutex},
 };
@@ -32,16 +46,494 @@ pub struct OfflineMPCProtocolType;
 type MsgSend<'a> = OutMessage<'a, (usize, Vec<c_char>), OfflineMPCProtocolType>;
 type MsgRcv = InMessage<(usize, Vec<c_char>), OfflineMPCProtocolType>;
 
-pub type ShareSend<'a, T> = OutMessage<'a, [AdditiveShare<T>], OfflineMPCProtocolType>;
-pub type ShareRcv<T> = InMessage<Vec<AdditiveShare<T>>, OfflineMPCProtocolType>;
+pub type ShareSend<'a, T> =
+    OutMessage<'a, PackedSlice<'a, AdditiveShare<T>>, OfflineMPCProtocolType>;
+pub type ShareRcv<T> = InMessage<Packed<AdditiveShare<T>>, OfflineMPCProtocolType>;
+
+type SeedSend<'a> = OutMessage<'a, [u8; 32], OfflineMPCProtocolType>;
+type SeedRcv = InMessage<[u8; 32], OfflineMPCProtocolType>;
+
+/// One ciphertext message per group of vectors packed by the ACG
+type CtBatchSend<'a> = OutMessage<'a, Vec<Vec<c_char>>, OfflineMPCProtocolType>;
+type CtBatchRcv = InMessage<Vec<Vec<c_char>>, OfflineMPCProtocolType>;
+
+type ChallengeSend<'a, F> = OutMessage<'a, (Vec<F>, Vec<F>), OfflineMPCProtocolType>;
+type ChallengeRcv<F> = InMessage<(Vec<F>, Vec<F>), OfflineMPCProtocolType>;
+type OpeningSend<'a, F> =
+    OutMessage<'a, (Vec<AuthAdditiveShare<F>>, Vec<AuthAdditiveShare<F>>), OfflineMPCProtocolType>;
+type OpeningRcv<F> =
+    InMessage<(Vec<AuthAdditiveShare<F>>, Vec<AuthAdditiveShare<F>>), OfflineMPCProtocolType>;
+
+/// Derives an independent RNG for each of `num_threads` threads from `rng`
+fn thread_rngs<RNG: RngCore + CryptoRng>(rng: &mut RNG, num_threads: usize) -> Vec<ChaChaRng> {
+    (0..num_threads)
+        .map(|_| {
+            let mut seed = [0u8; 32];
+            rng.fill_bytes(&mut seed);
+            ChaChaRng::from_seed(seed)
+        })
+        .collect()
+}
+
+/// Transposes the `rows x cols` matrix `values`
+fn transpose<E: Copy>(values: &[E], rows: usize, cols: usize) -> Vec<E> {
+    (0..rows * cols)
+        .map(|idx| values[(idx % rows) * cols + idx / rows])
+        .collect()
+}
+
+/// Multiplies the `m x k` matrix `a` by the `k x n` matrix `b`, whose entries
+/// may be values or shares
+fn matmul<P, E>(a: &[Fp64<P>], b: &[E], (m, k, n): (usize, usize, usize)) -> Vec<E>
+where
+    P: Fp64Parameters,
+    E: Copy + Zero + Mul<Fp64<P>, Output = E>,
+{
+    (0..m * n)
+        .map(|idx| {
+            let (i, j) = (idx / n, idx % n);
+            (0..k).fold(E::zero(), |sum, l| sum + b[l * n + j] * a[i * k + l])
+        })
+        .collect()
+}
+
+/// Client side of `server_matmul_acg`. Sends the client's `inputs`, which are
+/// vectors of length `k` laid out one after another, and returns its
+/// authenticated shares of `inputs` and of the product of the server's
+/// `out x k` matrix with each vector.
+fn client_matmul_acg<P, A, R, W>(
+    keys: A::Keys,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    inputs: &[Fp64<P>],
+    (k, out): (usize, usize),
+) -> Result<
+    (
+        Vec<AuthAdditiveShare<Fp64<P>>>,
+        Vec<AuthAdditiveShare<Fp64<P>>>,
+    ),
+    MpcError,
+>
+where
+    P: Fp64Parameters,
+    A: ClientACG,
+    A::Keys: Copy,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+{
+    let items = inputs.len() / k;
+    let group_size = acg_group_size(
+        &LinearLayerInfo::<u64, u64>::FullyConnected,
+        (items, k, 1, 1),
+    );
+    let groups: Vec<_> = inputs.chunks(group_size * k).collect();
+
+    // Encrypt each group of vectors
+    let mut acgs = Vec::with_capacity(groups.len());
+    let mut cts = Vec::with_capacity(groups.len());
+    for group in &groups {
+        let group_items = group.len() / k;
+        let mut acg = A::new(
+            keys,
+            &LinearLayerInfo::<u64, u64>::FullyConnected,
+            merged_dims(group_items, (items, k, 1, 1)),
+            merged_dims(group_items, (items, out, 1, 1)),
+        );
+        let input = Input::from_shape_vec(
+            (1, group.len(), 1, 1),
+            group.iter().map(|e| e.into_repr().0).collect(),
+        )
+        .expect("Shapes should be same");
+        cts.push(acg.preprocess(&input));
+        acgs.push(acg);
+    }
+    bytes::serialize(&mut *writer, &CtBatchSend::new(&cts))?;
+
+    let linear_cts: CtBatchRcv = bytes::deserialize(&mut *reader)?;
+    let linear_mac_cts: CtBatchRcv = bytes::deserialize(&mut *reader)?;
+    let r_mac_cts: CtBatchRcv = bytes::deserialize(&mut *reader)?;
+    let (linear_cts, linear_mac_cts, r_mac_cts) =
+        (linear_cts.msg(), linear_mac_cts.msg(), r_mac_cts.msg());
+    check_opening_len(&linear_cts, acgs.len())?;
+    check_opening_len(&linear_mac_cts, acgs.len())?;
+    check_opening_len(&r_mac_cts, acgs.len())?;
+
+    let mut input_shares = Vec::with_capacity(inputs.len());
+    let mut products = Vec::with_capacity(items * out);
+    for (acg, group, linear_ct, linear_mac_ct, r_mac_ct) in
+        izip!(&mut acgs, groups, linear_cts, linear_mac_cts, r_mac_cts)
+    {
+        let group_items = group.len() / k;
+        let mut linear_auth = Output::zeros((1, group_items * out, 1, 1));
+        let mut r_mac = Input::zeros((1, group.len(), 1, 1));
+        acg.decrypt(linear_ct, linear_mac_ct, r_mac_ct)?;
+        acg.postprocess::<Fp64<P>>(&mut linear_auth, &mut r_mac);
+        products.extend(linear_auth.iter().cloned());
+        input_shares
+            .extend(izip!(group, r_mac.iter()).map(|(r, mac)| AuthAdditiveShare::new(*r, *mac)));
+    }
+    Ok((input_shares, products))
+}
+
+/// Evaluates the server's `out x k` matrix `kernel` on each of the client's
+/// `items` vectors of length `k` with the fully connected ACG, which packs
+/// the vectors into as few ciphertexts as fit. Returns the server's
+/// authenticated shares of the client's vectors and of the products, under
+/// `mac_key`, laid out one vector after another.
+fn server_matmul_acg<P, A, R, W, RNG>(
+    keys: A::Keys,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    rng: &mut RNG,
+    mac_key: Fp64<P>,
+    kernel: &[Fp64<P>],
+    (items, k, out): (usize, usize, usize),
+) -> Result<
+    (
+        Vec<AuthAdditiveShare<Fp64<P>>>,
+        Vec<AuthAdditiveShare<Fp64<P>>>,
+    ),
+    MpcError,
+>
+where
+    P: Fp64Parameters,
+    A: ServerACG,
+    A::Keys: Copy,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let group_size = acg_group_size(
+        &LinearLayerInfo::<u64, u64>::FullyConnected,
+        (items, k, 1, 1),
+    );
+    let kernel = Kernel::from_shape_vec(
+        (out, k, 1, 1),
+        kernel.iter().map(|e| e.into_repr().0).collect(),
+    )
+    .expect("Shapes should be same");
+    let mac_key_repr = mac_key.into_repr().0;
+    let mut sample =
+        |len: usize| -> Vec<_> { (0..len).map(|_| Fp64::<P>::uniform(&mut *rng)).collect() };
+    let to_repr =
+        |values: &[Fp64<P>]| -> Vec<_> { values.iter().map(|e| e.into_repr().0).collect() };
+
+    // Preprocess the kernel and the server's randomizers for each group of
+    // vectors
+    let mut acgs = Vec::new();
+    let mut input_shares = Vec::with_capacity(items * k);
+    let mut products = Vec::with_capacity(items * out);
+    for start in (0..items).step_by(group_size) {
+        let group_items = group_size.min(items - start);
+        let input_dims = merged_dims(group_items, (items, k, 1, 1));
+        let output_dims = merged_dims(group_items, (items, out, 1, 1));
+        let merged = kernel.block_diagonal(group_items);
+        let layer = LinearLayer::<u64, u64>::FullyConnected {
+            dims: LayerDims {
+                input_dims,
+                output_dims,
+            },
+            params: FullyConnectedParams::new(
+                merged.clone(),
+                Kernel::zeros((output_dims.1, 1, 1, 1)),
+            ),
+        };
+        let mut acg = A::new(keys, &layer, &merged);
+
+        // The client receives the products minus `linear_share`, with their
+        // MACs minus `linear_mac_share`, and the MACs of its vectors minus
+        // `r_mac_share`
+        let linear_share = sample(output_dims.1);
+        let linear_mac_share = sample(output_dims.1);
+        let r_mac_share = sample(input_dims.1);
+        acg.preprocess(
+            &Output::from_shape_vec(output_dims, to_repr(&linear_share))
+                .expect("Shapes should be same"),
+            &Output::from_shape_vec(output_dims, to_repr(&linear_mac_share))
+                .expect("Shapes should be same"),
+            &Input::from_shape_vec(input_dims, to_repr(&r_mac_share))
+                .expect("Shapes should be same"),
+            mac_key_repr,
+            mac_key_repr,
+        );
+        acgs.push(acg);
+        products.extend(
+            izip!(linear_share, linear_mac_share)
+                .map(|(s, mac)| AuthAdditiveShare::new(s, mac + mac_key * s)),
+        );
+        input_shares.extend(
+            r_mac_share
+                .into_iter()
+                .map(|mac| AuthAdditiveShare::new(Fp64::zero(), mac)),
+        );
+    }
+
+    let client_cts: CtBatchRcv = bytes::deserialize(&mut *reader)?;
+    let client_cts = client_cts.msg();
+    check_opening_len(&client_cts, acgs.len())?;
+    let mut linear_cts = Vec::with_capacity(acgs.len());
+    let mut linear_mac_cts = Vec::with_capacity(acgs.len());
+    let mut r_mac_cts = Vec::with_capacity(acgs.len());
+    for (acg, client_ct) in izip!(&mut acgs, client_cts) {
+        let (linear_ct, linear_mac_ct, r_mac_ct) = acg.process(client_ct)?;
+        linear_cts.push(linear_ct);
+        linear_mac_cts.push(linear_mac_ct);
+        r_mac_cts.push(r_mac_ct);
+    }
+    bytes::serialize(&mut *writer, &CtBatchSend::new(&linear_cts))?;
+    bytes::serialize(&mut *writer, &CtBatchSend::new(&linear_mac_cts))?;
+    bytes::serialize(&mut *writer, &CtBatchSend::new(&r_mac_cts))?;
+    Ok((input_shares, products))
+}
+
+/// Splits the shares of a matrix triple of dimensions `(2 * m, k, n)` into
+/// the triple of the first `m` rows of `A` and `C`, and the triple of the
+/// other rows which is sacrificed to check it. Both triples have the same `B`.
+fn split_sacrificed<T: AuthShare>(
+    mut a: Vec<AuthAdditiveShare<T>>,
+    b: Vec<AuthAdditiveShare<T>>,
+    mut c: Vec<AuthAdditiveShare<T>>,
+    (m, k, n): (usize, usize, usize),
+) -> (MatrixTriple<T>, MatrixTriple<T>) {
+    let a_hat = a.split_off(m * k);
+    let c_hat = c.split_off(m * n);
+    let triple_hat = MatrixTriple {
+        a: a_hat,
+        b: b.clone(),
+        c: c_hat,
+        dims: (m, k, n),
+    };
+    let triple = MatrixTriple {
+        a,
+        b,
+        c,
+        dims: (m, k, n),
+    };
+    (triple, triple_hat)
+}
+
+/// Returns the openings `rho = t * A - A'` of the check that each triple in
+/// `triples` is a matrix triple, which sacrifices the triple in `sacrificed`
+/// with the same `B`
+fn matrix_triples_rho<P: Fp64Parameters>(
+    t: &[Fp64<P>],
+    triples: &[MatrixTriple<Fp64<P>>],
+    sacrificed: &[MatrixTriple<Fp64<P>>],
+) -> Vec<AuthAdditiveShare<Fp64<P>>> {
+    izip!(t, triples, sacrificed)
+        .flat_map(|(t, triple, triple_hat)| {
+            izip!(&triple.a, &triple_hat.a).map(move |(a, a_hat)| *a * *t - *a_hat)
+        })
+        .collect()
+}
+
+/// Returns the openings `sigma = t * C - C' - rho * B` of the check in
+/// `matrix_triples_rho`, given the opened `rho`
+fn matrix_triples_sigma<P: Fp64Parameters>(
+    t: &[Fp64<P>],
+    rho: &[Fp64<P>],
+    triples: &[MatrixTriple<Fp64<P>>],
+    sacrificed: &[MatrixTriple<Fp64<P>>],
+) -> Vec<AuthAdditiveShare<Fp64<P>>> {
+    let mut offset = 0;
+    let mut sigma = Vec::new();
+    for (t, triple, triple_hat) in izip!(t, triples, sacrificed) {
+        let (m, k, _) = triple.dims;
+        let rho_b = matmul(&rho[offset..offset + m * k], &triple.b, triple.dims);
+        offset += m * k;
+        sigma.extend(
+            izip!(&triple.c, &triple_hat.c, rho_b)
+                .map(|(c, c_hat, rho_b)| *c * *t - *c_hat - rho_b),
+        );
+    }
+    sigma
+}
+
+/// Client side of the check that each triple in `triples` is a matrix triple,
+/// which sacrifices the triple in `sacrificed` with the same `B`. The server
+/// samples a random `t` for each triple, and the parties open `rho = t * A -
+/// A'` and `sigma = t * C - C' - rho * B` with their MACs.
+fn client_check_matrix_triples<P, R, W>(
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    triples: &[MatrixTriple<Fp64<P>>],
+    sacrificed: &[MatrixTriple<Fp64<P>>],
+) -> Result<(), MpcError>
+where
+    P: Fp64Parameters,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+{
+    let check_time = timer_start!(|| "Checking matrix triples");
+    let challenge: ChallengeRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+    let (t, rho_s) = challenge.msg();
+    check_opening_len(&t, triples.len())?;
+
+    let rho = matrix_triples_rho(&t, triples, sacrificed);
+    check_opening_len(&rho_s, rho.len())?;
+    let rho_value: Vec<_> = izip!(&rho, rho_s)
+        .map(|(rho, rho_s)| rho.get_value().inner + rho_s)
+        .collect();
+    let sigma = matrix_triples_sigma(&t, &rho_value, triples, sacrificed);
+    let opening = (rho, sigma);
+    let sent_message = OpeningSend::new(&opening);
+    bytes::serialize(&mut *writer, &sent_message)?;
+    timer_end!(check_time);
+    Ok(())
+}
+
+/// Server side of `client_check_matrix_triples`, which aborts with an error
+/// unless every opening has a valid MAC and `sigma` is zero
+fn server_check_matrix_triples<P, R, W, RNG>(
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    rng: &mut RNG,
+    mac_key: Fp64<P>,
+    triples: &[MatrixTriple<Fp64<P>>],
+    sacrificed: &[MatrixTriple<Fp64<P>>],
+) -> Result<(), MpcError>
+where
+    P: Fp64Parameters,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let check_time = timer_start!(|| "Checking matrix triples");
+    let t: Vec<_> = (0..triples.len())
+        .map(|_| Fp64::<P>::uniform(rng))
+        .collect();
+    let rho = matrix_triples_rho(&t, triples, sacrificed);
+    let challenge = (t, rho.iter().map(|r| r.get_value().inner).collect());
+    let sent_message = ChallengeSend::new(&challenge);
+    bytes::serialize(&mut *writer, &sent_message)?;
+    let (t, _) = challenge;
+
+    let opening: OpeningRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+    let (rho_c, sigma_c) = opening.msg();
+    check_opening_len(&rho_c, rho.len())?;
+    let mut rho_value = Vec::with_capacity(rho.len());
+    for (rho_s, rho_c) in izip!(rho, rho_c) {
+        let rho = rho_s + rho_c;
+        if mac_key * rho.get_value().inner != rho.get_mac().inner {
+            return Err(MpcError::InvalidMAC);
+        }
+        rho_value.push(rho.get_value().inner);
+    }
+    let sigma = matrix_triples_sigma(&t, &rho_value, triples, sacrificed);
+    check_opening_len(&sigma_c, sigma.len())?;
+    for (sigma_s, sigma_c) in izip!(sigma, sigma_c) {
+        let sigma = sigma_s + sigma_c;
+        if !(sigma.get_value().inner.is_zero() && sigma.get_mac().inner.is_zero()) {
+            return Err(MpcError::InvalidTriple);
+        }
+    }
+    timer_end!(check_time);
+    Ok(())
+}
+
+/// Client side of the check that each pair in `squares` is a square pair,
+/// which sacrifices the corresponding pair in `sacrificed`. The server samples
+/// a random `t`, and the parties open `rho = t * a - a'` and
+/// `sigma = t^2 * a^2 - a'^2 - rho * (t * a + a')` with their MACs.
+fn client_check_squares<P, R, W>(
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    squares: &[SquarePair<Fp64<P>>],
+    sacrificed: &[SquarePair<Fp64<P>>],
+) -> Result<(), MpcError>
+where
+    P: Fp64Parameters,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+{
+    let check_time = timer_start!(|| "Checking squares");
+    let challenge: ChallengeRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+    let (t, rho_s) = challenge.msg();
+    check_opening_len(&t, squares.len())?;
+    check_opening_len(&rho_s, squares.len())?;
+
+    let rho: Vec<_> = izip!(&t, squares, sacrificed)
+        .map(|(t, pair, pair_hat)| pair.a * *t - pair_hat.a)
+        .collect();
+    let sigma: Vec<_> = izip!(&t, &rho, rho_s, squares, sacrificed)
+        .map(|(t, rho, rho_s, pair, pair_hat)| {
+            let rho = rho.get_value().inner + rho_s;
+            pair.a_squared * t.square() - pair_hat.a_squared - (pair.a * *t + pair_hat.a) * rho
+        })
+        .collect();
+    let opening = (rho, sigma);
+    let sent_message = OpeningSend::new(&opening);
+    bytes::serialize(&mut *writer, &sent_message)?;
+    timer_end!(check_time);
+    Ok(())
+}
+
+/// Server side of `client_check_squares`, which aborts with an error unless
+/// every opening has a valid MAC and `sigma` is zero
+fn server_check_squares<P, R, W, RNG>(
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    rng: &mut RNG,
+    mac_key: Fp64<P>,
+    squares: &[SquarePair<Fp64<P>>],
+    sacrificed: &[SquarePair<Fp64<P>>],
+) -> Result<(), MpcError>
+where
+    P: Fp64Parameters,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let check_time = timer_start!(|| "Checking squares");
+    let t: Vec<_> = (0..squares.len())
+        .map(|_| Fp64::<P>::uniform(rng))
+        .collect();
+    let rho: Vec<_> = izip!(&t, squares, sacrificed)
+        .map(|(t, pair, pair_hat)| pair.a * *t - pair_hat.a)
+        .collect();
+    let challenge = (t, rho.iter().map(|r| r.get_value().inner).collect());
+    let sent_message = ChallengeSend::new(&challenge);
+    bytes::serialize(&mut *writer, &sent_message)?;
+    let (t, _) = challenge;
+
+    let opening: OpeningRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+    let (rho_c, sigma_c) = opening.msg();
+    check_opening_len(&rho_c, squares.len())?;
+    check_opening_len(&sigma_c, squares.len())?;
+    for (t, rho_s, rho_c, sigma_c, pair, pair_hat) in
+        izip!(t, rho, rho_c, sigma_c, squares, sacrificed)
+    {
+        let rho = rho_s + rho_c;
+        let rho_value = rho.get_value().inner;
+        if mac_key * rho_value != rho.get_mac().inner {
+            return Err(MpcError::InvalidMAC);
+        }
+        let sigma = pair.a_squared * t.square()
+            - pair_hat.a_squared
+            - (pair.a * t + pair_hat.a) * rho_value
+            + sigma_c;
+        if !(sigma.get_value().inner.is_zero() && sigma.get_mac().inner.is_zero()) {
+            return Err(MpcError::InvalidTriple);
+        }
+    }
+    timer_end!(check_time);
+    Ok(())
+}
 
-const RANDOMNESS: [u8; 32] = [
-    0x99, 0xe0, 0x8f, 0xbc, 0x89, 0xa7, 0x34, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda, 0xf4, 0x76,
-    0x5d, 0xc9, 0x8d, 0x62, 0x23, 0xf2, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77, 0xd3, 0x4a, 0x52, 0xd2,
-];
+/// Returns an error unless the other party opened `n` values
+pub(crate) fn check_opening_len<T>(opened: &[T], n: usize) -> Result<(), MpcError> {
+    if opened.len() != n {
+        return Err(MpcError::MismatchedInputLength {
+            left: n,
+            right: opened.len(),
+        });
+    }
+    Ok(())
+}
 
 /// Represents a type which implements pairwise randomness and triple generation
-/// for a client-malicious SPDZ-style MPC
+/// for a client-malicious SPDZ-style MPC. Each method aborts with an error if
+/// the other party is caught deviating from the protocol.
 pub trait OfflineMPC<T: AuthShare> {
     /// Message batch size
     const BATCH_SIZE: usize = 8192;
@@ -53,7 +545,18 @@ pub trait OfflineMPC<T: AuthShare> {
         writer: &mut IMuxAsync<W>,
         rng: &mut RNG,
         num: usize,
-    ) -> Vec<AuthAdditiveShare<T>>;
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>;
+
+    /// Generates `num` authenticated shares of uniformly random values known
+    /// to the client, whose value shares are the whole values while the
+    /// server's value shares are zero
+    fn client_rands_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<T>>, MpcError>;
 
     /// Generates `num` authenticated triples
     fn triples_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
@@ -62,7 +565,154 @@ pub trait OfflineMPC<T: AuthShare> {
         writer: &mut IMuxAsync<W>,
         rng: &mut RNG,
         num: usize,
-    ) -> Vec<Triple<T>>;
+    ) -> Result<Vec<Triple<T>>, MpcError>;
+
+    /// Generates `num` authenticated square pairs
+    fn squares_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<SquarePair<T>>, MpcError>;
+
+    /// Returns additive shares of the product of each of `values` with the
+    /// other party's corresponding value, without revealing either
+    fn ole_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        values: &[T],
+    ) -> Result<Vec<T>, MpcError>;
+}
+
+/// Authenticates the client's `shares` under the MAC key shared by the
+/// client and the server, whose shares are `key_share` and the server's MAC
+/// key. `shares` must be authenticated under the server's MAC key alone, with
+/// no constants added. The server runs `server_share_mac_key` on its shares
+/// of the same values.
+pub fn client_share_mac_key<'a, P, G, R, W, RNG>(
+    gen: &G,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    rng: &mut RNG,
+    key_share: Fp64<P>,
+    shares: impl IntoIterator<Item = &'a mut AuthAdditiveShare<Fp64<P>>>,
+) -> Result<(), MpcError>
+where
+    P: Fp64Parameters,
+    G: OfflineMPC<Fp64<P>>,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let start_time = timer_start!(|| "Client sharing MAC key");
+    let _phase = comm::phase("MAC key");
+    let mut shares: Vec<_> = shares.into_iter().collect();
+    // The client adds `key_share` times its own value shares, and a share of
+    // `key_share` times the server's
+    let products = gen.ole_gen(reader, writer, rng, &vec![key_share; shares.len()])?;
+    izip!(shares.iter_mut(), products).for_each(|(s, p)| {
+        let value = s.get_value().inner;
+        **s = AuthAdditiveShare::new(value, s.get_mac().inner + key_share * value + p);
+    });
+    timer_end!(start_time);
+    Ok(())
+}
+
+/// Server side of `client_share_mac_key`
+pub fn server_share_mac_key<'a, P, G, R, W, RNG>(
+    gen: &G,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    rng: &mut RNG,
+    shares: impl IntoIterator<Item = &'a mut AuthAdditiveShare<Fp64<P>>>,
+) -> Result<(), MpcError>
+where
+    P: Fp64Parameters,
+    G: OfflineMPC<Fp64<P>>,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let start_time = timer_start!(|| "Server sharing MAC key");
+    let _phase = comm::phase("MAC key");
+    let mut shares: Vec<_> = shares.into_iter().collect();
+    let values: Vec<_> = shares.iter().map(|s| s.get_value().inner).collect();
+    let products = gen.ole_gen(reader, writer, rng, &values)?;
+    izip!(shares.iter_mut(), products).for_each(|(s, p)| {
+        **s = AuthAdditiveShare::new(s.get_value().inner, s.get_mac().inner + p);
+    });
+    timer_end!(start_time);
+    Ok(())
+}
+
+/// Generates `num` masks with which `ServerMPC` shares its inputs under the
+/// MAC key shared with the client, whose share the server holds as
+/// `key_share`. Each mask is a uniformly random value known only to the
+/// server: its value share is the whole value, and the client's is zero. The
+/// client runs `client_input_masks`.
+pub fn server_input_masks<P, G, R, W, RNG>(
+    gen: &G,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    rng: &mut RNG,
+    key_share: Fp64<P>,
+    num: usize,
+) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+where
+    P: Fp64Parameters,
+    G: OfflineMPC<Fp64<P>>,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let mut masks: Vec<_> = (0..num)
+        .map(|_| {
+            let mask = Fp64::uniform(&mut *rng);
+            AuthAdditiveShare::new(mask, key_share * mask)
+        })
+        .collect();
+    server_share_mac_key(gen, reader, writer, rng, masks.iter_mut())?;
+    Ok(masks)
+}
+
+/// Client side of `server_input_masks`
+pub fn client_input_masks<P, G, R, W, RNG>(
+    gen: &G,
+    reader: &mut IMuxAsync<R>,
+    writer: &mut IMuxAsync<W>,
+    rng: &mut RNG,
+    key_share: Fp64<P>,
+    num: usize,
+) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+where
+    P: Fp64Parameters,
+    G: OfflineMPC<Fp64<P>>,
+    R: Read + Send + Unpin,
+    W: Write + Send + Unpin,
+    RNG: RngCore + CryptoRng,
+{
+    let mut masks = vec![AuthAdditiveShare::zero(); num];
+    client_share_mac_key(gen, reader, writer, rng, key_share, masks.iter_mut())?;
+    Ok(masks)
+}
+
+/// An `OfflineMPC` run by the client, which is built from the keys agreed on
+/// with the server
+pub trait ClientOfflineGen<T: AuthShare>: OfflineMPC<T> {
+    type Keys: Copy;
+
+    fn from_keys(keys: Self::Keys) -> Self;
+}
+
+/// An `OfflineMPC` run by the server, which is built from the keys agreed on
+/// with the client and the MAC key to authenticate shares under
+pub trait ServerOfflineGen<T: AuthShare>: OfflineMPC<T> {
+    type Keys: Copy;
+
+    fn from_keys(keys: Self::Keys, mac_key: u64) -> Self;
 }
 
 pub struct ClientOfflineMPC<T: AuthShare, C: ClientGen> {
@@ -72,7 +722,39 @@ pub struct ClientOfflineMPC<T: AuthShare, C: ClientGen> {
 
 pub struct ServerOfflineMPC<T: AuthShare, S: ServerGen> {
     backend: S,
-    _share: PhantomData<T>,
+    mac_key: T,
+}
+
+impl<P, C> ClientOfflineGen<Fp64<P>> for ClientOfflineMPC<Fp64<P>, C>
+where
+    P: Fp64Parameters,
+    C: ClientGen<MsgType = c_char> + Sync,
+    C::Keys: Copy,
+{
+    type Keys = C::Keys;
+
+    fn from_keys(keys: C::Keys) -> Self {
+        Self {
+            backend: C::new(keys),
+            _share: PhantomData,
+        }
+    }
+}
+
+impl<P, S> ServerOfflineGen<Fp64<P>> for ServerOfflineMPC<Fp64<P>, S>
+where
+    P: Fp64Parameters,
+    S: ServerGen<MsgType = c_char> + Sync,
+    S::Keys: Copy,
+{
+    type Keys = S::Keys;
+
+    fn from_keys(keys: S::Keys, mac_key: u64) -> Self {
+        Self {
+            backend: S::new(keys, mac_key),
+            mac_key: Fp64::from_repr(mac_key.into()),
+        }
+    }
 }
 
 impl<'a, P: Fp64Parameters> ClientOfflineMPC<Fp64<P>, SealClientGen<'a>> {
@@ -88,7 +770,7 @@ impl<'a, P: Fp64Parameters> ServerOfflineMPC<Fp64<P>, SealServerGen<'a>> {
     pub fn new(sfhe: &'a ServerFHE, mac_key: u64) -> Self {
         Self {
             backend: SealServerGen::new(sfhe, mac_key),
-            _share: PhantomData,
+            mac_key: Fp64::from_repr(mac_key.into()),
         }
     }
 }
@@ -132,9 +814,10 @@ impl<P: Fp64Parameters> ClientOfflineMPC<Fp64<P>, SealClientGen<'_>> {
         }
         // Send shares in batches
         for i in 0..batches {
-            let send_message = ShareSend::new(
+            let packed = PackedSlice(
                 &server_shares[i * Self::BATCH_SIZE..min((i + 1) * Self::BATCH_SIZE, input.len())],
             );
+            let send_message = ShareSend::new(&packed);
             bytes::serialize(&mut *writer, &send_message).unwrap();
             let msg = (i, server_mac_ct[i].clone());
             let send_message = MsgSend::new(&msg);
@@ -163,7 +846,7 @@ impl<P: Fp64Parameters> ServerOfflineMPC<Fp64<P>, SealServerGen<'_>> {
         let mut shares = Vec::with_capacity(num);
         for i in 0..((num as f64 / Self::BATCH_SIZE as f64).ceil() as usize) {
             let recv_message: ShareRcv<_> = bytes::deserialize(&mut *reader).unwrap();
-            let share = recv_message.msg();
+            let share = recv_message.msg().0;
             let recv_message: MsgRcv = bytes::deserialize(&mut *reader).unwrap();
             let (_, mac_share_ct) = recv_message.msg();
             let mut tmp = SealCT::new();
@@ -188,20 +871,25 @@ impl<P: Fp64Parameters> ServerOfflineMPC<Fp64<P>, SealServerGen<'_>> {
     //    }
 }
 
-impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealClientGen<'_>> {
+impl<P, C> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, C>
+where
+    P: Fp64Parameters,
+    C: ClientGen<MsgType = c_char> + Sync,
+{
     fn rands_gen<R, W, RNG>(
         &self,
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
-        _rng: &mut RNG,
+        rng: &mut RNG,
         num: usize,
-    ) -> Vec<AuthAdditiveShare<Fp64<P>>>
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
     where
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: RngCore + CryptoRng,
     {
         let start_time = timer_start!(|| "Client pairwise randomness generation");
+        let _phase = comm::phase("Rands");
 
         // Calculate number of batches to send per thread
         let batches = (num as f64 / Self::BATCH_SIZE as f64).ceil() as usize;
@@ -212,23 +900,23 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
         // TODO: Benchmark w/o mutex in single thread
         let result = Arc::new(Mutex::new(vec![AuthAdditiveShare::zero(); num]));
 
-        // Vector which holds states for post processing server result
-        let states = RwLock::new(vec![None; batches]);
+        let mut rngs = thread_rngs(rng, num_threads);
 
+        let scope = comm::scope();
         rayon::scope(|s| {
+            // The closure runs on a worker thread
+            let _scope = scope.enter();
             // Create a channel which all threads will push state to be sent to the server
             let (send_1, mut recv_1) = channel::bounded(batches);
             // Create a channel which will contain all state receieved from the server
             let (send_2, recv_2) = channel::bounded(batches);
 
-            for thread_idx in 0..num_threads {
+            for (thread_idx, mut rng) in rngs.drain(..).enumerate() {
                 let send = send_1.clone(); // TODO: Change name
                 let mut recv = recv_2.clone();
                 let result = result.clone();
-                let states = &states;
                 s.spawn(move |_| {
-                    // TODO: Remove
-                    let rng = &mut ChaChaRng::from_seed(RANDOMNESS);
+                    let rng = &mut rng;
                     // If this is the last thread, only generate as many rands as needed
                     let num_rands = if thread_idx == num_threads - 1 {
                         num - thread_idx * Self::BATCH_SIZE * batches_per_thread
@@ -242,6 +930,20 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
 
                     for (i, rands_batch) in rands.chunks(Self::BATCH_SIZE).enumerate() {
                         let batch_idx = thread_idx * batches_per_thread + i;
+                        // The server uses its own rands as its value shares, so the
+                        // client's value shares are its rands
+                        {
+                            let mut result_lock = result.lock().unwrap();
+                            for (share, rand) in izip!(
+                                (*result_lock)[Self::BATCH_SIZE * batch_idx..].iter_mut(),
+                                rands_batch
+                            ) {
+                                *share = AuthAdditiveShare::new(
+                                    Fp64::from_repr((*rand).into()),
+                                    Fp64::zero(),
+                                );
+                            }
+                        }
                         // Preprocess state and ciphertexts
                         let (seal_state, ct) = self.backend.rands_preprocess(rands_batch);
                         // Push ciphertexts and state to channel
@@ -259,35 +961,15 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
 
                     task::block_on(async {
                         while let Some(msg) = recv.next().await {
-                            let (i, mut r_ct, mut r_mac_ct): (usize, Vec<i8>, Vec<i8>) = msg;
-
-                            // This is guaranteed to be Some(..) since we only receive `i`
-                            // that the server has finished processing (and thus received)
-                            let states = states.read().await;
-                            let mut seal_state = states[i].unwrap();
-                            drop(states);
-
-                            let (r_share, r_mac_share) = self.backend.rands_postprocess(
-                                &mut seal_state,
-                                r_ct.as_mut_slice(),
-                                r_mac_ct.as_mut_slice(),
-                            );
-                            let recv_shares = izip!(r_share, r_mac_share)
-                                .map(|(v, m)| {
-                                    AuthAdditiveShare::new(
-                                        Fp64::from_repr(v.into()),
-                                        Fp64::from_repr(m.into()),
-                                    )
-                                })
-                                .collect::<Vec<_>>();
+                            let (i, r_mac_ct): (usize, Vec<c_char>) = msg;
+                            let range = Self::BATCH_SIZE * i..min(Self::BATCH_SIZE * (i + 1), num);
+                            let r_mac_share = self.backend.auth_postprocess(r_mac_ct, range.len());
                             let mut result_lock = result.lock().unwrap();
-                            for (old, new) in izip!(
-                                (*result_lock)
-                                    [Self::BATCH_SIZE * i..min(Self::BATCH_SIZE * (i + 1), num)]
-                                    .iter_mut(),
-                                recv_shares.iter()
-                            ) {
-                                *old = *new;
+                            for (share, m) in izip!((*result_lock)[range].iter_mut(), r_mac_share) {
+                                *share = AuthAdditiveShare::new(
+                                    share.get_value().inner,
+                                    Fp64::from_repr(m.into()),
+                                );
                             }
                         }
                     });
@@ -297,7 +979,7 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
             drop(send_1);
 
             task::block_on(async {
-                // Future for sending ciphertexts to server and pushing states into `states`
+                // Future for sending ciphertexts to server
                 let send_future = async {
                     let send_time = timer_start!(|| "Sending ciphertexts to server");
                     while let Some((batch_idx, mut seal_state, ct)) = recv_1.next().await {
@@ -307,8 +989,6 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
                             .await
                             .unwrap();
                         self.backend.rands_free_ct(&mut seal_state);
-                        let mut states = states.write().await;
-                        states[batch_idx] = Some(seal_state);
                     }
                     timer_end!(send_time);
                 };
@@ -321,12 +1001,9 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
                         // Receive ciphertexts from the server
                         let recv_message: MsgRcv =
                             bytes::async_deserialize(&mut *reader).await.unwrap();
-                        let (i, r_ct) = recv_message.msg();
-                        let recv_message: MsgRcv =
-                            bytes::async_deserialize(&mut *reader).await.unwrap();
-                        let (_, r_mac_ct) = recv_message.msg();
+                        let (i, r_mac_ct) = recv_message.msg();
                         // Push ciphertexts to channel to be processed by a thread
-                        send.send((i, r_ct, r_mac_ct)).await.unwrap();
+                        send.send((i, r_mac_ct)).await.unwrap();
                     }
                     timer_end!(recv_time);
                     // Drop the remaining channel
@@ -338,22 +1015,208 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
             });
         });
         timer_end!(start_time);
-        Arc::try_unwrap(result).unwrap().into_inner().unwrap()
+        Ok(Arc::try_unwrap(result).unwrap().into_inner().unwrap())
+    }
+
+    fn client_rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        // The client's value shares are its own rands in either case
+        self.rands_gen(reader, writer, rng, num)
     }
 
     fn triples_gen<R, W, RNG>(
         &self,
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
-        _rng: &mut RNG,
+        rng: &mut RNG,
         num: usize,
-    ) -> Vec<Triple<Fp64<P>>>
+    ) -> Result<Vec<Triple<Fp64<P>>>, MpcError>
     where
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: RngCore + CryptoRng,
     {
         let start_time = timer_start!(|| "Client triples generation");
+        let _phase = comm::phase("Triples");
+
+        let a: Vec<_> = (0..num)
+            .map(|_| Fp64::<P>::uniform(rng).into_repr().0)
+            .collect();
+        let b: Vec<_> = (0..num)
+            .map(|_| Fp64::<P>::uniform(rng).into_repr().0)
+            .collect();
+        let triples = self.products_gen(reader, writer, &a, &b, false);
+        timer_end!(start_time);
+        Ok(triples)
+    }
+
+    fn squares_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Client squares generation");
+        let _phase = comm::phase("Squares");
+
+        // Generate twice as many pairs as needed so that half can be
+        // sacrificed
+        let a: Vec<_> = (0..2 * num)
+            .map(|_| Fp64::<P>::uniform(rng).into_repr().0)
+            .collect();
+        let mut squares: Vec<_> = self
+            .products_gen(reader, writer, &a, &a, true)
+            .into_iter()
+            .map(|t| SquarePair {
+                a: t.a,
+                a_squared: t.c,
+            })
+            .collect();
+        let sacrificed = squares.split_off(num);
+        client_check_squares(reader, writer, &squares, &sacrificed)?;
+        timer_end!(start_time);
+        Ok(squares)
+    }
+
+    fn ole_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        _rng: &mut RNG,
+        values: &[Fp64<P>],
+    ) -> Result<Vec<Fp64<P>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Client OLE generation");
+        // The client's values are its shares of `a` and the server holds all
+        // of `b`, so `c` is the product
+        let a: Vec<_> = values.iter().map(|v| v.into_repr().0).collect();
+        let products = self
+            .products_gen(reader, writer, &a, &vec![0; a.len()], false)
+            .iter()
+            .map(|t| t.c.get_value().inner)
+            .collect();
+        timer_end!(start_time);
+        Ok(products)
+    }
+}
+
+impl<P, C> ClientOfflineMPC<Fp64<P>, C>
+where
+    P: Fp64Parameters,
+    C: ClientGen<MsgType = c_char> + Sync,
+{
+    /// Generates an authenticated matrix triple for each of `dims` with packed
+    /// HE matrix-vector products. For a triple of dimensions `(m, k, n)`, the
+    /// server evaluates its share of `A` on the columns of the client's share
+    /// of `B`, and the transpose of its share of `B` on the rows of the
+    /// client's share of `A`, with the fully connected ACG of `keys`. As for
+    /// fully connected layers, `k` must fit in a single ciphertext. The
+    /// client's product of its own shares is authenticated with `ole_gen`.
+    ///
+    /// Each triple is generated with `2 * m` rows of `A` and `C`, and the last
+    /// `m` rows are sacrificed to check the first, so that the server aborts
+    /// with an error unless the client's encrypted shares are consistent with
+    /// the shares it authenticated.
+    pub fn matrix_triples_gen<A, R, W, RNG>(
+        &self,
+        keys: A::Keys,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        dims: &[(usize, usize, usize)],
+    ) -> Result<Vec<MatrixTriple<Fp64<P>>>, MpcError>
+    where
+        A: ClientACG,
+        A::Keys: Copy,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Client matrix triples generation");
+        let _phase = comm::phase("Triples");
+
+        let mut shares = Vec::with_capacity(dims.len());
+        let mut products = Vec::new();
+        for &(m, k, n) in dims {
+            assert!(m > 0 && k > 0 && n > 0, "matrix triples must be non-empty");
+            assert!(
+                k <= protocols_sys::POLY_MOD_DEGREE as usize,
+                "the inner dimension of matrix triples must fit in a ciphertext"
+            );
+            let a: Vec<_> = (0..2 * m * k).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let b: Vec<_> = (0..k * n).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let (b_shares, as_bc) = client_matmul_acg::<_, A, _, _>(
+                keys,
+                reader,
+                writer,
+                &transpose(&b, k, n),
+                (k, 2 * m),
+            )?;
+            let (a_shares, ac_bs) =
+                client_matmul_acg::<_, A, _, _>(keys, reader, writer, &a, (k, n))?;
+            let cross_terms: Vec<_> = izip!(transpose(&as_bc, n, 2 * m), ac_bs)
+                .map(|(x, y)| x + y)
+                .collect();
+            products.extend(matmul(&a, &b, (2 * m, k, n)));
+            shares.push((a_shares, transpose(&b_shares, n, k), cross_terms));
+        }
+        let macs = self.ole_gen(reader, writer, rng, &products)?;
+
+        let mut offset = 0;
+        let mut triples = Vec::with_capacity(dims.len());
+        let mut sacrificed = Vec::with_capacity(dims.len());
+        for (&(m, k, n), (a, b, cross_terms)) in izip!(dims, shares) {
+            let c: Vec<_> = izip!(&products[offset..], &macs[offset..], cross_terms)
+                .map(|(value, mac, cross)| AuthAdditiveShare::new(*value, *mac) + cross)
+                .collect();
+            offset += 2 * m * n;
+            let (triple, triple_hat) = split_sacrificed(a, b, c, (m, k, n));
+            triples.push(triple);
+            sacrificed.push(triple_hat);
+        }
+        client_check_matrix_triples(reader, writer, &triples, &sacrificed)?;
+        timer_end!(start_time);
+        Ok(triples)
+    }
+
+    /// Generates a triple for each pair of the client's `a` and `b` values,
+    /// which are added to the server's values in `ServerOfflineMPC::products_gen`.
+    /// If `square` is set then `b` must equal `a`, and only one ciphertext of
+    /// `a` is sent so that the server squares it homomorphically.
+    fn products_gen<R, W>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        a: &[u64],
+        b: &[u64],
+        square: bool,
+    ) -> Vec<Triple<Fp64<P>>>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+    {
+        assert_eq!(a.len(), b.len());
+        let num = a.len();
 
         // Calculate number of batches to send per thread
         let batches = (num as f64 / Self::BATCH_SIZE as f64).ceil() as usize;
@@ -374,33 +1237,25 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
         // Vector which holds states for post processing server result
         let states = RwLock::new(vec![None; batches]);
 
+        let scope = comm::scope();
         rayon::scope(|s| {
+            // The closure runs on a worker thread
+            let _scope = scope.enter();
             // Create a channel which all threads will push state to be sent to the server
             let (send_1, mut recv_1) = channel::bounded(batches);
             // Create a channel which will contain all state receieved from the server
             let (send_2, recv_2) = channel::bounded(batches);
 
-            for thread_idx in 0..num_threads {
+            for (thread_idx, (a, b)) in a
+                .chunks(Self::BATCH_SIZE * batches_per_thread)
+                .zip(b.chunks(Self::BATCH_SIZE * batches_per_thread))
+                .enumerate()
+            {
                 let send = send_1.clone(); // TODO: Change name
                 let mut recv = recv_2.clone();
                 let result = result.clone();
                 let states = &states;
                 s.spawn(move |_| {
-                    // TODO: Remove
-                    let rng = &mut ChaChaRng::from_seed(RANDOMNESS);
-                    // If this is the last thread, only generate as many rands as needed
-                    let num_rands = if thread_idx == num_threads - 1 {
-                        num - thread_idx * Self::BATCH_SIZE * batches_per_thread
-                    } else {
-                        Self::BATCH_SIZE * batches_per_thread
-                    };
-                    let mut a = Vec::with_capacity(num_rands);
-                    let mut b = Vec::with_capacity(num_rands);
-                    for _ in 0..num_rands {
-                        a.push(Fp64::<P>::uniform(rng).into_repr().0);
-                        b.push(Fp64::<P>::uniform(rng).into_repr().0);
-                    }
-
                     for (i, (a_batch, b_batch)) in a
                         .chunks(Self::BATCH_SIZE)
                         .zip(b.chunks(Self::BATCH_SIZE))
@@ -408,8 +1263,14 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
                     {
                         let batch_idx = thread_idx * batches_per_thread + i;
                         // Preprocess state and ciphertexts
-                        let (seal_state, a_ct, b_ct) =
-                            self.backend.triples_preprocess(a_batch, b_batch);
+                        let (seal_state, a_ct, b_ct) = if square {
+                            let (seal_state, a_ct) = self.backend.rands_preprocess(a_batch);
+                            (seal_state, a_ct, None)
+                        } else {
+                            let (seal_state, a_ct, b_ct) =
+                                self.backend.triples_preprocess(a_batch, b_batch);
+                            (seal_state, a_ct, Some(b_ct))
+                        };
                         // Push ciphertexts and state to channel
                         task::block_on(async {
                             send.send((batch_idx, seal_state, a_ct, b_ct))
@@ -437,12 +1298,12 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
                                 mut c_mac_ct,
                             ): (
                                 usize,
-                                Vec<i8>,
-                                Vec<i8>,
-                                Vec<i8>,
-                                Vec<i8>,
-                                Vec<i8>,
-                                Vec<i8>,
+                                Vec<c_char>,
+                                Vec<c_char>,
+                                Vec<c_char>,
+                                Vec<c_char>,
+                                Vec<c_char>,
+                                Vec<c_char>,
                             ) = msg;
 
                             // This is guaranteed to be Some(..) since we only receive `i`
@@ -511,12 +1372,16 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
                         bytes::async_serialize(&mut *writer, &send_message)
                             .await
                             .unwrap();
-                        let msg = (batch_idx, b_ct);
-                        let send_message = MsgSend::new(&msg);
-                        bytes::async_serialize(&mut *writer, &send_message)
-                            .await
-                            .unwrap();
-                        self.backend.triples_free_ct(&mut seal_state);
+                        if let Some(b_ct) = b_ct {
+                            let msg = (batch_idx, b_ct);
+                            let send_message = MsgSend::new(&msg);
+                            bytes::async_serialize(&mut *writer, &send_message)
+                                .await
+                                .unwrap();
+                            self.backend.triples_free_ct(&mut seal_state);
+                        } else {
+                            self.backend.rands_free_ct(&mut seal_state);
+                        }
                         let mut states = states.write().await;
                         states[batch_idx] = Some(seal_state);
                     }
@@ -532,18 +1397,28 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
                         let recv_message: MsgRcv =
                             bytes::async_deserialize(&mut *reader).await.unwrap();
                         let (i, a_ct) = recv_message.msg();
-                        let recv_message: MsgRcv =
-                            bytes::async_deserialize(&mut *reader).await.unwrap();
-                        let (_, b_ct) = recv_message.msg();
+                        // The server doesn't send `b` when squaring since it
+                        // is the same as `a`
+                        let b_ct = if square {
+                            a_ct.clone()
+                        } else {
+                            let recv_message: MsgRcv =
+                                bytes::async_deserialize(&mut *reader).await.unwrap();
+                            recv_message.msg().1
+                        };
                         let recv_message: MsgRcv =
                             bytes::async_deserialize(&mut *reader).await.unwrap();
                         let (_, c_ct) = recv_message.msg();
                         let recv_message: MsgRcv =
                             bytes::async_deserialize(&mut *reader).await.unwrap();
                         let (_, a_mac_ct) = recv_message.msg();
-                        let recv_message: MsgRcv =
-                            bytes::async_deserialize(&mut *reader).await.unwrap();
-                        let (_, b_mac_ct) = recv_message.msg();
+                        let b_mac_ct = if square {
+                            a_mac_ct.clone()
+                        } else {
+                            let recv_message: MsgRcv =
+                                bytes::async_deserialize(&mut *reader).await.unwrap();
+                            recv_message.msg().1
+                        };
                         let recv_message: MsgRcv =
                             bytes::async_deserialize(&mut *reader).await.unwrap();
                         let (_, c_mac_ct) = recv_message.msg();
@@ -560,44 +1435,191 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ClientOfflineMPC<Fp64<P>, SealCl
                 futures::future::join(send_future, recv_future).await;
             });
         });
-        timer_end!(start_time);
         Arc::try_unwrap(result).unwrap().into_inner().unwrap()
     }
 }
 
-impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealServerGen<'_>> {
+impl<P, S> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, S>
+where
+    P: Fp64Parameters,
+    S: ServerGen<MsgType = c_char> + Sync,
+{
     fn rands_gen<R, W, RNG>(
         &self,
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
         rng: &mut RNG,
         num: usize,
-    ) -> Vec<AuthAdditiveShare<Fp64<P>>>
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
     where
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: RngCore + CryptoRng,
     {
-        let start_time = timer_start!(|| "Server pairwise randomness generation");
+        self.seal_rands_gen(reader, writer, rng, num, false)
+    }
 
-        // Calculate number of batches to send per thread
-        let batches = (num as f64 / Self::BATCH_SIZE as f64).ceil() as usize;
-        let num_threads = min(batches, rayon::current_num_threads() - 1);
-        let batches_per_thread = (batches as f64 / num_threads as f64).ceil() as usize;
+    fn client_rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        self.seal_rands_gen(reader, writer, rng, num, true)
+    }
 
-        let pre_time = timer_start!(|| "Preprocessing");
-        let mut rands =
-            vec![Vec::with_capacity(Self::BATCH_SIZE * batches_per_thread); num_threads];
-        let mut shares =
-            vec![Vec::with_capacity(Self::BATCH_SIZE * batches_per_thread); num_threads];
-        let mut mac_shares =
-            vec![Vec::with_capacity(Self::BATCH_SIZE * batches_per_thread); num_threads];
-        let mut result = Vec::with_capacity(num);
+    fn triples_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<Triple<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server triples generation");
+        let _phase = comm::phase("Triples");
+
+        // Each value is paired with the server's share of it
+        let mut sample = || {
+            let value = Fp64::<P>::uniform(rng);
+            let share = AuthAdditiveShare::new(Fp64::uniform(rng), Fp64::uniform(rng));
+            (value, share)
+        };
+        let a: Vec<_> = (0..num).map(|_| sample()).collect();
+        let b: Vec<_> = (0..num).map(|_| sample()).collect();
+        let triples = self.products_gen(reader, writer, rng, &a, &b, false);
+        timer_end!(start_time);
+        Ok(triples)
+    }
+
+    fn squares_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server squares generation");
+        let _phase = comm::phase("Squares");
+
+        // Each value is paired with the server's share of it
+        let mut sample = || {
+            let value = Fp64::<P>::uniform(rng);
+            let share = AuthAdditiveShare::new(Fp64::uniform(rng), Fp64::uniform(rng));
+            (value, share)
+        };
+        // Generate twice as many pairs as needed so that half can be
+        // sacrificed
+        let a: Vec<_> = (0..2 * num).map(|_| sample()).collect();
+        let mut squares: Vec<_> = self
+            .products_gen(reader, writer, rng, &a, &a, true)
+            .into_iter()
+            .map(|t| SquarePair {
+                a: t.a,
+                a_squared: t.c,
+            })
+            .collect();
+        let sacrificed = squares.split_off(num);
+        server_check_squares(reader, writer, rng, self.mac_key, &squares, &sacrificed)?;
+        timer_end!(start_time);
+        Ok(squares)
+    }
+
+    fn ole_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        values: &[Fp64<P>],
+    ) -> Result<Vec<Fp64<P>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server OLE generation");
+        // The server adds nothing to the client's `a` and is the only holder
+        // of `b`. Its shares of `a`, `b` and their MACs are uniformly random so
+        // that the client learns nothing about `values` or the MAC key.
+        let mut sample = |value: Fp64<P>| {
+            let share = AuthAdditiveShare::new(Fp64::uniform(rng), Fp64::uniform(rng));
+            (value, share)
+        };
+        let a: Vec<_> = values.iter().map(|_| sample(Fp64::zero())).collect();
+        let b: Vec<_> = values.iter().map(|v| sample(*v)).collect();
+        let products = self
+            .products_gen(reader, writer, rng, &a, &b, false)
+            .iter()
+            .map(|t| t.c.get_value().inner)
+            .collect();
+        timer_end!(start_time);
+        Ok(products)
+    }
+}
+
+impl<P, S> ServerOfflineMPC<Fp64<P>, S>
+where
+    P: Fp64Parameters,
+    S: ServerGen<MsgType = c_char> + Sync,
+{
+    /// Generates `num` authenticated pairwise randomness shares. If
+    /// `known_to_client` is set, the server contributes no randomness so that
+    /// the client's value shares are the whole values.
+    fn seal_rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+        known_to_client: bool,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server pairwise randomness generation");
+        let _phase = comm::phase("Rands");
+
+        // Calculate number of batches to send per thread
+        let batches = (num as f64 / Self::BATCH_SIZE as f64).ceil() as usize;
+        let num_threads = min(batches, rayon::current_num_threads() - 1);
+        let batches_per_thread = (batches as f64 / num_threads as f64).ceil() as usize;
+
+        let pre_time = timer_start!(|| "Preprocessing");
+        let mut rands =
+            vec![Vec::with_capacity(Self::BATCH_SIZE * batches_per_thread); num_threads];
+        let mut shares =
+            vec![Vec::with_capacity(Self::BATCH_SIZE * batches_per_thread); num_threads];
+        let mut mac_shares =
+            vec![Vec::with_capacity(Self::BATCH_SIZE * batches_per_thread); num_threads];
+        let mut result = Vec::with_capacity(num);
         for i in 0..num {
             let idx = i / Self::BATCH_SIZE / batches_per_thread;
-            rands[idx].push(Fp64::<P>::uniform(rng).into_repr().0);
-            let share = Fp64::<P>::uniform(rng);
+            // The server's value share is its rand, so that the client's value
+            // share is its own rand and need not be sent back
+            let share = if known_to_client {
+                Fp64::zero()
+            } else {
+                Fp64::<P>::uniform(rng)
+            };
             let mac_share = Fp64::<P>::uniform(rng);
+            rands[idx].push(share.into_repr().0);
             shares[idx].push(share.into_repr().0);
             mac_shares[idx].push(mac_share.into_repr().0);
             result.push(AuthAdditiveShare::new(share, mac_share));
@@ -615,7 +1637,10 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
         // Create a channel which will contain all state to be sent to the client
         let (tx_out, mut rx_out) = channel::bounded(batches);
 
+        let scope = comm::scope();
         rayon::scope(|s| {
+            // The closure runs on a worker thread
+            let _scope = scope.enter();
             for thread_idx in (0..num_threads).rev() {
                 // Move thread batches into scope
                 let rands = rands.pop().unwrap();
@@ -677,13 +1702,8 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
                 };
                 // Future for sending cipehrtexts to the client
                 let send_future = async {
-                    while let Some((i, (r_share_ct, r_mac_share_ct))) = rx_out.next().await {
-                        // Send result to client
-                        let msg = (i, r_share_ct);
-                        let send_message = MsgSend::new(&msg);
-                        bytes::async_serialize(&mut *writer, &send_message)
-                            .await
-                            .unwrap();
+                    while let Some((i, (_, r_mac_share_ct))) = rx_out.next().await {
+                        // Send MAC shares to client
                         let msg = (i, r_mac_share_ct);
                         let send_message = MsgSend::new(&msg);
                         bytes::async_serialize(&mut *writer, &send_message)
@@ -697,22 +1717,112 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
             });
         });
         timer_end!(start_time);
-        result
+        Ok(result)
     }
 
-    fn triples_gen<R, W, RNG>(
+    /// Generates an authenticated matrix triple for each of `dims`, see
+    /// `ClientOfflineMPC::matrix_triples_gen`
+    pub fn matrix_triples_gen<A, R, W, RNG>(
         &self,
+        keys: A::Keys,
         reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
         rng: &mut RNG,
-        num: usize,
+        dims: &[(usize, usize, usize)],
+    ) -> Result<Vec<MatrixTriple<Fp64<P>>>, MpcError>
+    where
+        A: ServerACG,
+        A::Keys: Copy,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server matrix triples generation");
+        let _phase = comm::phase("Triples");
+
+        // The server authenticates its own shares of `A`, `B` and `C`
+        let auth = |value: Fp64<P>| AuthAdditiveShare::new(value, self.mac_key * value);
+        let mut shares = Vec::with_capacity(dims.len());
+        for &(m, k, n) in dims {
+            assert!(m > 0 && k > 0 && n > 0, "matrix triples must be non-empty");
+            assert!(
+                k <= protocols_sys::POLY_MOD_DEGREE as usize,
+                "the inner dimension of matrix triples must fit in a ciphertext"
+            );
+            let a: Vec<_> = (0..2 * m * k).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let b: Vec<_> = (0..k * n).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let (b_shares, as_bc) = server_matmul_acg::<_, A, _, _, _>(
+                keys,
+                reader,
+                writer,
+                rng,
+                self.mac_key,
+                &a,
+                (n, k, 2 * m),
+            )?;
+            let (a_shares, ac_bs) = server_matmul_acg::<_, A, _, _, _>(
+                keys,
+                reader,
+                writer,
+                rng,
+                self.mac_key,
+                &transpose(&b, k, n),
+                (2 * m, k, n),
+            )?;
+            let c: Vec<_> = izip!(
+                matmul(&a, &b, (2 * m, k, n)),
+                transpose(&as_bc, n, 2 * m),
+                ac_bs
+            )
+            .map(|(value, x, y)| auth(value) + x + y)
+            .collect();
+            let a: Vec<_> = izip!(a, a_shares).map(|(a, s)| auth(a) + s).collect();
+            let b: Vec<_> = izip!(b, transpose(&b_shares, n, k))
+                .map(|(b, s)| auth(b) + s)
+                .collect();
+            shares.push((a, b, c));
+        }
+        let num_products = dims.iter().map(|&(m, _, n)| 2 * m * n).sum();
+        let macs = self.ole_gen(reader, writer, rng, &vec![self.mac_key; num_products])?;
+
+        let mut offset = 0;
+        let mut triples = Vec::with_capacity(dims.len());
+        let mut sacrificed = Vec::with_capacity(dims.len());
+        for (&(m, k, n), (a, b, c)) in izip!(dims, shares) {
+            let c: Vec<_> = izip!(c, &macs[offset..])
+                .map(|(c, mac)| c + AuthAdditiveShare::new(Fp64::zero(), *mac))
+                .collect();
+            offset += 2 * m * n;
+            let (triple, triple_hat) = split_sacrificed(a, b, c, (m, k, n));
+            triples.push(triple);
+            sacrificed.push(triple_hat);
+        }
+        server_check_matrix_triples(reader, writer, rng, self.mac_key, &triples, &sacrificed)?;
+        timer_end!(start_time);
+        Ok(triples)
+    }
+
+    /// Generates a triple for each of the server's values in `a` and `b`,
+    /// which are added to the client's values in `ClientOfflineMPC::products_gen`.
+    /// Each value is paired with the server's share of it in the triple. If
+    /// `square` is set then `b` must equal `a`, and the client's single
+    /// ciphertext of `a` is squared homomorphically.
+    fn products_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        a: &[(Fp64<P>, AuthAdditiveShare<Fp64<P>>)],
+        b: &[(Fp64<P>, AuthAdditiveShare<Fp64<P>>)],
+        square: bool,
     ) -> Vec<Triple<Fp64<P>>>
     where
         R: Read + Send + Unpin,
         W: Write + Send + Unpin,
         RNG: RngCore + CryptoRng,
     {
-        let start_time = timer_start!(|| "Server triples generation");
+        assert_eq!(a.len(), b.len());
+        let num = a.len();
 
         // Calculate number of batches to send per thread
         let batches = (num as f64 / Self::BATCH_SIZE as f64).ceil() as usize;
@@ -739,16 +1849,14 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
         let mut c_mac_shares =
             vec![Vec::with_capacity(Self::BATCH_SIZE * batches_per_thread); num_threads];
         let mut triples = Vec::with_capacity(num);
-        for i in 0..num {
+        for (i, ((a_rand, a), (b_rand, b))) in a.iter().zip(b).enumerate() {
             let idx = i / Self::BATCH_SIZE / batches_per_thread;
-            let a_rand = Fp64::<P>::uniform(rng);
-            let b_rand = Fp64::<P>::uniform(rng);
-            let c_rand = a_rand * b_rand;
-            let a_share = Fp64::<P>::uniform(rng);
-            let b_share = Fp64::<P>::uniform(rng);
+            let c_rand = *a_rand * *b_rand;
+            let a_share = a.get_value().inner;
+            let b_share = b.get_value().inner;
             let c_share = Fp64::<P>::uniform(rng);
-            let a_mac_share = Fp64::<P>::uniform(rng);
-            let b_mac_share = Fp64::<P>::uniform(rng);
+            let a_mac_share = a.get_mac().inner;
+            let b_mac_share = b.get_mac().inner;
             let c_mac_share = Fp64::<P>::uniform(rng);
             a_rands[idx].push(a_rand.into_repr().0);
             b_rands[idx].push(b_rand.into_repr().0);
@@ -761,8 +1869,8 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
             c_mac_shares[idx].push(c_mac_share.into_repr().0);
 
             triples.push(Triple {
-                a: AuthAdditiveShare::new(a_share, a_mac_share),
-                b: AuthAdditiveShare::new(b_share, b_mac_share),
+                a: *a,
+                b: *b,
                 c: AuthAdditiveShare::new(c_share, c_mac_share),
             });
         }
@@ -779,7 +1887,10 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
         // Create a channel which will contain all state to be sent to the client
         let (tx_out, mut rx_out) = channel::bounded(batches);
 
+        let scope = comm::scope();
         rayon::scope(|s| {
+            // The closure runs on a worker thread
+            let _scope = scope.enter();
             for thread_idx in (0..num_threads).rev() {
                 // Move thread batches into scope
                 let a_rands = a_rands.pop().unwrap();
@@ -872,10 +1983,15 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
                         let recv_message: MsgRcv =
                             bytes::async_deserialize(&mut *reader).await.unwrap();
                         let (i, a_rands_ct) = recv_message.msg();
-                        let recv_message: MsgRcv =
-                            bytes::async_deserialize(&mut *reader).await.unwrap();
-                        let (j, b_rands_ct) = recv_message.msg();
-                        assert_eq!(i, j);
+                        let b_rands_ct = if square {
+                            a_rands_ct.clone()
+                        } else {
+                            let recv_message: MsgRcv =
+                                bytes::async_deserialize(&mut *reader).await.unwrap();
+                            let (j, b_rands_ct) = recv_message.msg();
+                            assert_eq!(i, j);
+                            b_rands_ct
+                        };
                         // Send input over appropriate channel
                         tx_inp[i / batches_per_thread]
                             .send((a_rands_ct, b_rands_ct))
@@ -896,11 +2012,13 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
                         bytes::async_serialize(&mut *writer, &send_message)
                             .await
                             .unwrap();
-                        let msg = (i, b_ct);
-                        let send_message = MsgSend::new(&msg);
-                        bytes::async_serialize(&mut *writer, &send_message)
-                            .await
-                            .unwrap();
+                        if !square {
+                            let msg = (i, b_ct);
+                            let send_message = MsgSend::new(&msg);
+                            bytes::async_serialize(&mut *writer, &send_message)
+                                .await
+                                .unwrap();
+                        }
                         let msg = (i, c_ct);
                         let send_message = MsgSend::new(&msg);
                         bytes::async_serialize(&mut *writer, &send_message)
@@ -911,11 +2029,13 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
                         bytes::async_serialize(&mut *writer, &send_message)
                             .await
                             .unwrap();
-                        let msg = (i, b_mac_ct);
-                        let send_message = MsgSend::new(&msg);
-                        bytes::async_serialize(&mut *writer, &send_message)
-                            .await
-                            .unwrap();
+                        if !square {
+                            let msg = (i, b_mac_ct);
+                            let send_message = MsgSend::new(&msg);
+                            bytes::async_serialize(&mut *writer, &send_message)
+                                .await
+                                .unwrap();
+                        }
                         let msg = (i, c_mac_ct);
                         let send_message = MsgSend::new(&msg);
                         bytes::async_serialize(&mut *writer, &send_message)
@@ -929,60 +2049,209 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for ServerOfflineMPC<Fp64<P>, SealSe
                 tx_inp.into_iter().for_each(|c| drop(c));
             });
         });
-        timer_end!(start_time);
         triples
     }
 }
 
-/// Insecure offline MPC phase for testing
-pub struct InsecureClientOfflineMPC<T: AuthShare, C: ClientGen> {
-    _backend: PhantomData<C>,
+/// Insecure offline MPC phase for testing, in which the server deals
+/// every triple and rand to the client by sending the seed which the client's
+/// shares are expanded from. Neither party needs HE keys.
+pub struct InsecureClientOfflineMPC<T: AuthShare> {
     _share: PhantomData<T>,
 }
 
-pub struct InsecureServerOfflineMPC<T: AuthShare, S: ServerGen> {
-    backend: S,
+pub struct InsecureServerOfflineMPC<T: AuthShare> {
+    mac_key: u64,
     _share: PhantomData<T>,
 }
 
-type InsecureTriplesSend<'a, F> = OutMessage<'a, Vec<Triple<F>>, OfflineMPCProtocolType>;
-type InsecureTriplesRcv<F> = InMessage<Vec<Triple<F>>, OfflineMPCProtocolType>;
-type InsecureRandsSend<'a, F> = OutMessage<'a, Vec<AuthAdditiveShare<F>>, OfflineMPCProtocolType>;
-type InsecureRandsRcv<F> = InMessage<Vec<AuthAdditiveShare<F>>, OfflineMPCProtocolType>;
+/// Groups shares laid out as `a, b, c` for each triple into triples
+fn triples_from_shares<T: AuthShare>(shares: &[AuthAdditiveShare<T>]) -> Vec<Triple<T>> {
+    shares
+        .chunks(3)
+        .map(|s| Triple {
+            a: s[0],
+            b: s[1],
+            c: s[2],
+        })
+        .collect()
+}
+
+/// Groups shares laid out as `a, a^2` for each pair into square pairs
+fn squares_from_shares<T: AuthShare>(shares: &[AuthAdditiveShare<T>]) -> Vec<SquarePair<T>> {
+    shares
+        .chunks(2)
+        .map(|s| SquarePair {
+            a: s[0],
+            a_squared: s[1],
+        })
+        .collect()
+}
+
+/// Groups shares laid out as the entries of `A`, `B` and `C` for each of
+/// `dims` into matrix triples
+fn matrix_triples_from_shares<T: AuthShare>(
+    shares: &[AuthAdditiveShare<T>],
+    dims: &[(usize, usize, usize)],
+) -> Vec<MatrixTriple<T>> {
+    let mut shares = shares.iter().copied();
+    dims.iter()
+        .map(|&(m, k, n)| MatrixTriple {
+            a: shares.by_ref().take(m * k).collect(),
+            b: shares.by_ref().take(k * n).collect(),
+            c: shares.by_ref().take(m * n).collect(),
+            dims: (m, k, n),
+        })
+        .collect()
+}
 
-impl<'a, P: Fp64Parameters> InsecureClientOfflineMPC<Fp64<P>, SealClientGen<'a>> {
-    pub fn new(_cfhe: &'a ClientFHE) -> Self {
+impl<P: Fp64Parameters> InsecureClientOfflineMPC<Fp64<P>> {
+    pub fn new() -> Self {
         Self {
-            _backend: PhantomData,
             _share: PhantomData,
         }
     }
+
+    /// Receives the seed which the client's `num` shares are expanded from
+    fn recv_shares<R: Read + Send + Unpin>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        num: usize,
+    ) -> Vec<AuthAdditiveShare<Fp64<P>>> {
+        let recv_message: SeedRcv = bytes::deserialize(&mut *reader).unwrap();
+        auth_shares_from_seed(recv_message.msg(), num)
+    }
+
+    /// Receives a matrix triple for each of `dims` from the server
+    pub fn matrix_triples_gen<
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        _writer: &mut IMuxAsync<W>,
+        _rng: &mut RNG,
+        dims: &[(usize, usize, usize)],
+    ) -> Vec<MatrixTriple<Fp64<P>>> {
+        let start_time = timer_start!(|| "Insecure Client matrix triples generation");
+        let _phase = comm::phase("Triples");
+        let num = dims.iter().map(|&(m, k, n)| m * k + k * n + m * n).sum();
+        let shares = self.recv_shares(reader, num);
+        let result = matrix_triples_from_shares(&shares, dims);
+        timer_end!(start_time);
+        result
+    }
 }
 
-impl<'a, P: Fp64Parameters> InsecureServerOfflineMPC<Fp64<P>, SealServerGen<'a>> {
-    pub fn new(sfhe: &'a ServerFHE, mac_key: u64) -> Self {
+impl<P: Fp64Parameters> InsecureServerOfflineMPC<Fp64<P>> {
+    pub fn new(mac_key: u64) -> Self {
         Self {
-            backend: SealServerGen::new(sfhe, mac_key),
+            mac_key,
             _share: PhantomData,
         }
     }
+
+    /// Deals authenticated shares of `values` by sending the seed which the
+    /// client's shares are expanded from. Returns the server's shares.
+    fn deal_shares<W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        values: &[Fp64<P>],
+    ) -> Vec<AuthAdditiveShare<Fp64<P>>> {
+        let mac_key = Fp64::from_repr(self.mac_key.into());
+        let seed = sample_seed(rng);
+        let client_shares = auth_shares_from_seed(seed, values.len());
+        let send_message = SeedSend::new(&seed);
+        bytes::serialize(&mut *writer, &send_message).unwrap();
+        izip!(values, &client_shares)
+            .map(|(v, s)| complement_auth_share(*v, mac_key, s))
+            .collect()
+    }
+
+    /// Deals a matrix triple for each of `dims` to the client
+    pub fn matrix_triples_gen<
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        &self,
+        _reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        dims: &[(usize, usize, usize)],
+    ) -> Vec<MatrixTriple<Fp64<P>>> {
+        let start_time = timer_start!(|| "Insecure Server matrix triples generation");
+        let _phase = comm::phase("Triples");
+        let mut values = Vec::new();
+        for &(m, k, n) in dims {
+            let a: Vec<Fp64<P>> = (0..m * k).map(|_| Fp64::uniform(rng)).collect();
+            let b: Vec<Fp64<P>> = (0..k * n).map(|_| Fp64::uniform(rng)).collect();
+            let c: Vec<_> = (0..m * n)
+                .map(|idx| {
+                    let (i, j) = (idx / n, idx % n);
+                    (0..k).fold(Fp64::zero(), |sum, l| sum + a[i * k + l] * b[l * n + j])
+                })
+                .collect();
+            values.extend(a.into_iter().chain(b).chain(c));
+        }
+        let shares = self.deal_shares(writer, rng, &values);
+        let result = matrix_triples_from_shares(&shares, dims);
+        timer_end!(start_time);
+        result
+    }
 }
 
-impl<P: Fp64Parameters> OfflineMPC<Fp64<P>>
-    for InsecureClientOfflineMPC<Fp64<P>, SealClientGen<'_>>
-{
+impl<P: Fp64Parameters> Default for InsecureClientOfflineMPC<Fp64<P>> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Fp64Parameters> ClientOfflineGen<Fp64<P>> for InsecureClientOfflineMPC<Fp64<P>> {
+    type Keys = ();
+
+    fn from_keys(_: ()) -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Fp64Parameters> ServerOfflineGen<Fp64<P>> for InsecureServerOfflineMPC<Fp64<P>> {
+    type Keys = ();
+
+    fn from_keys(_: (), mac_key: u64) -> Self {
+        Self::new(mac_key)
+    }
+}
+
+impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for InsecureClientOfflineMPC<Fp64<P>> {
     fn rands_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
         &self,
         reader: &mut IMuxAsync<R>,
         _writer: &mut IMuxAsync<W>,
         _rng: &mut RNG,
-        _num: usize,
-    ) -> Vec<AuthAdditiveShare<Fp64<P>>> {
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError> {
         let start_time = timer_start!(|| "Insecure Client pairwise randomness generation");
-        let recv_message: InsecureRandsRcv<Fp64<_>> = bytes::deserialize(&mut *reader).unwrap();
-        let result = recv_message.msg();
+        let _phase = comm::phase("Rands");
+        let result = self.recv_shares(reader, num);
         timer_end!(start_time);
-        result
+        Ok(result)
+    }
+
+    fn client_rands_gen<
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError> {
+        self.rands_gen(reader, writer, rng, num)
     }
 
     fn triples_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
@@ -990,87 +2259,173 @@ impl<P: Fp64Parameters> OfflineMPC<Fp64<P>>
         reader: &mut IMuxAsync<R>,
         _writer: &mut IMuxAsync<W>,
         _rng: &mut RNG,
-        _num: usize,
-    ) -> Vec<Triple<Fp64<P>>> {
+        num: usize,
+    ) -> Result<Vec<Triple<Fp64<P>>>, MpcError> {
         let start_time = timer_start!(|| "Insecure Client triples generation");
-        let recv_message: InsecureTriplesRcv<Fp64<_>> = bytes::deserialize(&mut *reader).unwrap();
-        let result = recv_message.msg();
+        let _phase = comm::phase("Triples");
+        let result = triples_from_shares(&self.recv_shares(reader, 3 * num));
         timer_end!(start_time);
-        result
+        Ok(result)
+    }
+
+    fn squares_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        _writer: &mut IMuxAsync<W>,
+        _rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError> {
+        let start_time = timer_start!(|| "Insecure Client squares generation");
+        let _phase = comm::phase("Squares");
+        let result = squares_from_shares(&self.recv_shares(reader, 2 * num));
+        timer_end!(start_time);
+        Ok(result)
+    }
+
+    /// The client sends its values in the clear and receives the seed its
+    /// shares of the products are expanded from
+    fn ole_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        _rng: &mut RNG,
+        values: &[Fp64<P>],
+    ) -> Result<Vec<Fp64<P>>, MpcError> {
+        let start_time = timer_start!(|| "Insecure Client OLE generation");
+        let values: Vec<_> = values.iter().map(|v| AdditiveShare::new(*v)).collect();
+        let packed = PackedSlice(values.as_slice());
+        let send_message = ShareSend::new(&packed);
+        bytes::serialize(&mut *writer, &send_message)?;
+        let recv_message: SeedRcv = bytes::deserialize(&mut *reader)?;
+        let result = expand_seed(recv_message.msg(), values.len());
+        timer_end!(start_time);
+        Ok(result)
     }
 }
 
-impl<P: Fp64Parameters> OfflineMPC<Fp64<P>>
-    for InsecureServerOfflineMPC<Fp64<P>, SealServerGen<'_>>
-{
+impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for InsecureServerOfflineMPC<Fp64<P>> {
     fn rands_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
         &self,
         _reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
         rng: &mut RNG,
         num: usize,
-    ) -> Vec<AuthAdditiveShare<Fp64<P>>> {
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError> {
         let start_time = timer_start!(|| "Insecure Server pairwise randomness generation");
-        let mut server_rands = Vec::with_capacity(num);
-        let mut client_rands = Vec::with_capacity(num);
-        let mac_key = Fp64::from_repr(self.backend.mac_key.into());
-
-        for _ in 0..num {
-            let value = Fp64::uniform(rng);
-            let (s_rand, c_rand) = value.auth_share(&mac_key, rng);
-            server_rands.push(s_rand);
-            client_rands.push(c_rand);
-        }
+        let _phase = comm::phase("Rands");
+        let values: Vec<Fp64<P>> = (0..num).map(|_| Fp64::uniform(rng)).collect();
+        let result = self.deal_shares(writer, rng, &values);
+        timer_end!(start_time);
+        Ok(result)
+    }
 
-        let send_message = InsecureRandsSend::new(&client_rands);
-        bytes::serialize(&mut *writer, &send_message).unwrap();
+    fn client_rands_gen<
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        &self,
+        _reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError> {
+        let start_time = timer_start!(|| "Insecure Server pairwise randomness generation");
+        let _phase = comm::phase("Rands");
+        // The values are those of the client's shares, so the server's
+        // value shares are zero
+        let mac_key = Fp64::from_repr(self.mac_key.into());
+        let seed = sample_seed(rng);
+        let send_message = SeedSend::new(&seed);
+        bytes::serialize(&mut *writer, &send_message)?;
+        let result = auth_shares_from_seed(seed, num)
+            .iter()
+            .map(|s| complement_auth_share(s.get_value().inner, mac_key, s))
+            .collect();
         timer_end!(start_time);
-        server_rands
+        Ok(result)
     }
 
     fn triples_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
         &self,
         _reader: &mut IMuxAsync<R>,
         writer: &mut IMuxAsync<W>,
-        _rng: &mut RNG,
+        rng: &mut RNG,
         num: usize,
-    ) -> Vec<Triple<Fp64<P>>> {
+    ) -> Result<Vec<Triple<Fp64<P>>>, MpcError> {
         let start_time = timer_start!(|| "Insecure Server triples generation");
-        let mut server_triples = Vec::with_capacity(num);
-        let mut client_triples = Vec::with_capacity(num);
-        let seed: [u8; 32] = [
-            0x99, 0xe0, 0x8f, 0xbc, 0x89, 0xa7, 0x34, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda,
-            0xf4, 0x76, 0x5d, 0xc9, 0x8d, 0x62, 0x23, 0xf2, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77,
-            0xd3, 0x4a, 0x52, 0xd2,
-        ];
-        let mut insecure_gen = crypto_primitives::beavers_mul::InsecureTripleGen::new(seed);
-        let mac_key = Fp64::from_repr(self.backend.mac_key.into());
-
-        for _ in 0..num {
-            let (s_triple, c_triple) = insecure_gen.generate_triple_shares(mac_key);
-            server_triples.push(s_triple);
-            client_triples.push(c_triple);
-        }
+        let _phase = comm::phase("Triples");
+        let values: Vec<Fp64<P>> = (0..num)
+            .flat_map(|_| {
+                let a = Fp64::uniform(rng);
+                let b = Fp64::uniform(rng);
+                vec![a, b, a * b]
+            })
+            .collect();
+        let result = triples_from_shares(&self.deal_shares(writer, rng, &values));
+        timer_end!(start_time);
+        Ok(result)
+    }
 
-        let send_message = InsecureTriplesSend::new(&client_triples);
-        bytes::serialize(&mut *writer, &send_message).unwrap();
+    fn squares_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        _reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError> {
+        let start_time = timer_start!(|| "Insecure Server squares generation");
+        let _phase = comm::phase("Squares");
+        let values: Vec<Fp64<P>> = (0..num)
+            .flat_map(|_| {
+                let a = Fp64::uniform(rng);
+                vec![a, a * a]
+            })
+            .collect();
+        let result = squares_from_shares(&self.deal_shares(writer, rng, &values));
         timer_end!(start_time);
-        server_triples
+        Ok(result)
+    }
+
+    fn ole_gen<R: Read + Send + Unpin, W: Write + Send + Unpin, RNG: RngCore + CryptoRng>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        values: &[Fp64<P>],
+    ) -> Result<Vec<Fp64<P>>, MpcError> {
+        let start_time = timer_start!(|| "Insecure Server OLE generation");
+        let recv_message: ShareRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+        let client_values = recv_message.msg().0;
+        check_opening_len(&client_values, values.len())?;
+        let seed = sample_seed(rng);
+        let send_message = SeedSend::new(&seed);
+        bytes::serialize(&mut *writer, &send_message)?;
+        let result = izip!(
+            values,
+            client_values,
+            expand_seed::<Fp64<P>>(seed, values.len())
+        )
+        .map(|(v, c, s)| *v * c.inner - s)
+        .collect();
+        timer_end!(start_time);
+        Ok(result)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{ClientKeySend, ServerKeyRcv};
-    use algebra::{fields::near_mersenne_64::F, PrimeField, UniformRandom};
-    use async_std::{
-        io::{BufReader, BufWriter, Read, Write},
-        net::{TcpListener, TcpStream},
+    use crate::{
+        mpc::{ClientMPC, ServerMPC, MPC},
+        transport::{memory, LinkConfig},
+        ClientKeySend, ServerKeyRcv,
     };
+    use algebra::{fields::near_mersenne_64::F, PrimeField, UniformRandom};
+    use async_std::io::{Read, Write};
     use io_utils::imux::IMuxAsync;
-    use protocols_sys::KeyShare;
-    use rand::SeedableRng;
+    use protocols_sys::{KeyShare, SealClientACG, SealServerACG};
+    use rand::{Rng, SeedableRng};
     use rand_chacha::ChaChaRng;
 
     const RANDOMNESS: [u8; 32] = [
@@ -1079,55 +2434,23 @@ mod tests {
         0x52, 0xd2,
     ];
 
-    fn get_connection(
-        server_addr: &str,
-    ) -> (
+    fn get_connection() -> (
         (IMuxAsync<impl Read>, IMuxAsync<impl Write>),
         (IMuxAsync<impl Read>, IMuxAsync<impl Write>),
     ) {
-        crossbeam::thread::scope(|s| {
-            let server_io = s.spawn(|_| {
-                task::block_on(async {
-                    let server_listener = TcpListener::bind(server_addr).await.unwrap();
-                    let stream = server_listener
-                        .incoming()
-                        .next()
-                        .await
-                        .unwrap()
-                        .expect("Server connection failed!");
-                    let read_stream = IMuxAsync::new(vec![BufReader::new(stream.clone())]);
-                    let write_stream = IMuxAsync::new(vec![BufWriter::new(stream)]);
-                    (read_stream, write_stream)
-                })
-            });
-            // Sometimes the client thread will start too soon and connection fails so put a
-            // small delay
-            std::thread::sleep(std::time::Duration::from_millis(10));
-            let client_io = s.spawn(|_| {
-                task::block_on(async {
-                    let stream = TcpStream::connect(server_addr)
-                        .await
-                        .expect("Client connection failed!");
-                    let read_stream = IMuxAsync::new(vec![BufReader::new(stream.clone())]);
-                    let write_stream = IMuxAsync::new(vec![BufWriter::new(stream)]);
-                    (read_stream, write_stream)
-                })
-            });
-            (client_io.join().unwrap(), server_io.join().unwrap())
-        })
-        .unwrap()
+        memory::connect(1, LinkConfig::default())
     }
 
     #[test]
     fn test_rands_gen() {
-        let server_addr = "127.0.0.1:8020";
-
         let num: usize = 1000000;
         let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
-            get_connection(&server_addr);
+            get_connection();
 
         let (client_rands, (server_rands, mac_key)) = crossbeam::thread::scope(|s| {
             let server_result = s.spawn(|_| {
+                // Phase names are unique to this test since the counts are global
+                let _phase = comm::phase("Rands test server");
                 let mut rng = ChaChaRng::from_seed(RANDOMNESS);
                 // Keygen
                 let mac_key = F::uniform(&mut rng);
@@ -1143,15 +2466,20 @@ mod tests {
                     .num_threads(rayon::current_num_threads() / 2)
                     .build()
                     .unwrap();
+                let scope = comm::scope();
                 pool.install(|| {
+                    let _scope = scope.enter();
                     (
-                        server_gen.rands_gen(&mut server_read, &mut server_write, &mut rng, num),
+                        server_gen
+                            .rands_gen(&mut server_read, &mut server_write, &mut rng, num)
+                            .unwrap(),
                         mac_key,
                     )
                 })
             });
 
             let client_rands = s.spawn(|_| {
+                let _phase = comm::phase("Rands test client");
                 let mut rng = ChaChaRng::from_seed(RANDOMNESS);
                 // Keygen
                 let keygen = timer_start!(|| "Generating Keys");
@@ -1170,8 +2498,12 @@ mod tests {
                     .num_threads(rayon::current_num_threads() / 2)
                     .build()
                     .unwrap();
+                let scope = comm::scope();
                 pool.install(|| {
-                    client_gen.rands_gen(&mut client_read, &mut client_write, &mut rng, num)
+                    let _scope = scope.enter();
+                    client_gen
+                        .rands_gen(&mut client_read, &mut client_write, &mut rng, num)
+                        .unwrap()
                 })
             });
             (client_rands.join().unwrap(), server_result.join().unwrap())
@@ -1180,15 +2512,24 @@ mod tests {
 
         izip!(client_rands, server_rands)
             .for_each(|(s1, s2)| assert!(s1.combine(&s2, &mac_key).is_ok()));
+
+        // The server only returns a MAC ciphertext for each ciphertext of the
+        // client, since the client's value shares are its own rands
+        let counts = comm::counts();
+        let get = |path: &str| counts.iter().find(|(p, _)| p == path).unwrap().1;
+        let client = get("Rands test client/Rands");
+        let server = get("Rands test server/Rands");
+        let batch_size = ClientOfflineMPC::<F, SealClientGen>::BATCH_SIZE;
+        assert_eq!(client.messages_written, (num + batch_size - 1) / batch_size);
+        assert_eq!(server.messages_written, client.messages_written);
+        assert_eq!(server.bytes_written, client.bytes_read);
     }
 
     #[test]
     fn test_triples_gen() {
-        let server_addr = "127.0.0.1:8021";
-
         let num: usize = 1000000;
         let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
-            get_connection(&server_addr);
+            get_connection();
 
         let (client_triples, (server_triples, mac_key)) = crossbeam::thread::scope(|s| {
             let server_result = s.spawn(|_| {
@@ -1209,7 +2550,9 @@ mod tests {
                     .unwrap();
                 pool.install(|| {
                     (
-                        server_gen.triples_gen(&mut server_read, &mut server_write, &mut rng, num),
+                        server_gen
+                            .triples_gen(&mut server_read, &mut server_write, &mut rng, num)
+                            .unwrap(),
                         mac_key,
                     )
                 })
@@ -1235,7 +2578,9 @@ mod tests {
                     .build()
                     .unwrap();
                 pool.install(|| {
-                    client_gen.triples_gen(&mut client_read, &mut client_write, &mut rng, num)
+                    client_gen
+                        .triples_gen(&mut client_read, &mut client_write, &mut rng, num)
+                        .unwrap()
                 })
             });
             (
@@ -1255,4 +2600,640 @@ mod tests {
             assert_eq!(c.unwrap(), a.unwrap() * b.unwrap());
         });
     }
+
+    #[test]
+    fn test_squares_gen() {
+        let num: usize = 100000;
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            get_connection();
+
+        let (client_squares, (server_squares, mac_key)) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                // Keygen
+                let mac_key = F::uniform(&mut rng);
+                let key_recv = timer_start!(|| "Receiving Keys");
+                let recv_message: ServerKeyRcv = bytes::deserialize(&mut server_read).unwrap();
+                let mut key_share = KeyShare::new();
+                let sfhe = key_share.receive(recv_message.msg());
+                timer_end!(key_recv);
+
+                // Generate squares
+                let server_gen = ServerOfflineMPC::<F, _>::new(&sfhe, mac_key.into_repr().0);
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(rayon::current_num_threads() / 2)
+                    .build()
+                    .unwrap();
+                pool.install(|| {
+                    (
+                        server_gen
+                            .squares_gen(&mut server_read, &mut server_write, &mut rng, num)
+                            .unwrap(),
+                        mac_key,
+                    )
+                })
+            });
+
+            let client_squares = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                // Keygen
+                let keygen = timer_start!(|| "Generating Keys");
+                let mut key_share = KeyShare::new();
+                let (cfhe, keys_vec) = key_share.generate();
+                timer_end!(keygen);
+
+                let key_send = timer_start!(|| "Sending Keys");
+                let send_message = ClientKeySend::new(&keys_vec);
+                bytes::serialize(&mut client_write, &send_message).unwrap();
+                timer_end!(key_send);
+
+                // Generate squares
+                let client_gen = ClientOfflineMPC::<F, _>::new(&cfhe);
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(rayon::current_num_threads() / 2)
+                    .build()
+                    .unwrap();
+                pool.install(|| {
+                    client_gen
+                        .squares_gen(&mut client_read, &mut client_write, &mut rng, num)
+                        .unwrap()
+                })
+            });
+            (
+                client_squares.join().unwrap(),
+                server_result.join().unwrap(),
+            )
+        })
+        .unwrap();
+
+        izip!(client_squares, server_squares).for_each(|(c, s)| {
+            let a = s.a.combine(&c.a, &mac_key).unwrap();
+            let a_squared = s.a_squared.combine(&c.a_squared, &mac_key).unwrap();
+            assert_eq!(a_squared, a * a);
+        });
+    }
+
+    #[test]
+    fn test_squares_check() {
+        let num = 100;
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+
+        // Deals `num` square pairs along with the pairs sacrificed to check
+        // them, where the first `bad` pairs hold a random value in place of
+        // `a^2`
+        let mut deal = |bad: usize| {
+            let mut client = Vec::with_capacity(2 * num);
+            let mut server = Vec::with_capacity(2 * num);
+            for i in 0..2 * num {
+                let a = F::uniform(&mut rng);
+                let a_squared = if i < bad { F::uniform(&mut rng) } else { a * a };
+                let (a_c, a_s) = a.auth_share(&mac_key, &mut rng);
+                let (c_c, c_s) = a_squared.auth_share(&mac_key, &mut rng);
+                client.push(SquarePair {
+                    a: a_c,
+                    a_squared: c_c,
+                });
+                server.push(SquarePair {
+                    a: a_s,
+                    a_squared: c_s,
+                });
+            }
+            let client_sacrificed = client.split_off(num);
+            let server_sacrificed = server.split_off(num);
+            (client, client_sacrificed, server, server_sacrificed)
+        };
+
+        for &bad in &[0, 1] {
+            let (client, client_sacrificed, server, server_sacrificed) = deal(bad);
+            let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+                get_connection();
+            let result = crossbeam::thread::scope(|s| {
+                let server_result = s.spawn(|_| {
+                    let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                    server_check_squares(
+                        &mut server_read,
+                        &mut server_write,
+                        &mut rng,
+                        mac_key,
+                        &server,
+                        &server_sacrificed,
+                    )
+                });
+                client_check_squares(
+                    &mut client_read,
+                    &mut client_write,
+                    &client,
+                    &client_sacrificed,
+                )
+                .unwrap();
+                server_result.join().unwrap()
+            })
+            .unwrap();
+            if bad == 0 {
+                assert!(result.is_ok());
+            } else {
+                assert!(matches!(result, Err(MpcError::InvalidTriple)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_matrix_triples_gen() {
+        let dims = [(3, 4, 5), (1, 7, 2), (16, 16, 16)];
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            get_connection();
+
+        let (client_triples, (server_triples, mac_key)) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                // Keygen
+                let mac_key = F::uniform(&mut rng);
+                let key_recv = timer_start!(|| "Receiving Keys");
+                let recv_message: ServerKeyRcv = bytes::deserialize(&mut server_read).unwrap();
+                let mut key_share = KeyShare::new();
+                let sfhe = key_share.receive(recv_message.msg());
+                timer_end!(key_recv);
+
+                // Generate matrix triples
+                let server_gen = ServerOfflineMPC::<F, _>::new(&sfhe, mac_key.into_repr().0);
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(rayon::current_num_threads() / 2)
+                    .build()
+                    .unwrap();
+                pool.install(|| {
+                    (
+                        server_gen
+                            .matrix_triples_gen::<SealServerACG, _, _, _>(
+                                &sfhe,
+                                &mut server_read,
+                                &mut server_write,
+                                &mut rng,
+                                &dims,
+                            )
+                            .unwrap(),
+                        mac_key,
+                    )
+                })
+            });
+
+            let client_triples = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                // Keygen
+                let keygen = timer_start!(|| "Generating Keys");
+                let mut key_share = KeyShare::new();
+                let (cfhe, keys_vec) = key_share.generate();
+                timer_end!(keygen);
+
+                let key_send = timer_start!(|| "Sending Keys");
+                let send_message = ClientKeySend::new(&keys_vec);
+                bytes::serialize(&mut client_write, &send_message).unwrap();
+                timer_end!(key_send);
+
+                // Generate matrix triples
+                let client_gen = ClientOfflineMPC::<F, _>::new(&cfhe);
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(rayon::current_num_threads() / 2)
+                    .build()
+                    .unwrap();
+                pool.install(|| {
+                    client_gen
+                        .matrix_triples_gen::<SealClientACG, _, _, _>(
+                            &cfhe,
+                            &mut client_read,
+                            &mut client_write,
+                            &mut rng,
+                            &dims,
+                        )
+                        .unwrap()
+                })
+            });
+            (
+                client_triples.join().unwrap(),
+                server_result.join().unwrap(),
+            )
+        })
+        .unwrap();
+
+        let combine = |c: &[AuthAdditiveShare<F>], s: &[AuthAdditiveShare<F>]| {
+            izip!(c, s)
+                .map(|(c, s)| s.combine(c, &mac_key).unwrap())
+                .collect::<Vec<_>>()
+        };
+        izip!(client_triples, server_triples).for_each(|(c, s)| {
+            let (m, k, n) = s.dims;
+            assert_eq!(c.dims, s.dims);
+            let a = combine(&c.a, &s.a);
+            let b = combine(&c.b, &s.b);
+            let c = combine(&c.c, &s.c);
+            for i in 0..m {
+                for j in 0..n {
+                    let sum = (0..k).fold(F::zero(), |sum, l| sum + a[i * k + l] * b[l * n + j]);
+                    assert_eq!(c[i * n + j], sum);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_matrix_triples_check() {
+        let (m, k, n) = (3, 4, 5);
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+
+        // Deals two matrix triples, each along with the triple sacrificed to
+        // check it, where the entry of `C` at `cheat` in the first triple is
+        // shifted if it is set
+        let mut deal = |cheat: Option<usize>| {
+            let mut client = (Vec::new(), Vec::new());
+            let mut server = (Vec::new(), Vec::new());
+            for triple in 0..2 {
+                let a: Vec<_> = (0..2 * m * k).map(|_| F::uniform(&mut rng)).collect();
+                let b: Vec<_> = (0..k * n).map(|_| F::uniform(&mut rng)).collect();
+                let mut c = matmul(&a, &b, (2 * m, k, n));
+                if let (0, Some(idx)) = (triple, cheat) {
+                    c[idx] += F::uniform(&mut rng);
+                }
+                let mut share = |values: Vec<F>| -> (Vec<_>, Vec<_>) {
+                    values
+                        .into_iter()
+                        .map(|v| v.auth_share(&mac_key, &mut rng))
+                        .unzip()
+                };
+                let (a_c, a_s) = share(a);
+                let (b_c, b_s) = share(b);
+                let (c_c, c_s) = share(c);
+                let (triple_c, sacrificed_c) = split_sacrificed(a_c, b_c, c_c, (m, k, n));
+                let (triple_s, sacrificed_s) = split_sacrificed(a_s, b_s, c_s, (m, k, n));
+                client.0.push(triple_c);
+                client.1.push(sacrificed_c);
+                server.0.push(triple_s);
+                server.1.push(sacrificed_s);
+            }
+            (client, server)
+        };
+
+        // Index `m * n` is in the rows of `C` which are sacrificed
+        for &cheat in &[None, Some(0), Some(m * n)] {
+            let (client, server) = deal(cheat);
+            let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+                get_connection();
+            let result = crossbeam::thread::scope(|s| {
+                let server_result = s.spawn(|_| {
+                    let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                    server_check_matrix_triples(
+                        &mut server_read,
+                        &mut server_write,
+                        &mut rng,
+                        mac_key,
+                        &server.0,
+                        &server.1,
+                    )
+                });
+                client_check_matrix_triples(
+                    &mut client_read,
+                    &mut client_write,
+                    &client.0,
+                    &client.1,
+                )
+                .unwrap();
+                server_result.join().unwrap()
+            })
+            .unwrap();
+            if cheat.is_none() {
+                assert!(result.is_ok());
+            } else {
+                assert!(matches!(result, Err(MpcError::InvalidTriple)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_insecure_gen() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            get_connection();
+
+        let num = 1000;
+        let dims = [(3, 4, 5), (1, 7, 2)];
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let gen = InsecureServerOfflineMPC::<F>::new(mac_key.into_repr().0);
+                let (r, w) = (&mut server_read, &mut server_write);
+                (
+                    gen.rands_gen(r, w, &mut server_rng, num).unwrap(),
+                    gen.triples_gen(r, w, &mut server_rng, num).unwrap(),
+                    gen.squares_gen(r, w, &mut server_rng, num).unwrap(),
+                    gen.matrix_triples_gen(r, w, &mut server_rng, &dims),
+                )
+            });
+            let client_result = s.spawn(|_| {
+                let gen = InsecureClientOfflineMPC::<F>::new();
+                let (r, w) = (&mut client_read, &mut client_write);
+                (
+                    gen.rands_gen(r, w, &mut client_rng, num).unwrap(),
+                    gen.triples_gen(r, w, &mut client_rng, num).unwrap(),
+                    gen.squares_gen(r, w, &mut client_rng, num).unwrap(),
+                    gen.matrix_triples_gen(r, w, &mut client_rng, &dims),
+                )
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        let combine = |c: &AuthAdditiveShare<F>, s: &AuthAdditiveShare<F>| {
+            s.combine(c, &mac_key).expect("MAC check failure")
+        };
+        let ((c_rands, c_triples, c_squares, c_matrix), (s_rands, s_triples, s_squares, s_matrix)) =
+            (client_result, server_result);
+        assert_eq!(c_rands.len(), num);
+        izip!(&c_rands, &s_rands).for_each(|(c, s)| {
+            combine(c, s);
+        });
+        izip!(&c_triples, &s_triples).for_each(|(c, s)| {
+            let (a, b) = (combine(&c.a, &s.a), combine(&c.b, &s.b));
+            assert_eq!(combine(&c.c, &s.c), a * b);
+        });
+        izip!(&c_squares, &s_squares).for_each(|(c, s)| {
+            let a = combine(&c.a, &s.a);
+            assert_eq!(combine(&c.a_squared, &s.a_squared), a * a);
+        });
+        izip!(c_matrix, s_matrix).for_each(|(c, s)| {
+            let (m, k, n) = s.dims;
+            assert_eq!(c.dims, s.dims);
+            let a: Vec<_> = izip!(&c.a, &s.a).map(|(c, s)| combine(c, s)).collect();
+            let b: Vec<_> = izip!(&c.b, &s.b).map(|(c, s)| combine(c, s)).collect();
+            let c: Vec<_> = izip!(&c.c, &s.c).map(|(c, s)| combine(c, s)).collect();
+            for i in 0..m {
+                for j in 0..n {
+                    let sum = (0..k).fold(F::zero(), |sum, l| sum + a[i * k + l] * b[l * n + j]);
+                    assert_eq!(c[i * n + j], sum);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_client_rands_gen() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            get_connection();
+
+        let num = 1000;
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        // Compare with authenticating uniformly random inputs of the client
+        // through pairwise randomness. Phase names are unique to this test
+        // since the counts are global.
+        let (client_rands, server_rands) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let gen = InsecureServerOfflineMPC::<F>::new(mac_key.into_repr().0);
+                let (r, w) = (&mut server_read, &mut server_write);
+                let rands = {
+                    let _phase = comm::phase("Client rands test");
+                    gen.client_rands_gen(r, w, &mut server_rng, num).unwrap()
+                };
+                let _phase = comm::phase("Client inputs test");
+                let pairwise = gen.rands_gen(r, w, &mut server_rng, num).unwrap();
+                let mut mpc = ServerMPC::new(pairwise, Vec::new(), mac_key);
+                mpc.recv_private_inputs(r, w, num).unwrap();
+                rands
+            });
+            let client_result = s.spawn(|_| {
+                let gen = InsecureClientOfflineMPC::<F>::new();
+                let (r, w) = (&mut client_read, &mut client_write);
+                let rands = gen.client_rands_gen(r, w, &mut client_rng, num).unwrap();
+                let pairwise = gen.rands_gen(r, w, &mut client_rng, num).unwrap();
+                let inputs: Vec<F> = (0..num).map(|_| F::uniform(&mut client_rng)).collect();
+                let mut mpc = ClientMPC::new(pairwise, Vec::new());
+                mpc.private_inputs(r, w, &inputs, &mut client_rng).unwrap();
+                rands
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        assert_eq!(client_rands.len(), num);
+        izip!(&client_rands, &server_rands).for_each(|(c, s)| {
+            assert_eq!(s.get_value().inner, F::zero());
+            assert_eq!(c.combine(s, &mac_key).unwrap(), c.get_value().inner);
+        });
+
+        // Only the seed of the client's shares is sent, while the inputs
+        // also take an opening and an epsilon per input
+        let counts = comm::counts();
+        let total = |prefix: &str| {
+            counts
+                .iter()
+                .filter(|(p, _)| p.starts_with(prefix))
+                .map(|(_, c)| c.bytes_read + c.bytes_written)
+                .sum::<usize>()
+        };
+        assert_eq!(total("Client rands test"), 32);
+        assert!(total("Client inputs test") > 32 + 2 * num * F::size_in_bits() / 8);
+    }
+
+    #[test]
+    fn test_share_mac_key() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let server_key = F::uniform(&mut rng);
+        let client_key = F::uniform(&mut rng);
+        let mac_key = server_key + client_key;
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            get_connection();
+
+        let num = 1000;
+        let x: Vec<F> = (0..num).map(|_| F::uniform(&mut rng)).collect();
+        let y: Vec<F> = (0..num).map(|_| F::uniform(&mut rng)).collect();
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        // The server inputs `x` and the client `y`, and the parties multiply
+        // them under the shared key
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let gen = InsecureServerOfflineMPC::<F>::new(server_key.into_repr().0);
+                let (r, w) = (&mut server_read, &mut server_write);
+                let mut rands = gen.rands_gen(r, w, &mut server_rng, num).unwrap();
+                let mut triples = gen.triples_gen(r, w, &mut server_rng, num).unwrap();
+                let mut x: Vec<_> = x
+                    .iter()
+                    .map(|x| AuthAdditiveShare::new(*x, server_key * *x))
+                    .collect();
+                server_share_mac_key(
+                    &gen,
+                    r,
+                    w,
+                    &mut server_rng,
+                    rands
+                        .iter_mut()
+                        .chain(
+                            triples
+                                .iter_mut()
+                                .flat_map(|t| vec![&mut t.a, &mut t.b, &mut t.c]),
+                        )
+                        .chain(x.iter_mut()),
+                )
+                .unwrap();
+                let mut mpc =
+                    ServerMPC::new(rands, triples, server_key).with_mac_key_share(Vec::new());
+                let y = mpc.recv_private_inputs(r, w, num).unwrap();
+                let product = mpc.mul(r, w, &x, &y).unwrap();
+                let check = mpc.mac_check(r, w, &mut server_rng);
+                (product, check)
+            });
+            let client_result = s.spawn(|_| {
+                let gen = InsecureClientOfflineMPC::<F>::new();
+                let (r, w) = (&mut client_read, &mut client_write);
+                let mut rands = gen.rands_gen(r, w, &mut client_rng, num).unwrap();
+                let mut triples = gen.triples_gen(r, w, &mut client_rng, num).unwrap();
+                let mut x = vec![AuthAdditiveShare::zero(); num];
+                client_share_mac_key(
+                    &gen,
+                    r,
+                    w,
+                    &mut client_rng,
+                    client_key,
+                    rands
+                        .iter_mut()
+                        .chain(
+                            triples
+                                .iter_mut()
+                                .flat_map(|t| vec![&mut t.a, &mut t.b, &mut t.c]),
+                        )
+                        .chain(x.iter_mut()),
+                )
+                .unwrap();
+                let mut mpc =
+                    ClientMPC::new(rands, triples).with_mac_key_share(client_key, Vec::new());
+                let y = mpc.private_inputs(r, w, &y, &mut client_rng).unwrap();
+                let product = mpc.mul(r, w, &x, &y).unwrap();
+                let check = mpc.mac_check(r, w, &mut client_rng);
+                (product, check)
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        let ((client_product, client_check), (server_product, server_check)) =
+            (client_result, server_result);
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+        izip!(&x, &y, &client_product, &server_product).for_each(|(x, y, c, s)| {
+            // The products are only authenticated under the shared key
+            assert_eq!(c.combine(s, &mac_key).unwrap(), *x * *y);
+            assert!(c.combine(s, &server_key).is_err());
+        });
+    }
+
+    #[test]
+    fn test_rand_bits_with_shared_key() {
+        use num_traits::One;
+
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let server_key = F::uniform(&mut rng);
+        let client_key = F::uniform(&mut rng);
+        let mac_key = server_key + client_key;
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            get_connection();
+
+        let num = 500;
+        let mut client_rng = ChaChaRng::from_seed(rng.gen());
+        let mut server_rng = ChaChaRng::from_seed(rng.gen());
+
+        // Each bit consumes a rand and a triple, and a square pair and an
+        // input mask of either party
+        let (client_result, server_result) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let gen = InsecureServerOfflineMPC::<F>::new(server_key.into_repr().0);
+                let (r, w) = (&mut server_read, &mut server_write);
+                let mut rands = gen.rands_gen(r, w, &mut server_rng, num).unwrap();
+                let mut triples = gen.triples_gen(r, w, &mut server_rng, num).unwrap();
+                let mut squares = gen.squares_gen(r, w, &mut server_rng, num).unwrap();
+                server_share_mac_key(
+                    &gen,
+                    r,
+                    w,
+                    &mut server_rng,
+                    rands
+                        .iter_mut()
+                        .chain(
+                            triples
+                                .iter_mut()
+                                .flat_map(|t| vec![&mut t.a, &mut t.b, &mut t.c]),
+                        )
+                        .chain(
+                            squares
+                                .iter_mut()
+                                .flat_map(|s| vec![&mut s.a, &mut s.a_squared]),
+                        ),
+                )
+                .unwrap();
+                let masks =
+                    server_input_masks(&gen, r, w, &mut server_rng, server_key, num).unwrap();
+                let mut mpc = ServerMPC::new(rands, triples, server_key)
+                    .with_squares(squares)
+                    .with_mac_key_share(masks);
+                let bits = mpc.rand_bits(r, w, num, &mut server_rng).unwrap();
+                let check = mpc.mac_check(r, w, &mut server_rng);
+                (bits, check, mpc.check_macs())
+            });
+            let client_result = s.spawn(|_| {
+                let gen = InsecureClientOfflineMPC::<F>::new();
+                let (r, w) = (&mut client_read, &mut client_write);
+                let mut rands = gen.rands_gen(r, w, &mut client_rng, num).unwrap();
+                let mut triples = gen.triples_gen(r, w, &mut client_rng, num).unwrap();
+                let mut squares = gen.squares_gen(r, w, &mut client_rng, num).unwrap();
+                client_share_mac_key(
+                    &gen,
+                    r,
+                    w,
+                    &mut client_rng,
+                    client_key,
+                    rands
+                        .iter_mut()
+                        .chain(
+                            triples
+                                .iter_mut()
+                                .flat_map(|t| vec![&mut t.a, &mut t.b, &mut t.c]),
+                        )
+                        .chain(
+                            squares
+                                .iter_mut()
+                                .flat_map(|s| vec![&mut s.a, &mut s.a_squared]),
+                        ),
+                )
+                .unwrap();
+                let masks =
+                    client_input_masks(&gen, r, w, &mut client_rng, client_key, num).unwrap();
+                let mut mpc = ClientMPC::new(rands, triples)
+                    .with_squares(squares)
+                    .with_mac_key_share(client_key, masks);
+                let bits = mpc.rand_bits(r, w, num, &mut client_rng).unwrap();
+                let check = mpc.mac_check(r, w, &mut client_rng);
+                (bits, check)
+            });
+            (client_result.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        let ((client_bits, client_check), (server_bits, server_check, server_check_macs)) =
+            (client_result, server_result);
+        assert!(client_check.is_ok());
+        assert!(server_check.is_ok());
+        match server_check_macs {
+            Err(MpcError::MacKeyShared) => {}
+            result => panic!("Expected MacKeyShared, got {:?}", result),
+        }
+        izip!(&client_bits, &server_bits).for_each(|(c, s)| {
+            let bit = c.combine(s, &mac_key).unwrap();
+            assert!(bit.is_zero() || bit.is_one());
+        });
+    }
 }
@@ -1,4 +1,6 @@
-use crate::{bytes, error::MpcError, AdditiveShare, AuthAdditiveShare, InMessage, OutMessage};
+use crate::{
+    bytes, comm, error::MpcError, AdditiveShare, AuthAdditiveShare, InMessage, OutMessage,
+};
 use bench_utils::{timer_end, timer_start};
 use neural_network::{
     layers::{Layer, LayerInfo, NonLinearLayer, NonLinearLayerInfo},
@@ -12,7 +14,7 @@ use std::{collections::BTreeMap, marker::PhantomData};
 use algebra::{
     fixed_point::{FixedPoint, FixedPointParameters},
     fp_64::{Fp64, Fp64Parameters},
-    PrimeField, UniformRandom,
+    PrimeField,
 };
 
 use neural_network::{
@@ -25,10 +27,17 @@ use crypto_primitives::{
     AuthShare, Share,
 };
 
-use crate::{gc::ReluProtocol, linear_layer::LinearProtocol};
+use crate::{
+    gc::ReluProtocol,
+    linear_layer::{InsecureClientACG, InsecureServerACG, LinearProtocol},
+    mpc_offline::{
+        ClientOfflineGen, ClientOfflineMPC, InsecureClientOfflineMPC, InsecureServerOfflineMPC,
+        ServerOfflineGen, ServerOfflineMPC,
+    },
+};
 use io_utils::imux::IMuxAsync;
 use protocols_sys::{
-    client_acg, server_acg, ClientACG, ClientFHE, SealClientACG, SealServerACG, ServerACG,
+    ClientACG, ClientFHE, SealClientACG, SealClientGen, SealServerACG, SealServerGen, ServerACG,
     ServerFHE,
 };
 
@@ -63,6 +72,27 @@ where
     pub linear_shares: BTreeMap<usize, Output<AdditiveShare<P>>>,
 }
 
+/// Where the offline phase gets its correlated randomness from. Both parties
+/// must use the same backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OfflineBackend {
+    /// Linear layers are preprocessed with `SealServerACG`, and randomness
+    /// is generated by `ServerOfflineMPC` with SEAL
+    Seal,
+    /// INSECURE: linear layers are preprocessed with `InsecureServerACG`,
+    /// which learns the client's randomizers, and the server deals all other
+    /// randomness with `InsecureServerOfflineMPC`. No keys are generated, so
+    /// this is only meant for testing protocol logic and measuring the online
+    /// phase.
+    InsecureDealer,
+}
+
+impl Default for OfflineBackend {
+    fn default() -> Self {
+        OfflineBackend::Seal
+    }
+}
+
 pub struct NNProtocolType;
 // The final message from the server to the client, contains a share of the
 // output.
@@ -131,6 +161,72 @@ where
         neural_network: &NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
         rng: &mut RNG,
     ) -> Result<ServerState<P>, MpcError> {
+        Self::offline_server_protocol_with_backend(
+            reader,
+            writer,
+            neural_network,
+            OfflineBackend::Seal,
+            rng,
+        )
+    }
+
+    pub fn offline_server_protocol_with_backend<
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: CryptoRng + RngCore,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        neural_network: &NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+        backend: OfflineBackend,
+        rng: &mut RNG,
+    ) -> Result<ServerState<P>, MpcError> {
+        match backend {
+            OfflineBackend::Seal => {
+                let sfhe: ServerFHE = crate::server_keygen(reader)?;
+                Self::offline_server_protocol_with::<
+                    SealServerACG,
+                    ServerOfflineMPC<P::Field, SealServerGen>,
+                    _,
+                    _,
+                    _,
+                >(reader, writer, neural_network, &sfhe, &sfhe, rng)
+            }
+            OfflineBackend::InsecureDealer => {
+                Self::offline_server_protocol_with::<
+                    InsecureServerACG<F>,
+                    InsecureServerOfflineMPC<P::Field>,
+                    _,
+                    _,
+                    _,
+                >(reader, writer, neural_network, (), (), rng)
+            }
+        }
+    }
+
+    /// Runs the server offline phase with linear layers preprocessed by `A`
+    /// and the rands, triples and squares of CDS and of authenticating the
+    /// client's randomizers generated by `G`. `acg_keys` and `gen_keys` are
+    /// the keys of each backend, which both parties must have agreed on
+    /// beforehand.
+    pub fn offline_server_protocol_with<
+        A: ServerACG,
+        G: ServerOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: CryptoRng + RngCore,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        neural_network: &NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+        acg_keys: A::Keys,
+        gen_keys: G::Keys,
+        rng: &mut RNG,
+    ) -> Result<ServerState<P>, MpcError>
+    where
+        A::Keys: Copy,
+    {
+        let _offline = comm::phase("Offline");
         let mut num_relu = 0;
         let mut linear_shares: BTreeMap<
             usize,
@@ -143,7 +239,6 @@ where
         let mut num_truncations = BTreeMap::new();
         let mut mac_keys: BTreeMap<usize, (P::Field, P::Field)> = BTreeMap::new();
         let mut relu_layers = Vec::new();
-        let sfhe: ServerFHE = crate::server_keygen(reader)?;
 
         let start_time = timer_start!(|| "Server offline phase");
         let linear_time = timer_start!(|| "Linear layers offline phase");
@@ -155,6 +250,7 @@ where
                     num_relu += b * c * h * w;
                 }
                 Layer::LL(layer) => {
+                    let _phase = comm::phase(format!("Linear layer {}", i));
                     // Keep track of the number of truncations needed for the output
                     // shares of each linear layer
                     let mut truncations = 0;
@@ -170,27 +266,17 @@ where
                     }
                     num_truncations.insert(i, truncations);
 
-                    let (shares, keys) = match &layer {
+                    let (shares, layer_keys) = match &layer {
                         LinearLayer::Conv2d { .. } | LinearLayer::FullyConnected { .. } => {
-                            let mut acg_handler = match &layer {
-                                LinearLayer::Conv2d { .. } => SealServerACG::Conv2D(
-                                    server_acg::Conv2D::new(&sfhe, layer, &layer.kernel_to_repr()),
-                                ),
-                                LinearLayer::FullyConnected { .. } => {
-                                    SealServerACG::FullyConnected(server_acg::FullyConnected::new(
-                                        &sfhe,
-                                        layer,
-                                        &layer.kernel_to_repr(),
-                                    ))
-                                }
-                                _ => unreachable!(),
-                            };
+                            let (group_size, mut acg_handlers) =
+                                LinearProtocol::<P>::server_acg_handlers::<A>(acg_keys, layer);
                             LinearProtocol::<P>::offline_server_acg_protocol(
                                 reader,
                                 writer,
                                 layer.input_dimensions(),
                                 layer.output_dimensions(),
-                                &mut acg_handler,
+                                group_size,
+                                &mut acg_handlers,
                                 rng,
                             )?
                         }
@@ -215,18 +301,17 @@ where
                                     prev_mac_keys.clone(),
                                 )
                             } else {
-                                // If the layer comes after a non-linear layer, receive the
-                                // randomizer from the client, authenticate it, and then apply the
+                                // If the layer comes after a non-linear layer, authenticate a
+                                // random randomizer known to the client, and then apply the
                                 // function to the MAC share
                                 let (key, input_share) =
-                                    LinearProtocol::<P>::offline_server_auth_share(
+                                    LinearProtocol::<P>::offline_server_auth_rand::<G, _, _, _>(
                                         reader,
                                         writer,
                                         dims.input_dimensions(),
-                                        &sfhe,
+                                        gen_keys,
                                         rng,
-                                    )
-                                    .unwrap();
+                                    )?;
                                 let mut output_share = Output::zeros(dims.output_dimensions());
                                 layer.evaluate_naive_auth(&input_share, &mut output_share);
                                 (
@@ -241,7 +326,7 @@ where
                         }
                     };
                     linear_shares.insert(i, shares);
-                    mac_keys.insert(i, keys);
+                    mac_keys.insert(i, layer_keys);
                 }
             }
         }
@@ -286,14 +371,15 @@ where
         }
 
         // TODO: Have a CDS struct contain all of these arguments
+        let relu_phase = comm::phase("ReLU");
         let crate::gc::ServerState {
             encoders: relu_encoders,
             output_randomizers: relu_output_randomizers,
-        } = ReluProtocol::<P>::offline_server_protocol(
+        } = ReluProtocol::<P>::offline_server_protocol::<G, _, _, _>(
             reader,
             writer,
             num_relu,
-            &sfhe,
+            gen_keys,
             layer_sizes.as_slice(),
             output_mac_keys.as_slice(),
             output_mac_shares.as_slice(),
@@ -302,6 +388,7 @@ where
             input_mac_shares.as_slice(),
             rng,
         )?;
+        drop(relu_phase);
         timer_end!(relu_time);
 
         // We no longer need the MACs so unwrap underlying values
@@ -328,11 +415,80 @@ where
         neural_network_architecture: &NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
         rng: &mut RNG,
     ) -> Result<ClientState<P>, MpcError> {
+        Self::offline_client_protocol_with_backend(
+            reader,
+            writer,
+            neural_network_architecture,
+            OfflineBackend::Seal,
+            rng,
+        )
+    }
+
+    pub fn offline_client_protocol_with_backend<
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        neural_network_architecture: &NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
+        backend: OfflineBackend,
+        rng: &mut RNG,
+    ) -> Result<ClientState<P>, MpcError> {
+        match backend {
+            OfflineBackend::Seal => {
+                let cfhe: ClientFHE = crate::client_keygen(writer)?;
+                Self::offline_client_protocol_with::<
+                    SealClientACG,
+                    ClientOfflineMPC<P::Field, SealClientGen>,
+                    _,
+                    _,
+                    _,
+                >(
+                    reader,
+                    writer,
+                    neural_network_architecture,
+                    &cfhe,
+                    &cfhe,
+                    rng,
+                )
+            }
+            OfflineBackend::InsecureDealer => {
+                Self::offline_client_protocol_with::<
+                    InsecureClientACG,
+                    InsecureClientOfflineMPC<P::Field>,
+                    _,
+                    _,
+                    _,
+                >(reader, writer, neural_network_architecture, (), (), rng)
+            }
+        }
+    }
+
+    /// Runs the client offline phase with linear layers preprocessed by `A`
+    /// and randomness generated by `G`, see `offline_server_protocol_with`
+    pub fn offline_client_protocol_with<
+        A: ClientACG,
+        G: ClientOfflineGen<P::Field>,
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    >(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        neural_network_architecture: &NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
+        acg_keys: A::Keys,
+        gen_keys: G::Keys,
+        rng: &mut RNG,
+    ) -> Result<ClientState<P>, MpcError>
+    where
+        A::Keys: Copy,
+    {
+        let _offline = comm::phase("Offline");
         let mut num_relu = 0;
         let mut in_shares = BTreeMap::new();
         let mut out_shares: BTreeMap<usize, Output<AuthAdditiveShare<P::Field>>> = BTreeMap::new();
         let mut relu_layers = Vec::new();
-        let cfhe: ClientFHE = crate::client_keygen(writer)?;
 
         let start_time = timer_start!(|| "Client offline phase");
         let linear_time = timer_start!(|| "Linear layers offline phase");
@@ -344,35 +500,25 @@ where
                     num_relu += b * c * h * w;
                 }
                 LayerInfo::LL(dims, linear_layer_info) => {
+                    let _phase = comm::phase(format!("Linear layer {}", i));
                     let input_dims = dims.input_dimensions();
                     let output_dims = dims.output_dimensions();
                     let (in_share, out_share) = match &linear_layer_info {
                         LinearLayerInfo::Conv2d { .. } | LinearLayerInfo::FullyConnected => {
-                            let mut acg_handler = match &linear_layer_info {
-                                LinearLayerInfo::Conv2d { .. } => {
-                                    SealClientACG::Conv2D(client_acg::Conv2D::new(
-                                        &cfhe,
-                                        linear_layer_info,
-                                        input_dims,
-                                        output_dims,
-                                    ))
-                                }
-                                LinearLayerInfo::FullyConnected => {
-                                    SealClientACG::FullyConnected(client_acg::FullyConnected::new(
-                                        &cfhe,
-                                        linear_layer_info,
-                                        input_dims,
-                                        output_dims,
-                                    ))
-                                }
-                                _ => unreachable!(),
-                            };
+                            let (group_size, mut acg_handlers) =
+                                LinearProtocol::<P>::client_acg_handlers::<A>(
+                                    acg_keys,
+                                    linear_layer_info,
+                                    input_dims,
+                                    output_dims,
+                                );
                             LinearProtocol::<P>::offline_client_acg_protocol(
                                 reader,
                                 writer,
-                                layer.input_dimensions(),
-                                layer.output_dimensions(),
-                                &mut acg_handler,
+                                input_dims,
+                                output_dims,
+                                group_size,
+                                &mut acg_handlers,
                                 rng,
                             )?
                         }
@@ -390,17 +536,13 @@ where
                                     output_share,
                                 )
                             } else {
-                                // If the layer comes after a non-linear layer, generate a
-                                // randomizer, send it to the server to receive back an
-                                // authenticated share, and apply the function to that share
-                                let mut randomizer = Input::zeros(input_dims);
-                                randomizer
-                                    .iter_mut()
-                                    .for_each(|e| *e = P::Field::uniform(rng));
-                                let randomizer = LinearProtocol::<P>::offline_client_auth_share(
-                                    reader, writer, randomizer, &cfhe,
-                                )
-                                .unwrap();
+                                // If the layer comes after a non-linear layer, generate an
+                                // authenticated random randomizer with the server, and apply
+                                // the function to that share
+                                let randomizer =
+                                    LinearProtocol::<P>::offline_client_auth_rand::<G, _, _, _>(
+                                        reader, writer, input_dims, gen_keys, rng,
+                                    )?;
                                 linear_layer_info
                                     .evaluate_naive_auth(&randomizer, &mut output_share);
                                 (-randomizer, output_share)
@@ -458,15 +600,16 @@ where
             layer_sizes.push(input_rand.len());
         }
 
+        let relu_phase = comm::phase("ReLU");
         let crate::gc::ClientState {
             gc_s: relu_circuits,
             server_randomizer_labels: randomizer_labels,
             client_input_labels: relu_labels,
-        } = ReluProtocol::<P>::offline_client_protocol(
+        } = ReluProtocol::<P>::offline_client_protocol::<G, _, _, _>(
             reader,
             writer,
             num_relu,
-            &cfhe,
+            gen_keys,
             layer_sizes.as_slice(),
             output_mac_shares.as_slice(),
             output_shares.as_slice(),
@@ -498,6 +641,7 @@ where
         } else {
             (vec![], vec![])
         };
+        drop(relu_phase);
         timer_end!(relu_time);
 
         // We no longer need the MACs so unwrap underlying values
@@ -537,10 +681,12 @@ where
 
         let mut next_layer_input = Output::zeros(first_layer_out_dims);
         let mut next_layer_derandomizer = Input::zeros(first_layer_in_dims);
+        let _online = comm::phase("Online");
         let start_time = timer_start!(|| "Server online phase");
         for (i, layer) in neural_network.layers.iter().enumerate() {
             match layer {
                 Layer::NLL(NonLinearLayer::ReLU { dims, .. }) => {
+                    let _phase = comm::phase(format!("ReLU layer {}", i));
                     let start_time = timer_start!(|| "ReLU layer");
                     // Have the server encode the current input, via the garbled circuit,
                     // and then send the labels over to the other party.
@@ -570,6 +716,7 @@ where
                     timer_end!(start_time);
                 }
                 Layer::LL(layer) => {
+                    let _phase = comm::phase(format!("Linear layer {}", i));
                     let start_time = timer_start!(|| "Linear layer");
                     // Input for the next layer.
                     let layer_randomizer = &state.linear_randomizers.get(&i).unwrap().1;
@@ -614,6 +761,7 @@ where
         }
 
         // Open the final share to the client
+        let _phase = comm::phase("Output");
         let sent_message = MsgSend::new(&next_layer_input);
         bytes::serialize(writer, &sent_message)?;
 
@@ -643,6 +791,7 @@ where
         let mut num_consumed_relus = 0;
         let mut num_muls = 0;
 
+        let _online = comm::phase("Online");
         let start_time = timer_start!(|| "Client online phase");
         let (mut next_layer_input, _) =
             input.share_with_randomness(&(state.linear_randomizers[&0]));
@@ -652,6 +801,7 @@ where
                 LayerInfo::NLL(dims, nll_info) => {
                     match nll_info {
                         NonLinearLayerInfo::ReLU { .. } => {
+                            let _phase = comm::phase(format!("ReLU layer {}", i));
                             let start_time = timer_start!(|| "ReLU layer");
                             // The client receives the garbled circuits from the server,
                             // uses its already encoded inputs to get the next linear
@@ -692,6 +842,7 @@ where
                     num_muls = 0;
                 }
                 LayerInfo::LL(_, layer_info) => {
+                    let _phase = comm::phase(format!("Linear layer {}", i));
                     let start_time = timer_start!(|| "Linear layer");
                     // Send server secret share if required by the layer
                     let input = next_layer_input;
@@ -711,6 +862,7 @@ where
                 }
             }
         }
+        let _phase = comm::phase("Output");
         let result = bytes::deserialize(reader).map(|output: MsgRcv<P>| {
             // Receive server input and reset multiplication count to
             // avoid an early reduction
@@ -729,4 +881,439 @@ where
         timer_end!(start_time);
         Ok(result)
     }
+
+    /// Runs the client online phase over a batch of inputs with a batch size
+    /// of one each, returning the output of each of them. `architecture` and
+    /// `state` must have a batch size of `inputs.len()`.
+    pub fn online_client_batch_protocol<R: Read + Send + Unpin, W: Write + Send + Unpin>(
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        inputs: &[Input<FixedPoint<P>>],
+        architecture: &NeuralArchitecture<AdditiveShare<P>, FixedPoint<P>>,
+        state: &ClientState<P>,
+    ) -> Result<Vec<Output<FixedPoint<P>>>, MpcError> {
+        let input = Input::stack_batch(inputs);
+        Self::online_client_protocol(reader, writer, &input, architecture, state)
+            .map(|output| output.split_batch())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ot_offline::{OtClientOfflineMPC, OtServerOfflineMPC},
+        simulator::{simulate, Truncation},
+        transport::{memory, LinkConfig},
+    };
+    use algebra::fields::near_mersenne_64::F;
+    use neural_network::{
+        layers::{
+            convolution::{Conv2dParams, Padding},
+            fully_connected::FullyConnectedParams,
+        },
+        tensors::Kernel,
+        Evaluate,
+    };
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+
+    struct TenBitExpParams {}
+    impl FixedPointParameters for TenBitExpParams {
+        type Field = F;
+        const MANTISSA_CAPACITY: u8 = 3;
+        const EXPONENT_CAPACITY: u8 = 8;
+    }
+
+    type TenBitExpFP = FixedPoint<TenBitExpParams>;
+    type TenBitAS = AdditiveShare<TenBitExpParams>;
+
+    const RANDOMNESS: [u8; 32] = [
+        0x11, 0xe0, 0x8f, 0xbc, 0x89, 0xa7, 0x34, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda, 0xf4,
+        0x76, 0x5d, 0xc9, 0x8d, 0xea, 0x23, 0xf2, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77, 0xd3, 0x4a,
+        0x52, 0xd2,
+    ];
+
+    fn generate_random_number<R: Rng>(rng: &mut R) -> TenBitExpFP {
+        let is_neg: bool = rng.gen();
+        let mul = if is_neg { -1.0 } else { 1.0 };
+        let float: f64 = rng.gen();
+        TenBitExpFP::from(TenBitExpFP::truncate_float(float * mul))
+    }
+
+    fn relu_layer<T, C>(dims: (usize, usize, usize, usize)) -> Layer<T, C> {
+        Layer::NLL(NonLinearLayer::ReLU {
+            dims: LayerDims {
+                input_dims: dims,
+                output_dims: dims,
+            },
+            _f: PhantomData,
+            _c: PhantomData,
+        })
+    }
+
+    /// Samples a conv -> relu -> fc network, returning it alongside a
+    /// plaintext network with the same weights
+    fn sample_network<R: Rng>(
+        input_dims: (usize, usize, usize, usize),
+        rng: &mut R,
+    ) -> (
+        NeuralNetwork<TenBitAS, TenBitExpFP>,
+        NeuralNetwork<TenBitExpFP, TenBitExpFP>,
+    ) {
+        let mut kernel = Kernel::zeros((2, input_dims.1, 3, 3));
+        let mut bias = Kernel::zeros((2, 1, 1, 1));
+        kernel
+            .iter_mut()
+            .chain(bias.iter_mut())
+            .for_each(|e| *e = generate_random_number(rng));
+        let conv = Conv2dParams::<TenBitAS, _>::new(Padding::Same, 1, kernel.clone(), bias.clone());
+        let pt_conv = Conv2dParams::<TenBitExpFP, _>::new(Padding::Same, 1, kernel, bias);
+        let conv_dims = LayerDims {
+            input_dims,
+            output_dims: conv.calculate_output_size(input_dims),
+        };
+
+        let fc_in_dims = conv_dims.output_dims;
+        let mut weights = Kernel::zeros((4, fc_in_dims.1, fc_in_dims.2, fc_in_dims.3));
+        let mut bias = Kernel::zeros((4, 1, 1, 1));
+        weights
+            .iter_mut()
+            .chain(bias.iter_mut())
+            .for_each(|e| *e = generate_random_number(rng));
+        let fc = FullyConnectedParams::<TenBitAS, _>::new(weights.clone(), bias.clone());
+        let pt_fc = FullyConnectedParams::<TenBitExpFP, _>::new(weights, bias);
+        let fc_dims = LayerDims {
+            input_dims: fc_in_dims,
+            output_dims: fc.calculate_output_size(fc_in_dims),
+        };
+
+        let network = NeuralNetwork {
+            layers: vec![
+                Layer::LL(LinearLayer::Conv2d {
+                    dims: conv_dims,
+                    params: conv,
+                }),
+                relu_layer(fc_in_dims),
+                Layer::LL(LinearLayer::FullyConnected {
+                    dims: fc_dims,
+                    params: fc,
+                }),
+            ],
+            ..Default::default()
+        };
+        let pt_network = NeuralNetwork {
+            layers: vec![
+                Layer::LL(LinearLayer::Conv2d {
+                    dims: conv_dims,
+                    params: pt_conv,
+                }),
+                relu_layer(fc_in_dims),
+                Layer::LL(LinearLayer::FullyConnected {
+                    dims: fc_dims,
+                    params: pt_fc,
+                }),
+            ],
+            ..Default::default()
+        };
+        (network, pt_network)
+    }
+
+    #[test]
+    fn test_in_memory_inference() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (1, 1, 4, 4);
+        let (network, pt_network) = sample_network(input_dims, &mut rng);
+        let architecture = (&network).into();
+
+        let mut input = Input::zeros(input_dims);
+        input
+            .iter_mut()
+            .for_each(|e| *e = generate_random_number(&mut rng));
+
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            memory::connect(4, LinkConfig::default());
+
+        let result = crossbeam::thread::scope(|s| {
+            let server = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let state = NNProtocol::offline_server_protocol(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    &mut rng,
+                )
+                .unwrap();
+                NNProtocol::online_server_protocol(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    &state,
+                )
+                .unwrap();
+            });
+            let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+            let state = NNProtocol::offline_client_protocol(
+                &mut client_reader,
+                &mut client_writer,
+                &architecture,
+                &mut rng,
+            )
+            .unwrap();
+            let result = NNProtocol::online_client_protocol(
+                &mut client_reader,
+                &mut client_writer,
+                &input,
+                &architecture,
+                &state,
+            )
+            .unwrap();
+            server.join().unwrap();
+            result
+        })
+        .unwrap();
+
+        let expected = pt_network.evaluate(&input);
+        for (r, e) in result.iter().zip(expected.iter()) {
+            let delta = f64::from(*r) - f64::from(*e);
+            assert!(delta.abs() < 0.5, "result {} != expected {}", r, e);
+        }
+
+        // The simulator should reproduce the protocol bit for bit
+        let simulated = simulate(&network, &input, Truncation::InCircuit, &mut rng);
+        for (r, s) in result.iter().zip(simulated.iter()) {
+            assert_eq!(r.inner, s.inner, "result {} != simulated {}", r, s);
+        }
+    }
+
+    #[test]
+    fn test_in_memory_dealer_inference() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (1, 1, 4, 4);
+        let (network, pt_network) = sample_network(input_dims, &mut rng);
+        let architecture = (&network).into();
+
+        let mut input = Input::zeros(input_dims);
+        input
+            .iter_mut()
+            .for_each(|e| *e = generate_random_number(&mut rng));
+
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            memory::connect(4, LinkConfig::default());
+
+        let result = crossbeam::thread::scope(|s| {
+            let server = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let state = NNProtocol::offline_server_protocol_with_backend(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    OfflineBackend::InsecureDealer,
+                    &mut rng,
+                )
+                .unwrap();
+                NNProtocol::online_server_protocol(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    &state,
+                )
+                .unwrap();
+            });
+            let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+            let state = NNProtocol::offline_client_protocol_with_backend(
+                &mut client_reader,
+                &mut client_writer,
+                &architecture,
+                OfflineBackend::InsecureDealer,
+                &mut rng,
+            )
+            .unwrap();
+            let result = NNProtocol::online_client_protocol(
+                &mut client_reader,
+                &mut client_writer,
+                &input,
+                &architecture,
+                &state,
+            )
+            .unwrap();
+            server.join().unwrap();
+            result
+        })
+        .unwrap();
+
+        // The dealt correlations should be indistinguishable from the SEAL ones
+        // to the online phase
+        let simulated = simulate(&network, &input, Truncation::InCircuit, &mut rng);
+        for (r, s) in result.iter().zip(simulated.iter()) {
+            assert_eq!(r.inner, s.inner, "result {} != simulated {}", r, s);
+        }
+        let expected = pt_network.evaluate(&input);
+        for (r, e) in result.iter().zip(expected.iter()) {
+            let delta = f64::from(*r) - f64::from(*e);
+            assert!(delta.abs() < 0.5, "result {} != expected {}", r, e);
+        }
+    }
+
+    #[test]
+    fn test_in_memory_ot_inference() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (1, 1, 4, 4);
+        let (network, pt_network) = sample_network(input_dims, &mut rng);
+        let architecture = (&network).into();
+
+        let mut input = Input::zeros(input_dims);
+        input
+            .iter_mut()
+            .for_each(|e| *e = generate_random_number(&mut rng));
+
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            memory::connect(4, LinkConfig::default());
+
+        // Linear layers are still preprocessed with SEAL, but all other
+        // randomness comes from the OT generator
+        let result = crossbeam::thread::scope(|s| {
+            let server = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let sfhe = crate::server_keygen(&mut server_reader).unwrap();
+                let state = NNProtocol::offline_server_protocol_with::<
+                    SealServerACG,
+                    OtServerOfflineMPC<F>,
+                    _,
+                    _,
+                    _,
+                >(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    &sfhe,
+                    (),
+                    &mut rng,
+                )
+                .unwrap();
+                NNProtocol::online_server_protocol(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    &state,
+                )
+                .unwrap();
+            });
+            let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+            let cfhe = crate::client_keygen(&mut client_writer).unwrap();
+            let state = NNProtocol::offline_client_protocol_with::<
+                SealClientACG,
+                OtClientOfflineMPC<F>,
+                _,
+                _,
+                _,
+            >(
+                &mut client_reader,
+                &mut client_writer,
+                &architecture,
+                &cfhe,
+                (),
+                &mut rng,
+            )
+            .unwrap();
+            let result = NNProtocol::online_client_protocol(
+                &mut client_reader,
+                &mut client_writer,
+                &input,
+                &architecture,
+                &state,
+            )
+            .unwrap();
+            server.join().unwrap();
+            result
+        })
+        .unwrap();
+
+        let simulated = simulate(&network, &input, Truncation::InCircuit, &mut rng);
+        for (r, s) in result.iter().zip(simulated.iter()) {
+            assert_eq!(r.inner, s.inner, "result {} != simulated {}", r, s);
+        }
+        let expected = pt_network.evaluate(&input);
+        for (r, e) in result.iter().zip(expected.iter()) {
+            let delta = f64::from(*r) - f64::from(*e);
+            assert!(delta.abs() < 0.5, "result {} != expected {}", r, e);
+        }
+    }
+
+    #[test]
+    fn test_in_memory_batch_inference() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let batch_size = 3;
+        let (network, pt_network) = sample_network((batch_size, 1, 4, 4), &mut rng);
+        let architecture = (&network).into();
+
+        let inputs: Vec<_> = (0..batch_size)
+            .map(|_| {
+                let mut input = Input::zeros((1, 1, 4, 4));
+                input
+                    .iter_mut()
+                    .for_each(|e| *e = generate_random_number(&mut rng));
+                input
+            })
+            .collect();
+
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            memory::connect(4, LinkConfig::default());
+
+        let results = crossbeam::thread::scope(|s| {
+            let server = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let state = NNProtocol::offline_server_protocol(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    &mut rng,
+                )
+                .unwrap();
+                NNProtocol::online_server_protocol(
+                    &mut server_reader,
+                    &mut server_writer,
+                    &network,
+                    &state,
+                )
+                .unwrap();
+            });
+            let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+            let state = NNProtocol::offline_client_protocol(
+                &mut client_reader,
+                &mut client_writer,
+                &architecture,
+                &mut rng,
+            )
+            .unwrap();
+            let results = NNProtocol::online_client_batch_protocol(
+                &mut client_reader,
+                &mut client_writer,
+                &inputs,
+                &architecture,
+                &state,
+            )
+            .unwrap();
+            server.join().unwrap();
+            results
+        })
+        .unwrap();
+
+        // Each item should match the corresponding item of the simulated and
+        // plaintext outputs for the whole batch
+        assert_eq!(results.len(), batch_size);
+        let input = Input::stack_batch(&inputs);
+        let simulated = simulate(&network, &input, Truncation::InCircuit, &mut rng).split_batch();
+        let expected = pt_network.evaluate(&input).split_batch();
+        for ((result, simulated), expected) in results.iter().zip(&simulated).zip(&expected) {
+            assert_eq!(result.dim(), (1, 4, 1, 1));
+            for (r, s) in result.iter().zip(simulated.iter()) {
+                assert_eq!(r.inner, s.inner, "result {} != simulated {}", r, s);
+            }
+            for (r, e) in result.iter().zip(expected.iter()) {
+                let delta = f64::from(*r) - f64::from(*e);
+                assert!(delta.abs() < 0.5, "result {} != expected {}", r, e);
+            }
+        }
+    }
 }
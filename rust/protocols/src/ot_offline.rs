@@ -0,0 +1,945 @@
+//! Generation of authenticated randomness and triples from oblivious transfer.
+//!
+//! This is an alternative to the SEAL-backed generators in `mpc_offline`, in
+//! the style of MASCOT (Keller, Orsini and Scholl, CCS 2016) specialized to
+//! the client-malicious setting. The server holds the MAC key, so only the
+//! client's inputs need to be checked.
+//!
+//! Every product of a client value `x` and a server value `y` is computed by
+//! Gilboa's oblivious linear evaluation: for each bit `x_i` of `x`, the server
+//! acts as OT sender with messages `(r_i, r_i + 2^i * y)` and the client
+//! chooses with `x_i`, so that the client receives `sum(r_i) + x * y` and the
+//! server keeps `-sum(r_i)`. MAC shares are products with the MAC key, and
+//! the cross terms of a triple are products with the other party's shares.
+//! The OTs are provided by KOS OT extension from `ocelot`.
+//!
+//! Each triple `(a, b, c)` is generated alongside a second triple
+//! `(a', b, c')` which is sacrificed to check it: the server samples a random
+//! `t`, the parties open `rho = t * a - a'` and `sigma = t * c - c' - rho * b`
+//! with their MACs, and the server aborts unless `sigma` is zero and both
+//! MACs verify. This catches a client which multiplies inconsistent values
+//! in different OLEs.
+//!
+//! Square pairs `(a, a^2)` only need the single cross term `a_c * a_s`, and
+//! are checked in the same way with a second pair `(a', a'^2)`: the parties
+//! open `rho = t * a - a'` and `sigma = t^2 * a^2 - a'^2 - rho * (t * a + a')`.
+//!
+//! `ole_gen` exposes the bare OLE, with which the client's MAC key share is
+//! multiplied into the server's shares by `mpc_offline::client_share_mac_key`.
+
+use crate::{
+    bytes, comm,
+    error::MpcError,
+    mpc_offline::{check_opening_len, ClientOfflineGen, OfflineMPC, ServerOfflineGen},
+    InMessage, OutMessage,
+};
+use algebra::{Field, Fp64, Fp64Parameters, FpParameters, PrimeField, UniformRandom};
+use async_std::{
+    io::{Read, Write},
+    task,
+};
+use crypto_primitives::{
+    additive_share::{AuthAdditiveShare, AuthShare},
+    beavers_mul::{SquarePair, Triple},
+};
+use io_utils::imux::IMuxAsync;
+use itertools::izip;
+use num_traits::Zero;
+use ocelot::ot::{KosReceiver, KosSender, Receiver as OtReceiver, Sender as OtSender};
+use rand::{CryptoRng, RngCore};
+use scuttlebutt::{AbstractChannel, Block, Channel};
+use std::{cmp::min, io, marker::PhantomData};
+
+pub struct OtOfflineMPCProtocolType;
+
+type ChallengeSend<'a, F> = OutMessage<'a, (Vec<F>, Vec<F>), OtOfflineMPCProtocolType>;
+type ChallengeRcv<F> = InMessage<(Vec<F>, Vec<F>), OtOfflineMPCProtocolType>;
+type OpeningSend<'a, F> = OutMessage<
+    'a,
+    (Vec<AuthAdditiveShare<F>>, Vec<AuthAdditiveShare<F>>),
+    OtOfflineMPCProtocolType,
+>;
+type OpeningRcv<F> =
+    InMessage<(Vec<AuthAdditiveShare<F>>, Vec<AuthAdditiveShare<F>>), OtOfflineMPCProtocolType>;
+
+/// Presents an `IMuxAsync` reader as a blocking byte stream, as expected by
+/// `scuttlebutt` channels. Each message read from `inner` is buffered until
+/// it has been consumed.
+struct SyncReader<'a, R: Read + Unpin> {
+    inner: &'a mut IMuxAsync<R>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<'a, R: Read + Unpin> io::Read for SyncReader<'a, R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            self.buf = task::block_on(self.inner.read())?;
+            self.pos = 0;
+            comm::record_read(self.buf.len());
+        }
+        let len = min(out.len(), self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Presents an `IMuxAsync` writer as a blocking byte stream. Writes are
+/// buffered and sent as one message on every flush.
+struct SyncWriter<'a, W: Write + Unpin> {
+    inner: &'a mut IMuxAsync<W>,
+    buf: Vec<u8>,
+}
+
+impl<'a, W: Write + Unpin> io::Write for SyncWriter<'a, W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        comm::record_written(self.buf.len());
+        let (inner, buf) = (&mut self.inner, &self.buf);
+        task::block_on(async {
+            inner.write(buf).await?;
+            inner
+                .flush()
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "Error attempting to flush"))
+        })?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<'a, W: Write + Unpin> Drop for SyncWriter<'a, W> {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+/// Opens a `scuttlebutt` channel over `reader` and `writer` for the duration
+/// of one OT invocation
+fn channel<'a, R: Read + Unpin, W: Write + Unpin>(
+    reader: &'a mut IMuxAsync<R>,
+    writer: &'a mut IMuxAsync<W>,
+) -> Channel<SyncReader<'a, R>, SyncWriter<'a, W>> {
+    Channel::new(
+        SyncReader {
+            inner: reader,
+            buf: Vec::new(),
+            pos: 0,
+        },
+        SyncWriter {
+            inner: writer,
+            buf: Vec::new(),
+        },
+    )
+}
+
+#[inline]
+fn to_block<P: Fp64Parameters>(elem: Fp64<P>) -> Block {
+    u128::from(elem.into_repr().0).into()
+}
+
+#[inline]
+fn from_block<P: Fp64Parameters>(block: Block) -> Fp64<P> {
+    Fp64::from_repr((u128::from(block) as u64).into())
+}
+
+/// Server side of Gilboa multiplication: returns a share of `x * y` for each
+/// `y` in `ys`, where `x` is the corresponding input of the client
+fn ole_send<P, C, RNG>(
+    ot: &mut KosSender,
+    channel: &mut C,
+    ys: &[Fp64<P>],
+    rng: &mut RNG,
+) -> Result<Vec<Fp64<P>>, MpcError>
+where
+    P: Fp64Parameters,
+    C: AbstractChannel,
+    RNG: RngCore + CryptoRng,
+{
+    let bits = P::MODULUS_BITS as usize;
+    let mut shares = Vec::with_capacity(ys.len());
+    let mut messages = Vec::with_capacity(ys.len() * bits);
+    for y in ys {
+        let mut share = Fp64::zero();
+        let mut power = *y;
+        for _ in 0..bits {
+            let r = Fp64::uniform(rng);
+            share -= r;
+            messages.push((to_block(r), to_block(r + power)));
+            power.double_in_place();
+        }
+        shares.push(share);
+    }
+    ot.send(channel, &messages, rng)?;
+    Ok(shares)
+}
+
+/// Client side of Gilboa multiplication: returns a share of `x * y` for each
+/// `x` in `xs`, where `y` is the corresponding input of the server
+fn ole_receive<P, C, RNG>(
+    ot: &mut KosReceiver,
+    channel: &mut C,
+    xs: &[Fp64<P>],
+    rng: &mut RNG,
+) -> Result<Vec<Fp64<P>>, MpcError>
+where
+    P: Fp64Parameters,
+    C: AbstractChannel,
+    RNG: RngCore + CryptoRng,
+{
+    let bits = P::MODULUS_BITS as usize;
+    let choices: Vec<bool> = xs
+        .iter()
+        .flat_map(|x| {
+            let x = x.into_repr().0;
+            (0..bits).map(move |i| (x >> i) & 1 == 1)
+        })
+        .collect();
+    Ok(ot
+        .receive(channel, &choices, rng)?
+        .chunks(bits)
+        .map(|blocks| {
+            blocks
+                .iter()
+                .fold(Fp64::zero(), |sum, b| sum + from_block::<P>(*b))
+        })
+        .collect())
+}
+
+/// Client side of OT-based pairwise randomness and triple generation
+pub struct OtClientOfflineMPC<T: AuthShare> {
+    _share: PhantomData<T>,
+}
+
+/// Server side of OT-based pairwise randomness and triple generation
+pub struct OtServerOfflineMPC<T: AuthShare> {
+    mac_key: T,
+}
+
+impl<P: Fp64Parameters> OtClientOfflineMPC<Fp64<P>> {
+    pub fn new() -> Self {
+        Self {
+            _share: PhantomData,
+        }
+    }
+}
+
+impl<P: Fp64Parameters> Default for OtClientOfflineMPC<Fp64<P>> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Fp64Parameters> OtServerOfflineMPC<Fp64<P>> {
+    pub fn new(mac_key: u64) -> Self {
+        Self {
+            mac_key: Fp64::from_repr(mac_key.into()),
+        }
+    }
+
+    /// Generates `num` authenticated pairwise randomness shares. If
+    /// `known_to_client` is set, the server's value shares are zero so that
+    /// the client's value shares are the whole values.
+    fn ot_rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+        known_to_client: bool,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server OT pairwise randomness generation");
+        let _phase = comm::phase("Rands");
+        let mut ot = KosSender::init(&mut channel(reader, writer), rng)?;
+
+        let mut rands = Vec::with_capacity(num);
+        let mut remaining = num;
+        while remaining > 0 {
+            let n = min(remaining, Self::BATCH_SIZE);
+            let keys = vec![self.mac_key; n];
+            let macs = ole_send(&mut ot, &mut channel(reader, writer), &keys, rng)?;
+            rands.extend(macs.into_iter().map(|m| {
+                let value = if known_to_client {
+                    Fp64::zero()
+                } else {
+                    Fp64::uniform(rng)
+                };
+                AuthAdditiveShare::new(value, self.mac_key * value + m)
+            }));
+            remaining -= n;
+        }
+        timer_end!(start_time);
+        Ok(rands)
+    }
+}
+
+impl<P: Fp64Parameters> ClientOfflineGen<Fp64<P>> for OtClientOfflineMPC<Fp64<P>> {
+    type Keys = ();
+
+    fn from_keys(_: ()) -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Fp64Parameters> ServerOfflineGen<Fp64<P>> for OtServerOfflineMPC<Fp64<P>> {
+    type Keys = ();
+
+    fn from_keys(_: (), mac_key: u64) -> Self {
+        Self::new(mac_key)
+    }
+}
+
+impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for OtClientOfflineMPC<Fp64<P>> {
+    fn rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Client OT pairwise randomness generation");
+        let _phase = comm::phase("Rands");
+        let mut ot = KosReceiver::init(&mut channel(reader, writer), rng)?;
+
+        let mut rands = Vec::with_capacity(num);
+        let mut remaining = num;
+        while remaining > 0 {
+            let n = min(remaining, Self::BATCH_SIZE);
+            let values: Vec<_> = (0..n).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let macs = ole_receive(&mut ot, &mut channel(reader, writer), &values, rng)?;
+            rands.extend(izip!(values, macs).map(|(v, m)| AuthAdditiveShare::new(v, m)));
+            remaining -= n;
+        }
+        timer_end!(start_time);
+        Ok(rands)
+    }
+
+    fn client_rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        // The client's value shares are its own values in either case
+        self.rands_gen(reader, writer, rng, num)
+    }
+
+    fn triples_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<Triple<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Client OT triples generation");
+        let _phase = comm::phase("Triples");
+        let mut ot = KosReceiver::init(&mut channel(reader, writer), rng)?;
+
+        let mut triples = Vec::with_capacity(num);
+        let mut remaining = num;
+        while remaining > 0 {
+            let n = min(remaining, Self::BATCH_SIZE);
+            let sample = |rng: &mut RNG| (0..n).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let a: Vec<_> = sample(rng);
+            let a_hat: Vec<_> = sample(rng);
+            let b: Vec<_> = sample(rng);
+
+            // Multiply the client's shares by the server's shares and by the
+            // MAC key
+            let ole_time = timer_start!(|| "Multiplying shares");
+            let inputs = [&a[..], &a_hat, &b, &b, &a, &a_hat, &b].concat();
+            let products = ole_receive(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let products: Vec<_> = products.chunks(n).collect();
+            let (a_b_s, a_hat_b_s, a_s_b, a_hat_s_b) =
+                (products[0], products[1], products[2], products[3]);
+            let (a_mac, a_hat_mac, b_mac) = (products[4], products[5], products[6]);
+            let c: Vec<_> = izip!(&a, &b, a_b_s, a_s_b)
+                .map(|(a, b, p1, p2)| *a * b + p1 + p2)
+                .collect();
+            let c_hat: Vec<_> = izip!(&a_hat, &b, a_hat_b_s, a_hat_s_b)
+                .map(|(a, b, p1, p2)| *a * b + p1 + p2)
+                .collect();
+
+            let inputs = [&c[..], &c_hat].concat();
+            let products = ole_receive(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let (c_mac, c_hat_mac) = products.split_at(n);
+            timer_end!(ole_time);
+
+            // Sacrifice the second triple to check the first
+            let check_time = timer_start!(|| "Checking triples");
+            let challenge: ChallengeRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+            let (t, rho_s) = challenge.msg();
+            let rho: Vec<_> = izip!(&t, &a, &a_hat, a_mac, a_hat_mac)
+                .map(|(t, a, a_hat, a_mac, a_hat_mac)| {
+                    AuthAdditiveShare::new(*t * a - a_hat, *t * a_mac - a_hat_mac)
+                })
+                .collect();
+            let sigma: Vec<_> = izip!(&t, &rho, &rho_s, &c, &c_hat, &b, c_mac, c_hat_mac, b_mac)
+                .map(|(t, rho, rho_s, c, c_hat, b, c_mac, c_hat_mac, b_mac)| {
+                    let rho = rho.get_value().inner + rho_s;
+                    AuthAdditiveShare::new(
+                        *t * c - c_hat - rho * b,
+                        *t * c_mac - c_hat_mac - rho * b_mac,
+                    )
+                })
+                .collect();
+            let opening = (rho, sigma);
+            let sent_message = OpeningSend::new(&opening);
+            bytes::serialize(&mut *writer, &sent_message)?;
+            timer_end!(check_time);
+
+            triples.extend(izip!(a, a_mac, b, b_mac, c, c_mac).map(
+                |(a, a_mac, b, b_mac, c, c_mac)| Triple {
+                    a: AuthAdditiveShare::new(a, *a_mac),
+                    b: AuthAdditiveShare::new(b, *b_mac),
+                    c: AuthAdditiveShare::new(c, *c_mac),
+                },
+            ));
+            remaining -= n;
+        }
+        timer_end!(start_time);
+        Ok(triples)
+    }
+
+    fn squares_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Client OT squares generation");
+        let _phase = comm::phase("Squares");
+        let mut ot = KosReceiver::init(&mut channel(reader, writer), rng)?;
+
+        let mut squares = Vec::with_capacity(num);
+        let mut remaining = num;
+        while remaining > 0 {
+            let n = min(remaining, Self::BATCH_SIZE);
+            let sample = |rng: &mut RNG| (0..n).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let a: Vec<_> = sample(rng);
+            let a_hat: Vec<_> = sample(rng);
+
+            // Multiply the client's shares by the server's shares and by the
+            // MAC key
+            let ole_time = timer_start!(|| "Multiplying shares");
+            let inputs = [&a[..], &a_hat, &a, &a_hat].concat();
+            let products = ole_receive(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let products: Vec<_> = products.chunks(n).collect();
+            let (a_a_s, a_hat_a_hat_s) = (products[0], products[1]);
+            let (a_mac, a_hat_mac) = (products[2], products[3]);
+            let square = |a: &[Fp64<P>], p: &[Fp64<P>]| -> Vec<Fp64<P>> {
+                izip!(a, p).map(|(a, p)| *a * a + p.double()).collect()
+            };
+            let (c, c_hat) = (square(&a, a_a_s), square(&a_hat, a_hat_a_hat_s));
+
+            let inputs = [&c[..], &c_hat].concat();
+            let products = ole_receive(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let (c_mac, c_hat_mac) = products.split_at(n);
+            timer_end!(ole_time);
+
+            // Sacrifice the second pair to check the first
+            let check_time = timer_start!(|| "Checking squares");
+            let challenge: ChallengeRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+            let (t, rho_s) = challenge.msg();
+            let rho: Vec<_> = izip!(&t, &a, &a_hat, a_mac, a_hat_mac)
+                .map(|(t, a, a_hat, a_mac, a_hat_mac)| {
+                    AuthAdditiveShare::new(*t * a - a_hat, *t * a_mac - a_hat_mac)
+                })
+                .collect();
+            let sigma: Vec<_> =
+                izip!(&t, &rho, &rho_s, &a, &a_hat, &c, &c_hat, a_mac, a_hat_mac, c_mac, c_hat_mac)
+                    .map(
+                        |(
+                            t,
+                            rho,
+                            rho_s,
+                            a,
+                            a_hat,
+                            c,
+                            c_hat,
+                            a_mac,
+                            a_hat_mac,
+                            c_mac,
+                            c_hat_mac,
+                        )| {
+                            let rho = rho.get_value().inner + rho_s;
+                            let t_squared = t.square();
+                            AuthAdditiveShare::new(
+                                t_squared * c - c_hat - rho * (*t * a + a_hat),
+                                t_squared * c_mac - c_hat_mac - rho * (*t * a_mac + a_hat_mac),
+                            )
+                        },
+                    )
+                    .collect();
+            let opening = (rho, sigma);
+            let sent_message = OpeningSend::new(&opening);
+            bytes::serialize(&mut *writer, &sent_message)?;
+            timer_end!(check_time);
+
+            squares.extend(
+                izip!(a, a_mac, c, c_mac).map(|(a, a_mac, c, c_mac)| SquarePair {
+                    a: AuthAdditiveShare::new(a, *a_mac),
+                    a_squared: AuthAdditiveShare::new(c, *c_mac),
+                }),
+            );
+            remaining -= n;
+        }
+        timer_end!(start_time);
+        Ok(squares)
+    }
+
+    fn ole_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        values: &[Fp64<P>],
+    ) -> Result<Vec<Fp64<P>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Client OT OLE generation");
+        let mut ot = KosReceiver::init(&mut channel(reader, writer), rng)?;
+        let mut products = Vec::with_capacity(values.len());
+        for batch in values.chunks(Self::BATCH_SIZE) {
+            products.extend(ole_receive(
+                &mut ot,
+                &mut channel(reader, writer),
+                batch,
+                rng,
+            )?);
+        }
+        timer_end!(start_time);
+        Ok(products)
+    }
+}
+
+impl<P: Fp64Parameters> OfflineMPC<Fp64<P>> for OtServerOfflineMPC<Fp64<P>> {
+    fn rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        self.ot_rands_gen(reader, writer, rng, num, false)
+    }
+
+    fn client_rands_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<AuthAdditiveShare<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        self.ot_rands_gen(reader, writer, rng, num, true)
+    }
+
+    fn triples_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<Triple<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server OT triples generation");
+        let _phase = comm::phase("Triples");
+        let mut ot = KosSender::init(&mut channel(reader, writer), rng)?;
+        let key = self.mac_key;
+
+        let mut triples = Vec::with_capacity(num);
+        let mut remaining = num;
+        while remaining > 0 {
+            let n = min(remaining, Self::BATCH_SIZE);
+            let sample = |rng: &mut RNG| (0..n).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let a: Vec<_> = sample(rng);
+            let a_hat: Vec<_> = sample(rng);
+            let b: Vec<_> = sample(rng);
+            let keys = vec![key; n];
+
+            // Multiply the client's shares by the server's shares and by the
+            // MAC key
+            let ole_time = timer_start!(|| "Multiplying shares");
+            let inputs = [&b[..], &b, &a, &a_hat, &keys, &keys, &keys].concat();
+            let products = ole_send(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let products: Vec<_> = products.chunks(n).collect();
+            let (a_c_b, a_hat_c_b, a_b_c, a_hat_b_c) =
+                (products[0], products[1], products[2], products[3]);
+            let c: Vec<_> = izip!(&a, &b, a_c_b, a_b_c)
+                .map(|(a, b, p1, p2)| *a * b + p1 + p2)
+                .collect();
+            let c_hat: Vec<_> = izip!(&a_hat, &b, a_hat_c_b, a_hat_b_c)
+                .map(|(a, b, p1, p2)| *a * b + p1 + p2)
+                .collect();
+            let mac = |values: &[Fp64<P>], products: &[Fp64<P>]| -> Vec<Fp64<P>> {
+                izip!(values, products).map(|(v, p)| key * v + p).collect()
+            };
+            let (a_mac, a_hat_mac, b_mac) = (
+                mac(&a, products[4]),
+                mac(&a_hat, products[5]),
+                mac(&b, products[6]),
+            );
+
+            let inputs = [&keys[..], &keys].concat();
+            let products = ole_send(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let (c_products, c_hat_products) = products.split_at(n);
+            let (c_mac, c_hat_mac) = (mac(&c, c_products), mac(&c_hat, c_hat_products));
+            timer_end!(ole_time);
+
+            // Sacrifice the second triple to check the first
+            let check_time = timer_start!(|| "Checking triples");
+            let t: Vec<_> = sample(rng);
+            let rho: Vec<_> = izip!(&t, &a, &a_hat, &a_mac, &a_hat_mac)
+                .map(|(t, a, a_hat, a_mac, a_hat_mac)| (*t * a - a_hat, *t * a_mac - a_hat_mac))
+                .collect();
+            let challenge = (t, rho.iter().map(|(value, _)| *value).collect());
+            let sent_message = ChallengeSend::new(&challenge);
+            bytes::serialize(&mut *writer, &sent_message)?;
+            let (t, _) = challenge;
+
+            let opening: OpeningRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+            let (rho_c, sigma_c) = opening.msg();
+            check_opening_len(&rho_c, n)?;
+            check_opening_len(&sigma_c, n)?;
+            for (i, ((rho_s, rho_mac_s), rho_c, sigma_c)) in izip!(rho, rho_c, sigma_c).enumerate()
+            {
+                let rho = rho_s + rho_c.get_value().inner;
+                if key * rho != rho_mac_s + rho_c.get_mac().inner {
+                    return Err(MpcError::InvalidMAC);
+                }
+                let sigma = t[i] * c[i] - c_hat[i] - rho * b[i] + sigma_c.get_value().inner;
+                let sigma_mac =
+                    t[i] * c_mac[i] - c_hat_mac[i] - rho * b_mac[i] + sigma_c.get_mac().inner;
+                if !(sigma.is_zero() && sigma_mac.is_zero()) {
+                    return Err(MpcError::InvalidTriple);
+                }
+            }
+            timer_end!(check_time);
+
+            triples.extend(izip!(a, a_mac, b, b_mac, c, c_mac).map(
+                |(a, a_mac, b, b_mac, c, c_mac)| Triple {
+                    a: AuthAdditiveShare::new(a, a_mac),
+                    b: AuthAdditiveShare::new(b, b_mac),
+                    c: AuthAdditiveShare::new(c, c_mac),
+                },
+            ));
+            remaining -= n;
+        }
+        timer_end!(start_time);
+        Ok(triples)
+    }
+
+    fn squares_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        num: usize,
+    ) -> Result<Vec<SquarePair<Fp64<P>>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server OT squares generation");
+        let _phase = comm::phase("Squares");
+        let mut ot = KosSender::init(&mut channel(reader, writer), rng)?;
+        let key = self.mac_key;
+
+        let mut squares = Vec::with_capacity(num);
+        let mut remaining = num;
+        while remaining > 0 {
+            let n = min(remaining, Self::BATCH_SIZE);
+            let sample = |rng: &mut RNG| (0..n).map(|_| Fp64::<P>::uniform(rng)).collect();
+            let a: Vec<_> = sample(rng);
+            let a_hat: Vec<_> = sample(rng);
+            let keys = vec![key; n];
+
+            // Multiply the client's shares by the server's shares and by the
+            // MAC key
+            let ole_time = timer_start!(|| "Multiplying shares");
+            let inputs = [&a[..], &a_hat, &keys, &keys].concat();
+            let products = ole_send(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let products: Vec<_> = products.chunks(n).collect();
+            let square = |a: &[Fp64<P>], p: &[Fp64<P>]| -> Vec<Fp64<P>> {
+                izip!(a, p).map(|(a, p)| *a * a + p.double()).collect()
+            };
+            let (c, c_hat) = (square(&a, products[0]), square(&a_hat, products[1]));
+            let mac = |values: &[Fp64<P>], products: &[Fp64<P>]| -> Vec<Fp64<P>> {
+                izip!(values, products).map(|(v, p)| key * v + p).collect()
+            };
+            let (a_mac, a_hat_mac) = (mac(&a, products[2]), mac(&a_hat, products[3]));
+
+            let inputs = [&keys[..], &keys].concat();
+            let products = ole_send(&mut ot, &mut channel(reader, writer), &inputs, rng)?;
+            let (c_products, c_hat_products) = products.split_at(n);
+            let (c_mac, c_hat_mac) = (mac(&c, c_products), mac(&c_hat, c_hat_products));
+            timer_end!(ole_time);
+
+            // Sacrifice the second pair to check the first
+            let check_time = timer_start!(|| "Checking squares");
+            let t: Vec<_> = sample(rng);
+            let rho: Vec<_> = izip!(&t, &a, &a_hat, &a_mac, &a_hat_mac)
+                .map(|(t, a, a_hat, a_mac, a_hat_mac)| (*t * a - a_hat, *t * a_mac - a_hat_mac))
+                .collect();
+            let challenge = (t, rho.iter().map(|(value, _)| *value).collect());
+            let sent_message = ChallengeSend::new(&challenge);
+            bytes::serialize(&mut *writer, &sent_message)?;
+            let (t, _) = challenge;
+
+            let opening: OpeningRcv<Fp64<P>> = bytes::deserialize(&mut *reader)?;
+            let (rho_c, sigma_c) = opening.msg();
+            check_opening_len(&rho_c, n)?;
+            check_opening_len(&sigma_c, n)?;
+            for (i, ((rho_s, rho_mac_s), rho_c, sigma_c)) in izip!(rho, rho_c, sigma_c).enumerate()
+            {
+                let rho = rho_s + rho_c.get_value().inner;
+                if key * rho != rho_mac_s + rho_c.get_mac().inner {
+                    return Err(MpcError::InvalidMAC);
+                }
+                let t_squared = t[i].square();
+                let sigma = t_squared * c[i] - c_hat[i] - rho * (t[i] * a[i] + a_hat[i])
+                    + sigma_c.get_value().inner;
+                let sigma_mac =
+                    t_squared * c_mac[i] - c_hat_mac[i] - rho * (t[i] * a_mac[i] + a_hat_mac[i])
+                        + sigma_c.get_mac().inner;
+                if !(sigma.is_zero() && sigma_mac.is_zero()) {
+                    return Err(MpcError::InvalidTriple);
+                }
+            }
+            timer_end!(check_time);
+
+            squares.extend(
+                izip!(a, a_mac, c, c_mac).map(|(a, a_mac, c, c_mac)| SquarePair {
+                    a: AuthAdditiveShare::new(a, a_mac),
+                    a_squared: AuthAdditiveShare::new(c, c_mac),
+                }),
+            );
+            remaining -= n;
+        }
+        timer_end!(start_time);
+        Ok(squares)
+    }
+
+    fn ole_gen<R, W, RNG>(
+        &self,
+        reader: &mut IMuxAsync<R>,
+        writer: &mut IMuxAsync<W>,
+        rng: &mut RNG,
+        values: &[Fp64<P>],
+    ) -> Result<Vec<Fp64<P>>, MpcError>
+    where
+        R: Read + Send + Unpin,
+        W: Write + Send + Unpin,
+        RNG: RngCore + CryptoRng,
+    {
+        let start_time = timer_start!(|| "Server OT OLE generation");
+        let mut ot = KosSender::init(&mut channel(reader, writer), rng)?;
+        let mut products = Vec::with_capacity(values.len());
+        for batch in values.chunks(Self::BATCH_SIZE) {
+            products.extend(ole_send(&mut ot, &mut channel(reader, writer), batch, rng)?);
+        }
+        timer_end!(start_time);
+        Ok(products)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{memory, LinkConfig};
+    use algebra::fields::near_mersenne_64::F;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    const RANDOMNESS: [u8; 32] = [
+        0x99, 0xe0, 0x8f, 0xbc, 0x89, 0xa7, 0x34, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda, 0xf4,
+        0x76, 0x5d, 0xc9, 0x8d, 0x62, 0x23, 0xf2, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77, 0xd3, 0x4a,
+        0x52, 0xd3,
+    ];
+
+    #[test]
+    fn test_ot_rands_gen() {
+        let num: usize = 10000;
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            memory::connect(1, LinkConfig::default());
+
+        let (client_rands, (server_rands, mac_key)) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let mac_key = F::uniform(&mut rng);
+                let server_gen = OtServerOfflineMPC::<F>::new(mac_key.into_repr().0);
+                (
+                    server_gen
+                        .rands_gen(&mut server_read, &mut server_write, &mut rng, num)
+                        .unwrap(),
+                    mac_key,
+                )
+            });
+            let client_rands = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let client_gen = OtClientOfflineMPC::<F>::new();
+                client_gen
+                    .rands_gen(&mut client_read, &mut client_write, &mut rng, num)
+                    .unwrap()
+            });
+            (client_rands.join().unwrap(), server_result.join().unwrap())
+        })
+        .unwrap();
+
+        assert_eq!(client_rands.len(), num);
+        izip!(client_rands, server_rands)
+            .for_each(|(s1, s2)| assert!(s1.combine(&s2, &mac_key).is_ok()));
+    }
+
+    #[test]
+    fn test_ot_triples_gen() {
+        let num: usize = 10000;
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            memory::connect(1, LinkConfig::default());
+
+        let (client_triples, (server_triples, mac_key)) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let mac_key = F::uniform(&mut rng);
+                let server_gen = OtServerOfflineMPC::<F>::new(mac_key.into_repr().0);
+                (
+                    server_gen
+                        .triples_gen(&mut server_read, &mut server_write, &mut rng, num)
+                        .unwrap(),
+                    mac_key,
+                )
+            });
+            let client_triples = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let client_gen = OtClientOfflineMPC::<F>::new();
+                client_gen
+                    .triples_gen(&mut client_read, &mut client_write, &mut rng, num)
+                    .unwrap()
+            });
+            (
+                client_triples.join().unwrap(),
+                server_result.join().unwrap(),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(client_triples.len(), num);
+        izip!(client_triples, server_triples).for_each(|(c, s)| {
+            let a = s.a.combine(&c.a, &mac_key);
+            let b = s.b.combine(&c.b, &mac_key);
+            let c = s.c.combine(&c.c, &mac_key);
+            assert!(a.is_ok());
+            assert!(b.is_ok());
+            assert!(c.is_ok());
+            assert_eq!(c.unwrap(), a.unwrap() * b.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_ot_squares_gen() {
+        let num: usize = 10000;
+        let ((mut client_read, mut client_write), (mut server_read, mut server_write)) =
+            memory::connect(1, LinkConfig::default());
+
+        let (client_squares, (server_squares, mac_key)) = crossbeam::thread::scope(|s| {
+            let server_result = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let mac_key = F::uniform(&mut rng);
+                let server_gen = OtServerOfflineMPC::<F>::new(mac_key.into_repr().0);
+                (
+                    server_gen
+                        .squares_gen(&mut server_read, &mut server_write, &mut rng, num)
+                        .unwrap(),
+                    mac_key,
+                )
+            });
+            let client_squares = s.spawn(|_| {
+                let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+                let client_gen = OtClientOfflineMPC::<F>::new();
+                client_gen
+                    .squares_gen(&mut client_read, &mut client_write, &mut rng, num)
+                    .unwrap()
+            });
+            (
+                client_squares.join().unwrap(),
+                server_result.join().unwrap(),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(client_squares.len(), num);
+        izip!(client_squares, server_squares).for_each(|(c, s)| {
+            let a = s.a.combine(&c.a, &mac_key).unwrap();
+            let a_squared = s.a_squared.combine(&c.a_squared, &mac_key).unwrap();
+            assert_eq!(a_squared, a * a);
+        });
+    }
+
+    #[test]
+    fn test_ot_server_aborts_on_disconnect() {
+        let ((client_read, client_write), (mut server_read, mut server_write)) =
+            memory::connect(1, LinkConfig::default());
+        // The client hangs up without taking part
+        drop((client_read, client_write));
+
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mac_key = F::uniform(&mut rng);
+        let server_gen = OtServerOfflineMPC::<F>::new(mac_key.into_repr().0);
+        assert!(server_gen
+            .triples_gen(&mut server_read, &mut server_write, &mut rng, 10)
+            .is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! Bit-packed encoding of field elements for network messages.
+//!
+//! bincode encodes every `Fp64` as a full `u64`, although an element only
+//! needs `size_in_bits` bits, e.g. 44 for `near_mersenne_64`. Message payloads
+//! of field elements and shares are instead wrapped in `PackedSlice` when sent
+//! and `Packed` when received, which concatenate the canonical representation
+//! of every element using exactly that many bits.
+
+use algebra::{
+    fields::{Fp64, Fp64Parameters, PrimeField},
+    fixed_point::{FixedPoint, FixedPointParameters},
+    BigInteger64,
+};
+use crypto_primitives::additive_share::{AdditiveShare, AuthAdditiveShare};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Represents a type which is encoded as a fixed number of canonical field
+/// elements
+pub trait Packable: Sized {
+    /// Number of bits per element
+    const BITS: usize;
+
+    /// Number of elements encoding each value
+    const ELEMS: usize;
+
+    /// Calls `write` with the canonical representation of each element of
+    /// `self`
+    fn pack(&self, write: &mut impl FnMut(u64));
+
+    /// Decodes a value from elements returned by `read`, returning `None` if
+    /// any element is not canonical
+    fn unpack(read: &mut impl FnMut() -> Option<u64>) -> Option<Self>;
+}
+
+impl<P: Fp64Parameters> Packable for Fp64<P> {
+    const BITS: usize = P::MODULUS_BITS as usize;
+    const ELEMS: usize = 1;
+
+    #[inline]
+    fn pack(&self, write: &mut impl FnMut(u64)) {
+        write(self.into_repr().0)
+    }
+
+    #[inline]
+    fn unpack(read: &mut impl FnMut() -> Option<u64>) -> Option<Self> {
+        let repr = read()?;
+        if repr < P::MODULUS.0 {
+            Some(Fp64::from_repr(BigInteger64(repr)))
+        } else {
+            None
+        }
+    }
+}
+
+impl<P: Fp64Parameters> Packable for AdditiveShare<Fp64<P>> {
+    const BITS: usize = <Fp64<P> as Packable>::BITS;
+    const ELEMS: usize = 1;
+
+    #[inline]
+    fn pack(&self, write: &mut impl FnMut(u64)) {
+        self.inner.pack(write)
+    }
+
+    #[inline]
+    fn unpack(read: &mut impl FnMut() -> Option<u64>) -> Option<Self> {
+        Fp64::unpack(read).map(AdditiveShare::new)
+    }
+}
+
+/// Only the field element of each share is sent, so the receiver gets shares
+/// with no pending multiplications
+impl<P: FixedPointParameters> Packable for AdditiveShare<FixedPoint<P>>
+where
+    P::Field: Packable,
+{
+    const BITS: usize = <P::Field as Packable>::BITS;
+    const ELEMS: usize = <P::Field as Packable>::ELEMS;
+
+    #[inline]
+    fn pack(&self, write: &mut impl FnMut(u64)) {
+        self.inner.inner.pack(write)
+    }
+
+    #[inline]
+    fn unpack(read: &mut impl FnMut() -> Option<u64>) -> Option<Self> {
+        <P::Field as Packable>::unpack(read).map(|f| AdditiveShare::new(FixedPoint::new(f)))
+    }
+}
+
+impl<P: Fp64Parameters> Packable for AuthAdditiveShare<Fp64<P>> {
+    const BITS: usize = <Fp64<P> as Packable>::BITS;
+    const ELEMS: usize = 3;
+
+    #[inline]
+    fn pack(&self, write: &mut impl FnMut(u64)) {
+        self.get_value().pack(write);
+        self.get_mac().pack(write);
+        self.get_epsilon().pack(write);
+    }
+
+    #[inline]
+    fn unpack(read: &mut impl FnMut() -> Option<u64>) -> Option<Self> {
+        let value = Fp64::unpack(read)?;
+        let mac = Fp64::unpack(read)?;
+        let epsilon = Fp64::unpack(read)?;
+        Some(AuthAdditiveShare::from_parts(value, mac, epsilon))
+    }
+}
+
+/// Number of bytes holding `len` packed values of type `T`, or `None` if it
+/// overflows
+fn packed_len<T: Packable>(len: usize) -> Option<usize> {
+    len.checked_mul(T::ELEMS)?
+        .checked_mul(T::BITS)?
+        .checked_add(7)
+        .map(|bits| bits / 8)
+}
+
+/// Packs `values` into little-endian bits
+fn pack<T: Packable>(values: &[T]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(packed_len::<T>(values.len()).unwrap_or(0));
+    let (mut buf, mut buf_bits) = (0u128, 0);
+    let mut write = |elem: u64| {
+        buf |= (elem as u128) << buf_bits;
+        buf_bits += T::BITS;
+        while buf_bits >= 8 {
+            bytes.push(buf as u8);
+            buf >>= 8;
+            buf_bits -= 8;
+        }
+    };
+    values.iter().for_each(|v| v.pack(&mut write));
+    if buf_bits > 0 {
+        bytes.push(buf as u8);
+    }
+    bytes
+}
+
+/// Unpacks `len` values from `bytes`, rejecting any non-canonical element,
+/// nonzero padding bits or length mismatch
+fn unpack<T: Packable>(bytes: &[u8], len: usize) -> Option<Vec<T>> {
+    if packed_len::<T>(len) != Some(bytes.len()) {
+        return None;
+    }
+    let mask = u64::max_value() >> (64 - T::BITS);
+    let mut bytes_iter = bytes.iter();
+    let (mut buf, mut buf_bits) = (0u128, 0);
+    let mut read = || {
+        while buf_bits < T::BITS {
+            buf |= (*bytes_iter.next()? as u128) << buf_bits;
+            buf_bits += 8;
+        }
+        let elem = buf as u64 & mask;
+        buf >>= T::BITS;
+        buf_bits -= T::BITS;
+        Some(elem)
+    };
+    let values = (0..len)
+        .map(|_| T::unpack(&mut read))
+        .collect::<Option<Vec<_>>>()?;
+    // Only the unused high bits of the last byte may remain
+    if buf == 0 {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+/// A borrowed slice of values which is bit-packed when serialized
+pub struct PackedSlice<'a, T>(pub &'a [T]);
+
+/// A vector of values which is bit-packed when serialized
+pub struct Packed<T>(pub Vec<T>);
+
+impl<'a, T: Packable> Serialize for PackedSlice<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.0.len() as u64, pack(self.0)).serialize(serializer)
+    }
+}
+
+impl<T: Packable> Serialize for Packed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PackedSlice(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de, T: Packable> Deserialize<'de> for Packed<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (len, bytes): (u64, Vec<u8>) = Deserialize::deserialize(deserializer)?;
+        unpack(&bytes, len as usize)
+            .map(Packed)
+            .ok_or_else(|| D::Error::custom("invalid packed field elements"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::{fields::near_mersenne_64::F, UniformRandom};
+    use itertools::izip;
+    use num_traits::One;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    const RANDOMNESS: [u8; 32] = [
+        0x99, 0xe0, 0x8f, 0xbc, 0x89, 0xa7, 0x34, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda, 0xf4,
+        0x76, 0x5d, 0xc9, 0x8d, 0x62, 0x23, 0xf2, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77, 0xd3, 0x4a,
+        0x52, 0xd2,
+    ];
+
+    #[test]
+    fn test_packing() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let mut elems: Vec<F> = (0..1001).map(|_| F::uniform(&mut rng)).collect();
+        elems.push(-F::one());
+        let shares: Vec<_> = elems
+            .chunks(3)
+            .map(|e| AuthAdditiveShare::from_parts(e[0], e[1], e[2]))
+            .collect();
+
+        let bytes = bincode::serialize(&PackedSlice(&elems)).unwrap();
+        assert!(bytes.len() < 8 * elems.len() * 44 / 64 + 32);
+        let unpacked: Packed<F> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(unpacked.0, elems);
+
+        let bytes = bincode::serialize(&PackedSlice(&shares)).unwrap();
+        let unpacked: Packed<AuthAdditiveShare<F>> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(unpacked.0.len(), shares.len());
+        izip!(unpacked.0, shares).for_each(|(u, s)| {
+            assert_eq!(u.get_value(), s.get_value());
+            assert_eq!(u.get_mac(), s.get_mac());
+            assert_eq!(u.get_epsilon(), s.get_epsilon());
+        });
+
+        let empty: Packed<F> =
+            bincode::deserialize(&bincode::serialize(&PackedSlice::<F>(&[])).unwrap()).unwrap();
+        assert!(empty.0.is_empty());
+    }
+
+    #[test]
+    fn test_packing_rejects_non_canonical() {
+        // The modulus itself is not a canonical element
+        let modulus = F::characteristic().0;
+        let mut bytes = modulus.to_le_bytes()[..6].to_vec();
+        assert!(unpack::<F>(&bytes, 1).is_none());
+
+        // Nonzero padding bits
+        bytes = 1u64.to_le_bytes()[..6].to_vec();
+        assert_eq!(unpack::<F>(&bytes, 1), Some(vec![F::one()]));
+        bytes[5] |= 0x80;
+        assert!(unpack::<F>(&bytes, 1).is_none());
+
+        // Length mismatch
+        assert!(unpack::<F>(&bytes[..5], 1).is_none());
+        assert!(unpack::<F>(&bytes, 2).is_none());
+        let message = bincode::serialize(&(2u64, 1u64.to_le_bytes()[..6].to_vec())).unwrap();
+        assert!(bincode::deserialize::<Packed<F>>(&message).is_err());
+
+        // Lengths whose packed size overflows
+        assert!(unpack::<F>(&bytes, usize::max_value()).is_none());
+        assert!(unpack::<AuthAdditiveShare<F>>(&bytes, usize::max_value() / 3 + 1).is_none());
+        let message = bincode::serialize(&(u64::max_value(), bytes)).unwrap();
+        assert!(bincode::deserialize::<Packed<F>>(&message).is_err());
+    }
+}
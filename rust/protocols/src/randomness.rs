@@ -0,0 +1,140 @@
+//! Randomness for protocol runs.
+//!
+//! Every protocol entry point draws its secret randomizers, MAC keys and
+//! garbling randomness from the RNG passed in by the caller. Callers should
+//! obtain that RNG from `Randomness::rng`, which seeds from OS entropy unless
+//! deterministic mode is requested explicitly.
+//!
+//! Whenever one party's shares are uniformly random, the other party sends a
+//! seed in their place: both parties expand it with `auth_shares_from_seed`
+//! and the sender keeps the complement of the expanded shares.
+
+use algebra::{Fp64, Fp64Parameters, UniformRandom};
+use crypto_primitives::additive_share::{AuthAdditiveShare, AuthShare};
+use rand::{rngs::OsRng, CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+
+/// How to seed the RNG of a party
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Randomness {
+    /// Seed from operating system entropy. This is the only secure choice.
+    OsEntropy,
+    /// INSECURE: seed from a fixed value so that runs are reproducible. Every
+    /// run with the same seed reuses the same secrets, so this must only be
+    /// used for benchmarks and tests.
+    InsecureDeterministic([u8; 32]),
+}
+
+impl Default for Randomness {
+    fn default() -> Self {
+        Randomness::OsEntropy
+    }
+}
+
+impl Randomness {
+    /// Deterministic mode with a seed expanded from `seed`
+    pub fn insecure_from_u64(seed: u64) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&seed.to_le_bytes());
+        Randomness::InsecureDeterministic(bytes)
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        match self {
+            Randomness::OsEntropy => false,
+            Randomness::InsecureDeterministic(_) => true,
+        }
+    }
+
+    /// Returns a freshly seeded RNG. Warns on stderr in deterministic mode.
+    pub fn rng(&self) -> ChaChaRng {
+        match self {
+            Randomness::OsEntropy => {
+                let mut os_rng = OsRng::new().expect("OS entropy is unavailable");
+                ChaChaRng::from_rng(&mut os_rng).expect("OS entropy is unavailable")
+            }
+            Randomness::InsecureDeterministic(seed) => {
+                eprintln!(
+                    "WARNING: using a fixed RNG seed. This run is NOT secure and must only be \
+                     used for benchmarks and tests."
+                );
+                ChaChaRng::from_seed(*seed)
+            }
+        }
+    }
+}
+
+/// Samples a seed for `expand_seed` or `auth_shares_from_seed`
+pub fn sample_seed<RNG: RngCore + CryptoRng>(rng: &mut RNG) -> [u8; 32] {
+    let mut seed = [0u8; 32];
+    rng.fill_bytes(&mut seed);
+    seed
+}
+
+/// Expands `seed` into `num` uniformly random elements
+pub fn expand_seed<F: UniformRandom>(seed: [u8; 32], num: usize) -> Vec<F> {
+    let mut rng = ChaChaRng::from_seed(seed);
+    (0..num).map(|_| F::uniform(&mut rng)).collect()
+}
+
+/// Expands `seed` into `num` authenticated shares with uniformly random values
+/// and MACs
+pub fn auth_shares_from_seed<T: AuthShare + UniformRandom>(
+    seed: [u8; 32],
+    num: usize,
+) -> Vec<AuthAdditiveShare<T>> {
+    expand_seed::<T>(seed, 2 * num)
+        .chunks(2)
+        .map(|s| AuthAdditiveShare::new(s[0], s[1]))
+        .collect()
+}
+
+/// Returns the share of `value`, authenticated under `mac_key`, which
+/// complements the other party's `share`
+pub fn complement_auth_share<P: Fp64Parameters>(
+    value: Fp64<P>,
+    mac_key: Fp64<P>,
+    share: &AuthAdditiveShare<Fp64<P>>,
+) -> AuthAdditiveShare<Fp64<P>> {
+    AuthAdditiveShare::new(
+        value - share.get_value().inner,
+        value * mac_key - share.get_mac().inner,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::fields::near_mersenne_64::F;
+    use itertools::izip;
+
+    #[test]
+    fn test_randomness() {
+        let seeded = Randomness::insecure_from_u64(42);
+        assert!(seeded.is_deterministic());
+        assert_eq!(seeded.rng().next_u64(), seeded.rng().next_u64());
+
+        let entropy = Randomness::default();
+        assert!(!entropy.is_deterministic());
+        assert_ne!(entropy.rng().next_u64(), entropy.rng().next_u64());
+    }
+
+    #[test]
+    fn test_auth_shares_from_seed() {
+        let mut rng = Randomness::insecure_from_u64(42).rng();
+        let mac_key = F::uniform(&mut rng);
+        let seed = sample_seed(&mut rng);
+        let shares = auth_shares_from_seed::<F>(seed, 1000);
+        assert_eq!(shares.len(), 1000);
+        izip!(&shares, auth_shares_from_seed::<F>(seed, 1000)).for_each(|(s1, s2)| {
+            assert_eq!(s1.get_value().inner, s2.get_value().inner);
+            assert_eq!(s1.get_mac().inner, s2.get_mac().inner);
+        });
+
+        let values: Vec<F> = (0..1000).map(|_| F::uniform(&mut rng)).collect();
+        izip!(&values, &shares).for_each(|(v, s)| {
+            let complement = complement_auth_share(*v, mac_key, s);
+            assert_eq!(complement.combine(s, &mac_key).unwrap(), *v);
+        });
+    }
+}
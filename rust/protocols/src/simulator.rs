@@ -0,0 +1,249 @@
+//! Plaintext simulation of the arithmetic performed by `NNProtocol`.
+//!
+//! Evaluating a `NeuralNetwork` over `FixedPoint`s does not reproduce what the
+//! protocol computes: the protocol accumulates consecutive linear layers
+//! without truncating, truncates only once the ReLU circuit has reconstructed
+//! its input, and clips with the bit tricks of
+//! `crypto_primitives::gc::truncated_relu`. The functions in this module
+//! mirror those steps on reconstructed field elements without running any
+//! cryptography, so that the accuracy loss caused by the protocol can be
+//! predicted and regression-tested cheaply.
+
+use crate::AdditiveShare;
+use algebra::{
+    fixed_point::{FixedPoint, FixedPointParameters},
+    fp_64::{Fp64, Fp64Parameters},
+    BigInteger64, PrimeField, UniformRandom,
+};
+use crypto_primitives::{gc::RELU_INTEGER_BITS, AuthShare, Share};
+use neural_network::{
+    layers::{Layer, NonLinearLayer},
+    tensors::{Input, Output},
+    Evaluate, NeuralNetwork,
+};
+use num_traits::Zero;
+use rand::{CryptoRng, RngCore};
+
+/// Where the output of a linear layer is truncated before a ReLU
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truncation {
+    /// Truncate the reconstructed input inside the ReLU circuit, as
+    /// `NNProtocol` does. The result is exact.
+    InCircuit,
+    /// Truncate each party's share locally before the ReLU circuit. The
+    /// result may be off by one in the last place, and is wrong entirely
+    /// when the shares wrap around the modulus.
+    LocalShares,
+}
+
+/// Mirrors `crypto_primitives::gc::truncated_relu` on a reconstructed input
+pub fn truncated_relu<P>(input: P::Field, trunc_bits: u8) -> P::Field
+where
+    P: FixedPointParameters,
+    P::Field: PrimeField<BigInt = BigInteger64>,
+{
+    if FixedPoint::<P>::new(input).is_negative() {
+        return P::Field::zero();
+    }
+    let exponent_bits = u32::from(P::EXPONENT_CAPACITY);
+    let window = exponent_bits + RELU_INTEGER_BITS as u32;
+    // The circuit drops the truncated bits along with every bit above
+    // `2^RELU_INTEGER_BITS`
+    let value: u64 = input.into_repr().into();
+    let value = (value >> trunc_bits) & ((1u64 << window) - 1);
+    // Integer parts of 6 and 7 are detected by their two top bits, and larger
+    // ones by any higher bit in the window
+    let value = if value >> exponent_bits >= 6 {
+        6 << exponent_bits
+    } else {
+        value
+    };
+    P::Field::from_repr(value.into())
+}
+
+/// Splits `input` into two random shares and truncates each of them by
+/// `trunc_bits`, as each party would do locally
+pub fn truncate_shares<P, R>(input: P::Field, trunc_bits: u8, rng: &mut R) -> P::Field
+where
+    P: FixedPointParameters,
+    R: RngCore + CryptoRng,
+{
+    let share_1 = P::Field::uniform(rng);
+    let share_2 = input - &share_1;
+    let share_1 = FixedPoint::<P>::new(share_1).signed_truncate_by(trunc_bits.into());
+    let share_2 = FixedPoint::<P>::new(share_2).signed_truncate_by(trunc_bits.into());
+    share_1.inner + &share_2.inner
+}
+
+/// Simulates `NNProtocol` on `input`, returning the reconstructed output of
+/// every layer. The output of a linear layer carries the number of linear
+/// layers since the last ReLU as its number of multiplications, so that
+/// `f64::from` decodes it correctly.
+pub fn simulate_layers<P, F, R>(
+    network: &NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+    input: &Input<FixedPoint<P>>,
+    truncation: Truncation,
+    rng: &mut R,
+) -> Vec<Output<FixedPoint<P>>>
+where
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P: FixedPointParameters<Field = Fp64<F>>,
+    P::Field: Share<
+        Constant = <P as FixedPointParameters>::Field,
+        Ring = <P as FixedPointParameters>::Field,
+    >,
+    P::Field: AuthShare,
+    F: Fp64Parameters,
+    R: RngCore + CryptoRng,
+{
+    assert!(
+        network.layers.first().map_or(false, Layer::is_linear),
+        "first layer of the network should always be linear."
+    );
+    let mut outputs = Vec::with_capacity(network.layers.len());
+    let mut next_layer_input = input.clone();
+    let mut num_muls = 0;
+    for layer in &network.layers {
+        let output: Vec<_> = match layer {
+            Layer::NLL(NonLinearLayer::ReLU { .. }) => {
+                let trunc_bits = P::EXPONENT_CAPACITY * num_muls;
+                num_muls = 0;
+                next_layer_input
+                    .iter()
+                    .map(|e| {
+                        let relu_input = match truncation {
+                            Truncation::InCircuit => truncated_relu::<P>(e.inner, trunc_bits),
+                            Truncation::LocalShares => truncated_relu::<P>(
+                                truncate_shares::<P, _>(e.inner, trunc_bits, rng),
+                                0,
+                            ),
+                        };
+                        FixedPoint::new(relu_input)
+                    })
+                    .collect()
+            }
+            Layer::LL(layer) => {
+                // The server evaluates the layer on shares which have forgotten
+                // any previous multiplications
+                let shares = Input::from_shape_vec(
+                    next_layer_input.dim(),
+                    next_layer_input
+                        .iter()
+                        .map(|e| AdditiveShare::new(FixedPoint::new(e.inner)))
+                        .collect(),
+                )
+                .expect("shape should be correct");
+                num_muls += 1;
+                layer
+                    .evaluate(&shares)
+                    .iter()
+                    .map(|e| FixedPoint::with_num_muls(e.inner.inner, num_muls))
+                    .collect()
+            }
+        };
+        next_layer_input = Output::from_shape_vec(layer.output_dimensions(), output)
+            .expect("shape should be correct");
+        outputs.push(next_layer_input.clone());
+    }
+    outputs
+}
+
+/// Simulates `NNProtocol` on `input`, returning the output received by the
+/// client
+pub fn simulate<P, F, R>(
+    network: &NeuralNetwork<AdditiveShare<P>, FixedPoint<P>>,
+    input: &Input<FixedPoint<P>>,
+    truncation: Truncation,
+    rng: &mut R,
+) -> Output<FixedPoint<P>>
+where
+    <P::Field as PrimeField>::Params: Fp64Parameters,
+    P: FixedPointParameters<Field = Fp64<F>>,
+    P::Field: Share<
+        Constant = <P as FixedPointParameters>::Field,
+        Ring = <P as FixedPointParameters>::Field,
+    >,
+    P::Field: AuthShare,
+    F: Fp64Parameters,
+    R: RngCore + CryptoRng,
+{
+    let mut output = simulate_layers(network, input, truncation, rng)
+        .pop()
+        .expect("network should have at least one layer");
+    // The client reduces the output based on how many linear layers there have
+    // been since the last ReLU
+    output.iter_mut().for_each(|e| {
+        e.signed_reduce_in_place();
+    });
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use algebra::fields::near_mersenne_64::F;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+
+    struct TenBitExpParams {}
+    impl FixedPointParameters for TenBitExpParams {
+        type Field = F;
+        const MANTISSA_CAPACITY: u8 = 3;
+        const EXPONENT_CAPACITY: u8 = 8;
+    }
+
+    type TenBitExpFP = FixedPoint<TenBitExpParams>;
+
+    const RANDOMNESS: [u8; 32] = [
+        0x11, 0xe0, 0x8f, 0xbc, 0x89, 0xa7, 0x34, 0x01, 0x45, 0x86, 0x82, 0xb6, 0x51, 0xda, 0xf4,
+        0x76, 0x5d, 0xc9, 0x8d, 0xea, 0x23, 0xf2, 0x90, 0x8f, 0x9d, 0x03, 0xf2, 0x77, 0xd3, 0x4a,
+        0x52, 0xd2,
+    ];
+
+    /// Encodes `f` as the output of a linear layer with `num_muls`
+    /// multiplications
+    fn linear_output(f: f64, num_muls: u8) -> F {
+        let mut fp = TenBitExpFP::from(f);
+        for _ in 0..(num_muls * TenBitExpParams::EXPONENT_CAPACITY) {
+            fp.double_in_place();
+        }
+        fp.inner
+    }
+
+    fn relu(f: f64, num_muls: u8) -> f64 {
+        let trunc_bits = num_muls * TenBitExpParams::EXPONENT_CAPACITY;
+        let output = truncated_relu::<TenBitExpParams>(linear_output(f, num_muls), trunc_bits);
+        f64::from(TenBitExpFP::new(output))
+    }
+
+    #[test]
+    fn test_truncated_relu() {
+        for &num_muls in &[1, 2] {
+            assert_eq!(relu(-3.5, num_muls), 0.0);
+            assert_eq!(relu(2.75, num_muls), 2.75);
+            assert_eq!(relu(6.0, num_muls), 6.0);
+            assert_eq!(relu(7.5, num_muls), 6.0);
+            assert_eq!(relu(100.0, num_muls), 6.0);
+            // Bits above the window of the circuit are dropped
+            assert_eq!(relu(257.5, num_muls), 1.5);
+        }
+    }
+
+    #[test]
+    fn test_truncate_shares() {
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let ulp = 1.0 / f64::from(1 << TenBitExpParams::EXPONENT_CAPACITY);
+        let trunc_bits = TenBitExpParams::EXPONENT_CAPACITY;
+        for &f in &[-3.5, 0.0, 0.25, 2.75, 5.5] {
+            for _ in 0..100 {
+                let truncated = truncate_shares::<TenBitExpParams, _>(
+                    linear_output(f, 1),
+                    trunc_bits,
+                    &mut rng,
+                );
+                let delta = f64::from(TenBitExpFP::new(truncated)) - f;
+                assert!(delta.abs() <= ulp, "{} truncated to {}", f, f + delta);
+            }
+        }
+    }
+}
@@ -708,6 +708,38 @@ mod linear {
                 assert_eq!(o, r, "{:?}-th index failed", i);
             });
     }
+
+    #[test]
+    fn test_insecure_acg_rejects_malformed_messages() {
+        use algebra::{fields::near_mersenne_64::FParameters, FpParameters};
+        use std::os::raw::c_char;
+
+        let mut rng = ChaChaRng::from_seed(RANDOMNESS);
+        let input_dims = (1, 2, 2, 2);
+        let (layer, _) = sample_fc_layer(input_dims, 3, &mut rng);
+        let mut server_acg =
+            InsecureServerACG::<FParameters>::new((), &layer, &layer.kernel_to_repr());
+        let to_bytes = |elems: &[u64]| -> Vec<c_char> {
+            elems
+                .iter()
+                .flat_map(|e| e.to_le_bytes().to_vec())
+                .map(|b| b as c_char)
+                .collect()
+        };
+
+        let r = vec![1u64; 8];
+        assert!(server_acg.process(to_bytes(&r)).is_ok());
+        // Too few elements
+        assert!(server_acg.process(to_bytes(&r[..7])).is_err());
+        // Trailing bytes
+        let mut trailing = to_bytes(&r);
+        trailing.push(0);
+        assert!(server_acg.process(trailing).is_err());
+        // Non-canonical element
+        let mut non_canonical = r.clone();
+        non_canonical[3] = FParameters::MODULUS.0;
+        assert!(server_acg.process(to_bytes(&non_canonical)).is_err());
+    }
 }
 
 mod network {
@@ -0,0 +1,248 @@
+//! In-process duplex channels.
+//!
+//! `duplex` returns two connected `MemoryStream`s which behave like the two
+//! ends of a `TcpStream`: each implements `Read` and `Write` and can be
+//! cloned to obtain separate read and write handles for `IMuxAsync`. A
+//! `LinkConfig` optionally delays delivery to model one-way latency, jitter and
+//! a bandwidth limit; with the default config data is delivered immediately and
+//! runs are fully deterministic.
+
+use super::{LinkConfig, LinkState};
+use async_std::{
+    io::{Read, Write},
+    task,
+};
+use futures::ready;
+use io_utils::imux::IMuxAsync;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Instant,
+};
+
+/// One direction of a duplex channel
+struct Pipe {
+    /// Written chunks along with the time at which they become readable
+    chunks: VecDeque<(Instant, Vec<u8>)>,
+    /// Number of bytes already consumed from the front chunk
+    offset: usize,
+    link: LinkState,
+    /// Number of live handles that can write into this pipe
+    writers: usize,
+    reader: Option<Waker>,
+}
+
+impl Pipe {
+    fn new() -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            chunks: VecDeque::new(),
+            offset: 0,
+            link: LinkState::new(),
+            writers: 1,
+            reader: None,
+        }))
+    }
+}
+
+/// One end of an in-memory duplex channel
+pub struct MemoryStream {
+    incoming: Arc<Mutex<Pipe>>,
+    outgoing: Arc<Mutex<Pipe>>,
+    config: LinkConfig,
+    delay: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+/// Creates a connected pair of streams where both directions follow `config`
+pub fn duplex(config: LinkConfig) -> (MemoryStream, MemoryStream) {
+    let a_to_b = Pipe::new();
+    let b_to_a = Pipe::new();
+    let a = MemoryStream {
+        incoming: b_to_a.clone(),
+        outgoing: a_to_b.clone(),
+        config,
+        delay: None,
+    };
+    let b = MemoryStream {
+        incoming: a_to_b,
+        outgoing: b_to_a,
+        config,
+        delay: None,
+    };
+    (a, b)
+}
+
+/// Creates `num_streams` duplex channels and multiplexes them, returning the
+/// client's and the server's `(reader, writer)`
+pub fn connect(
+    num_streams: usize,
+    config: LinkConfig,
+) -> (
+    (IMuxAsync<MemoryStream>, IMuxAsync<MemoryStream>),
+    (IMuxAsync<MemoryStream>, IMuxAsync<MemoryStream>),
+) {
+    let mut client_readers = Vec::with_capacity(num_streams);
+    let mut client_writers = Vec::with_capacity(num_streams);
+    let mut server_readers = Vec::with_capacity(num_streams);
+    let mut server_writers = Vec::with_capacity(num_streams);
+    for _ in 0..num_streams {
+        let (client, server) = duplex(config);
+        client_readers.push(client.clone());
+        client_writers.push(client);
+        server_readers.push(server.clone());
+        server_writers.push(server);
+    }
+    (
+        (
+            IMuxAsync::new(client_readers),
+            IMuxAsync::new(client_writers),
+        ),
+        (
+            IMuxAsync::new(server_readers),
+            IMuxAsync::new(server_writers),
+        ),
+    )
+}
+
+impl Clone for MemoryStream {
+    fn clone(&self) -> Self {
+        self.outgoing.lock().unwrap().writers += 1;
+        Self {
+            incoming: self.incoming.clone(),
+            outgoing: self.outgoing.clone(),
+            config: self.config,
+            delay: None,
+        }
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        let mut pipe = self.outgoing.lock().unwrap();
+        pipe.writers -= 1;
+        // Signal EOF once the last writer is gone
+        if pipe.writers == 0 {
+            if let Some(waker) = pipe.reader.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Read for MemoryStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(delay) = this.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                this.delay = None;
+            }
+
+            let mut pipe = this.incoming.lock().unwrap();
+            let ready_at = match pipe.chunks.front() {
+                Some((ready_at, _)) => *ready_at,
+                None if pipe.writers == 0 => return Poll::Ready(Ok(0)),
+                None => {
+                    pipe.reader = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            };
+
+            let now = Instant::now();
+            if ready_at > now {
+                // The chunk is still in flight
+                drop(pipe);
+                this.delay = Some(Box::pin(task::sleep(ready_at - now)));
+                continue;
+            }
+
+            let offset = pipe.offset;
+            let (n, consumed) = {
+                let chunk = &pipe.chunks.front().unwrap().1;
+                let n = std::cmp::min(buf.len(), chunk.len() - offset);
+                buf[..n].copy_from_slice(&chunk[offset..offset + n]);
+                (n, offset + n == chunk.len())
+            };
+            if consumed {
+                pipe.chunks.pop_front();
+                pipe.offset = 0;
+            } else {
+                pipe.offset += n;
+            }
+            return Poll::Ready(Ok(n));
+        }
+    }
+}
+
+impl Write for MemoryStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let mut pipe = self.outgoing.lock().unwrap();
+        let pipe = &mut *pipe;
+        let ready_at = self.config.schedule(&mut pipe.link, buf.len());
+        pipe.chunks.push_back((ready_at, buf.to_vec()));
+        if let Some(waker) = pipe.reader.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{deserialize, serialize};
+    use std::time::Duration;
+
+    #[test]
+    fn test_duplex_roundtrip() {
+        let ((mut client_reader, mut client_writer), (mut server_reader, mut server_writer)) =
+            connect(4, LinkConfig::default());
+        let message: Vec<u64> = (0..100_000).collect();
+
+        serialize(&mut client_writer, &message).unwrap();
+        let received: Vec<u64> = deserialize(&mut server_reader).unwrap();
+        serialize(&mut server_writer, &received).unwrap();
+        let echoed: Vec<u64> = deserialize(&mut client_reader).unwrap();
+        assert_eq!(echoed, message);
+    }
+
+    #[test]
+    fn test_link_delay() {
+        let config = LinkConfig {
+            latency: Duration::from_millis(50),
+            // 1 MB/s, so 100 KB takes 100ms to transmit
+            bandwidth: Some(1_000_000),
+            ..Default::default()
+        };
+        let ((_, mut client_writer), (mut server_reader, _)) = connect(1, config);
+        let message = vec![0u8; 100_000];
+
+        let start = Instant::now();
+        serialize(&mut client_writer, &message).unwrap();
+        let received: Vec<u8> = deserialize(&mut server_reader).unwrap();
+        assert_eq!(received, message);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+}
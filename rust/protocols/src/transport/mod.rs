@@ -0,0 +1,62 @@
+//! Byte-stream transports that can be placed underneath `IMuxAsync`.
+//!
+//! Every transport here implements the `async_std` `Read`/`Write` traits, so
+//! it composes with `BufReader`/`BufWriter` and `CountingIO` exactly like a
+//! raw `TcpStream` does.
+
+pub mod memory;
+pub mod secure;
+pub mod wan;
+
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// Delivery characteristics of one direction of a link
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LinkConfig {
+    /// One-way delay added to every write
+    pub latency: Duration,
+    /// Link capacity in bytes per second. `None` is unlimited.
+    pub bandwidth: Option<u64>,
+    /// Upper bound on a uniformly random extra delay added to every write
+    pub jitter: Duration,
+}
+
+/// Scheduling state of one direction of a link
+pub(crate) struct LinkState {
+    /// Time at which the link finishes transmitting the last write
+    free_at: Instant,
+    /// Delivery time of the last write, which later writes may not precede
+    last_delivery: Instant,
+}
+
+impl LinkState {
+    pub(crate) fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            free_at: now,
+            last_delivery: now,
+        }
+    }
+}
+
+impl LinkConfig {
+    /// Returns the time at which a write of `len` bytes issued now is
+    /// delivered, and advances `state` past its transmission
+    pub(crate) fn schedule(&self, state: &mut LinkState, len: usize) -> Instant {
+        let start = std::cmp::max(state.free_at, Instant::now());
+        let transmit = self.bandwidth.map_or(Duration::from_secs(0), |b| {
+            Duration::from_secs_f64(len as f64 / b as f64)
+        });
+        state.free_at = start + transmit;
+        let jitter = if self.jitter > Duration::from_secs(0) {
+            self.jitter.mul_f64(rand::thread_rng().gen())
+        } else {
+            Duration::from_secs(0)
+        };
+        // Jitter must not reorder bytes within the link
+        let delivery = std::cmp::max(state.free_at + self.latency + jitter, state.last_delivery);
+        state.last_delivery = delivery;
+        delivery
+    }
+}
@@ -0,0 +1,532 @@
+//! Authenticated encryption for the streams multiplexed by `IMuxAsync`.
+//!
+//! Each stream runs a Noise `XK` handshake (X25519, ChaCha20-Poly1305,
+//! BLAKE2s) before any protocol message is exchanged. The client must know
+//! the server's static public key in advance, so a man-in-the-middle cannot
+//! impersonate the server; the server may additionally restrict which client
+//! keys it accepts.
+//!
+//! After the handshake a stream is split into a `SecureReader` and a
+//! `SecureWriter` which frame the data as `[u16 length][ciphertext]` records.
+//! Wrapping these in `CountingIO` (instead of the raw stream) keeps byte
+//! accounting in terms of protocol payload, i.e. excluding the two length
+//! bytes and 16 byte tag added to each record.
+
+use async_std::io::{prelude::*, Read, Write};
+use futures::ready;
+use snow::{params::NoiseParams, Builder, HandshakeState, TransportState};
+use std::{
+    io,
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+const NOISE_PARAMS: &str = "Noise_XK_25519_ChaChaPoly_BLAKE2s";
+
+/// Maximum length of a Noise message
+const MAX_FRAME_LEN: usize = 65535;
+/// Length of the Poly1305 tag appended to each record
+const TAG_LEN: usize = 16;
+/// Maximum number of plaintext bytes carried by a single record
+const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - TAG_LEN;
+/// Length of an X25519 key
+const KEY_LEN: usize = 32;
+
+fn noise_params() -> NoiseParams {
+    NOISE_PARAMS
+        .parse()
+        .expect("Noise parameters should be valid")
+}
+
+fn noise_error(e: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+fn key_length_error(len: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid key length {}", len),
+    )
+}
+
+/// A static X25519 keypair identifying one party
+#[derive(Clone)]
+pub struct Keypair {
+    pub private: Vec<u8>,
+    pub public: Vec<u8>,
+}
+
+impl Keypair {
+    /// Samples a fresh keypair from OS randomness
+    pub fn generate() -> io::Result<Self> {
+        let keypair = Builder::new(noise_params())
+            .generate_keypair()
+            .map_err(noise_error)?;
+        Ok(Self {
+            private: keypair.private,
+            public: keypair.public,
+        })
+    }
+
+    /// Reads a keypair stored by `Keypair::write_to`
+    pub fn read_from<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() != 2 * KEY_LEN {
+            return Err(key_length_error(bytes.len()));
+        }
+        let (private, public) = bytes.split_at(KEY_LEN);
+        Ok(Self {
+            private: private.to_vec(),
+            public: public.to_vec(),
+        })
+    }
+
+    /// Stores the private key followed by the public key at `path`
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path)?;
+        std::io::Write::write_all(&mut file, &self.private)?;
+        std::io::Write::write_all(&mut file, &self.public)
+    }
+
+    /// Reads a bare public key, e.g. one stored by `Keypair::write_public_to`
+    pub fn read_public_from<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() != KEY_LEN {
+            return Err(key_length_error(bytes.len()));
+        }
+        Ok(bytes)
+    }
+
+    /// Stores only the public key at `path` so it can be pinned by peers
+    pub fn write_public_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, &self.public)
+    }
+}
+
+/// Configuration for the listening party
+#[derive(Clone)]
+pub struct ServerConfig {
+    pub keypair: Keypair,
+    /// Client public keys which may connect. `None` accepts any client.
+    pub allowed_clients: Option<Vec<Vec<u8>>>,
+}
+
+/// Configuration for the connecting party
+#[derive(Clone)]
+pub struct ClientConfig {
+    pub keypair: Keypair,
+    /// The pinned static public key of the server
+    pub server_public: Vec<u8>,
+}
+
+impl ClientConfig {
+    /// Creates a config with an ephemeral client identity
+    pub fn new(server_public: Vec<u8>) -> io::Result<Self> {
+        Ok(Self {
+            keypair: Keypair::generate()?,
+            server_public,
+        })
+    }
+}
+
+/// Established channel keys for one stream
+pub struct Session {
+    state: Arc<Mutex<TransportState>>,
+}
+
+impl Session {
+    fn new(handshake: HandshakeState) -> io::Result<Self> {
+        let state = handshake.into_transport_mode().map_err(noise_error)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    /// Splits the session into encrypting halves over `reader` and `writer`,
+    /// which should be the two halves of the stream used for the handshake
+    pub fn split<R: Read + Unpin, W: Write + Unpin>(
+        self,
+        reader: R,
+        writer: W,
+    ) -> (SecureReader<R>, SecureWriter<W>) {
+        let reader = SecureReader {
+            inner: reader,
+            state: self.state.clone(),
+            header: [0u8; 2],
+            header_pos: 0,
+            frame: Vec::with_capacity(MAX_FRAME_LEN),
+            frame_pos: 0,
+            plaintext: vec![0u8; MAX_FRAME_LEN],
+            plaintext_pos: 0,
+            plaintext_len: 0,
+        };
+        let writer = SecureWriter {
+            inner: writer,
+            state: self.state,
+            plaintext: Vec::with_capacity(MAX_PAYLOAD_LEN),
+            frame: Vec::with_capacity(MAX_FRAME_LEN + 2),
+            frame_pos: 0,
+        };
+        (reader, writer)
+    }
+}
+
+/// Runs the initiator side of the handshake over `stream`
+pub async fn client_handshake<S: Read + Write + Unpin>(
+    stream: &mut S,
+    config: &ClientConfig,
+) -> io::Result<Session> {
+    if config.server_public.len() != KEY_LEN {
+        return Err(key_length_error(config.server_public.len()));
+    }
+    let mut handshake = Builder::new(noise_params())
+        .local_private_key(&config.keypair.private)
+        .remote_public_key(&config.server_public)
+        .build_initiator()
+        .map_err(noise_error)?;
+    // -> e, es
+    send_handshake(stream, &mut handshake).await?;
+    // <- e, ee
+    recv_handshake(stream, &mut handshake).await?;
+    // -> s, se
+    send_handshake(stream, &mut handshake).await?;
+    Session::new(handshake)
+}
+
+/// Runs the responder side of the handshake over `stream`
+pub async fn server_handshake<S: Read + Write + Unpin>(
+    stream: &mut S,
+    config: &ServerConfig,
+) -> io::Result<Session> {
+    let mut handshake = Builder::new(noise_params())
+        .local_private_key(&config.keypair.private)
+        .build_responder()
+        .map_err(noise_error)?;
+    recv_handshake(stream, &mut handshake).await?;
+    send_handshake(stream, &mut handshake).await?;
+    recv_handshake(stream, &mut handshake).await?;
+
+    if let Some(allowed) = &config.allowed_clients {
+        let client_key = handshake.get_remote_static().unwrap_or(&[]);
+        if !allowed.iter().any(|k| k.as_slice() == client_key) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Client key is not in the allowed set",
+            ));
+        }
+    }
+    Session::new(handshake)
+}
+
+async fn send_handshake<S: Write + Unpin>(
+    stream: &mut S,
+    handshake: &mut HandshakeState,
+) -> io::Result<()> {
+    let mut buf = vec![0u8; MAX_FRAME_LEN];
+    let len = handshake
+        .write_message(&[], &mut buf)
+        .map_err(noise_error)?;
+    stream.write_all(&(len as u16).to_be_bytes()).await?;
+    stream.write_all(&buf[..len]).await?;
+    stream.flush().await
+}
+
+async fn recv_handshake<S: Read + Unpin>(
+    stream: &mut S,
+    handshake: &mut HandshakeState,
+) -> io::Result<()> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header).await?;
+    let mut frame = vec![0u8; u16::from_be_bytes(header) as usize];
+    stream.read_exact(&mut frame).await?;
+    let mut buf = vec![0u8; MAX_FRAME_LEN];
+    handshake
+        .read_message(&frame, &mut buf)
+        .map_err(noise_error)?;
+    Ok(())
+}
+
+/// Decrypting half of a secure stream
+pub struct SecureReader<R> {
+    inner: R,
+    state: Arc<Mutex<TransportState>>,
+    header: [u8; 2],
+    header_pos: usize,
+    frame: Vec<u8>,
+    frame_pos: usize,
+    plaintext: Vec<u8>,
+    plaintext_pos: usize,
+    plaintext_len: usize,
+}
+
+impl<R: Read + Unpin> Read for SecureReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            // Hand out any plaintext left over from the last record
+            if this.plaintext_pos < this.plaintext_len {
+                let n = std::cmp::min(buf.len(), this.plaintext_len - this.plaintext_pos);
+                buf[..n]
+                    .copy_from_slice(&this.plaintext[this.plaintext_pos..this.plaintext_pos + n]);
+                this.plaintext_pos += n;
+                return Poll::Ready(Ok(n));
+            }
+
+            while this.header_pos < this.header.len() {
+                let n =
+                    ready!(Pin::new(&mut this.inner)
+                        .poll_read(cx, &mut this.header[this.header_pos..]))?;
+                if n == 0 {
+                    return if this.header_pos == 0 {
+                        Poll::Ready(Ok(0))
+                    } else {
+                        Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()))
+                    };
+                }
+                this.header_pos += n;
+            }
+
+            let frame_len = u16::from_be_bytes(this.header) as usize;
+            this.frame.resize(frame_len, 0);
+            while this.frame_pos < frame_len {
+                let n = ready!(
+                    Pin::new(&mut this.inner).poll_read(cx, &mut this.frame[this.frame_pos..])
+                )?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                this.frame_pos += n;
+            }
+
+            this.plaintext_len = this
+                .state
+                .lock()
+                .unwrap()
+                .read_message(&this.frame, &mut this.plaintext)
+                .map_err(noise_error)?;
+            this.plaintext_pos = 0;
+            this.header_pos = 0;
+            this.frame_pos = 0;
+        }
+    }
+}
+
+/// Encrypting half of a secure stream. Data is sealed into a record once
+/// `MAX_PAYLOAD_LEN` bytes are buffered or on `flush`.
+pub struct SecureWriter<W> {
+    inner: W,
+    state: Arc<Mutex<TransportState>>,
+    plaintext: Vec<u8>,
+    frame: Vec<u8>,
+    frame_pos: usize,
+}
+
+impl<W: Write + Unpin> SecureWriter<W> {
+    /// Seals any buffered plaintext and writes the resulting record to
+    /// `inner`
+    fn poll_write_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.frame.is_empty() && !self.plaintext.is_empty() {
+            self.frame.resize(MAX_FRAME_LEN + 2, 0);
+            let len = self
+                .state
+                .lock()
+                .unwrap()
+                .write_message(&self.plaintext, &mut self.frame[2..])
+                .map_err(noise_error)?;
+            self.frame[..2].copy_from_slice(&(len as u16).to_be_bytes());
+            self.frame.truncate(len + 2);
+            self.plaintext.clear();
+        }
+        while self.frame_pos < self.frame.len() {
+            let n =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &self.frame[self.frame_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.frame_pos += n;
+        }
+        self.frame.clear();
+        self.frame_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: Write + Unpin> Write for SecureWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.frame.is_empty() && this.plaintext.len() < MAX_PAYLOAD_LEN {
+                let n = std::cmp::min(buf.len(), MAX_PAYLOAD_LEN - this.plaintext.len());
+                this.plaintext.extend_from_slice(&buf[..n]);
+                return Poll::Ready(Ok(n));
+            }
+            ready!(this.poll_write_frame(cx))?;
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_frame(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_frame(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::{async_deserialize, async_serialize};
+    use async_std::{
+        io::{BufReader, BufWriter},
+        net::{TcpListener, TcpStream},
+        task,
+    };
+    use futures::stream::StreamExt;
+    use io_utils::{counting::CountingIO, imux::IMuxAsync};
+    use std::net::SocketAddr;
+
+    /// Binds a listener on an unused port
+    fn listen() -> (TcpListener, SocketAddr) {
+        task::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            (listener, addr)
+        })
+    }
+
+    #[test]
+    fn test_secure_roundtrip() {
+        let (listener, server_addr) = listen();
+        let server_config = ServerConfig {
+            keypair: Keypair::generate().unwrap(),
+            allowed_clients: None,
+        };
+        let client_config = ClientConfig::new(server_config.keypair.public.clone()).unwrap();
+        // Larger than a single record so framing across records is exercised
+        let message: Vec<u64> = (0..100_000).collect();
+
+        let received = crossbeam::thread::scope(|s| {
+            let server = s.spawn(|_| {
+                task::block_on(async {
+                    let mut stream = listener.incoming().next().await.unwrap().unwrap();
+                    let session = server_handshake(&mut stream, &server_config).await.unwrap();
+                    let (reader, writer) =
+                        session.split(BufReader::new(stream.clone()), BufWriter::new(stream));
+                    let mut reader = IMuxAsync::new(vec![CountingIO::new(reader)]);
+                    let mut writer = IMuxAsync::new(vec![writer]);
+                    let received: Vec<u64> = async_deserialize(&mut reader).await.unwrap();
+                    async_serialize(&mut writer, &received).await.unwrap();
+                    reader.count()
+                })
+            });
+            task::block_on(async {
+                let mut stream = TcpStream::connect(server_addr).await.unwrap();
+                let session = client_handshake(&mut stream, &client_config).await.unwrap();
+                let (reader, writer) =
+                    session.split(BufReader::new(stream.clone()), BufWriter::new(stream));
+                let mut reader = IMuxAsync::new(vec![reader]);
+                let mut writer = IMuxAsync::new(vec![writer]);
+                async_serialize(&mut writer, &message).await.unwrap();
+                let echoed: Vec<u64> = async_deserialize(&mut reader).await.unwrap();
+                assert_eq!(echoed, message);
+            });
+            server.join().unwrap()
+        })
+        .unwrap();
+        // `CountingIO` still sees every byte of the message
+        let payload_len = bincode::serialized_size(&message).unwrap() as usize;
+        assert!(received >= payload_len);
+    }
+
+    #[test]
+    fn test_unpinned_server_rejected() {
+        let (listener, server_addr) = listen();
+        let server_config = ServerConfig {
+            keypair: Keypair::generate().unwrap(),
+            allowed_clients: None,
+        };
+        // The client pins a key which doesn't belong to the server
+        let client_config = ClientConfig::new(Keypair::generate().unwrap().public).unwrap();
+
+        crossbeam::thread::scope(|s| {
+            let server = s.spawn(|_| {
+                task::block_on(async {
+                    let mut stream = listener.incoming().next().await.unwrap().unwrap();
+                    server_handshake(&mut stream, &server_config).await.is_err()
+                })
+            });
+            let client_failed = task::block_on(async {
+                let mut stream = TcpStream::connect(server_addr).await.unwrap();
+                client_handshake(&mut stream, &client_config).await.is_err()
+            });
+            assert!(server.join().unwrap());
+            assert!(client_failed);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_unallowed_client_rejected() {
+        let (listener, server_addr) = listen();
+        let allowed_client = Keypair::generate().unwrap();
+        let server_config = ServerConfig {
+            keypair: Keypair::generate().unwrap(),
+            allowed_clients: Some(vec![allowed_client.public.clone()]),
+        };
+        let server_public = server_config.keypair.public.clone();
+        let allowed_config = ClientConfig {
+            keypair: allowed_client,
+            server_public: server_public.clone(),
+        };
+        // Pins the right server key, but uses an identity the server doesn't know
+        let unknown_config = ClientConfig::new(server_public).unwrap();
+
+        crossbeam::thread::scope(|s| {
+            let server = s.spawn(|_| {
+                task::block_on(async {
+                    let mut incoming = listener.incoming();
+                    let mut results = Vec::new();
+                    for _ in 0..2 {
+                        let mut stream = incoming.next().await.unwrap().unwrap();
+                        results.push(
+                            server_handshake(&mut stream, &server_config)
+                                .await
+                                .map(|_| ())
+                                .map_err(|e| e.kind()),
+                        );
+                    }
+                    results
+                })
+            });
+            task::block_on(async {
+                for config in &[&unknown_config, &allowed_config] {
+                    let mut stream = TcpStream::connect(server_addr).await.unwrap();
+                    client_handshake(&mut stream, config).await.unwrap();
+                }
+            });
+            let results = server.join().unwrap();
+            assert_eq!(results[0], Err(io::ErrorKind::PermissionDenied));
+            assert_eq!(results[1], Ok(()));
+        })
+        .unwrap();
+    }
+}
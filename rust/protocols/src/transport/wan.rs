@@ -0,0 +1,168 @@
+//! Wide-area network emulation for real connections.
+//!
+//! A `WanLink` shapes every write made through its `WanWriter`s according to
+//! a `LinkConfig`: bytes are handed to the underlying stream only once their
+//! scheduled delivery time has passed. All writers created from one link
+//! share its bandwidth, so a link should correspond to all of the streams
+//! multiplexed by one `IMuxAsync`.
+//!
+//! Only outgoing data is delayed, so to emulate a round-trip time of `rtt`
+//! both parties should wrap their writers in a link with latency `rtt / 2`
+//! (see `LinkConfig::with_rtt`).
+
+use super::{LinkConfig, LinkState};
+use async_std::{
+    channel,
+    io::{prelude::*, Write},
+    task,
+};
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+impl LinkConfig {
+    /// Creates a config for one direction of a link with round-trip time
+    /// `rtt`, capacity `bandwidth` in bytes per second, and `jitter`
+    pub fn with_rtt(rtt: Duration, bandwidth: Option<u64>, jitter: Duration) -> Self {
+        Self {
+            latency: rtt / 2,
+            bandwidth,
+            jitter,
+        }
+    }
+}
+
+/// Shared state of an emulated link
+#[derive(Clone)]
+pub struct WanLink {
+    config: LinkConfig,
+    state: Arc<Mutex<LinkState>>,
+}
+
+impl WanLink {
+    pub fn new(config: LinkConfig) -> Self {
+        Self {
+            config,
+            state: Arc::new(Mutex::new(LinkState::new())),
+        }
+    }
+
+    /// Wraps `inner` so that writes are delivered according to this link.
+    /// The returned writer forwards data from a background task.
+    pub fn writer<W: Write + Unpin + Send + 'static>(&self, mut inner: W) -> WanWriter {
+        let (sender, receiver) = channel::unbounded::<(Instant, Vec<u8>)>();
+        let forwarder = task::spawn(async move {
+            while let Ok((deliver_at, bytes)) = receiver.recv().await {
+                let now = Instant::now();
+                if deliver_at > now {
+                    task::sleep(deliver_at - now).await;
+                }
+                inner.write_all(&bytes).await?;
+                if receiver.is_empty() {
+                    inner.flush().await?;
+                }
+            }
+            inner.flush().await
+        });
+        WanWriter {
+            link: self.clone(),
+            sender: Some(sender),
+            forwarder: Some(forwarder),
+        }
+    }
+}
+
+/// Writer which delays data according to a `WanLink`
+pub struct WanWriter {
+    link: WanLink,
+    sender: Option<channel::Sender<(Instant, Vec<u8>)>>,
+    forwarder: Option<task::JoinHandle<io::Result<()>>>,
+}
+
+fn broken_pipe() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "WAN forwarder has stopped")
+}
+
+impl Write for WanWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => return Poll::Ready(Err(broken_pipe())),
+        };
+        let deliver_at = self
+            .link
+            .config
+            .schedule(&mut self.link.state.lock().unwrap(), buf.len());
+        sender
+            .try_send((deliver_at, buf.to_vec()))
+            .map_err(|_| broken_pipe())?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Data is in flight once written, just as with a socket, so flushing
+    /// doesn't wait for delivery
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.sender.is_some() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Ready(Err(broken_pipe()))
+        }
+    }
+
+    /// Waits until all written data has been delivered
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.sender = None;
+        match this.forwarder.as_mut() {
+            Some(forwarder) => {
+                let result = futures::ready!(Pin::new(forwarder).poll(cx));
+                this.forwarder = None;
+                Poll::Ready(result)
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bytes::{deserialize, serialize},
+        transport::memory,
+    };
+    use io_utils::imux::IMuxAsync;
+
+    #[test]
+    fn test_wan_delay() {
+        let link = WanLink::new(LinkConfig::with_rtt(
+            Duration::from_millis(100),
+            None,
+            Duration::from_millis(10),
+        ));
+        let (client, server) = memory::duplex(LinkConfig::default());
+        let mut writer = IMuxAsync::new(vec![link.writer(client)]);
+        let mut reader = IMuxAsync::new(vec![server]);
+
+        let start = Instant::now();
+        for i in 0..10u64 {
+            serialize(&mut writer, &i).unwrap();
+            let received: u64 = deserialize(&mut reader).unwrap();
+            assert_eq!(received, i);
+        }
+        // Each message incurs at least half the round-trip time
+        assert!(start.elapsed() >= Duration::from_millis(500));
+    }
+}